@@ -1,4 +1,4 @@
-use std::net::IpAddr;
+use std::{net::IpAddr, sync::Mutex};
 
 use http::uri::Scheme;
 use llm_service_client::LlmServiceClient;
@@ -9,7 +9,11 @@ use anyhow::{anyhow, Context, Result};
 use tonic::{transport::Channel, Request, Response, Streaming};
 use tracing::{info, trace};
 
-use crate::collection_manager::dto::InteractionMessage;
+use crate::{
+    collection_manager::dto::InteractionMessage,
+    lru_cache::LruCache,
+    metrics::{EmbeddingCacheLabels, EMBEDDING_CACHE_HIT_COUNTER, EMBEDDING_CACHE_MISS_COUNTER},
+};
 
 tonic::include_proto!("orama_ai_service");
 
@@ -36,18 +40,26 @@ pub struct AIServiceConfig {
     pub api_key: Option<String>,
     #[serde(default = "default_max_connections")]
     pub max_connections: u64,
+    /// The number of query embeddings kept in memory. 0 disables the cache.
+    #[serde(default = "default_embedding_cache_size")]
+    pub embedding_cache_size: usize,
 }
 
 #[derive(Debug)]
 pub struct AIService {
     pool: Pool<GrpcManager>,
+    query_embedding_cache: Mutex<LruCache<(OramaModel, String), Vec<f32>>>,
 }
 
 impl AIService {
     pub fn new(config: AIServiceConfig) -> Self {
+        let query_embedding_cache = Mutex::new(LruCache::new(config.embedding_cache_size));
         let pool = Pool::builder().max_open(15).build(GrpcManager { config });
 
-        Self { pool }
+        Self {
+            pool,
+            query_embedding_cache,
+        }
     }
 
     async fn embed(
@@ -81,12 +93,64 @@ impl AIService {
         Ok(v)
     }
 
+    /// The query embeddings are cached: the same queries are commonly repeated.
     pub async fn embed_query(
         &self,
         model: OramaModel,
         input: Vec<&String>,
     ) -> Result<Vec<Vec<f32>>> {
-        self.embed(model, input, OramaIntent::Query).await
+        let mut output: Vec<Option<Vec<f32>>> = {
+            let mut cache = self.query_embedding_cache.lock().unwrap();
+            input
+                .iter()
+                .map(|text| cache.get(&(model, (*text).clone())).cloned())
+                .collect()
+        };
+
+        let misses: Vec<&String> = input
+            .iter()
+            .zip(&output)
+            .filter(|(_, embedding)| embedding.is_none())
+            .map(|(text, _)| *text)
+            .collect();
+
+        let labels = || EmbeddingCacheLabels {
+            model: model.as_str_name().to_string(),
+        };
+        EMBEDDING_CACHE_HIT_COUNTER
+            .create(labels())
+            .increment_by(input.len() - misses.len());
+        if misses.is_empty() {
+            return Ok(output.into_iter().flatten().collect());
+        }
+        EMBEDDING_CACHE_MISS_COUNTER
+            .create(labels())
+            .increment_by(misses.len());
+
+        let embeddings = self
+            .embed(model, misses.clone(), OramaIntent::Query)
+            .await?;
+        if embeddings.len() != misses.len() {
+            return Err(anyhow!(
+                "Expected {} embeddings, got {}",
+                misses.len(),
+                embeddings.len()
+            ));
+        }
+
+        let mut cache = self.query_embedding_cache.lock().unwrap();
+        let mut embeddings = embeddings.into_iter();
+        for (text, embedding) in input.iter().zip(output.iter_mut()) {
+            if embedding.is_some() {
+                continue;
+            }
+            // Checked above: there's an embedding for each miss
+            let calculated = embeddings.next().unwrap();
+            cache.insert((model, (*text).clone()), calculated.clone());
+            *embedding = Some(calculated);
+        }
+
+        Ok(output.into_iter().flatten().collect())
     }
 
     pub async fn embed_passage(
@@ -274,6 +338,9 @@ where
 fn default_max_connections() -> u64 {
     15
 }
+fn default_embedding_cache_size() -> usize {
+    1_000
+}
 fn default_scheme() -> Scheme {
     Scheme::HTTP
 }
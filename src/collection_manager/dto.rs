@@ -1,7 +1,12 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    time::Duration,
+};
 
 use axum_openapi3::utoipa::{self, IntoParams};
 use axum_openapi3::utoipa::{PartialSchema, ToSchema};
+use duration_str::deserialize_option_duration;
 use redact::Secret;
 use serde::{de, Deserialize, Serialize};
 
@@ -30,27 +35,131 @@ pub struct TokenScore {
     pub score: f32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema, PartialEq, Eq, Hash)]
 pub enum LanguageDTO {
+    Arabic,
+    Bulgarian,
+    Danish,
+    German,
+    Greek,
     English,
+    Spanish,
+    Estonian,
+    Finnish,
+    French,
+    Irish,
+    Hindi,
+    Hungarian,
+    Armenian,
+    Indonesian,
+    Italian,
+    Japanese,
+    Korean,
+    Lithuanian,
+    Nepali,
+    Dutch,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Sanskrit,
+    Slovenian,
+    Serbian,
+    Swedish,
+    Tamil,
+    Turkish,
+    Ukrainian,
+    Chinese,
 }
 
 impl From<LanguageDTO> for Locale {
     fn from(language: LanguageDTO) -> Self {
         match language {
+            LanguageDTO::Arabic => Locale::AR,
+            LanguageDTO::Bulgarian => Locale::BG,
+            LanguageDTO::Danish => Locale::DA,
+            LanguageDTO::German => Locale::DE,
+            LanguageDTO::Greek => Locale::EL,
             LanguageDTO::English => Locale::EN,
+            LanguageDTO::Spanish => Locale::ES,
+            LanguageDTO::Estonian => Locale::ET,
+            LanguageDTO::Finnish => Locale::FI,
+            LanguageDTO::French => Locale::FR,
+            LanguageDTO::Irish => Locale::GA,
+            LanguageDTO::Hindi => Locale::HI,
+            LanguageDTO::Hungarian => Locale::HU,
+            LanguageDTO::Armenian => Locale::HY,
+            LanguageDTO::Indonesian => Locale::ID,
+            LanguageDTO::Italian => Locale::IT,
+            LanguageDTO::Japanese => Locale::JP,
+            LanguageDTO::Korean => Locale::KO,
+            LanguageDTO::Lithuanian => Locale::LT,
+            LanguageDTO::Nepali => Locale::NE,
+            LanguageDTO::Dutch => Locale::NL,
+            LanguageDTO::Norwegian => Locale::NO,
+            LanguageDTO::Portuguese => Locale::PT,
+            LanguageDTO::Romanian => Locale::RO,
+            LanguageDTO::Russian => Locale::RU,
+            LanguageDTO::Sanskrit => Locale::SA,
+            LanguageDTO::Slovenian => Locale::SL,
+            LanguageDTO::Serbian => Locale::SR,
+            LanguageDTO::Swedish => Locale::SV,
+            LanguageDTO::Tamil => Locale::TA,
+            LanguageDTO::Turkish => Locale::TR,
+            LanguageDTO::Ukrainian => Locale::UK,
+            LanguageDTO::Chinese => Locale::ZH,
         }
     }
 }
 impl From<Locale> for LanguageDTO {
     fn from(language: Locale) -> Self {
         match language {
+            Locale::AR => LanguageDTO::Arabic,
+            Locale::BG => LanguageDTO::Bulgarian,
+            Locale::DA => LanguageDTO::Danish,
+            Locale::DE => LanguageDTO::German,
+            Locale::EL => LanguageDTO::Greek,
             Locale::EN => LanguageDTO::English,
-            _ => LanguageDTO::English,
+            Locale::ES => LanguageDTO::Spanish,
+            Locale::ET => LanguageDTO::Estonian,
+            Locale::FI => LanguageDTO::Finnish,
+            Locale::FR => LanguageDTO::French,
+            Locale::GA => LanguageDTO::Irish,
+            Locale::HI => LanguageDTO::Hindi,
+            Locale::HU => LanguageDTO::Hungarian,
+            Locale::HY => LanguageDTO::Armenian,
+            Locale::ID => LanguageDTO::Indonesian,
+            Locale::IT => LanguageDTO::Italian,
+            Locale::JP => LanguageDTO::Japanese,
+            Locale::KO => LanguageDTO::Korean,
+            Locale::LT => LanguageDTO::Lithuanian,
+            Locale::NE => LanguageDTO::Nepali,
+            Locale::NL => LanguageDTO::Dutch,
+            Locale::NO => LanguageDTO::Norwegian,
+            Locale::PT => LanguageDTO::Portuguese,
+            Locale::RO => LanguageDTO::Romanian,
+            Locale::RU => LanguageDTO::Russian,
+            Locale::SA => LanguageDTO::Sanskrit,
+            Locale::SL => LanguageDTO::Slovenian,
+            Locale::SR => LanguageDTO::Serbian,
+            Locale::SV => LanguageDTO::Swedish,
+            Locale::TA => LanguageDTO::Tamil,
+            Locale::TR => LanguageDTO::Turkish,
+            Locale::UK => LanguageDTO::Ukrainian,
+            Locale::ZH => LanguageDTO::Chinese,
         }
     }
 }
 
+impl FromStr for LanguageDTO {
+    type Err = anyhow::Error;
+
+    /// Accepts both the locale code (ie: "it") and the language name (ie: "Italian")
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Locale::from_str(&s.to_lowercase()).map(LanguageDTO::from)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, ToSchema, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum DocumentFields {
@@ -68,11 +177,86 @@ pub struct EmbeddingTypedField {
 #[derive(Debug, Clone)]
 pub enum TypedField {
     Text(Locale),
+    /// The whole value is indexed as a single term
+    Keyword,
     Embedding(EmbeddingTypedField),
     Number,
+    /// A RFC 3339 string, indexed as a number of milliseconds since the epoch
+    Datetime,
     Bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum SchemaFieldType {
+    #[serde(rename = "text")]
+    Text,
+    /// Indexed as a single term, neither tokenized nor stemmed (ie: a SKU or a tag)
+    #[serde(rename = "keyword")]
+    Keyword,
+    #[serde(rename = "number")]
+    Number,
+    #[serde(rename = "bool")]
+    Bool,
+    /// A RFC 3339 string (ie: "2024-10-18T10:00:00Z"), filtered as milliseconds since the epoch
+    #[serde(rename = "datetime")]
+    Datetime,
+    /// Calculated from `document_fields`: the field isn't in the document
+    #[serde(rename = "embedding")]
+    Embedding,
+}
+
+impl std::fmt::Display for SchemaFieldType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SchemaFieldType::Text => "text",
+            SchemaFieldType::Keyword => "keyword",
+            SchemaFieldType::Number => "number",
+            SchemaFieldType::Bool => "bool",
+            SchemaFieldType::Datetime => "datetime",
+            SchemaFieldType::Embedding => "embedding",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SchemaField {
+    #[serde(rename = "type")]
+    pub field_type: SchemaFieldType,
+    /// The language of a `text` field. Default to the collection language
+    #[serde(default)]
+    #[schema(inline)]
+    pub language: Option<LanguageDTO>,
+    /// If `false`, the field is only stored and returned with the document
+    #[serde(default = "default_indexed")]
+    pub indexed: bool,
+    /// The model of an `embedding` field
+    #[serde(default)]
+    pub model: Option<OramaModelSerializable>,
+    /// The fields used to calculate an `embedding` field
+    #[serde(default)]
+    pub document_fields: Vec<String>,
+}
+
+fn default_indexed() -> bool {
+    true
+}
+
+/// What to do with the document fields not declared in the schema
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum UnknownFieldsPolicy {
+    /// The type is inferred from the value
+    #[default]
+    #[serde(rename = "index")]
+    Index,
+    /// The field is stored but not indexed
+    #[serde(rename = "ignore")]
+    Ignore,
+    /// The document is rejected
+    #[serde(rename = "reject")]
+    Reject,
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CreateCollectionEmbeddings {
     pub model: Option<OramaModelSerializable>,
@@ -114,11 +298,55 @@ pub struct CreateCollection {
 
     #[schema(inline)]
     pub language: Option<LanguageDTO>,
+    /// Overrides the collection language for the given fields
+    #[serde(default)]
+    #[schema(inline)]
+    pub field_languages: HashMap<String, LanguageDTO>,
+    /// The document property which contains the document language (ie: "it" or "Italian").
+    /// It's used to tokenize the string fields without a language override.
+    #[serde(default)]
+    pub language_property: Option<String>,
+    /// The string fields searchable also by substring (ie: "4520" finds "XJ-45201-B").
+    /// The infix matches rank below the whole-token and prefix matches.
+    #[serde(default)]
+    pub infix_fields: HashSet<String>,
+    /// The type of the document fields, by path (ie: "author.name").
+    /// The fields not declared here follow `unknown_fields`.
+    #[serde(default)]
+    pub schema: HashMap<String, SchemaField>,
+    #[serde(default)]
+    pub unknown_fields: UnknownFieldsPolicy,
+    /// The documents are deleted after this time from their last write (ie: "7d").
+    /// The `expiresAt` document property overrides it.
+    #[serde(default, deserialize_with = "deserialize_option_duration")]
+    #[schema(value_type = Option<String>)]
+    pub ttl: Option<Duration>,
     #[serde(default)]
     #[schema(inline)]
     pub embeddings: Option<CreateCollectionEmbeddings>,
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DeleteCollection {
+    #[schema(inline)]
+    pub id: CollectionId,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CollectionAlias {
+    /// Usable in place of the collection id, both for reading and writing
+    #[schema(inline)]
+    pub alias: CollectionId,
+    #[schema(inline)]
+    pub collection_id: CollectionId,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DeleteAlias {
+    #[schema(inline)]
+    pub alias: CollectionId,
+}
+
 impl TryFrom<serde_json::Value> for CreateCollection {
     type Error = anyhow::Error;
 
@@ -130,6 +358,176 @@ impl TryFrom<serde_json::Value> for CreateCollection {
 
 pub type DeleteDocuments = Vec<String>;
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DeleteDocumentsByQuery {
+    /// The documents matching all the filters are deleted.
+    /// Only the number, datetime and boolean fields can be filtered.
+    #[serde(rename = "where")]
+    #[schema(inline)]
+    pub where_filter: HashMap<String, Filter>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum ImportFormat {
+    /// One JSON document per line
+    #[serde(rename = "ndjson")]
+    Ndjson,
+    /// The first record contains the property names
+    #[serde(rename = "csv")]
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub enum ImportState {
+    #[serde(rename = "running")]
+    Running,
+    #[serde(rename = "completed")]
+    Completed,
+    /// The body cannot be read till the end
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ImportLineError {
+    pub line: u64,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ImportStatus {
+    pub id: String,
+    #[schema(inline)]
+    pub collection_id: CollectionId,
+    pub state: ImportState,
+    /// The number of documents read from the body
+    pub processed: u64,
+    pub inserted: u64,
+    pub failed: u64,
+    /// The errors of the first failed lines
+    pub errors: Vec<ImportLineError>,
+    /// Why the import is stopped, if `state` is `failed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The offset the read side has to reach to see the imported documents.
+    /// Set when the import is done
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum TaskKind {
+    #[serde(rename = "document_insertion")]
+    DocumentInsertion,
+    #[serde(rename = "document_deletion")]
+    DocumentDeletion,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum TaskStatus {
+    #[serde(rename = "enqueued")]
+    Enqueued,
+    #[serde(rename = "processing")]
+    Processing,
+    #[serde(rename = "succeeded")]
+    Succeeded,
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TaskDocumentError {
+    pub id: String,
+    pub error: String,
+}
+
+/// The offsets of the operations sent to the read side while the task ran, both included.
+/// The writes out of the queue, like the imports, and the embeddings of other documents
+/// run meanwhile: the range can include their operations too.
+/// It tells when the task is visible to the read side, not which operations are the task's own.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct TaskOffsets {
+    pub from: u64,
+    pub to: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Task {
+    pub id: u64,
+    #[schema(inline)]
+    pub collection_id: CollectionId,
+    pub kind: TaskKind,
+    pub status: TaskStatus,
+    /// The number of documents given to the task
+    pub document_count: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// The errors of the first failed documents
+    pub errors: Vec<TaskDocumentError>,
+    /// Why the task is failed, if it is not a document error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// RFC 3339 datetimes
+    pub enqueued_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    /// Set when the task is finished: the search can wait for `to` with `min_offset`.
+    /// The range can include operations of other writes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offsets: Option<TaskOffsets>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ReindexCollection {
+    /// The new collection: the documents are copied into it
+    pub collection: CreateCollection,
+    /// Points this alias to the new collection when the reindex is completed
+    #[serde(default)]
+    #[schema(inline)]
+    pub alias: Option<CollectionId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub enum ReindexState {
+    #[serde(rename = "running")]
+    Running,
+    #[serde(rename = "completed")]
+    Completed,
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReindexDocumentError {
+    pub id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReindexStatus {
+    pub id: String,
+    #[schema(inline)]
+    pub source_collection_id: CollectionId,
+    #[schema(inline)]
+    pub collection_id: CollectionId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(inline)]
+    pub alias: Option<CollectionId>,
+    pub state: ReindexState,
+    /// The number of documents of the source collection when the reindex is started
+    pub total: u64,
+    pub processed: u64,
+    pub inserted: u64,
+    pub failed: u64,
+    /// The errors of the first failed documents
+    pub errors: Vec<ReindexDocumentError>,
+    /// Why the reindex is stopped, if `state` is `failed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
 pub struct CollectionDTO {
     #[schema(inline)]
@@ -206,6 +604,12 @@ pub struct HybridMode {
     pub term: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SimilarMode {
+    /// The id of the document used as source
+    pub id: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(tag = "mode")]
 pub enum SearchMode {
@@ -215,6 +619,8 @@ pub enum SearchMode {
     Vector(#[schema(inline)] VectorMode),
     #[serde(rename = "hybrid")]
     Hybrid(#[schema(inline)] HybridMode),
+    #[serde(rename = "similar")]
+    Similar(#[schema(inline)] SimilarMode),
     #[serde(untagged)]
     Default(#[schema(inline)] FulltextMode),
 }
@@ -239,7 +645,20 @@ impl Default for Properties {
     }
 }
 
-#[derive(Debug, Clone, Deserialize, ToSchema)]
+impl Serialize for Properties {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Properties::None => serializer.serialize_none(),
+            Properties::Star => serializer.serialize_str("*"),
+            Properties::Specified(properties) => properties.serialize(serializer),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct SearchParams {
     #[serde(flatten)]
     #[schema(inline)]
@@ -258,6 +677,19 @@ pub struct SearchParams {
     #[serde(default)]
     #[schema(inline)]
     pub facets: HashMap<String, FacetDefinition>,
+    /// Dotted paths of the fields to return. Empty means all the fields.
+    #[serde(default)]
+    pub select: Vec<String>,
+    /// Dotted paths of the fields to remove from the returned documents
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Returns the chunk which matches the query the most.
+    /// Used only by the `vector` and `hybrid` modes.
+    #[serde(default)]
+    pub include_chunk: bool,
+    /// The offset returned by a write: the search waits until the write is visible
+    #[serde(default, skip_serializing)]
+    pub min_offset: Option<u64>,
 }
 
 fn deserialize_json_string<'de, D>(deserializer: D) -> Result<Properties, D::Error>
@@ -315,8 +747,13 @@ impl TryFrom<serde_json::Value> for SearchParams {
 #[cfg_attr(test, derive(PartialEq))]
 pub struct SearchResultHit {
     pub id: String,
+    /// Changed on every write of the document: accepted as `if_version` by the write side
+    pub version: u64,
     pub score: f32,
     pub document: Option<RawJSONDocument>,
+    /// The part of the document which matches the query the most
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -335,6 +772,72 @@ pub struct SearchResult {
     pub facets: Option<HashMap<String, FacetResult>>,
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct GetDocumentsParams {
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema, PartialEq, Eq, Default)]
+pub enum FederatedMergeStrategy {
+    /// Min-max normalization of the scores of every collection
+    #[default]
+    #[serde(rename = "normalized")]
+    Normalized,
+    /// Reciprocal Rank Fusion: only the position of the hit is considered
+    #[serde(rename = "rrf")]
+    ReciprocalRankFusion,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct FederatedSearchQuery {
+    #[schema(inline)]
+    pub collection: CollectionId,
+    pub api_key: ApiKey,
+    #[schema(inline)]
+    pub params: SearchParams,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct FederatedSearchParams {
+    #[schema(inline)]
+    pub queries: Vec<FederatedSearchQuery>,
+    #[serde(default)]
+    #[schema(inline)]
+    pub limit: Limit,
+    #[serde(default)]
+    #[schema(inline)]
+    pub merge: FederatedMergeStrategy,
+}
+
+impl TryFrom<serde_json::Value> for FederatedSearchParams {
+    type Error = serde_json::Error;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        serde_json::from_value(value)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct FederatedSearchResultHit {
+    pub collection_id: CollectionId,
+    pub id: String,
+    pub version: u64,
+    pub score: f32,
+    pub document: Option<RawJSONDocument>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct FederatedSearchResult {
+    pub hits: Vec<FederatedSearchResultHit>,
+    pub count: usize,
+    /// Facets are calculated per query, keyed by the index of the query in `queries`
+    pub facets: HashMap<usize, HashMap<String, FacetResult>>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub enum RelatedQueriesFormat {
     Question,
@@ -3,11 +3,37 @@ use axum_openapi3::utoipa::ToSchema;
 use serde::{ser::Error, Deserialize, Serialize};
 use serde_json::Value;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+/// A numeric value.
+/// The 32-bit variants are used whenever the value fits in them without loss:
+/// the 64-bit variants are used only for the values that would be truncated otherwise.
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
 #[serde(untagged)]
 pub enum Number {
     I32(#[schema(inline)] i32),
     F32(#[schema(inline)] f32),
+    I64(#[schema(inline)] i64),
+    F64(#[schema(inline)] f64),
+}
+
+/// Internal representation used to compare and sum numbers of different variants
+enum Wide {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn wide(&self) -> Wide {
+        match self {
+            Number::I32(v) => Wide::Int(*v as i64),
+            Number::I64(v) => Wide::Int(*v),
+            Number::F32(v) => Wide::Float(*v as f64),
+            Number::F64(v) => Wide::Float(*v),
+        }
+    }
+
+    fn is_64_bit(&self) -> bool {
+        matches!(self, Number::I64(_) | Number::F64(_))
+    }
 }
 
 impl std::fmt::Display for Number {
@@ -15,6 +41,8 @@ impl std::fmt::Display for Number {
         match self {
             Number::I32(value) => write!(f, "{}", value),
             Number::F32(value) => write!(f, "{}", value),
+            Number::I64(value) => write!(f, "{}", value),
+            Number::F64(value) => write!(f, "{}", value),
         }
     }
 }
@@ -29,6 +57,24 @@ impl From<f32> for Number {
         Number::F32(value)
     }
 }
+impl From<i64> for Number {
+    fn from(value: i64) -> Self {
+        match i32::try_from(value) {
+            Ok(value) => Number::I32(value),
+            Err(_) => Number::I64(value),
+        }
+    }
+}
+impl From<f64> for Number {
+    fn from(value: f64) -> Self {
+        let narrow = value as f32;
+        if value.is_nan() || narrow as f64 == value {
+            Number::F32(narrow)
+        } else {
+            Number::F64(value)
+        }
+    }
+}
 impl TryFrom<&Value> for Number {
     type Error = serde_json::Error;
 
@@ -36,9 +82,9 @@ impl TryFrom<&Value> for Number {
         match value {
             Value::Number(n) => {
                 if let Some(i) = n.as_i64() {
-                    Ok(Number::I32(i as i32))
+                    Ok(Number::from(i))
                 } else if let Some(f) = n.as_f64() {
-                    Ok(Number::F32(f as f32))
+                    Ok(Number::from(f))
                 } else {
                     Err(serde_json::Error::custom("Not a number"))
                 }
@@ -48,21 +94,55 @@ impl TryFrom<&Value> for Number {
     }
 }
 
-impl PartialEq for Number {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Number::I32(a), Number::I32(b)) => a == b,
-            (Number::I32(a), Number::F32(b)) => *a as f32 == *b,
-            (Number::F32(a), Number::F32(b)) => {
-                // This is against the IEEE 754-2008 standard,
-                // But we don't care here.
-                if a.is_nan() && b.is_nan() {
-                    return true;
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Visitor;
+
+        struct NumberVisitor;
+
+        impl Visitor<'_> for NumberVisitor {
+            type Value = Number;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(formatter, "a number")
+            }
+
+            fn visit_i32<E>(self, v: i32) -> Result<Self::Value, E> {
+                Ok(Number::I32(v))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(Number::from(v))
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+                match i64::try_from(v) {
+                    Ok(v) => Ok(Number::from(v)),
+                    Err(_) => Ok(Number::F64(v as f64)),
                 }
-                a == b
             }
-            (Number::F32(a), Number::I32(b)) => *a == *b as f32,
+
+            fn visit_f32<E>(self, v: f32) -> Result<Self::Value, E> {
+                Ok(Number::F32(v))
+            }
+
+            fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+                Ok(Number::from(v))
+            }
         }
+
+        deserializer.deserialize_any(NumberVisitor)
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        // This is against the IEEE 754-2008 standard (NaN is equal to NaN),
+        // But we don't care here: it has to be consistent with `Ord`.
+        self.cmp(other) == std::cmp::Ordering::Equal
     }
 }
 impl Eq for Number {}
@@ -74,32 +154,63 @@ impl PartialOrd for Number {
 }
 impl Ord for Number {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        // f32 is implemented as "binary32" type defined in IEEE 754-2008
-        // So, it means, it can represent also +/- Infinity and NaN
+        // Floats are implemented as "binary32" and "binary64" types defined in IEEE 754-2008
+        // So, it means, they can represent also +/- Infinity and NaN
         // Threat NaN as "more" the Infinity
-        // See `total_cmp` method in f32
-        match (self, other) {
-            (Number::I32(a), Number::I32(b)) => a.cmp(b),
-            (Number::I32(a), Number::F32(b)) => (*a as f32).total_cmp(b),
-            (Number::F32(a), Number::F32(b)) => a.total_cmp(b),
-            (Number::F32(a), Number::I32(b)) => a.total_cmp(&(*b as f32)),
+        // See `total_cmp` method in f64
+        // The integers are compared exactly, also against floats:
+        // `f64` cannot represent all the `i64` values.
+        match (self.wide(), other.wide()) {
+            (Wide::Int(a), Wide::Int(b)) => a.cmp(&b),
+            (Wide::Float(a), Wide::Float(b)) => a.total_cmp(&b),
+            (Wide::Int(a), Wide::Float(b)) => cmp_int_float(a, b),
+            (Wide::Float(a), Wide::Int(b)) => cmp_int_float(b, a).reverse(),
         }
     }
 }
 
+fn cmp_int_float(a: i64, b: f64) -> std::cmp::Ordering {
+    match (a as f64).total_cmp(&b) {
+        // `b` is an integer value here: compare without losing precision.
+        // `i64::MAX as f64` is 2^63, which is greater than any `i64`
+        std::cmp::Ordering::Equal if b < i64::MAX as f64 => a.cmp(&(b as i64)),
+        std::cmp::Ordering::Equal => std::cmp::Ordering::Less,
+        ord => ord,
+    }
+}
+
 impl std::ops::Add for Number {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Number::I32(a), Number::I32(b)) => (a + b).into(),
-            (Number::I32(a), Number::F32(b)) => (a as f32 + b).into(),
-            (Number::F32(a), Number::F32(b)) => (a + b).into(),
-            (Number::F32(a), Number::I32(b)) => (a + b as f32).into(),
+        let is_64_bit = self.is_64_bit() || rhs.is_64_bit();
+        match (self.wide(), rhs.wide()) {
+            (Wide::Int(a), Wide::Int(b)) => match a.checked_add(b) {
+                Some(v) => v.into(),
+                None => Number::F64(a as f64 + b as f64),
+            },
+            (a, b) => {
+                let a = match a {
+                    Wide::Int(v) => v as f64,
+                    Wide::Float(v) => v,
+                };
+                let b = match b {
+                    Wide::Int(v) => v as f64,
+                    Wide::Float(v) => v,
+                };
+                if is_64_bit {
+                    Number::F64(a + b)
+                } else {
+                    Number::F32(a as f32 + b as f32)
+                }
+            }
         }
     }
 }
 
+/// The on-disk representation of `Number`: a discriminant followed by the value.
+/// 1 to 4 are the original discriminants, 5 (`i64`) and 6 (`f64`) are added later:
+/// the indexes written before keep being readable.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct SerializableNumber(pub Number);
 
@@ -133,6 +244,28 @@ impl Serialize for SerializableNumber {
                 tuple.serialize_element(v)?;
                 tuple.end()
             }
+            Number::I64(v) => {
+                let mut tuple = serializer.serialize_tuple(2)?;
+                tuple.serialize_element(&5_u8)?;
+                tuple.serialize_element(v)?;
+                tuple.end()
+            }
+            Number::F64(v) => {
+                let mut tuple = serializer.serialize_tuple(2)?;
+
+                if v.is_infinite() && v.is_sign_positive() {
+                    tuple.serialize_element(&3_u8)?;
+                    return tuple.end();
+                }
+                if v.is_infinite() && v.is_sign_negative() {
+                    tuple.serialize_element(&4_u8)?;
+                    return tuple.end();
+                }
+
+                tuple.serialize_element(&6_u8)?;
+                tuple.serialize_element(v)?;
+                tuple.end()
+            }
         }
     }
 }
@@ -178,9 +311,21 @@ impl<'de> Deserialize<'de> for SerializableNumber {
                     }
                     3 => Ok(SerializableNumber(Number::F32(f32::INFINITY))),
                     4 => Ok(SerializableNumber(Number::F32(f32::NEG_INFINITY))),
+                    5 => {
+                        let y = seq
+                            .next_element()?
+                            .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+                        Ok(SerializableNumber(Number::I64(y)))
+                    }
+                    6 => {
+                        let x = seq
+                            .next_element()?
+                            .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+                        Ok(SerializableNumber(Number::F64(x)))
+                    }
                     d => Err(A::Error::invalid_value(
                         serde::de::Unexpected::Unsigned(d.into()),
-                        &"1, 2, 3, 4",
+                        &"1, 2, 3, 4, 5, 6",
                     )),
                 }
             }
@@ -206,10 +351,26 @@ pub enum NumberFilter {
     Between(#[schema(inline)] (Number, Number)),
 }
 
+impl NumberFilter {
+    /// The bounds of `between` are inclusive, as in the indexes
+    pub fn matches(&self, value: &Number) -> bool {
+        match self {
+            NumberFilter::Equal(n) => value == n,
+            NumberFilter::GreaterThan(n) => value > n,
+            NumberFilter::GreaterThanOrEqual(n) => value >= n,
+            NumberFilter::LessThan(n) => value < n,
+            NumberFilter::LessThanOrEqual(n) => value <= n,
+            NumberFilter::Between((min, max)) => min <= value && value <= max,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::cmp::Ordering;
 
+    use serde_json::json;
+
     use super::*;
 
     #[test]
@@ -331,4 +492,96 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_number_filter_matches() {
+        let value = Number::from(5);
+        assert!(NumberFilter::Equal(Number::from(5.0)).matches(&value));
+        assert!(!NumberFilter::GreaterThan(Number::from(5)).matches(&value));
+        assert!(NumberFilter::GreaterThanOrEqual(Number::from(5)).matches(&value));
+        assert!(NumberFilter::LessThan(Number::from(5.5)).matches(&value));
+        assert!(!NumberFilter::LessThanOrEqual(Number::from(4)).matches(&value));
+        assert!(NumberFilter::Between((Number::from(5), Number::from(6))).matches(&value));
+        assert!(!NumberFilter::Between((Number::from(6), Number::from(7))).matches(&value));
+    }
+
+    #[test]
+    fn test_number_64_bit() {
+        let n = Number::try_from(&json!(1_700_000_000_123_i64)).unwrap();
+        assert!(matches!(n, Number::I64(1_700_000_000_123)));
+        let n = Number::try_from(&json!(42)).unwrap();
+        assert!(matches!(n, Number::I32(42)));
+
+        let n = Number::try_from(&json!(0.5)).unwrap();
+        assert!(matches!(n, Number::F32(v) if v == 0.5));
+        let n = Number::try_from(&json!(19.99)).unwrap();
+        assert!(matches!(n, Number::F64(v) if v == 19.99));
+
+        let n: Number = serde_json::from_value(json!(9_007_199_254_740_993_i64)).unwrap();
+        assert!(matches!(n, Number::I64(9_007_199_254_740_993)));
+        let n: Number = serde_json::from_value(json!(19.99)).unwrap();
+        assert!(matches!(n, Number::F64(v) if v == 19.99));
+
+        // Not representable as `f64`: they have to be different
+        let a = Number::I64(9_007_199_254_740_993);
+        let b = Number::F64(9_007_199_254_740_992.0);
+        assert_ne!(a, b);
+        assert!(a > b);
+        assert!(Number::I64(9_007_199_254_740_992) == b);
+
+        assert!(Number::I64(i64::MAX) < Number::F64(i64::MAX as f64));
+        assert!(Number::I64(i64::MIN) == Number::F64(i64::MIN as f64));
+        assert!(Number::I64(i64::MAX) < Number::from(f32::INFINITY));
+        assert!(Number::I64(i64::MIN) > Number::from(f32::NEG_INFINITY));
+        assert!(Number::F64(1e300) < Number::from(f32::NAN));
+
+        assert_eq!(Number::I32(i32::MAX) + Number::I32(1), Number::I64(1 << 31));
+        assert!(matches!(
+            Number::F64(0.25) + Number::I32(1),
+            Number::F64(v) if v == 1.25
+        ));
+
+        let v = [
+            Number::I32(1),
+            Number::I64(1 << 40),
+            Number::I64(-(1 << 40)),
+            Number::F32(2.5),
+            Number::F64(2.5),
+            Number::F64(0.1),
+            Number::F64(1e300),
+            Number::I64(i64::MAX),
+            Number::I64(i64::MIN),
+            Number::from(f32::INFINITY),
+            Number::from(f32::NEG_INFINITY),
+            Number::from(f32::NAN),
+        ];
+        for i in 0..v.len() {
+            for j in 0..v.len() {
+                assert_eq!(v[i].cmp(&v[j]).reverse(), v[j].cmp(&v[i]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_serializable_number_roundtrip() {
+        let numbers = [
+            Number::I32(-7),
+            Number::F32(2.5),
+            Number::I64(1_700_000_000_123),
+            Number::F64(19.99),
+            Number::F32(f32::INFINITY),
+            Number::F64(f64::NEG_INFINITY),
+        ];
+        for n in numbers {
+            let serialized = serde_json::to_string(&SerializableNumber(n)).unwrap();
+            let deserialized: SerializableNumber = serde_json::from_str(&serialized).unwrap();
+            assert_eq!(deserialized.0, n);
+        }
+
+        // The format written before the 64-bit variants is still readable
+        let n: SerializableNumber = serde_json::from_str("[2,42]").unwrap();
+        assert!(matches!(n.0, Number::I32(42)));
+        let n: SerializableNumber = serde_json::from_str("[1,0.5]").unwrap();
+        assert!(matches!(n.0, Number::F32(v) if v == 0.5));
+    }
 }
@@ -113,6 +113,8 @@ impl CommittedDiskDocumentStorage {
 #[derive(Debug)]
 pub struct DocumentStorageConfig {
     pub data_dir: PathBuf,
+    /// Used only as metric label
+    pub side: &'static str,
 }
 
 #[derive(Debug)]
@@ -120,6 +122,7 @@ pub struct DocumentStorage {
     uncommitted: tokio::sync::RwLock<HashMap<DocumentId, RawJSONDocument>>,
     committed: CommittedDiskDocumentStorage,
     uncommitted_document_deletions: tokio::sync::RwLock<HashSet<DocumentId>>,
+    side: &'static str,
 }
 
 impl DocumentStorage {
@@ -130,6 +133,7 @@ impl DocumentStorage {
             uncommitted: Default::default(),
             committed: CommittedDiskDocumentStorage::new(config.data_dir),
             uncommitted_document_deletions: Default::default(),
+            side: config.side,
         })
     }
 
@@ -151,10 +155,16 @@ impl DocumentStorage {
     #[tracing::instrument(skip(self, doc_ids))]
     pub async fn get_documents_by_ids(
         &self,
-        mut doc_ids: Vec<DocumentId>,
+        doc_ids: Vec<DocumentId>,
     ) -> Result<Vec<Option<RawJSONDocument>>> {
+        // The output has the same length of the input:
+        // deleted documents are returned as `None`
         let uncommitted_document_deletions = self.uncommitted_document_deletions.read().await;
-        doc_ids.retain(|doc_id| !uncommitted_document_deletions.contains(doc_id));
+        let is_deleted: Vec<bool> = doc_ids
+            .iter()
+            .map(|doc_id| uncommitted_document_deletions.contains(doc_id))
+            .collect();
+        drop(uncommitted_document_deletions);
 
         debug!("Get from committed documents");
         let committed = self.committed.get_documents_by_ids(&doc_ids).await?;
@@ -170,7 +180,12 @@ impl DocumentStorage {
         let result = committed
             .into_iter()
             .zip(uncommitted)
-            .map(|(committed, uncommitted)| {
+            .zip(is_deleted)
+            .map(|((committed, uncommitted), is_deleted)| {
+                if is_deleted {
+                    return None;
+                }
+
                 if let Some(doc) = uncommitted {
                     Some(doc)
                 } else {
@@ -195,13 +210,19 @@ impl DocumentStorage {
         // TODO: fix me
 
         let m = COMMIT_METRIC.create(CommitLabels {
-            side: "read",
+            side: self.side,
             collection: "".to_string(),
             index_type: "document",
         });
+        let uncommitted_document_deletions = self.uncommitted_document_deletions.read().await;
         let mut lock = self.uncommitted.write().await;
-        let uncommitted: Vec<_> = lock.drain().collect();
+        // A document replaced before the commit is never written on disk
+        let uncommitted: Vec<_> = lock
+            .drain()
+            .filter(|(doc_id, _)| !uncommitted_document_deletions.contains(doc_id))
+            .collect();
         drop(lock);
+        drop(uncommitted_document_deletions);
 
         self.committed
             .add(uncommitted)
@@ -212,9 +233,13 @@ impl DocumentStorage {
         let mut uncommitted_document_deletions = self.uncommitted_document_deletions.write().await;
         for doc_id in uncommitted_document_deletions.drain() {
             let doc_path = self.committed.path.join(format!("{}", doc_id.0));
-            tokio::fs::remove_file(doc_path)
-                .await
-                .context("Cannot remove document")?;
+            match tokio::fs::remove_file(doc_path).await {
+                std::result::Result::Ok(()) => {}
+                // The document was deleted before being committed
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).context("Cannot remove document"),
+            }
+            self.committed.cache.write().await.remove(&doc_id);
         }
         uncommitted_document_deletions.clear();
         drop(uncommitted_document_deletions);
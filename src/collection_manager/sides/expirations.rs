@@ -0,0 +1,97 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    hash::Hash,
+};
+
+/// The expiration, in milliseconds, of the documents.
+/// Indexed also by expiration: the expired documents are found without a scan.
+#[derive(Debug)]
+pub struct Expirations<K> {
+    by_key: HashMap<K, i64>,
+    by_expiration: BTreeMap<i64, HashSet<K>>,
+}
+
+impl<K> Default for Expirations<K> {
+    fn default() -> Self {
+        Self {
+            by_key: HashMap::new(),
+            by_expiration: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash> Expirations<K> {
+    pub fn insert(&mut self, key: K, expires_at: i64) {
+        self.remove(&key);
+        self.by_key.insert(key.clone(), expires_at);
+        self.by_expiration
+            .entry(expires_at)
+            .or_default()
+            .insert(key);
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        let Some(expires_at) = self.by_key.remove(key) else {
+            return;
+        };
+        if let Some(keys) = self.by_expiration.get_mut(&expires_at) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.by_expiration.remove(&expires_at);
+            }
+        }
+    }
+
+    /// The documents expired at `now`, the oldest first
+    pub fn expired(&self, now: i64) -> impl Iterator<Item = &K> {
+        self.by_expiration.range(..=now).flat_map(|(_, keys)| keys)
+    }
+
+    /// The last expiration until `now`: the expired documents change only with it
+    pub fn last_expiration(&self, now: i64) -> Option<i64> {
+        self.by_expiration
+            .range(..=now)
+            .next_back()
+            .map(|(expires_at, _)| *expires_at)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, i64)> {
+        self.by_key
+            .iter()
+            .map(|(key, expires_at)| (key, *expires_at))
+    }
+}
+
+impl<K: Clone + Eq + Hash> FromIterator<(K, i64)> for Expirations<K> {
+    fn from_iter<T: IntoIterator<Item = (K, i64)>>(iter: T) -> Self {
+        let mut expirations = Self::default();
+        for (key, expires_at) in iter {
+            expirations.insert(key, expires_at);
+        }
+        expirations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expirations() {
+        let mut expirations: Expirations<&str> =
+            [("a", 10), ("b", 30), ("c", 20)].into_iter().collect();
+        // Moved after "c"
+        expirations.insert("a", 25);
+        expirations.remove(&"b");
+
+        assert_eq!(expirations.expired(19).count(), 0);
+        assert_eq!(expirations.last_expiration(19), None);
+        assert_eq!(expirations.expired(20).collect::<Vec<_>>(), vec![&"c"]);
+        assert_eq!(expirations.last_expiration(20), Some(20));
+        assert_eq!(
+            expirations.expired(100).collect::<Vec<_>>(),
+            vec![&"c", &"a"]
+        );
+        assert_eq!(expirations.last_expiration(100), Some(25));
+    }
+}
@@ -1,3 +1,5 @@
+mod document_storage;
+mod expirations;
 pub mod hooks;
 mod read;
 mod write;
@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     collections::{HashMap, HashSet},
     path::PathBuf,
     sync::{
@@ -8,8 +9,10 @@ use std::{
 };
 
 use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
 use committed::CommittedCollection;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
+use doc_id_storage::DocIdStorage;
 use dump::{CollectionInfo, CollectionInfoV1};
 use merge::{merge_bool_field, merge_number_field, merge_string_field, merge_vector_field};
 use redact::Secret;
@@ -22,6 +25,7 @@ use tracing::{debug, error, info, instrument, trace, warn};
 use uncommitted::UncommittedCollection;
 
 mod committed;
+mod doc_id_storage;
 mod merge;
 mod uncommitted;
 
@@ -29,10 +33,14 @@ use crate::{
     ai::{AIService, OramaModel},
     collection_manager::{
         dto::{
-            self, ApiKey, BM25Scorer, FacetDefinition, FacetResult, FieldId, Filter, Limit,
+            self, ApiKey, BM25Scorer, FacetDefinition, FacetResult, FieldId, Filter, Limit, Number,
             NumberFilter, Properties, SearchMode, SearchParams,
         },
-        sides::{CollectionWriteOperation, Offset, OramaModelSerializable},
+        sides::{
+            document_storage::DocumentStorage, expirations::Expirations, keyword_term,
+            CollectionWriteOperation, DocumentFieldIndexOperation, Offset, OramaModelSerializable,
+            INFIX_MIN_LENGTH, INFIX_TERM_PREFIX,
+        },
     },
     file_utils::BufferedFile,
     metrics::{
@@ -41,11 +49,17 @@ use crate::{
     },
     nlp::{locales::Locale, NLPService, TextParser},
     offset_storage::OffsetStorage,
-    types::{CollectionId, DocumentId},
+    types::{CollectionId, Document, DocumentId, RawJSONDocument},
 };
 
 use super::IndexesConfig;
 
+/// Maximum number of terms extracted from the source document in a "similar" search
+const SIMILAR_MAX_TERMS: usize = 25;
+
+/// Number of documents read at once while migrating a number field
+const NUMBER_MIGRATION_BATCH_SIZE: usize = 1_000;
+
 #[derive(Debug)]
 pub struct CollectionReader {
     id: CollectionId,
@@ -60,10 +74,17 @@ pub struct CollectionReader {
     uncommitted_collection: RwLock<UncommittedCollection>,
     committed_collection: RwLock<CommittedCollection>,
     uncommitted_deleted_documents: RwLock<HashSet<DocumentId>>,
+    /// The expiration in milliseconds: the expired documents are excluded from the search
+    /// until the write side deletes them
+    expirations: RwLock<Expirations<DocumentId>>,
+
+    doc_id_storage: RwLock<DocIdStorage>,
 
     fields_per_model: DashMap<OramaModel, Vec<FieldId>>,
 
     text_parser_per_field: DashMap<FieldId, (Locale, Arc<TextParser>)>,
+    /// The string fields matched as a whole: they have no text parser
+    keyword_fields: DashSet<FieldId>,
 
     offset_storage: OffsetStorage,
     commit_insert_mutex: Mutex<()>,
@@ -85,11 +106,15 @@ impl CollectionReader {
             document_count: AtomicU64::new(0),
             fields_per_model: Default::default(),
             text_parser_per_field: Default::default(),
+            keyword_fields: Default::default(),
             fields: Default::default(),
 
             uncommitted_collection: RwLock::new(UncommittedCollection::new()),
             committed_collection: RwLock::new(CommittedCollection::new()),
             uncommitted_deleted_documents: RwLock::new(HashSet::new()),
+            expirations: Default::default(),
+
+            doc_id_storage: Default::default(),
 
             offset_storage: Default::default(),
             commit_insert_mutex: Default::default(),
@@ -108,6 +133,11 @@ impl CollectionReader {
         self.id.clone()
     }
 
+    /// The offset of the last write operation applied to this collection
+    pub fn get_offset(&self) -> Offset {
+        self.offset_storage.get_offset()
+    }
+
     pub fn get_field_id(&self, field_name: String) -> Result<FieldId> {
         let field_id = self.fields.get(&field_name);
 
@@ -124,7 +154,11 @@ impl CollectionReader {
             .ok_or_else(|| anyhow!("Field not found"))
     }
 
-    pub async fn load(&mut self, data_dir: PathBuf) -> Result<()> {
+    pub async fn load(
+        &mut self,
+        data_dir: PathBuf,
+        document_storage: &DocumentStorage,
+    ) -> Result<()> {
         info!("Loading collection from {:?}", data_dir);
 
         let collection_info_path = data_dir.join("info.info");
@@ -142,6 +176,8 @@ impl CollectionReader {
         info!("Current offset: {:?}", current_offset);
         assert!(current_offset.0 > 0);
         println!("Loaded offset: {:?}", current_offset);
+        // The operations up to this offset are committed: the replay of the side channel skips them
+        self.offset_storage.set_offset(current_offset);
 
         let collection_info_path = data_dir.join(format!("info-offset-{}.info", current_offset.0));
         let collection_info: CollectionInfo = BufferedFile::open(collection_info_path)
@@ -155,6 +191,7 @@ impl CollectionReader {
         for (field_name, (field_id, field_type)) in collection_info.fields {
             let typed_field: TypedField = match field_type {
                 dump::TypedField::Text(locale) => TypedField::Text(locale),
+                dump::TypedField::Keyword => TypedField::Keyword,
                 dump::TypedField::Embedding(embedding) => TypedField::Embedding(embedding.model.0),
                 dump::TypedField::Number => TypedField::Number,
                 dump::TypedField::Bool => TypedField::Bool,
@@ -162,6 +199,8 @@ impl CollectionReader {
             self.fields.insert(field_name, (field_id, typed_field));
         }
 
+        self.expirations = RwLock::new(collection_info.expirations.into_iter().collect());
+
         for (orama_model, fields) in collection_info.used_models {
             self.fields_per_model.insert(orama_model.0, fields);
         }
@@ -178,6 +217,19 @@ impl CollectionReader {
                 }
             })
             .collect();
+        self.keyword_fields = self
+            .fields
+            .iter()
+            .filter(|e| matches!(e.1, TypedField::Keyword))
+            .map(|e| e.0)
+            .collect();
+
+        let legacy_number_fields: Vec<_> = collection_info
+            .number_field_infos
+            .iter()
+            .filter(|(_, info)| info.version < committed::fields::NUMBER_FIELD_VERSION)
+            .map(|(field_id, _)| *field_id)
+            .collect();
 
         let mut lock = self.committed_collection.write().await;
         lock.load(
@@ -186,6 +238,87 @@ impl CollectionReader {
             collection_info.string_field_infos,
             collection_info.vector_field_infos,
         )?;
+        drop(lock);
+
+        let doc_id_storage_path = data_dir.join("doc_id_storage");
+        if doc_id_storage_path.exists() {
+            self.doc_id_storage = RwLock::new(
+                DocIdStorage::load(doc_id_storage_path).context("Cannot load doc id storage")?,
+            );
+        } else {
+            warn!("Doc id storage not found. Documents are not retrievable by id");
+        }
+
+        for field_id in legacy_number_fields {
+            self.migrate_number_field(field_id, document_storage)
+                .await
+                .with_context(|| format!("Cannot migrate number field {:?}", field_id))?;
+        }
+
+        Ok(())
+    }
+
+    /// The number indexes written before the 64-bit numbers support contain truncated values.
+    /// The index is rebuilt from the stored documents and kept as uncommitted:
+    /// the next commit persists it with the current format.
+    async fn migrate_number_field(
+        &self,
+        field_id: FieldId,
+        document_storage: &DocumentStorage,
+    ) -> Result<()> {
+        let field_name = self
+            .fields
+            .iter()
+            .find(|e| e.value().0 == field_id)
+            .map(|e| e.key().clone())
+            .context("Number field not registered")?;
+
+        info!(
+            "Migrating number field {:?} ({}) of collection {:?}",
+            field_id, field_name, self.id
+        );
+
+        let mut committed = self.committed_collection.write().await;
+        let legacy_field = match committed.number_index.remove(&field_id) {
+            Some(legacy_field) => legacy_field,
+            None => return Ok(()),
+        };
+        let mut doc_ids: Vec<DocumentId> = legacy_field
+            .iter()
+            .flat_map(|(_, doc_ids)| doc_ids)
+            .collect();
+        doc_ids.sort_unstable();
+        doc_ids.dedup();
+        drop(committed);
+
+        let mut number_field = uncommitted::fields::NumberField::empty();
+        for doc_ids in doc_ids.chunks(NUMBER_MIGRATION_BATCH_SIZE) {
+            let docs = document_storage
+                .get_documents_by_ids(doc_ids.to_vec())
+                .await
+                .context("Cannot get documents to migrate")?;
+
+            for (doc_id, doc) in doc_ids.iter().zip(docs) {
+                // The document has been deleted
+                let doc = match doc {
+                    Some(doc) => doc,
+                    None => continue,
+                };
+                let doc: serde_json::Value =
+                    serde_json::from_str(doc.inner.get()).context("Cannot deserialize document")?;
+                let doc: Document = doc.try_into()?;
+                let value = doc
+                    .into_flatten()
+                    .get(&field_name)
+                    .and_then(|v| Number::try_from(v).ok());
+                if let Some(value) = value {
+                    number_field.insert(*doc_id, value);
+                }
+            }
+        }
+
+        let mut uncommitted = self.uncommitted_collection.write().await;
+        uncommitted.number_index.insert(field_id, number_field);
 
         Ok(())
     }
@@ -201,6 +334,12 @@ impl CollectionReader {
         assert!(offset.0 > 0);
         debug!("Committing with offset: {:?}", offset);
 
+        self.doc_id_storage
+            .read()
+            .await
+            .commit(data_dir.join("doc_id_storage"))
+            .context("Cannot commit doc id storage")?;
+
         let collection_info_path = data_dir.join("info.info");
         let previous_offset: Option<Offset> = match BufferedFile::open(collection_info_path.clone())
             .context("Cannot open previous collection info")?
@@ -237,6 +376,7 @@ impl CollectionReader {
                 string_field_infos: Default::default(),
                 bool_field_infos: Default::default(),
                 vector_field_infos: Default::default(),
+                expirations: Default::default(),
             }
         };
 
@@ -371,19 +511,24 @@ impl CollectionReader {
                 .string_field_infos
                 .push((field_id, field_info));
 
-            let field_locale = self
-                .text_parser_per_field
-                .get(&field_id)
-                .map(|e| e.0)
-                .context("String field not registered")?;
+            let field_type = if self.keyword_fields.contains(&field_id) {
+                dump::TypedField::Keyword
+            } else {
+                let field_locale = self
+                    .text_parser_per_field
+                    .get(&field_id)
+                    .map(|e| e.0)
+                    .context("String field not registered")?;
+                dump::TypedField::Text(field_locale)
+            };
             let field = current_collection_info
                 .fields
                 .iter_mut()
                 .find(|(_, (f, _))| f == &field_id);
             match field {
                 Some((_, (_, typed_field))) => {
-                    if typed_field != &mut dump::TypedField::Text(field_locale) {
-                        error!("Field {:?} is changing type and this is not allowed. before {:?} after {:?}", field_id, typed_field, dump::TypedField::Text(field_locale));
+                    if typed_field != &field_type {
+                        error!("Field {:?} is changing type and this is not allowed. before {:?} after {:?}", field_id, typed_field, field_type);
                         return Err(anyhow!(
                             "Field {:?} is changing type and this is not allowed",
                             field_id
@@ -399,7 +544,7 @@ impl CollectionReader {
                     let field_name = field_name.key().to_string();
                     current_collection_info
                         .fields
-                        .push((field_name, (field_id, dump::TypedField::Text(field_locale))));
+                        .push((field_name, (field_id, field_type)));
                 }
             }
             drop(m);
@@ -582,6 +727,14 @@ impl CollectionReader {
         drop(committed);
         drop(uncommitted);
 
+        current_collection_info.expirations = self
+            .expirations
+            .read()
+            .await
+            .iter()
+            .map(|(doc_id, expires_at)| (*doc_id, expires_at))
+            .collect();
+
         let new_offset_collection_info_path =
             data_dir.join(format!("info-offset-{}.info", offset.0));
         BufferedFile::create(new_offset_collection_info_path)
@@ -601,8 +754,41 @@ impl CollectionReader {
         Ok(())
     }
 
-    pub fn increment_document_count(&self) {
+    /// The document is stored by the read side: the collection counts it and maps its id.
+    /// The offset is tracked to not count it twice on recovery.
+    pub async fn insert_document(
+        &self,
+        offset: Offset,
+        document_id: DocumentId,
+        doc_id: Option<String>,
+    ) {
+        let commit_insert_mutex_lock = self.commit_insert_mutex.lock().await;
+
+        self.offset_storage.set_offset(offset);
         self.document_count.fetch_add(1, Ordering::Relaxed);
+        if let Some(doc_id) = doc_id {
+            self.doc_id_storage
+                .write()
+                .await
+                .insert_document_id(doc_id, document_id);
+        }
+
+        drop(commit_insert_mutex_lock);
+    }
+
+    pub async fn get_document_id(&self, doc_id: &str) -> Option<DocumentId> {
+        self.doc_id_storage.read().await.get_document_id(doc_id)
+    }
+
+    pub async fn list_document_ids(
+        &self,
+        cursor: Option<DocumentId>,
+        limit: usize,
+    ) -> Vec<DocumentId> {
+        self.doc_id_storage
+            .read()
+            .await
+            .list_document_ids(cursor, limit)
     }
 
     pub async fn update(
@@ -619,11 +805,26 @@ impl CollectionReader {
             }
             CollectionWriteOperation::DeleteDocuments { doc_ids } => {
                 self.offset_storage.set_offset(offset);
+                self.document_count
+                    .fetch_sub(doc_ids.len() as u64, Ordering::Relaxed);
+                self.doc_id_storage
+                    .write()
+                    .await
+                    .remove_document_ids(&doc_ids);
+                let mut expirations = self.expirations.write().await;
+                for doc_id in &doc_ids {
+                    expirations.remove(doc_id);
+                }
+                drop(expirations);
                 let mut uncommitted_deleted_documents =
                     self.uncommitted_deleted_documents.write().await;
                 uncommitted_deleted_documents.extend(doc_ids);
                 info!("Document deleted: {:?}", uncommitted_deleted_documents);
             }
+            CollectionWriteOperation::SetExpiration { doc_id, expires_at } => {
+                self.offset_storage.set_offset(offset);
+                self.expirations.write().await.insert(doc_id, expires_at);
+            }
             CollectionWriteOperation::CreateField {
                 field_id,
                 field_name,
@@ -634,7 +835,10 @@ impl CollectionReader {
                 let typed_field = match typed_field {
                     dto::TypedField::Embedding(model) => TypedField::Embedding(model.model),
                     dto::TypedField::Text(locale) => TypedField::Text(locale),
+                    dto::TypedField::Keyword => TypedField::Keyword,
                     dto::TypedField::Number => TypedField::Number,
+                    // The datetime values are indexed as numbers
+                    dto::TypedField::Datetime => TypedField::Number,
                     dto::TypedField::Bool => TypedField::Bool,
                 };
 
@@ -656,6 +860,9 @@ impl CollectionReader {
                         self.text_parser_per_field
                             .insert(field_id, (locale, text_parser));
                     }
+                    TypedField::Keyword => {
+                        self.keyword_fields.insert(field_id);
+                    }
                     _ => {}
                 }
 
@@ -673,6 +880,39 @@ impl CollectionReader {
 
                 trace!("Value indexed");
             }
+            CollectionWriteOperation::CopyEmbedding { field_id, from, to } => {
+                trace!(collection_id=?self.id, ?field_id, ?from, ?to, "Copying embedding");
+
+                self.offset_storage.set_offset(offset);
+
+                let committed = self.committed_collection.read().await;
+                let mut chunks = committed.get_chunks(field_id, from);
+                if chunks.is_empty() {
+                    // The fields committed before the introduction of the chunks have only the vectors
+                    chunks = committed
+                        .get_embeddings(field_id, from)
+                        .into_iter()
+                        .map(|vector| (String::new(), vector))
+                        .collect();
+                }
+                drop(committed);
+
+                let mut uncommitted = self.uncommitted_collection.write().await;
+                chunks.extend(uncommitted.get_chunks(field_id, from));
+                if chunks.is_empty() {
+                    warn!(?from, "No embedding to copy");
+                } else {
+                    let (chunks, vectors) = chunks.into_iter().unzip();
+                    uncommitted.insert(
+                        field_id,
+                        to,
+                        DocumentFieldIndexOperation::IndexEmbedding { vectors, chunks },
+                    )?;
+                }
+                drop(uncommitted);
+
+                trace!("Embedding copied");
+            }
         };
 
         drop(commit_insert_mutex_lock);
@@ -699,7 +939,7 @@ impl CollectionReader {
         } = search_params;
 
         let uncommitted_deleted_documents = self.uncommitted_deleted_documents.read().await;
-        let uncommitted_deleted_documents = uncommitted_deleted_documents.clone();
+        let uncommitted_deleted_documents = self.with_expired(&uncommitted_deleted_documents).await;
 
         let filtered_doc_ids = self
             .calculate_filtered_doc_ids(where_filter, &uncommitted_deleted_documents)
@@ -770,6 +1010,11 @@ impl CollectionReader {
                 }
                 fulltext
             }
+            SearchMode::Similar(_) => {
+                return Err(anyhow!(
+                    "Similar search requires the source document. Use `search_similar` instead"
+                ));
+            }
         };
 
         info!("token_scores len: {:?}", token_scores.len());
@@ -780,6 +1025,204 @@ impl CollectionReader {
         Ok(token_scores)
     }
 
+    /// Searches the documents similar to the given one.
+    /// The stored embeddings of the document are used as query vectors;
+    /// if there's no embedding, the most relevant terms of the document are used instead.
+    #[instrument(skip(self, document, search_params), level="debug", fields(coll_id = ?self.id))]
+    pub async fn search_similar(
+        &self,
+        document_id: DocumentId,
+        document: RawJSONDocument,
+        search_params: SearchParams,
+    ) -> Result<HashMap<DocumentId, f32>> {
+        let metric = SEARCH_METRIC.create(SearchLabels {
+            collection: self.id.0.to_string(),
+        });
+        let SearchParams {
+            properties,
+            boost,
+            limit,
+            where_filter,
+            ..
+        } = search_params;
+
+        let uncommitted_deleted_documents = self.uncommitted_deleted_documents.read().await;
+        let uncommitted_deleted_documents = self.with_expired(&uncommitted_deleted_documents).await;
+
+        let filtered_doc_ids = self
+            .calculate_filtered_doc_ids(where_filter, &uncommitted_deleted_documents)
+            .await?;
+
+        let mut token_scores = self
+            .search_similar_vector(
+                document_id,
+                filtered_doc_ids.as_ref(),
+                &limit,
+                &uncommitted_deleted_documents,
+            )
+            .await?;
+
+        if token_scores.is_empty() {
+            debug!("No embeddings found for the document. Fallback to terms");
+
+            let properties = self.calculate_string_properties(properties)?;
+            let boost = self.calculate_boost(boost);
+            let document: Document = serde_json::from_str(document.inner.get())
+                .context("Cannot deserialize the source document")?;
+            let terms = self.get_most_relevant_terms(&document, &properties).await;
+
+            for term in terms {
+                let scores = self
+                    .search_full_text(
+                        &term,
+                        properties.clone(),
+                        boost.clone(),
+                        filtered_doc_ids.as_ref(),
+                        &uncommitted_deleted_documents,
+                    )
+                    .await?;
+                for (doc_id, score) in scores {
+                    *token_scores.entry(doc_id).or_default() += score;
+                }
+            }
+        }
+
+        token_scores.remove(&document_id);
+
+        drop(metric);
+
+        Ok(token_scores)
+    }
+
+    async fn search_similar_vector(
+        &self,
+        document_id: DocumentId,
+        filtered_doc_ids: Option<&HashSet<DocumentId>>,
+        limit: &Limit,
+        uncommitted_deleted_documents: &HashSet<DocumentId>,
+    ) -> Result<HashMap<DocumentId, f32>> {
+        let mut output: HashMap<DocumentId, f32> = HashMap::new();
+
+        let committed_lock = self.committed_collection.read().await;
+        let uncommitted_lock = self.uncommitted_collection.read().await;
+
+        for e in &self.fields_per_model {
+            for field_id in e.value() {
+                let embeddings = committed_lock
+                    .get_embeddings(*field_id, document_id)
+                    .into_iter()
+                    .chain(uncommitted_lock.get_embeddings(*field_id, document_id));
+
+                for embedding in embeddings {
+                    // The source document is always the nearest one:
+                    // we ask one more result to return `limit` documents
+                    committed_lock.vector_search(
+                        &embedding,
+                        &[*field_id],
+                        filtered_doc_ids,
+                        limit.0 + 1,
+                        &mut output,
+                        uncommitted_deleted_documents,
+                    )?;
+                    uncommitted_lock.vector_search(
+                        &embedding,
+                        &[*field_id],
+                        filtered_doc_ids,
+                        &mut output,
+                        uncommitted_deleted_documents,
+                    )?;
+                }
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Extracts the terms of the document with the highest TF-IDF.
+    async fn get_most_relevant_terms(
+        &self,
+        document: &Document,
+        properties: &[FieldId],
+    ) -> Vec<String> {
+        let document = document.into_flatten();
+
+        let committed_lock = self.committed_collection.read().await;
+        let uncommitted_lock = self.uncommitted_collection.read().await;
+
+        let mut term_scores: HashMap<String, f32> = HashMap::new();
+        for e in &self.fields {
+            let (field_name, (field_id, _)) = (e.key(), e.value());
+            // The id is unique per document: it doesn't help to find similar ones
+            if field_name == "id" || !properties.contains(field_id) {
+                continue;
+            }
+
+            let text = match document.get(field_name).and_then(|v| v.as_str()) {
+                Some(text) => text,
+                None => continue,
+            };
+            let text_parser = match self.text_parser_per_field.get(field_id) {
+                Some(text_parser) => text_parser.1.clone(),
+                None => continue,
+            };
+
+            let mut term_frequencies: HashMap<String, usize> = HashMap::new();
+            for token in text_parser.tokenize(text) {
+                *term_frequencies.entry(token).or_default() += 1;
+            }
+
+            let global_info =
+                committed_lock.global_info(field_id) + uncommitted_lock.global_info(field_id);
+            let total_documents = global_info.total_documents as f32;
+
+            for (term, frequency) in term_frequencies {
+                let document_frequency = (committed_lock.document_frequency(*field_id, &term)
+                    + uncommitted_lock.document_frequency(*field_id, &term))
+                    as f32;
+                let idf = (1.0
+                    + (total_documents - document_frequency + 0.5) / (document_frequency + 0.5))
+                    .ln();
+                let score = frequency as f32 * idf;
+
+                let e = term_scores.entry(term).or_default();
+                *e = e.max(score);
+            }
+        }
+
+        let mut term_scores: Vec<_> = term_scores.into_iter().collect();
+        term_scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        term_scores
+            .into_iter()
+            .take(SIMILAR_MAX_TERMS)
+            .map(|(term, _)| term)
+            .collect()
+    }
+
+    /// The last expiration passed: a search result is valid until the next one
+    pub async fn last_expiration(&self) -> Option<i64> {
+        let now = Utc::now().timestamp_millis();
+        self.expirations.read().await.last_expiration(now)
+    }
+
+    /// Adds the expired documents to the deleted ones.
+    /// They are few: the write side deletes them in background.
+    async fn with_expired<'d>(
+        &self,
+        deleted_documents: &'d HashSet<DocumentId>,
+    ) -> Cow<'d, HashSet<DocumentId>> {
+        let now = Utc::now().timestamp_millis();
+        let expirations = self.expirations.read().await;
+        let mut expired = expirations.expired(now).peekable();
+        if expired.peek().is_none() {
+            return Cow::Borrowed(deleted_documents);
+        }
+
+        let mut deleted_documents = deleted_documents.clone();
+        deleted_documents.extend(expired);
+        Cow::Owned(deleted_documents)
+    }
+
     pub fn count_documents(&self) -> u64 {
         self.document_count.load(Ordering::Relaxed)
     }
@@ -878,7 +1321,7 @@ impl CollectionReader {
                         None => return Err(anyhow!("Unknown field name {}", field_name)),
                         Some(field) => field,
                     };
-                    if !matches!(field.1, TypedField::Text(_)) {
+                    if !matches!(field.1, TypedField::Text(_) | TypedField::Keyword) {
                         return Err(anyhow!("Cannot search on non-string field {}", field_name));
                     }
                     r.push(field.0);
@@ -888,7 +1331,7 @@ impl CollectionReader {
             Properties::None | Properties::Star => {
                 let mut r = Vec::with_capacity(self.fields.len());
                 for field in &self.fields {
-                    if !matches!(field.1, TypedField::Text(_)) {
+                    if !matches!(field.1, TypedField::Text(_) | TypedField::Keyword) {
                         continue;
                     }
                     r.push(field.0);
@@ -909,6 +1352,8 @@ impl CollectionReader {
         uncommitted_deleted_documents: &HashSet<DocumentId>,
     ) -> Result<HashMap<DocumentId, f32>> {
         let mut scorer: BM25Scorer<DocumentId> = BM25Scorer::new();
+        // The infix matches are scored apart: they rank below the other ones
+        let mut infix_scorer: BM25Scorer<DocumentId> = BM25Scorer::new();
 
         let mut tokens_cache: HashMap<Locale, Vec<String>> = Default::default();
 
@@ -917,6 +1362,34 @@ impl CollectionReader {
 
         for field_id in properties {
             info!(?field_id, "Searching on field");
+
+            let global_info =
+                committed_lock.global_info(&field_id) + uncommitted_lock.global_info(&field_id);
+
+            if self.keyword_fields.contains(&field_id) {
+                // The whole query is the keyword, and it has to match exactly
+                let term = keyword_term(term);
+                committed_lock.keyword_search(
+                    &term,
+                    field_id,
+                    &boost,
+                    filtered_doc_ids,
+                    &mut scorer,
+                    &global_info,
+                    uncommitted_deleted_documents,
+                )?;
+                uncommitted_lock.keyword_search(
+                    &term,
+                    field_id,
+                    &boost,
+                    filtered_doc_ids,
+                    &mut scorer,
+                    &global_info,
+                    uncommitted_deleted_documents,
+                )?;
+                continue;
+            }
+
             let text_parser = self.text_parser_per_field.get(&field_id);
             let (locale, text_parser) = match text_parser.as_ref() {
                 None => return Err(anyhow!("No text parser for this field")),
@@ -927,10 +1400,6 @@ impl CollectionReader {
                 .entry(locale)
                 .or_insert_with(|| text_parser.tokenize(term));
 
-            let committed_global_info = committed_lock.global_info(&field_id);
-            let uncommitted_global_info = uncommitted_lock.global_info(&field_id);
-            let global_info = committed_global_info + uncommitted_global_info;
-
             committed_lock.fulltext_search(
                 tokens,
                 vec![field_id],
@@ -949,9 +1418,40 @@ impl CollectionReader {
                 &global_info,
                 uncommitted_deleted_documents,
             )?;
+
+            // Only the fields with the infix search enabled contain these terms
+            let infix_tokens: Vec<String> = tokens
+                .iter()
+                .filter(|token| token.chars().count() >= INFIX_MIN_LENGTH)
+                .map(|token| format!("{}{}", INFIX_TERM_PREFIX, token))
+                .collect();
+            if infix_tokens.is_empty() {
+                continue;
+            }
+            committed_lock.fulltext_search(
+                &infix_tokens,
+                vec![field_id],
+                &boost,
+                filtered_doc_ids,
+                &mut infix_scorer,
+                &global_info,
+                uncommitted_deleted_documents,
+            )?;
+            uncommitted_lock.fulltext_search(
+                &infix_tokens,
+                vec![field_id],
+                &boost,
+                filtered_doc_ids,
+                &mut infix_scorer,
+                &global_info,
+                uncommitted_deleted_documents,
+            )?;
         }
 
-        Ok(scorer.get_scores())
+        let mut scores = scorer.get_scores();
+        merge_infix_scores(&mut scores, infix_scorer.get_scores());
+
+        Ok(scores)
     }
 
     async fn search_vector(
@@ -1001,6 +1501,59 @@ impl CollectionReader {
         Ok(output)
     }
 
+    /// Returns, for every document, the chunk most similar to `term`
+    pub async fn get_matching_chunks(
+        &self,
+        term: &str,
+        doc_ids: &[DocumentId],
+    ) -> Result<HashMap<DocumentId, String>> {
+        let fields_per_model: Vec<(OramaModel, Vec<FieldId>)> = self
+            .fields_per_model
+            .iter()
+            .map(|e| (*e.key(), e.value().clone()))
+            .collect();
+
+        let mut targets = Vec::with_capacity(fields_per_model.len());
+        for (model, fields) in fields_per_model {
+            let target = self
+                .ai_service
+                .embed_query(model, vec![&term.to_string()])
+                .await?;
+            if let Some(target) = target.into_iter().next() {
+                targets.push((target, fields));
+            }
+        }
+
+        let committed_lock = self.committed_collection.read().await;
+        let uncommitted_lock = self.uncommitted_collection.read().await;
+
+        let mut best_chunks: HashMap<DocumentId, (f32, String)> = HashMap::new();
+        for (target, fields) in &targets {
+            for field_id in fields {
+                for doc_id in doc_ids {
+                    let chunks = committed_lock
+                        .get_chunks(*field_id, *doc_id)
+                        .into_iter()
+                        .chain(uncommitted_lock.get_chunks(*field_id, *doc_id));
+                    for (chunk, vector) in chunks {
+                        let distance = euclidean_distance(&vector, target);
+                        match best_chunks.get(doc_id) {
+                            Some((best_distance, _)) if *best_distance <= distance => {}
+                            _ => {
+                                best_chunks.insert(*doc_id, (distance, chunk));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(best_chunks
+            .into_iter()
+            .map(|(doc_id, (_, chunk))| (doc_id, chunk))
+            .collect())
+    }
+
     pub async fn calculate_facets(
         &self,
         token_scores: &HashMap<DocumentId, f32>,
@@ -1126,6 +1679,41 @@ impl CollectionReader {
     }
 }
 
+/// Adds the documents matched only by infix, ranking them below the other documents
+fn merge_infix_scores(
+    scores: &mut HashMap<DocumentId, f32>,
+    infix_scores: HashMap<DocumentId, f32>,
+) {
+    let infix_scores: Vec<_> = infix_scores
+        .into_iter()
+        .filter(|(doc_id, _)| !scores.contains_key(doc_id))
+        .collect();
+
+    let min_score = scores.values().copied().fold(f32::INFINITY, f32::min);
+    let max_infix_score = infix_scores
+        .iter()
+        .map(|(_, score)| *score)
+        .fold(0.0, f32::max);
+    if !min_score.is_finite() || max_infix_score <= 0.0 {
+        // Nothing to rank above the infix matches
+        scores.extend(infix_scores);
+        return;
+    }
+
+    // The infix scores are scaled in the `(0, min_score / 2]` range
+    for (doc_id, score) in infix_scores {
+        scores.insert(doc_id, score / max_infix_score * min_score / 2.0);
+    }
+}
+
+fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - b).powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Committed {
     pub epoch: u64,
@@ -1137,7 +1725,7 @@ mod dump {
     use crate::{
         collection_manager::{dto::FieldId, sides::OramaModelSerializable},
         nlp::locales::Locale,
-        types::CollectionId,
+        types::{CollectionId, DocumentId},
     };
 
     use super::committed;
@@ -1159,6 +1747,9 @@ mod dump {
         pub string_field_infos: Vec<(FieldId, committed::fields::StringFieldInfo)>,
         pub bool_field_infos: Vec<(FieldId, committed::fields::BoolFieldInfo)>,
         pub vector_field_infos: Vec<(FieldId, committed::fields::VectorFieldInfo)>,
+        /// The expiration, in milliseconds, by document
+        #[serde(default)]
+        pub expirations: Vec<(DocumentId, i64)>,
     }
 
     #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -1169,6 +1760,7 @@ mod dump {
     #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
     pub enum TypedField {
         Text(Locale),
+        Keyword,
         Embedding(EmbeddingTypedField),
         Number,
         Bool,
@@ -1278,6 +1870,7 @@ async fn get_filtered_document(
 #[derive(Debug, Clone)]
 pub enum TypedField {
     Text(Locale),
+    Keyword,
     Embedding(OramaModel),
     Number,
     Bool,
@@ -9,6 +9,10 @@ use crate::{
     types::DocumentId,
 };
 
+/// Bumped when the content of the number index changes.
+/// Version 0 (missing in the field info) has the 64-bit values truncated to 32 bits.
+pub const NUMBER_FIELD_VERSION: u32 = 1;
+
 #[derive(Debug)]
 pub struct NumberField {
     inner: OrderedKeyIndex<SerializableNumber, DocumentId>,
@@ -33,6 +37,7 @@ impl NumberField {
     pub fn get_field_info(&self) -> NumberFieldInfo {
         NumberFieldInfo {
             data_dir: self.data_dir.clone(),
+            version: NUMBER_FIELD_VERSION,
         }
     }
 
@@ -88,4 +93,6 @@ impl BoundedValue for SerializableNumber {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NumberFieldInfo {
     pub data_dir: PathBuf,
+    #[serde(default)]
+    pub version: u32,
 }
@@ -265,6 +265,15 @@ impl StringField {
         self.document_lengths_per_document.global_info.clone()
     }
 
+    /// Number of documents that contain exactly `token`
+    pub fn document_frequency(&self, token: &str) -> usize {
+        self.index
+            .get(token)
+            .and_then(|posting_list_id| self.posting_storage.get_posting(&posting_list_id))
+            .map(|postings| postings.len())
+            .unwrap_or_default()
+    }
+
     pub fn search(
         &self,
         tokens: &[String],
@@ -299,6 +308,29 @@ impl StringField {
         }
     }
 
+    /// Like `search`, but only the documents with exactly `token` match:
+    /// the terms starting with `token` are ignored
+    pub fn search_exact(
+        &self,
+        token: &str,
+        boost: f32,
+        scorer: &mut BM25Scorer<DocumentId>,
+        filtered_doc_ids: Option<&HashSet<DocumentId>>,
+        global_info: &GlobalInfo,
+        uncommitted_deleted_documents: &HashSet<DocumentId>,
+    ) -> Result<()> {
+        self.score(
+            self.index.get(token).into_iter(),
+            boost,
+            scorer,
+            filtered_doc_ids,
+            global_info,
+            uncommitted_deleted_documents,
+        );
+
+        Ok(())
+    }
+
     fn search_without_phrase_match(
         &self,
         tokens: &[String],
@@ -308,60 +340,72 @@ impl StringField {
         global_info: &GlobalInfo,
         uncommitted_deleted_documents: &HashSet<DocumentId>,
     ) -> Result<()> {
+        for token in tokens {
+            self.score(
+                self.index.search(token),
+                boost,
+                scorer,
+                filtered_doc_ids,
+                global_info,
+                uncommitted_deleted_documents,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn score(
+        &self,
+        posting_list_ids: impl Iterator<Item = u64>,
+        boost: f32,
+        scorer: &mut BM25Scorer<DocumentId>,
+        filtered_doc_ids: Option<&HashSet<DocumentId>>,
+        global_info: &GlobalInfo,
+        uncommitted_deleted_documents: &HashSet<DocumentId>,
+    ) {
         let total_field_length = global_info.total_document_length as f32;
         let total_documents_with_field = global_info.total_documents as f32;
         let average_field_length = total_field_length / total_documents_with_field;
 
-        for token in tokens {
-            let matches = self
-                .index
-                .search(token)
-                .flat_map(|posting_list_id| self.posting_storage.get_posting(&posting_list_id))
-                .flat_map(|postings| {
-                    let total_documents_with_term_in_field = postings.len();
-
-                    postings
-                        .iter()
-                        .filter(|(doc_id, _)| {
-                            filtered_doc_ids
-                                .map_or(true, |filtered_doc_ids| filtered_doc_ids.contains(doc_id))
-                        })
-                        .filter(|(doc_id, _)| !uncommitted_deleted_documents.contains(doc_id))
-                        .map(move |(doc_id, positions)| {
-                            let field_length =
-                                self.document_lengths_per_document.get_length(doc_id);
-                            let term_occurrence_in_field = positions.len() as u32;
-                            (
-                                doc_id,
-                                term_occurrence_in_field,
-                                field_length,
-                                total_documents_with_term_in_field,
-                            )
-                        })
-                });
-
-            for (
-                doc_id,
+        let matches = posting_list_ids
+            .flat_map(|posting_list_id| self.posting_storage.get_posting(&posting_list_id))
+            .flat_map(|postings| {
+                let total_documents_with_term_in_field = postings.len();
+
+                postings
+                    .iter()
+                    .filter(|(doc_id, _)| {
+                        filtered_doc_ids
+                            .map_or(true, |filtered_doc_ids| filtered_doc_ids.contains(doc_id))
+                    })
+                    .filter(|(doc_id, _)| !uncommitted_deleted_documents.contains(doc_id))
+                    .map(move |(doc_id, positions)| {
+                        let field_length = self.document_lengths_per_document.get_length(doc_id);
+                        let term_occurrence_in_field = positions.len() as u32;
+                        (
+                            doc_id,
+                            term_occurrence_in_field,
+                            field_length,
+                            total_documents_with_term_in_field,
+                        )
+                    })
+            });
+
+        for (doc_id, term_occurrence_in_field, field_length, total_documents_with_term_in_field) in
+            matches
+        {
+            scorer.add(
+                *doc_id,
                 term_occurrence_in_field,
                 field_length,
+                average_field_length,
+                global_info.total_documents as f32,
                 total_documents_with_term_in_field,
-            ) in matches
-            {
-                scorer.add(
-                    *doc_id,
-                    term_occurrence_in_field,
-                    field_length,
-                    average_field_length,
-                    global_info.total_documents as f32,
-                    total_documents_with_term_in_field,
-                    1.2,
-                    0.75,
-                    boost,
-                );
-            }
+                1.2,
+                0.75,
+                boost,
+            );
         }
-
-        Ok(())
     }
 
     fn search_with_phrase_match(
@@ -1,5 +1,8 @@
 use std::{
     collections::{HashMap, HashSet},
+    fs::File,
+    io::Write,
+    ops::Range,
     path::{Path, PathBuf},
 };
 
@@ -12,6 +15,7 @@ use hora::{
     },
     index::{hnsw_idx::HNSWIndex, hnsw_params::HNSWParams},
 };
+use memmap::Mmap;
 use serde::{Deserialize, Serialize};
 use tracing::warn;
 
@@ -26,15 +30,35 @@ use crate::{
 pub struct IdxID(Option<DocumentId>);
 impl IdxType for IdxID {}
 
+/// The HNSW index doesn't allow to get back the vectors of a document,
+/// so we keep them in a separate file, sorted by document id.
+/// Every record is `[document id (u64 LE)][dimension * f32 LE]`.
+const EMBEDDINGS_FILE_NAME: &str = "embeddings.bin";
+
+/// The texts the vectors are calculated from, in the same order of the records of `EMBEDDINGS_FILE_NAME`.
+/// The file starts with a `[offset (u64 LE)][length (u64 LE)]` entry per record, followed by the texts.
+const CHUNKS_FILE_NAME: &str = "chunks.bin";
+const CHUNK_INDEX_RECORD_SIZE: usize = 16;
+
 #[derive(Debug)]
 pub struct VectorField {
     inner: HNSWIndex<f32, IdxID>,
     data_dir: PathBuf,
     deleted_documents: HashSet<DocumentId>,
+    embeddings: Option<Mmap>,
+    chunks: Option<Mmap>,
+    // The HNSW index contains a node per chunk:
+    // used to ask enough nodes to return `limit` documents
+    max_vectors_per_document: usize,
 }
 
 impl VectorField {
-    pub fn from_iter<I>(iter: I, dimension: usize, data_dir: PathBuf) -> Result<Self>
+    pub fn from_iter<I>(
+        iter: I,
+        chunks: HashMap<DocumentId, Vec<String>>,
+        dimension: usize,
+        data_dir: PathBuf,
+    ) -> Result<Self>
     where
         I: Iterator<Item = (DocumentId, Vec<Vec<f32>>)>,
     {
@@ -45,9 +69,12 @@ impl VectorField {
             inner,
             data_dir,
             deleted_documents: HashSet::new(),
+            embeddings: None,
+            chunks: None,
+            max_vectors_per_document: 1,
         };
 
-        s.add_and_dump(iter)?;
+        s.add_and_dump(iter, chunks)?;
 
         Ok(s)
     }
@@ -55,6 +82,7 @@ impl VectorField {
     pub fn from_dump_and_iter(
         data_dir: PathBuf,
         iter: impl Iterator<Item = (DocumentId, Vec<Vec<f32>>)>,
+        chunks: HashMap<DocumentId, Vec<String>>,
         uncommitted_document_deletions: &HashSet<DocumentId>,
     ) -> Result<Self> {
         let dump_file_path = data_dir.join("index.hnsw");
@@ -70,10 +98,16 @@ impl VectorField {
             inner,
             data_dir,
             deleted_documents: uncommitted_document_deletions.clone(),
+            embeddings: None,
+            chunks: None,
+            max_vectors_per_document: 1,
         };
+        // `clone_to` already copied the previous embeddings here
+        s.load_embeddings()?;
 
         s.add_and_dump(
             iter.filter(|(doc_id, _)| !uncommitted_document_deletions.contains(doc_id)),
+            chunks,
         )?;
 
         Ok(s)
@@ -106,11 +140,17 @@ impl VectorField {
             ));
         }
 
-        Ok(Self {
+        let mut s = Self {
             inner,
             data_dir: info.data_dir,
             deleted_documents,
-        })
+            embeddings: None,
+            chunks: None,
+            max_vectors_per_document: 1,
+        };
+        s.load_embeddings()?;
+
+        Ok(s)
     }
 
     pub fn get_field_info(&self) -> VectorFieldInfo {
@@ -128,7 +168,9 @@ impl VectorField {
         output: &mut HashMap<DocumentId, f32>,
         uncommitted_deleted_documents: &HashSet<DocumentId>,
     ) -> Result<()> {
-        let search_output = self.inner.search_nodes(target, limit);
+        let search_output = self
+            .inner
+            .search_nodes(target, limit.saturating_mul(self.max_vectors_per_document));
         if search_output.is_empty() {
             return Ok(());
         }
@@ -165,18 +207,77 @@ impl VectorField {
             // TODO: put `0.01` number in config.
             let inc = 1.0 / distance.max(0.01);
 
+            // The document scores as its best chunk
             let v = output.entry(doc_id).or_insert(0.0);
-            *v += inc;
+            *v = v.max(inc);
         }
 
         Ok(())
     }
 
+    /// Returns the vectors stored for the document
+    pub fn get_embeddings(&self, doc_id: DocumentId) -> Vec<Vec<f32>> {
+        let embeddings = match &self.embeddings {
+            Some(embeddings) => embeddings,
+            None => return vec![],
+        };
+
+        let dimension = self.inner.dimension();
+        self.document_records(embeddings, doc_id)
+            .map(|i| read_vector(embeddings, dimension, i))
+            .collect()
+    }
+
+    /// Returns the chunks of the document with their vectors.
+    /// Fields committed before the introduction of the chunks file don't have them.
+    pub fn get_chunks(&self, doc_id: DocumentId) -> Vec<(String, Vec<f32>)> {
+        let (embeddings, chunks) = match (&self.embeddings, &self.chunks) {
+            (Some(embeddings), Some(chunks)) => (embeddings, chunks),
+            _ => return vec![],
+        };
+
+        let dimension = self.inner.dimension();
+        self.document_records(embeddings, doc_id)
+            .filter_map(|i| {
+                let chunk = read_chunk(chunks, i)?;
+                Some((chunk, read_vector(embeddings, dimension, i)))
+            })
+            .collect()
+    }
+
+    /// Returns the indexes of the records of the document
+    fn document_records(&self, embeddings: &Mmap, doc_id: DocumentId) -> Range<usize> {
+        let record_size = embedding_record_size(self.inner.dimension());
+        let record_count = embeddings.len() / record_size;
+
+        // Records are sorted by document id: find the first one for `doc_id`
+        let mut low = 0;
+        let mut high = record_count;
+        while low < high {
+            let mid = (low + high) / 2;
+            if read_doc_id(embeddings, record_size, mid) < doc_id.0 {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        let mut end = low;
+        while end < record_count && read_doc_id(embeddings, record_size, end) == doc_id.0 {
+            end += 1;
+        }
+
+        low..end
+    }
+
     fn add_and_dump(
         &mut self,
         iter: impl Iterator<Item = (DocumentId, Vec<Vec<f32>>)>,
+        chunks: HashMap<DocumentId, Vec<String>>,
     ) -> Result<()> {
-        self.add(iter)?;
+        let new_embeddings: Vec<_> = iter.collect();
+
+        self.add(new_embeddings.iter())?;
 
         create_if_not_exists(&self.data_dir)?;
 
@@ -198,6 +299,106 @@ impl VectorField {
             .write_bincode_data(&self.deleted_documents)
             .context("Cannot serialize deleted documents file")?;
 
+        self.dump_embeddings(new_embeddings, chunks)
+            .context("Cannot dump embeddings")?;
+
+        Ok(())
+    }
+
+    fn dump_embeddings(
+        &mut self,
+        new_embeddings: Vec<(DocumentId, Vec<Vec<f32>>)>,
+        new_chunks: HashMap<DocumentId, Vec<String>>,
+    ) -> Result<()> {
+        let dimension = self.inner.dimension();
+
+        // The previous files could be the same we are going to overwrite,
+        // so we read all the records before releasing the memory maps.
+        let old_chunks = self.chunks.take();
+        let mut records: Vec<(DocumentId, Vec<f32>, String)> = match self.embeddings.take() {
+            Some(embeddings) => {
+                let record_size = embedding_record_size(dimension);
+                (0..embeddings.len() / record_size)
+                    .map(|i| {
+                        let doc_id = DocumentId(read_doc_id(&embeddings, record_size, i));
+                        let vector = read_vector(&embeddings, dimension, i);
+                        let chunk = old_chunks
+                            .as_ref()
+                            .and_then(|chunks| read_chunk(chunks, i))
+                            .unwrap_or_default();
+                        (doc_id, vector, chunk)
+                    })
+                    .filter(|(doc_id, _, _)| !self.deleted_documents.contains(doc_id))
+                    .collect()
+            }
+            None => vec![],
+        };
+        drop(old_chunks);
+
+        let mut new_chunks: HashMap<_, _> = new_chunks
+            .into_iter()
+            .map(|(doc_id, chunks)| (doc_id, chunks.into_iter()))
+            .collect();
+        records.extend(
+            new_embeddings
+                .into_iter()
+                .flat_map(|(doc_id, vectors)| vectors.into_iter().map(move |v| (doc_id, v)))
+                .map(|(doc_id, vector)| {
+                    let chunk = new_chunks
+                        .get_mut(&doc_id)
+                        .and_then(|chunks| chunks.next())
+                        .unwrap_or_default();
+                    (doc_id, vector, chunk)
+                }),
+        );
+        records.sort_by_key(|(doc_id, _, _)| *doc_id);
+
+        let mut file = BufferedFile::create_or_overwrite(self.data_dir.join(EMBEDDINGS_FILE_NAME))
+            .context("Cannot create embeddings file")?;
+        for (doc_id, vector, _) in &records {
+            file.write_all(&doc_id.0.to_le_bytes())?;
+            for v in vector {
+                file.write_all(&v.to_le_bytes())?;
+            }
+        }
+        file.close().context("Cannot close embeddings file")?;
+
+        let mut file = BufferedFile::create_or_overwrite(self.data_dir.join(CHUNKS_FILE_NAME))
+            .context("Cannot create chunks file")?;
+        let mut offset = (records.len() * CHUNK_INDEX_RECORD_SIZE) as u64;
+        for (_, _, chunk) in &records {
+            file.write_all(&offset.to_le_bytes())?;
+            file.write_all(&(chunk.len() as u64).to_le_bytes())?;
+            offset += chunk.len() as u64;
+        }
+        for (_, _, chunk) in &records {
+            file.write_all(chunk.as_bytes())?;
+        }
+        file.close().context("Cannot close chunks file")?;
+
+        self.load_embeddings()
+    }
+
+    fn load_embeddings(&mut self) -> Result<()> {
+        // Fields committed before the introduction of the embeddings (or chunks) file don't have it
+        self.embeddings = load_mmap(&self.data_dir, EMBEDDINGS_FILE_NAME)?;
+        self.chunks = load_mmap(&self.data_dir, CHUNKS_FILE_NAME)?;
+
+        let record_size = embedding_record_size(self.inner.dimension());
+        let mut max_vectors_per_document = 1;
+        if let Some(embeddings) = &self.embeddings {
+            let mut current = (None, 0);
+            for i in 0..embeddings.len() / record_size {
+                let doc_id = read_doc_id(embeddings, record_size, i);
+                current = match current {
+                    (Some(id), count) if id == doc_id => (Some(id), count + 1),
+                    _ => (Some(doc_id), 1),
+                };
+                max_vectors_per_document = max_vectors_per_document.max(current.1);
+            }
+        }
+        self.max_vectors_per_document = max_vectors_per_document;
+
         Ok(())
     }
 
@@ -216,14 +417,25 @@ impl VectorField {
         std::fs::copy(old_dump_file_path, new_dump_file_path)
             .map_err(|e| anyhow!("Cannot copy hnsw file: {}", e))?;
 
+        for file_name in [EMBEDDINGS_FILE_NAME, CHUNKS_FILE_NAME] {
+            let old_file_path = self.data_dir.join(file_name);
+            if old_file_path.exists() {
+                std::fs::copy(old_file_path, data_dir.join(file_name))
+                    .map_err(|e| anyhow!("Cannot copy {} file: {}", file_name, e))?;
+            }
+        }
+
         Ok(())
     }
 
-    fn add(&mut self, iter: impl Iterator<Item = (DocumentId, Vec<Vec<f32>>)>) -> Result<()> {
+    fn add<'a>(
+        &mut self,
+        iter: impl Iterator<Item = &'a (DocumentId, Vec<Vec<f32>>)>,
+    ) -> Result<()> {
         for (doc_id, vectors) in iter {
             for vector in vectors {
                 self.inner
-                    .add(&vector, IdxID(Some(doc_id)))
+                    .add(vector, IdxID(Some(*doc_id)))
                     .map_err(|e| anyhow!("Cannot add vector to index: {}", e))?;
             }
         }
@@ -236,6 +448,57 @@ impl VectorField {
     }
 }
 
+fn embedding_record_size(dimension: usize) -> usize {
+    8 + dimension * 4
+}
+
+fn read_doc_id(embeddings: &[u8], record_size: usize, i: usize) -> u64 {
+    let start = i * record_size;
+    let bytes: [u8; 8] = embeddings[start..start + 8]
+        .try_into()
+        .expect("the slice is 8 bytes long");
+    u64::from_le_bytes(bytes)
+}
+
+fn read_vector(embeddings: &[u8], dimension: usize, i: usize) -> Vec<f32> {
+    let start = i * embedding_record_size(dimension) + 8;
+    embeddings[start..start + dimension * 4]
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+fn read_chunk(chunks: &[u8], i: usize) -> Option<String> {
+    let start = i * CHUNK_INDEX_RECORD_SIZE;
+    let entry = chunks.get(start..start + CHUNK_INDEX_RECORD_SIZE)?;
+    let (offset, len) = entry.split_at(8);
+    let offset = u64::from_le_bytes(offset.try_into().ok()?) as usize;
+    let len = u64::from_le_bytes(len.try_into().ok()?) as usize;
+    // The records committed before the introduction of the chunks have no text
+    if len == 0 {
+        return None;
+    }
+    let bytes = chunks.get(offset..offset + len)?;
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn load_mmap(data_dir: &Path, file_name: &str) -> Result<Option<Mmap>> {
+    let file_path = data_dir.join(file_name);
+    if !file_path.exists() {
+        warn!("File {} not found in {:?}", file_name, data_dir);
+        return Ok(None);
+    }
+
+    let file = File::open(&file_path).with_context(|| format!("Cannot open {}", file_name))?;
+    // An empty file cannot be mapped
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+    let mmap = unsafe { Mmap::map(&file).with_context(|| format!("Cannot map {}", file_name))? };
+
+    Ok(Some(mmap))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VectorFieldInfo {
     pub dimension: usize,
@@ -0,0 +1,118 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound,
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    file_utils::{create_if_not_exists, BufferedFile},
+    types::DocumentId,
+};
+
+const DOC_ID_STORAGE_FILE_NAME: &str = "doc_id_storage.bin";
+
+/// Maps the user-defined document id to the internal `DocumentId`
+#[derive(Debug, Default)]
+pub struct DocIdStorage {
+    document_id: HashMap<String, DocumentId>,
+    /// The reverse of `document_id`, sorted to list the ids by pages
+    sorted_document_ids: BTreeMap<DocumentId, String>,
+}
+
+impl DocIdStorage {
+    pub fn get_document_id(&self, doc_id: &str) -> Option<DocumentId> {
+        self.document_id.get(doc_id).copied()
+    }
+
+    /// Returns at most `limit` document ids greater than `cursor`, sorted
+    pub fn list_document_ids(&self, cursor: Option<DocumentId>, limit: usize) -> Vec<DocumentId> {
+        let from = match cursor {
+            Some(cursor) => Bound::Excluded(cursor),
+            None => Bound::Unbounded,
+        };
+        self.sorted_document_ids
+            .range((from, Bound::Unbounded))
+            .take(limit)
+            .map(|(document_id, _)| *document_id)
+            .collect()
+    }
+
+    pub fn insert_document_id(&mut self, doc_id: String, document_id: DocumentId) {
+        if let Some(previous) = self.document_id.insert(doc_id.clone(), document_id) {
+            self.sorted_document_ids.remove(&previous);
+        }
+        self.sorted_document_ids.insert(document_id, doc_id);
+    }
+
+    /// The ids of a replaced version are not in the storage anymore: they are ignored
+    pub fn remove_document_ids(&mut self, document_ids: &[DocumentId]) {
+        for document_id in document_ids {
+            if let Some(doc_id) = self.sorted_document_ids.remove(document_id) {
+                self.document_id.remove(&doc_id);
+            }
+        }
+    }
+
+    pub fn commit(&self, data_dir: PathBuf) -> Result<()> {
+        create_if_not_exists(&data_dir)
+            .context("Cannot create the base directory for the doc id storage")?;
+
+        let file_path = data_dir.join(DOC_ID_STORAGE_FILE_NAME);
+        BufferedFile::create_or_overwrite(file_path)
+            .context("Cannot create file")?
+            .write_bincode_data(&self.document_id)
+            .context("Cannot write map to file")?;
+
+        Ok(())
+    }
+
+    pub fn load(data_dir: PathBuf) -> Result<Self> {
+        let file_path = data_dir.join(DOC_ID_STORAGE_FILE_NAME);
+        let document_id: HashMap<String, DocumentId> = BufferedFile::open(file_path)
+            .context("Cannot open file")?
+            .read_bincode_data()
+            .context("Cannot read doc_id_storage from file")?;
+
+        let sorted_document_ids = document_id
+            .iter()
+            .map(|(doc_id, document_id)| (*document_id, doc_id.clone()))
+            .collect();
+
+        Ok(Self {
+            document_id,
+            sorted_document_ids,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doc_id_storage_list_document_ids() {
+        let mut storage = DocIdStorage::default();
+        storage.insert_document_id("a".to_string(), DocumentId(3));
+        storage.insert_document_id("b".to_string(), DocumentId(1));
+        storage.insert_document_id("c".to_string(), DocumentId(2));
+        // Replaced by a new version
+        storage.insert_document_id("b".to_string(), DocumentId(5));
+        storage.remove_document_ids(&[DocumentId(2)]);
+        // The replaced version is ignored
+        storage.remove_document_ids(&[DocumentId(1)]);
+
+        assert_eq!(
+            storage.list_document_ids(None, 10),
+            vec![DocumentId(3), DocumentId(5)]
+        );
+        assert_eq!(storage.list_document_ids(None, 1), vec![DocumentId(3)]);
+        assert_eq!(
+            storage.list_document_ids(Some(DocumentId(3)), 10),
+            vec![DocumentId(5)]
+        );
+        assert_eq!(storage.get_document_id("b"), Some(DocumentId(5)));
+        assert_eq!(storage.get_document_id("c"), None);
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 
 use anyhow::{bail, Context, Result};
@@ -187,6 +187,7 @@ pub fn merge_vector_field(
             committed_fields::VectorField::from_dump_and_iter(
                 data_dir,
                 std::iter::empty(),
+                HashMap::new(),
                 uncommitted_document_deletions,
             )?
         }
@@ -194,6 +195,7 @@ pub fn merge_vector_field(
             uncommitted
                 .iter()
                 .filter(|(doc_id, _)| !uncommitted_document_deletions.contains(doc_id)),
+            uncommitted.iter_chunks().collect(),
             uncommitted.dimension(),
             data_dir,
         )?,
@@ -204,6 +206,7 @@ pub fn merge_vector_field(
             committed_fields::VectorField::from_dump_and_iter(
                 data_dir,
                 uncommitted.iter(),
+                uncommitted.iter_chunks().collect(),
                 uncommitted_document_deletions,
             )?
         }
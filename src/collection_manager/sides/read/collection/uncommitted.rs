@@ -130,6 +130,27 @@ impl UncommittedCollection {
         Ok(())
     }
 
+    pub fn get_embeddings(&self, field_id: FieldId, doc_id: DocumentId) -> Vec<Vec<f32>> {
+        self.vector_index
+            .get(&field_id)
+            .map(|vector_field| vector_field.get_embeddings(doc_id))
+            .unwrap_or_default()
+    }
+
+    pub fn get_chunks(&self, field_id: FieldId, doc_id: DocumentId) -> Vec<(String, Vec<f32>)> {
+        self.vector_index
+            .get(&field_id)
+            .map(|vector_field| vector_field.get_chunks(doc_id))
+            .unwrap_or_default()
+    }
+
+    pub fn document_frequency(&self, field_id: FieldId, token: &str) -> usize {
+        self.string_index
+            .get(&field_id)
+            .map(|string_field| string_field.document_frequency(token))
+            .unwrap_or_default()
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn fulltext_search(
         &self,
@@ -166,6 +187,34 @@ impl UncommittedCollection {
         Ok(())
     }
 
+    /// Scores the documents whose keyword `field_id` is exactly `term`
+    pub fn keyword_search(
+        &self,
+        term: &str,
+        field_id: FieldId,
+        boost: &HashMap<FieldId, f32>,
+        filtered_doc_ids: Option<&HashSet<DocumentId>>,
+        scorer: &mut BM25Scorer<DocumentId>,
+        global_info: &GlobalInfo,
+        uncommitted_deleted_documents: &HashSet<DocumentId>,
+    ) -> Result<()> {
+        let index = match self.string_index.get(&field_id) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+
+        let field_boost = boost.get(&field_id).copied().unwrap_or(1.0);
+
+        index.search_exact(
+            term,
+            field_boost,
+            scorer,
+            filtered_doc_ids,
+            global_info,
+            uncommitted_deleted_documents,
+        )
+    }
+
     pub fn calculate_number_filter<'s, 'iter>(
         &'s self,
         field_id: FieldId,
@@ -225,11 +274,15 @@ impl UncommittedCollection {
                     .or_insert_with(StringField::empty)
                     .insert(doc_id, field_length, terms);
             }
-            DocumentFieldIndexOperation::IndexEmbedding { value } => {
+            DocumentFieldIndexOperation::IndexEmbedding { vectors, chunks } => {
+                let dimension = match vectors.first() {
+                    Some(vector) => vector.len(),
+                    None => return Ok(()),
+                };
                 self.vector_index
                     .entry(field_id)
-                    .or_insert_with(|| VectorField::empty(value.len()))
-                    .insert(doc_id, vec![value])?;
+                    .or_insert_with(|| VectorField::empty(dimension))
+                    .insert(doc_id, vectors, chunks)?;
             }
         };
 
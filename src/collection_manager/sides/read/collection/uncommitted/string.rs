@@ -6,7 +6,7 @@ use tracing::{debug, warn};
 use crate::{
     collection_manager::{
         dto::{BM25Scorer, GlobalInfo},
-        sides::{InsertStringTerms, TermStringField},
+        sides::{InsertStringTerms, TermStringField, INFIX_TERM_PREFIX},
     },
     indexes::radix::RadixIndex,
     types::DocumentId,
@@ -96,7 +96,10 @@ impl StringField {
 
             let TermStringField { positions } = term_string_field;
 
-            self.total_field_length += usize::from(field_length);
+            // The infix terms are derived from the other ones: they don't make the field longer
+            if !k.starts_with(INFIX_TERM_PREFIX) {
+                self.total_field_length += usize::from(field_length);
+            }
 
             match self.inner.get_mut(k.bytes()) {
                 Some(v) => {
@@ -117,6 +120,14 @@ impl StringField {
         }
     }
 
+    /// Number of documents that contain exactly `token`
+    pub fn document_frequency(&self, token: &str) -> usize {
+        self.inner
+            .get(token.bytes())
+            .map(|(_, position_per_document)| position_per_document.len())
+            .unwrap_or_default()
+    }
+
     pub fn field_length_per_doc(&self) -> HashMap<DocumentId, u32> {
         self.field_length_per_doc.clone()
     }
@@ -130,10 +141,6 @@ impl StringField {
         global_info: &GlobalInfo,
         uncommitted_deleted_documents: &HashSet<DocumentId>,
     ) -> Result<()> {
-        let total_field_length = global_info.total_document_length as f32;
-        let total_documents_with_field = global_info.total_documents as f32;
-        let average_field_length = total_field_length / total_documents_with_field;
-
         let mut total_matches = 0_usize;
         for token in tokens {
             // We don't "boost" the exact match at all.
@@ -141,55 +148,114 @@ impl StringField {
             // TODO: think about this
             let matches = self.inner.search(token)?;
 
-            for (total_documents_with_term_in_field, position_per_document) in matches {
-                for (doc_id, positions) in position_per_document {
-                    if let Some(filtered_doc_ids) = filtered_doc_ids {
-                        if !filtered_doc_ids.contains(doc_id) {
-                            continue;
-                        }
+            total_matches += self.score(
+                matches.into_iter(),
+                boost,
+                scorer,
+                filtered_doc_ids,
+                global_info,
+                uncommitted_deleted_documents,
+            );
+        }
+
+        debug!(total_matches = total_matches, "Uncommitted total matches");
+
+        Ok(())
+    }
+
+    /// Like `search`, but only the documents with exactly `token` match:
+    /// the terms starting with `token` are ignored
+    pub fn search_exact(
+        &self,
+        token: &str,
+        boost: f32,
+        scorer: &mut BM25Scorer<DocumentId>,
+        filtered_doc_ids: Option<&HashSet<DocumentId>>,
+        global_info: &GlobalInfo,
+        uncommitted_deleted_documents: &HashSet<DocumentId>,
+    ) -> Result<()> {
+        let total_matches = self.score(
+            self.inner.get(token.bytes()).into_iter(),
+            boost,
+            scorer,
+            filtered_doc_ids,
+            global_info,
+            uncommitted_deleted_documents,
+        );
+
+        debug!(
+            total_matches = total_matches,
+            "Uncommitted total exact matches"
+        );
+
+        Ok(())
+    }
+
+    fn score<'a>(
+        &self,
+        matches: impl Iterator<
+            Item = &'a (
+                TotalDocumentsWithTermInField,
+                HashMap<DocumentId, Positions>,
+            ),
+        >,
+        boost: f32,
+        scorer: &mut BM25Scorer<DocumentId>,
+        filtered_doc_ids: Option<&HashSet<DocumentId>>,
+        global_info: &GlobalInfo,
+        uncommitted_deleted_documents: &HashSet<DocumentId>,
+    ) -> usize {
+        let total_field_length = global_info.total_document_length as f32;
+        let total_documents_with_field = global_info.total_documents as f32;
+        let average_field_length = total_field_length / total_documents_with_field;
+
+        let mut total_matches = 0_usize;
+        for (total_documents_with_term_in_field, position_per_document) in matches {
+            for (doc_id, positions) in position_per_document {
+                if let Some(filtered_doc_ids) = filtered_doc_ids {
+                    if !filtered_doc_ids.contains(doc_id) {
+                        continue;
                     }
-                    if uncommitted_deleted_documents.contains(doc_id) {
+                }
+                if uncommitted_deleted_documents.contains(doc_id) {
+                    continue;
+                }
+
+                let field_length = match self.field_length_per_doc.get(doc_id) {
+                    Some(field_length) => *field_length,
+                    None => {
+                        warn!("Document length not found for document_id: {:?}", doc_id);
                         continue;
                     }
+                };
 
-                    let field_length = match self.field_length_per_doc.get(doc_id) {
-                        Some(field_length) => *field_length,
-                        None => {
-                            warn!("Document length not found for document_id: {:?}", doc_id);
-                            continue;
-                        }
-                    };
-
-                    let term_occurrence_in_field = positions.0.len() as u32;
-
-                    // We aren't consider the phrase matching here
-                    // Instead for committed data, we do.
-                    // We should also here consider the phrase matching.
-                    // TODO: Implement phrase matching
-
-                    let total_documents_with_term_in_field =
-                        total_documents_with_term_in_field.0 as usize;
-
-                    scorer.add(
-                        *doc_id,
-                        term_occurrence_in_field,
-                        field_length,
-                        average_field_length,
-                        global_info.total_documents as f32,
-                        total_documents_with_term_in_field,
-                        1.2,
-                        0.75,
-                        boost,
-                    );
+                let term_occurrence_in_field = positions.0.len() as u32;
 
-                    total_matches += 1;
-                }
+                // We aren't consider the phrase matching here
+                // Instead for committed data, we do.
+                // We should also here consider the phrase matching.
+                // TODO: Implement phrase matching
+
+                let total_documents_with_term_in_field =
+                    total_documents_with_term_in_field.0 as usize;
+
+                scorer.add(
+                    *doc_id,
+                    term_occurrence_in_field,
+                    field_length,
+                    average_field_length,
+                    global_info.total_documents as f32,
+                    total_documents_with_term_in_field,
+                    1.2,
+                    0.75,
+                    boost,
+                );
+
+                total_matches += 1;
             }
         }
 
-        debug!(total_matches = total_matches, "Uncommitted total matches");
-
-        Ok(())
+        total_matches
     }
 
     pub fn iter(
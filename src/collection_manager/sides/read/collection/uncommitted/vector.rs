@@ -9,6 +9,8 @@ type VectorWithMagnetude = (f32, Vec<f32>);
 #[derive(Debug)]
 pub struct VectorField {
     pub data: Vec<(DocumentId, Vec<VectorWithMagnetude>)>,
+    /// The texts the vectors are calculated from, in the same order of the vectors
+    pub chunks: HashMap<DocumentId, Vec<String>>,
     pub dimension: usize,
 }
 
@@ -16,6 +18,7 @@ impl VectorField {
     pub fn empty(dimension: usize) -> Self {
         Self {
             data: Vec::new(),
+            chunks: HashMap::new(),
             dimension,
         }
     }
@@ -41,6 +44,7 @@ impl VectorField {
                 continue;
             }
 
+            // The document scores as its best chunk
             for (m, vector) in vectors {
                 let score = score_vector(vector, target)?;
 
@@ -51,18 +55,26 @@ impl VectorField {
                 let score = score / (m * magnetude);
 
                 let s = output.entry(*id).or_insert(0.0);
-                *s += score;
+                *s = s.max(score);
             }
         }
 
         Ok(())
     }
 
-    pub fn insert(&mut self, doc_id: DocumentId, vectors: Vec<Vec<f32>>) -> Result<()> {
+    pub fn insert(
+        &mut self,
+        doc_id: DocumentId,
+        vectors: Vec<Vec<f32>>,
+        chunks: Vec<String>,
+    ) -> Result<()> {
         let is_different = vectors.iter().any(|v| v.len() != self.dimension);
         if is_different {
             bail!("Vector dimension is different from the field dimension");
         }
+        if vectors.len() != chunks.len() {
+            bail!("Every vector should have its chunk");
+        }
 
         let vectors = vectors
             .into_iter()
@@ -73,6 +85,7 @@ impl VectorField {
             .collect();
 
         self.data.push((doc_id, vectors));
+        self.chunks.entry(doc_id).or_default().extend(chunks);
 
         Ok(())
     }
@@ -81,11 +94,36 @@ impl VectorField {
         self.dimension
     }
 
+    pub fn get_embeddings(&self, doc_id: DocumentId) -> Vec<Vec<f32>> {
+        self.data
+            .iter()
+            .filter(|(id, _)| *id == doc_id)
+            .flat_map(|(_, vectors)| vectors.iter().map(|(_, v)| v.clone()))
+            .collect()
+    }
+
+    /// Returns the chunks of the document with their vectors
+    pub fn get_chunks(&self, doc_id: DocumentId) -> Vec<(String, Vec<f32>)> {
+        let chunks = match self.chunks.get(&doc_id) {
+            Some(chunks) => chunks,
+            None => return vec![],
+        };
+        chunks
+            .iter()
+            .cloned()
+            .zip(self.get_embeddings(doc_id))
+            .collect()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (DocumentId, Vec<Vec<f32>>)> + '_ {
         self.data
             .iter()
             .map(|(id, vectors)| (*id, vectors.iter().map(|(_, v)| v.clone()).collect()))
     }
+
+    pub fn iter_chunks(&self) -> impl Iterator<Item = (DocumentId, Vec<String>)> + '_ {
+        self.chunks.iter().map(|(id, chunks)| (*id, chunks.clone()))
+    }
 }
 
 fn calculate_magnetude(vector: &[f32]) -> f32 {
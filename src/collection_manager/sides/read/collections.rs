@@ -6,13 +6,16 @@ use std::{
 
 use crate::{
     ai::AIService,
-    collection_manager::{dto::ApiKey, sides::Offset},
+    collection_manager::{
+        dto::ApiKey,
+        sides::{document_storage::DocumentStorage, Offset},
+    },
     file_utils::{
         create_if_not_exists, create_if_not_exists_async, create_or_overwrite, BufferedFile,
     },
     nlp::NLPService,
     offset_storage::OffsetStorage,
-    types::CollectionId,
+    types::{CollectionId, DocumentId},
 };
 
 use anyhow::{Context, Result};
@@ -28,6 +31,9 @@ pub struct CollectionsReader {
     ai_service: Arc<AIService>,
     nlp_service: Arc<NLPService>,
     collections: RwLock<HashMap<CollectionId, CollectionReader>>,
+    /// Alias -> collection id.
+    /// To avoid deadlocks, this lock is never taken while holding `collections`.
+    aliases: RwLock<HashMap<CollectionId, CollectionId>>,
     indexes_config: IndexesConfig,
 
     offset_storage: OffsetStorage,
@@ -43,6 +49,7 @@ impl CollectionsReader {
             nlp_service,
 
             collections: Default::default(),
+            aliases: Default::default(),
             indexes_config,
 
             offset_storage: OffsetStorage::new(),
@@ -60,12 +67,46 @@ impl CollectionsReader {
     where
         's: 'coll,
     {
+        let id = self.resolve_alias(id).await;
         let r = self.collections.read().await;
         CollectionReadLock::try_new(r, id)
     }
 
-    #[instrument(skip(self))]
-    pub async fn load(&mut self) -> Result<()> {
+    /// The offset of the last operation applied to a collection
+    pub async fn last_offset(&self) -> Offset {
+        let collections = self.collections.read().await;
+        collections
+            .values()
+            .map(|collection| collection.get_offset())
+            .chain(std::iter::once(self.offset_storage.get_offset()))
+            .max()
+            .unwrap_or(Offset(0))
+    }
+
+    /// The operations after this offset could be not committed:
+    /// they are replayed on startup, skipping the ones already applied
+    pub async fn recovery_offset(&self) -> Offset {
+        let collections = self.collections.read().await;
+        collections
+            .values()
+            .map(|collection| collection.get_offset())
+            .chain(std::iter::once(self.offset_storage.get_offset()))
+            .min()
+            .unwrap_or(Offset(0))
+    }
+
+    /// The offset of the last collection or alias operation applied
+    pub fn get_offset(&self) -> Offset {
+        self.offset_storage.get_offset()
+    }
+
+    async fn resolve_alias(&self, id: CollectionId) -> CollectionId {
+        let aliases = self.aliases.read().await;
+        aliases.get(&id).cloned().unwrap_or(id)
+    }
+
+    #[instrument(skip(self, document_storage))]
+    pub async fn load(&mut self, document_storage: &DocumentStorage) -> Result<()> {
         let data_dir = &self.indexes_config.data_dir;
         info!("Loading collections from disk '{:?}'.", data_dir);
 
@@ -87,6 +128,11 @@ impl CollectionsReader {
 
         let CollectionsInfo::V1(collections_info) = collections_info;
 
+        *self.aliases.write().await = collections_info.aliases.into_iter().collect();
+        if let Some(offset) = collections_info.offset {
+            self.offset_storage.set_offset(offset);
+        }
+
         let base_dir_for_collections = data_dir.join("collections");
 
         for collection_id in collections_info.collection_ids {
@@ -103,7 +149,10 @@ impl CollectionsReader {
             )?;
 
             collection
-                .load(base_dir_for_collections.join(&collection.get_id().0))
+                .load(
+                    base_dir_for_collections.join(&collection.get_id().0),
+                    document_storage,
+                )
                 .await
                 .with_context(|| format!("Cannot load {:?} collection", collection_id))?;
 
@@ -125,6 +174,10 @@ impl CollectionsReader {
 
         let col = self.collections.read().await;
         let col = &*col;
+
+        // Read before the aliases: an alias set in the meantime is applied again on recovery
+        let offset = self.offset_storage.get_offset();
+        let aliases = self.aliases.read().await.clone();
         let collection_ids: Vec<_> = col.keys().cloned().collect();
         for (id, reader) in col {
             let collection_dir = collections_dir.join(&id.0);
@@ -141,6 +194,8 @@ impl CollectionsReader {
 
         let collections_info = CollectionsInfo::V1(CollectionsInfoV1 {
             collection_ids: collection_ids.into_iter().collect(),
+            aliases: aliases.into_iter().collect(),
+            offset: Some(offset),
         });
 
         create_or_overwrite(data_dir.join("info.json"), &collections_info)
@@ -181,6 +236,83 @@ impl CollectionsReader {
 
         Ok(())
     }
+
+    /// Removes the collection, also from disk.
+    /// Returns the documents of the collection, which have to be removed from the storage.
+    pub async fn delete_collection(
+        &self,
+        offset: Offset,
+        id: CollectionId,
+    ) -> Result<Vec<DocumentId>> {
+        info!(collection_id=?id, "Deleting collection {:?}", id);
+
+        let mut aliases = self.aliases.write().await;
+        let mut guard = self.collections.write().await;
+        let collection = match guard.remove(&id) {
+            Some(collection) => collection,
+            None => {
+                warn!(collection_id=?id, "Collection not found");
+                return Ok(vec![]);
+            }
+        };
+        // The write side doesn't allow it, but it is cheap to be sure
+        aliases.retain(|_, collection_id| collection_id != &id);
+
+        let doc_ids = collection.list_document_ids(None, usize::MAX).await;
+
+        // The collection is removed from the committed list before removing its directory:
+        // a crash in between doesn't leave a collection without data on disk
+        let data_dir = &self.indexes_config.data_dir;
+        let info_path = data_dir.join("info.json");
+        if info_path.exists() {
+            let collections_info: CollectionsInfo = BufferedFile::open(&info_path)
+                .and_then(|f| f.read_json_data())
+                .context("Cannot deserialize info.json file")?;
+            let CollectionsInfo::V1(mut collections_info) = collections_info;
+            collections_info.collection_ids.remove(&id);
+            collections_info
+                .aliases
+                .retain(|(_, collection_id)| collection_id != &id);
+            create_or_overwrite(info_path, &CollectionsInfo::V1(collections_info))
+                .await
+                .context("Cannot update info.json file")?;
+        }
+
+        let collection_dir = data_dir.join("collections").join(&id.0);
+        match tokio::fs::remove_dir_all(&collection_dir).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| {
+                    format!("Cannot remove collection directory {:?}", collection_dir)
+                });
+            }
+            _ => {}
+        }
+
+        self.offset_storage.set_offset(offset);
+        drop(guard);
+        drop(aliases);
+
+        Ok(doc_ids)
+    }
+
+    pub async fn set_alias(
+        &self,
+        offset: Offset,
+        alias: CollectionId,
+        collection_id: CollectionId,
+    ) {
+        info!(?alias, ?collection_id, "Setting alias");
+
+        self.aliases.write().await.insert(alias, collection_id);
+        self.offset_storage.set_offset(offset);
+    }
+
+    pub async fn delete_alias(&self, offset: Offset, alias: CollectionId) {
+        info!(?alias, "Deleting alias");
+
+        self.aliases.write().await.remove(&alias);
+        self.offset_storage.set_offset(offset);
+    }
 }
 
 pub struct CollectionReadLock<'guard> {
@@ -223,4 +355,10 @@ enum CollectionsInfo {
 #[derive(Deserialize, Serialize)]
 struct CollectionsInfoV1 {
     collection_ids: HashSet<CollectionId>,
+    /// Alias -> collection id
+    #[serde(default)]
+    aliases: Vec<(CollectionId, CollectionId)>,
+    /// The offset of the last collection or alias operation committed
+    #[serde(default)]
+    offset: Option<Offset>,
 }
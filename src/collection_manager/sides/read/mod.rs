@@ -1,39 +1,79 @@
 mod collection;
 mod collections;
-mod document_storage;
+mod projection;
+mod search_cache;
+mod snapshot;
 
 use duration_str::deserialize_duration;
 use std::time::Duration;
 use std::{collections::HashMap, path::PathBuf, sync::Arc};
 use tokio::time::MissedTickBehavior;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use collections::CollectionsReader;
-use document_storage::{DocumentStorage, DocumentStorageConfig};
+use futures::{Stream, StreamExt, TryStreamExt};
 use ordered_float::NotNan;
+use projection::Projection;
+pub use search_cache::SearchCacheConfig;
+use search_cache::{SearchCache, SearchCacheKey};
 use serde::Deserialize;
-use tokio::sync::RwLock;
-use tracing::{info, trace};
+pub use snapshot::SnapshotConfig;
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+use tokio::sync::{watch, Mutex, RwLock};
+use tracing::{info, trace, warn};
 
 use crate::{
     ai::AIService,
     capped_heap::CappedHeap,
-    collection_manager::dto::{ApiKey, SearchParams, SearchResult, SearchResultHit, TokenScore},
+    collection_manager::dto::{
+        ApiKey, FederatedMergeStrategy, FederatedSearchParams, FederatedSearchResult,
+        FederatedSearchResultHit, HybridMode, SearchMode, SearchParams, SearchResult,
+        SearchResultHit, SimilarMode, TokenScore, VectorMode,
+    },
     metrics::{
         CollectionAddedLabels, CollectionOperationLabels, COLLECTION_ADDED_COUNTER,
         COLLECTION_OPERATION_COUNTER,
     },
     nlp::NLPService,
-    types::{CollectionId, DocumentId},
+    offset_storage::OffsetStorage,
+    types::{CollectionId, DocumentId, RawJSONDocument},
     SideChannelType,
 };
 
-use super::{CollectionWriteOperation, Offset, WriteOperation};
+use super::{
+    document_storage::{DocumentStorage, DocumentStorageConfig},
+    CollectionWriteOperation, Offset, WriteOperation,
+};
+
+/// How long a search waits for the read side to reach its `min_offset`
+const MIN_OFFSET_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The read side replays the side channel on startup: a search would see an old state
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("The read side is catching up: retry later")]
+pub struct CatchingUpError;
+
+/// A federated search which is not valid, regardless of the data
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FederatedSearchError {
+    #[error("At least one query is required")]
+    NoQueries,
+    #[error("Query {index}: collection {collection:?} not found")]
+    CollectionNotFound { index: usize, collection: String },
+    #[error("Query {index}: invalid read api key")]
+    InvalidApiKey { index: usize },
+}
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ReadSideConfig {
     pub input: SideChannelType,
     pub config: IndexesConfig,
+    /// Caches the search results. Disabled if not set.
+    #[serde(default)]
+    pub search_cache: Option<SearchCacheConfig>,
+    #[serde(default)]
+    pub snapshot: SnapshotConfig,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -51,21 +91,53 @@ pub struct ReadSide {
     operation_counter: RwLock<u64>,
     insert_batch_commit_size: u64,
     commit_interval: Duration,
+    search_cache: Option<SearchCache>,
+    /// The offset of the last applied operation
+    applied_offset: watch::Sender<Offset>,
+    /// The operations until this offset are committed by every collection
+    committed_offset: watch::Sender<Offset>,
+    /// The last operation in the side channel on startup: the read side is catching up until it is applied
+    catch_up_target: OffsetStorage,
+    data_dir: PathBuf,
+    snapshot_api_key: Option<ApiKey>,
+    /// Held to change the files in the data dir: a snapshot sees no change while it's written
+    commit_mutex: Mutex<()>,
 }
 
 impl ReadSide {
-    pub fn try_new(
+    pub async fn try_new(
         ai_service: Arc<AIService>,
         nlp_service: Arc<NLPService>,
         config: ReadSideConfig,
     ) -> Result<Self> {
+        let data_dir = config.config.data_dir.clone();
+        if let Some(snapshot_path) = config.snapshot.import_from.clone() {
+            let data_dir = data_dir.clone();
+            tokio::task::spawn_blocking(move || {
+                // A replica with its own data doesn't import the snapshot again
+                if !snapshot::is_empty_dir(&data_dir)? {
+                    return Ok(());
+                }
+                let file = std::fs::File::open(&snapshot_path)
+                    .with_context(|| format!("Cannot open the snapshot {:?}", snapshot_path))?;
+                let offset = snapshot::import_snapshot(file, &data_dir)
+                    .with_context(|| format!("Cannot import the snapshot {:?}", snapshot_path))?;
+                info!(?offset, ?snapshot_path, "Snapshot imported");
+                anyhow::Ok(())
+            })
+            .await
+            .context("The snapshot task panicked")??;
+        }
+
         let document_storage = DocumentStorage::try_new(DocumentStorageConfig {
             data_dir: config.config.data_dir.join("docs"),
+            side: "read",
         })
         .context("Cannot create document storage")?;
 
         let insert_batch_commit_size = config.config.insert_batch_commit_size;
         let commit_interval = config.config.commit_interval;
+        let search_cache = config.search_cache.map(SearchCache::new);
 
         Ok(Self {
             collections: CollectionsReader::try_new(ai_service, nlp_service, config.config)?,
@@ -73,16 +145,28 @@ impl ReadSide {
             operation_counter: Default::default(),
             insert_batch_commit_size,
             commit_interval,
+            search_cache,
+            applied_offset: watch::Sender::new(Offset(0)),
+            committed_offset: watch::Sender::new(Offset(0)),
+            catch_up_target: OffsetStorage::new(),
+            data_dir,
+            snapshot_api_key: config.snapshot.api_key,
+            commit_mutex: Mutex::new(()),
         })
     }
 
     pub async fn load(mut self) -> Result<Arc<Self>> {
-        self.collections.load().await?;
-
         self.document_storage
             .load()
             .context("Cannot load document storage")?;
 
+        // The document storage is needed to migrate the indexes written with an old format
+        self.collections.load(&self.document_storage).await?;
+        self.applied_offset
+            .send_replace(self.collections.last_offset().await);
+        self.committed_offset
+            .send_replace(self.collections.recovery_offset().await);
+
         let s = Arc::new(self);
 
         s.clone().start_commit_loop(s.commit_interval);
@@ -114,6 +198,92 @@ impl ReadSide {
     }
 
     pub async fn commit(&self) -> Result<()> {
+        let commit_lock = self.commit_mutex.lock().await;
+        self.commit_data().await?;
+        drop(commit_lock);
+
+        let committed_offset = self.recovery_offset().await;
+        self.committed_offset.send_if_modified(|committed| {
+            let modified = *committed != committed_offset;
+            *committed = committed_offset;
+            modified
+        });
+
+        Ok(())
+    }
+
+    pub fn check_snapshot_api_key(&self, api_key: ApiKey) -> Result<()> {
+        match &self.snapshot_api_key {
+            // In constant time: the time doesn't tell how much of the key is right
+            Some(snapshot_api_key)
+                if bool::from(
+                    snapshot_api_key
+                        .0
+                        .expose_secret()
+                        .as_bytes()
+                        .ct_eq(api_key.0.expose_secret().as_bytes()),
+                ) =>
+            {
+                Ok(())
+            }
+            Some(_) => bail!("Invalid snapshot api key"),
+            None => bail!("The snapshot export is disabled"),
+        }
+    }
+
+    /// Commits and writes a snapshot of the data dir in `writer`.
+    /// The commits wait only until the committed files are copied in a staging directory:
+    /// the snapshot is written from the copy.
+    /// Returns the offset of the snapshot: a replica importing it receives the operations after it.
+    pub async fn export_snapshot<W>(&self, writer: W) -> Result<Offset>
+    where
+        W: std::io::Write + Send + 'static,
+    {
+        let data_dir = self.data_dir.clone();
+        let staging_dir =
+            data_dir.with_extension(format!("snapshot-export-{}", cuid2::create_id()));
+
+        let commit_lock = self.commit_mutex.lock().await;
+        self.commit_data().await?;
+        let offset = self.recovery_offset().await;
+        let staged = {
+            let staging_dir = staging_dir.clone();
+            tokio::task::spawn_blocking(move || snapshot::stage_snapshot(&data_dir, &staging_dir))
+                .await
+                .context("The snapshot task panicked")
+                .and_then(|staged| staged)
+        };
+        drop(commit_lock);
+
+        info!(?offset, "Writing a snapshot");
+        let output = match staged {
+            Ok(()) => {
+                let staging_dir = staging_dir.clone();
+                tokio::task::spawn_blocking(move || {
+                    snapshot::write_snapshot(&staging_dir, offset, writer)
+                })
+                .await
+                .context("The snapshot task panicked")
+                .and_then(|written| written)
+                .context("Cannot write the snapshot")
+            }
+            Err(e) => Err(e).context("Cannot copy the committed files"),
+        };
+
+        if let Err(e) = tokio::fs::remove_dir_all(&staging_dir).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!(
+                    ?e,
+                    ?staging_dir,
+                    "Cannot remove the snapshot staging directory"
+                );
+            }
+        }
+
+        output.map(|()| offset)
+    }
+
+    async fn commit_data(&self) -> Result<()> {
         self.collections.commit().await?;
 
         self.document_storage
@@ -130,17 +300,75 @@ impl ReadSide {
         collection_id: CollectionId,
         mut search_params: SearchParams,
     ) -> Result<SearchResult> {
-        let facets = std::mem::take(&mut search_params.facets);
-        let limit = search_params.limit;
+        // Fails right away: the catch up can take longer than a client waits
+        if self.is_catching_up() {
+            return Err(CatchingUpError.into());
+        }
+        if let Some(min_offset) = search_params.min_offset.take() {
+            self.wait_for_offset(Offset(min_offset), MIN_OFFSET_TIMEOUT)
+                .await?;
+        }
 
         let collection = self
             .collections
-            .get_collection(collection_id)
+            .get_collection(collection_id.clone())
             .await
             .ok_or_else(|| anyhow::anyhow!("Collection not found"))?;
         collection.check_read_api_key(read_api_key)?;
 
-        let token_scores = collection.search(search_params).await?;
+        let cache_key = match &self.search_cache {
+            Some(search_cache) => {
+                // The collection id, not the alias: the cache is invalidated by collection.
+                // A document expiring changes the key as a write does.
+                let key = SearchCacheKey::try_new(
+                    collection.get_id(),
+                    collection.get_offset(),
+                    collection.last_expiration().await,
+                    &search_params,
+                )?;
+                if let Some(result) = search_cache.get(&key) {
+                    return Ok(result);
+                }
+                Some(key)
+            }
+            None => None,
+        };
+
+        let facets = std::mem::take(&mut search_params.facets);
+        let projection = Projection::new(
+            std::mem::take(&mut search_params.select),
+            std::mem::take(&mut search_params.exclude),
+        );
+        let limit = search_params.limit;
+        // The matching chunk is calculated only for the search modes which use the embeddings
+        let chunk_term = match &search_params.mode {
+            SearchMode::Vector(VectorMode { term }) | SearchMode::Hybrid(HybridMode { term })
+                if search_params.include_chunk =>
+            {
+                Some(term.clone())
+            }
+            _ => None,
+        };
+
+        let token_scores = if let SearchMode::Similar(SimilarMode { id }) = &search_params.mode {
+            let document_id = collection
+                .get_document_id(id)
+                .await
+                .ok_or_else(|| anyhow::anyhow!("Document {:?} not found", id))?;
+            let document = self
+                .document_storage
+                .get_documents_by_ids(vec![document_id])
+                .await?
+                .pop()
+                .flatten()
+                .ok_or_else(|| anyhow::anyhow!("Document {:?} not found", id))?;
+
+            collection
+                .search_similar(document_id, document, search_params)
+                .await?
+        } else {
+            collection.search(search_params).await?
+        };
 
         let facets = collection.calculate_facets(&token_scores, facets).await?;
 
@@ -149,6 +377,14 @@ impl ReadSide {
         let top_results: Vec<TokenScore> = top_n(token_scores, limit.0);
 
         trace!("Top results: {:?}", top_results);
+        let mut chunks = match chunk_term {
+            Some(term) => {
+                let doc_ids: Vec<_> = top_results.iter().map(|m| m.document_id).collect();
+                collection.get_matching_chunks(&term, &doc_ids).await?
+            }
+            None => HashMap::new(),
+        };
+
         let docs = self
             .document_storage
             .get_documents_by_ids(top_results.iter().map(|m| m.document_id).collect())
@@ -163,25 +399,202 @@ impl ReadSide {
                     .as_ref()
                     .and_then(|d| d.id.clone())
                     .unwrap_or_default();
-                SearchResultHit {
+                let document = document
+                    .map(|document| projection.apply(document))
+                    .transpose()?;
+                Ok(SearchResultHit {
                     id,
+                    version: token_score.document_id.version(),
                     score: token_score.score,
                     document,
-                }
+                    chunk: chunks.remove(&token_score.document_id),
+                })
             })
-            .collect();
+            .collect::<Result<_>>()?;
 
-        Ok(SearchResult {
+        let result = SearchResult {
             count,
             hits,
             facets,
+        };
+
+        if let (Some(search_cache), Some(cache_key)) = (&self.search_cache, cache_key) {
+            search_cache.insert(cache_key, result.clone());
+        }
+
+        Ok(result)
+    }
+
+    pub async fn federated_search(
+        &self,
+        federated_params: FederatedSearchParams,
+    ) -> Result<FederatedSearchResult> {
+        let FederatedSearchParams {
+            queries,
+            limit,
+            merge,
+        } = federated_params;
+
+        if queries.is_empty() {
+            return Err(FederatedSearchError::NoQueries.into());
+        }
+        for (index, query) in queries.iter().enumerate() {
+            let collection = self
+                .collections
+                .get_collection(query.collection.clone())
+                .await
+                .ok_or_else(|| FederatedSearchError::CollectionNotFound {
+                    index,
+                    collection: query.collection.0.clone(),
+                })?;
+            if collection
+                .check_read_api_key(query.api_key.clone())
+                .is_err()
+            {
+                return Err(FederatedSearchError::InvalidApiKey { index }.into());
+            }
+        }
+
+        let searches = queries.into_iter().map(|query| async move {
+            let collection_id = query.collection;
+            let result = self
+                .search(query.api_key, collection_id.clone(), query.params)
+                .await
+                .with_context(|| format!("Cannot search in collection {:?}", collection_id.0))?;
+            Ok::<_, anyhow::Error>((collection_id, result))
+        });
+        let results = futures::future::try_join_all(searches).await?;
+
+        let mut count = 0;
+        let mut facets = HashMap::new();
+        let mut hits_per_collection = Vec::with_capacity(results.len());
+        // The same collection can be searched by more queries
+        for (index, (collection_id, result)) in results.into_iter().enumerate() {
+            count += result.count;
+            if let Some(query_facets) = result.facets {
+                facets.insert(index, query_facets);
+            }
+            hits_per_collection.push((collection_id, result.hits));
+        }
+
+        let hits = merge_federated_hits(hits_per_collection, merge, limit.0);
+
+        Ok(FederatedSearchResult {
+            hits,
+            count,
+            facets,
         })
     }
 
+    /// Returns the documents in the same order of `doc_ids`.
+    /// Unknown or deleted documents are returned as `None`.
+    pub async fn get_documents(
+        &self,
+        read_api_key: ApiKey,
+        collection_id: CollectionId,
+        doc_ids: Vec<String>,
+    ) -> Result<Vec<Option<RawJSONDocument>>> {
+        let docs = self
+            .get_versioned_documents(read_api_key, collection_id, doc_ids)
+            .await?;
+
+        Ok(docs
+            .into_iter()
+            .map(|doc| doc.map(|(_, doc)| doc))
+            .collect())
+    }
+
+    /// Like `get_documents`, with the version of every document
+    pub async fn get_versioned_documents(
+        &self,
+        read_api_key: ApiKey,
+        collection_id: CollectionId,
+        doc_ids: Vec<String>,
+    ) -> Result<Vec<Option<(u64, RawJSONDocument)>>> {
+        let collection = self
+            .collections
+            .get_collection(collection_id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Collection not found"))?;
+        collection.check_read_api_key(read_api_key)?;
+
+        let mut document_ids = Vec::with_capacity(doc_ids.len());
+        for doc_id in &doc_ids {
+            document_ids.push(collection.get_document_id(doc_id).await);
+        }
+
+        let mut docs = self
+            .document_storage
+            .get_documents_by_ids(document_ids.iter().flatten().copied().collect())
+            .await?
+            .into_iter();
+
+        Ok(document_ids
+            .into_iter()
+            .map(|document_id| {
+                document_id.and_then(|document_id| {
+                    docs.next()
+                        .flatten()
+                        .map(|document| (document_id.version(), document))
+                })
+            })
+            .collect())
+    }
+
+    /// Returns a page of the documents of the collection as a stream, and the cursor of the next page.
+    /// The cursor is `None` when there are no more documents.
+    pub async fn export_documents(
+        self: Arc<Self>,
+        read_api_key: ApiKey,
+        collection_id: CollectionId,
+        cursor: Option<DocumentId>,
+        limit: usize,
+    ) -> Result<(
+        impl Stream<Item = Result<RawJSONDocument>> + Send + 'static,
+        Option<DocumentId>,
+    )> {
+        let collection = self
+            .collections
+            .get_collection(collection_id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Collection not found"))?;
+        collection.check_read_api_key(read_api_key)?;
+
+        let document_ids = collection.list_document_ids(cursor, limit).await;
+        drop(collection);
+
+        let next_cursor = if document_ids.len() == limit {
+            document_ids.last().copied()
+        } else {
+            None
+        };
+
+        // Documents are loaded in batches, only when the stream is polled
+        let batches: Vec<Vec<DocumentId>> = document_ids
+            .chunks(EXPORT_BATCH_SIZE)
+            .map(|batch| batch.to_vec())
+            .collect();
+        let documents = futures::stream::iter(batches)
+            .then(move |batch| {
+                let read_side = self.clone();
+                async move { read_side.document_storage.get_documents_by_ids(batch).await }
+            })
+            .map_ok(|docs| futures::stream::iter(docs.into_iter().flatten().map(Ok)))
+            .try_flatten();
+
+        Ok((documents, next_cursor))
+    }
+
     pub async fn update(&self, op: (Offset, WriteOperation)) -> Result<()> {
         trace!(offset=?op.0, "Updating read side");
 
         let (offset, op) = op;
+        if self.is_applied(offset, &op).await {
+            trace!(?offset, "Operation already applied: skip it");
+            self.set_applied_offset(offset);
+            return Ok(());
+        }
+
         match op {
             WriteOperation::CreateCollection { id, read_api_key } => {
                 COLLECTION_ADDED_COUNTER
@@ -202,7 +615,7 @@ impl ReadSide {
 
                 let collection = self
                     .collections
-                    .get_collection(collection_id)
+                    .get_collection(collection_id.clone())
                     .await
                     .ok_or_else(|| anyhow::anyhow!("Collection not found"))?;
 
@@ -217,15 +630,51 @@ impl ReadSide {
                     collection_operation
                 {
                     trace!(?doc_id, "Inserting document");
-                    collection.increment_document_count();
+                    let id = doc.id.clone();
+                    // Stored before the collection offset moves: a commit in between includes it
                     self.document_storage.add_document(doc_id, doc).await?;
+                    collection.insert_document(offset, doc_id, id).await;
                     trace!(?doc_id, "Document inserted");
                 } else {
                     collection.update(offset, collection_operation).await?;
                 }
+
+                if let Some(search_cache) = &self.search_cache {
+                    search_cache.invalidate(&collection_id);
+                }
+            }
+            WriteOperation::DeleteCollection(collection_id) => {
+                // The collection directory is removed
+                let commit_lock = self.commit_mutex.lock().await;
+                let doc_ids = self
+                    .collections
+                    .delete_collection(offset, collection_id.clone())
+                    .await?;
+                drop(commit_lock);
+                for doc_id in &doc_ids {
+                    self.document_storage.delete_document(doc_id).await?;
+                }
+
+                if let Some(search_cache) = &self.search_cache {
+                    search_cache.invalidate(&collection_id);
+                }
+            }
+            WriteOperation::SetAlias {
+                alias,
+                collection_id,
+            } => {
+                self.collections
+                    .set_alias(offset, alias, collection_id)
+                    .await;
+            }
+            WriteOperation::DeleteAlias { alias } => {
+                self.collections.delete_alias(offset, alias).await;
             }
         }
 
+        // The operation is visible to the searches from now
+        self.set_applied_offset(offset);
+
         let mut lock = self.operation_counter.write().await;
         *lock += 1;
         let should_commit = if *lock >= self.insert_batch_commit_size {
@@ -248,6 +697,78 @@ impl ReadSide {
         Ok(())
     }
 
+    /// The offset of the last applied operation
+    pub fn offset(&self) -> Offset {
+        *self.applied_offset.borrow()
+    }
+
+    /// The operations after this offset have to be replayed on startup:
+    /// every collection tracks its committed offset, so the oldest one is returned
+    pub async fn recovery_offset(&self) -> Offset {
+        self.collections.recovery_offset().await
+    }
+
+    /// Changed on every commit: the side channel keeps the operations after it
+    pub fn committed_offset(&self) -> watch::Receiver<Offset> {
+        self.committed_offset.subscribe()
+    }
+
+    /// The read side is catching up until the operation with `target` is applied
+    pub fn catch_up(&self, target: Offset) {
+        self.catch_up_target.set_offset(target);
+    }
+
+    pub fn is_catching_up(&self) -> bool {
+        self.offset() < self.catch_up_target.get_offset()
+    }
+
+    /// The replayed operations are older than the already loaded ones:
+    /// the offset never goes back
+    fn set_applied_offset(&self, offset: Offset) {
+        self.applied_offset.send_if_modified(|applied| {
+            if offset > *applied {
+                *applied = offset;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    /// On recovery the operations are replayed from the oldest committed offset:
+    /// the ones already committed by their collection are skipped.
+    /// Every side channel delivers the operations sorted by offset,
+    /// so an operation after the replay is never older than the applied ones.
+    async fn is_applied(&self, offset: Offset, op: &WriteOperation) -> bool {
+        match op {
+            WriteOperation::Collection(collection_id, _) => {
+                match self.collections.get_collection(collection_id.clone()).await {
+                    Some(collection) => offset <= collection.get_offset(),
+                    // The collection is deleted by a next operation
+                    None => offset <= self.catch_up_target.get_offset(),
+                }
+            }
+            _ => offset <= self.collections.get_offset(),
+        }
+    }
+
+    /// Waits until the operation with `offset` is applied
+    pub async fn wait_for_offset(&self, offset: Offset, timeout: Duration) -> Result<()> {
+        let mut receiver = self.applied_offset.subscribe();
+        tokio::time::timeout(timeout, receiver.wait_for(|applied| *applied >= offset))
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "The offset {} is not reached in {:?}: the write is not visible yet",
+                    offset.0,
+                    timeout
+                )
+            })?
+            .context("The read side is stopped")?;
+
+        Ok(())
+    }
+
     // This is wrong. We should not expose the ai service to the read side.
     // TODO: Remove this method.
     pub fn get_ai_service(&self) -> Arc<AIService> {
@@ -283,20 +804,124 @@ fn top_n(map: HashMap<DocumentId, f32>, n: usize) -> Vec<TokenScore> {
     result
 }
 
+const EXPORT_BATCH_SIZE: usize = 100;
+
+/// Constant used by Reciprocal Rank Fusion to smooth the contribution of the top ranked hits
+const RRF_K: f32 = 60.0;
+
+fn merge_federated_hits(
+    hits_per_collection: Vec<(CollectionId, Vec<SearchResultHit>)>,
+    strategy: FederatedMergeStrategy,
+    limit: usize,
+) -> Vec<FederatedSearchResultHit> {
+    let mut merged: Vec<FederatedSearchResultHit> = Vec::new();
+
+    for (collection_id, hits) in hits_per_collection {
+        // Scores of different collections are not comparable,
+        // so we bring them into the same range before merging
+        let (min, max) = hits.iter().fold((f32::MAX, f32::MIN), |(min, max), hit| {
+            (min.min(hit.score), max.max(hit.score))
+        });
+
+        // `hits` are already sorted by score
+        for (rank, hit) in hits.into_iter().enumerate() {
+            let score = match strategy {
+                FederatedMergeStrategy::Normalized => {
+                    if max > min {
+                        (hit.score - min) / (max - min)
+                    } else {
+                        1.0
+                    }
+                }
+                FederatedMergeStrategy::ReciprocalRankFusion => 1.0 / (RRF_K + rank as f32 + 1.0),
+            };
+
+            merged.push(FederatedSearchResultHit {
+                collection_id: collection_id.clone(),
+                id: hit.id,
+                version: hit.version,
+                score,
+                document: hit.document,
+                chunk: hit.chunk,
+            });
+        }
+    }
+
+    merged.sort_by(|a, b| b.score.total_cmp(&a.score));
+    merged.truncate(limit);
+
+    merged
+}
+
 fn default_insert_batch_commit_size() -> u64 {
     300
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::collection_manager::sides::read::{
-        collection::CollectionReader, collections::CollectionsReader,
+    use crate::{
+        collection_manager::{
+            dto::{FederatedMergeStrategy, SearchResultHit},
+            sides::read::{collection::CollectionReader, collections::CollectionsReader},
+        },
+        types::CollectionId,
     };
 
+    use super::merge_federated_hits;
+
     #[test]
     fn test_side_read_sync_send() {
         fn assert_sync_send<T: Sync + Send>() {}
         assert_sync_send::<CollectionsReader>();
         assert_sync_send::<CollectionReader>();
     }
+
+    fn hit(id: &str, score: f32) -> SearchResultHit {
+        SearchResultHit {
+            id: id.to_string(),
+            version: 0,
+            score,
+            document: None,
+            chunk: None,
+        }
+    }
+
+    #[test]
+    fn test_side_read_merge_federated_hits() {
+        let hits_per_collection = vec![
+            (
+                CollectionId("a".to_string()),
+                vec![hit("a1", 10.0), hit("a2", 5.0), hit("a3", 0.0)],
+            ),
+            (
+                CollectionId("b".to_string()),
+                vec![hit("b1", 0.4), hit("b2", 0.3)],
+            ),
+        ];
+
+        let merged = merge_federated_hits(
+            hits_per_collection.clone(),
+            FederatedMergeStrategy::Normalized,
+            10,
+        );
+        let ids: Vec<_> = merged.iter().map(|h| h.id.as_str()).collect();
+        assert_eq!(ids.len(), 5);
+        assert_eq!(merged[0].score, 1.0);
+        assert_eq!(merged[4].score, 0.0);
+        assert!(ids[0..2].contains(&"a1"));
+        assert!(ids[0..2].contains(&"b1"));
+        assert_eq!(ids[2], "a2");
+        assert_eq!(merged[2].collection_id, CollectionId("a".to_string()));
+
+        let merged = merge_federated_hits(
+            hits_per_collection,
+            FederatedMergeStrategy::ReciprocalRankFusion,
+            3,
+        );
+        let ids: Vec<_> = merged.iter().map(|h| h.id.as_str()).collect();
+        assert_eq!(ids.len(), 3);
+        assert!(ids[0..2].contains(&"a1"));
+        assert!(ids[0..2].contains(&"b1"));
+        assert!(ids[2] == "a2" || ids[2] == "b2");
+    }
 }
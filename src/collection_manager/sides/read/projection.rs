@@ -0,0 +1,263 @@
+use std::{borrow::Cow, collections::HashMap, fmt};
+
+use anyhow::{Context, Result};
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use serde_json::value::RawValue;
+
+use crate::types::RawJSONDocument;
+
+/// Prunes the documents returned to the user.
+/// Only the objects along the projected paths are parsed:
+/// the values are kept as raw JSON and copied as they are.
+#[derive(Debug, Default)]
+pub struct Projection {
+    select: Option<PathTree>,
+    exclude: Option<PathTree>,
+}
+
+impl Projection {
+    pub fn new(select: Vec<String>, exclude: Vec<String>) -> Self {
+        Self {
+            select: PathTree::from_paths(select),
+            exclude: PathTree::from_paths(exclude),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.select.is_none() && self.exclude.is_none()
+    }
+
+    pub fn apply(&self, doc: RawJSONDocument) -> Result<RawJSONDocument> {
+        if self.is_empty() {
+            return Ok(doc);
+        }
+
+        let mut inner = doc.inner.get().to_string();
+        if let Some(select) = &self.select {
+            inner = project(&inner, select, Mode::Select)?;
+        }
+        if let Some(exclude) = &self.exclude {
+            inner = project(&inner, exclude, Mode::Exclude)?;
+        }
+
+        let inner = RawValue::from_string(inner).context("Cannot build projected document")?;
+
+        Ok(RawJSONDocument { id: doc.id, inner })
+    }
+}
+
+#[derive(Debug, Default)]
+struct PathTree {
+    /// `true` if the path ends here: the whole value is selected (or excluded)
+    is_leaf: bool,
+    children: HashMap<String, PathTree>,
+}
+
+impl PathTree {
+    fn from_paths(paths: Vec<String>) -> Option<Self> {
+        if paths.is_empty() {
+            return None;
+        }
+
+        let mut root = PathTree::default();
+        for path in paths {
+            let mut node = &mut root;
+            for segment in path.split('.') {
+                node = node.children.entry(segment.to_string()).or_default();
+            }
+            node.is_leaf = true;
+        }
+
+        Some(root)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    Select,
+    Exclude,
+}
+
+fn project(raw: &str, tree: &PathTree, mode: Mode) -> Result<String> {
+    let object: RawObject = serde_json::from_str(raw).context("Document is not an object")?;
+
+    let mut output = String::with_capacity(raw.len());
+    output.push('{');
+    let mut first = true;
+    for (key, value) in object.0 {
+        let value: Cow<str> = match (tree.children.get(key.as_ref()), mode) {
+            (None, Mode::Select) => continue,
+            (None, Mode::Exclude) => Cow::Borrowed(value.get()),
+            (Some(node), Mode::Select) if node.is_leaf => Cow::Borrowed(value.get()),
+            (Some(node), Mode::Exclude) if node.is_leaf => continue,
+            (Some(node), _) => {
+                if value.get().trim_start().starts_with('{') {
+                    Cow::Owned(project(value.get(), node, mode)?)
+                } else {
+                    match mode {
+                        // The selected nested path doesn't exist
+                        Mode::Select => continue,
+                        // Nothing to exclude here
+                        Mode::Exclude => Cow::Borrowed(value.get()),
+                    }
+                }
+            }
+        };
+
+        if !first {
+            output.push(',');
+        }
+        first = false;
+        output.push_str(&serde_json::to_string(key.as_ref())?);
+        output.push(':');
+        output.push_str(&value);
+    }
+    output.push('}');
+
+    Ok(output)
+}
+
+/// The entries of a JSON object, in the original order, without parsing the values
+struct RawObject<'a>(Vec<(Cow<'a, str>, &'a RawValue)>);
+
+impl<'de> Deserialize<'de> for RawObject<'de> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct RawObjectVisitor;
+
+        impl<'de> Visitor<'de> for RawObjectVisitor {
+            type Value = RawObject<'de>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a JSON object")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0));
+                while let Some((key, value)) = map.next_entry::<Cow<'de, str>, &'de RawValue>()? {
+                    entries.push((key, value));
+                }
+                Ok(RawObject(entries))
+            }
+        }
+
+        deserializer.deserialize_map(RawObjectVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn apply(doc: serde_json::Value, select: &[&str], exclude: &[&str]) -> serde_json::Value {
+        let projection = Projection::new(
+            select.iter().map(|s| s.to_string()).collect(),
+            exclude.iter().map(|s| s.to_string()).collect(),
+        );
+        let doc: RawJSONDocument = doc.try_into().unwrap();
+        let doc = projection.apply(doc).unwrap();
+        serde_json::from_str(doc.inner.get()).unwrap()
+    }
+
+    #[test]
+    fn test_projection_select_and_exclude() {
+        let doc = json!({
+            "id": "1",
+            "title": "The title",
+            "url": "https://example.com",
+            "content": "A very long content",
+            "meta": {
+                "author": "John",
+                "tags": ["a", "b"],
+                "nested": { "deep": 1, "other": 2 },
+            },
+        });
+
+        assert_eq!(apply(doc.clone(), &[], &[]), doc);
+
+        assert_eq!(
+            apply(doc.clone(), &["id", "title", "url"], &[]),
+            json!({
+                "id": "1",
+                "title": "The title",
+                "url": "https://example.com",
+            })
+        );
+
+        assert_eq!(
+            apply(
+                doc.clone(),
+                &["title", "meta.author", "meta.nested.deep"],
+                &[]
+            ),
+            json!({
+                "title": "The title",
+                "meta": {
+                    "author": "John",
+                    "nested": { "deep": 1 },
+                },
+            })
+        );
+
+        assert_eq!(
+            apply(doc.clone(), &[], &["content", "meta.tags"]),
+            json!({
+                "id": "1",
+                "title": "The title",
+                "url": "https://example.com",
+                "meta": {
+                    "author": "John",
+                    "nested": { "deep": 1, "other": 2 },
+                },
+            })
+        );
+
+        assert_eq!(
+            apply(doc.clone(), &["title", "meta"], &["meta.nested"]),
+            json!({
+                "title": "The title",
+                "meta": {
+                    "author": "John",
+                    "tags": ["a", "b"],
+                },
+            })
+        );
+
+        // Paths that don't exist are ignored
+        assert_eq!(
+            apply(
+                doc.clone(),
+                &["title", "title.inner", "unknown"],
+                &["url.inner"]
+            ),
+            json!({
+                "title": "The title",
+            })
+        );
+    }
+
+    #[test]
+    fn test_projection_keeps_escaped_keys() {
+        let doc = json!({
+            "with \"quotes\"": 1,
+            "other": 2,
+        });
+
+        assert_eq!(
+            apply(doc, &["with \"quotes\""], &[]),
+            json!({
+                "with \"quotes\"": 1,
+            })
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    collection_manager::{
+        dto::{SearchParams, SearchResult},
+        sides::Offset,
+    },
+    lru_cache::LruCache,
+    metrics::{SearchCacheLabels, SEARCH_CACHE_HIT_COUNTER, SEARCH_CACHE_MISS_COUNTER},
+    types::CollectionId,
+};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SearchCacheConfig {
+    /// The maximum number of search results kept in memory
+    pub capacity: usize,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct SearchCacheKey {
+    collection_id: CollectionId,
+    offset: Offset,
+    /// The last expiration passed, in milliseconds
+    last_expiration: Option<i64>,
+    params: String,
+}
+
+impl SearchCacheKey {
+    pub fn try_new(
+        collection_id: CollectionId,
+        offset: Offset,
+        last_expiration: Option<i64>,
+        search_params: &SearchParams,
+    ) -> Result<Self> {
+        let params = serde_json::to_value(search_params).context("Cannot serialize params")?;
+        let params = normalize(params).to_string();
+
+        Ok(Self {
+            collection_id,
+            offset,
+            last_expiration,
+            params,
+        })
+    }
+}
+
+/// Caches the search results.
+/// The key contains the offset of the collection, so a write operation makes the entries stale:
+/// they are also removed explicitly by `invalidate`.
+#[derive(Debug)]
+pub struct SearchCache {
+    inner: Mutex<SearchCacheInner>,
+}
+
+#[derive(Debug)]
+struct SearchCacheInner {
+    entries: LruCache<SearchCacheKey, SearchResult>,
+    // The number of entries per collection.
+    // Used to avoid scanning the entries for the collections that are not cached.
+    entries_per_collection: HashMap<CollectionId, usize>,
+}
+
+impl SearchCache {
+    pub fn new(config: SearchCacheConfig) -> Self {
+        Self {
+            inner: Mutex::new(SearchCacheInner {
+                entries: LruCache::new(config.capacity),
+                entries_per_collection: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn get(&self, key: &SearchCacheKey) -> Option<SearchResult> {
+        let mut inner = self.inner.lock().unwrap();
+        let result = inner.entries.get(key).cloned();
+        drop(inner);
+
+        let labels = SearchCacheLabels {
+            collection: key.collection_id.0.clone(),
+        };
+        if result.is_some() {
+            SEARCH_CACHE_HIT_COUNTER.create(labels).increment_by_one();
+        } else {
+            SEARCH_CACHE_MISS_COUNTER.create(labels).increment_by_one();
+        }
+
+        result
+    }
+
+    pub fn insert(&self, key: SearchCacheKey, result: SearchResult) {
+        let mut inner = self.inner.lock().unwrap();
+
+        let collection_id = key.collection_id.clone();
+        let evicted = inner.entries.insert(key, result);
+        *inner
+            .entries_per_collection
+            .entry(collection_id)
+            .or_default() += 1;
+
+        for (key, _) in evicted {
+            inner.decrement(&key.collection_id);
+        }
+    }
+
+    pub fn invalidate(&self, collection_id: &CollectionId) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.entries_per_collection.remove(collection_id).is_none() {
+            return;
+        }
+        inner
+            .entries
+            .retain(|key| &key.collection_id != collection_id);
+    }
+}
+
+impl SearchCacheInner {
+    fn decrement(&mut self, collection_id: &CollectionId) {
+        if let Some(count) = self.entries_per_collection.get_mut(collection_id) {
+            *count -= 1;
+            if *count == 0 {
+                self.entries_per_collection.remove(collection_id);
+            }
+        }
+    }
+}
+
+/// Sorts the object keys, so the same params always produce the same key
+fn normalize(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k, normalize(v)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(normalize).collect()),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn key(collection_id: &str, offset: u64, params: Value) -> SearchCacheKey {
+        let params: SearchParams = params.try_into().unwrap();
+        SearchCacheKey::try_new(
+            CollectionId(collection_id.to_string()),
+            Offset(offset),
+            None,
+            &params,
+        )
+        .unwrap()
+    }
+
+    fn result(count: usize) -> SearchResult {
+        SearchResult {
+            hits: vec![],
+            count,
+            facets: None,
+        }
+    }
+
+    #[test]
+    fn test_search_cache_key_is_normalized() {
+        let a = key(
+            "coll",
+            1,
+            json!({
+                "term": "foo",
+                "where": { "a": { "eq": 1 }, "b": true, "c": { "gt": 2 } },
+            }),
+        );
+        let b = key(
+            "coll",
+            1,
+            json!({
+                "where": { "c": { "gt": 2 }, "b": true, "a": { "eq": 1 } },
+                "term": "foo",
+            }),
+        );
+        assert_eq!(a, b);
+
+        assert_ne!(a, key("coll", 2, json!({ "term": "foo" })));
+        assert_ne!(
+            key("coll", 1, json!({ "term": "foo" })),
+            key("coll", 2, json!({ "term": "foo" }))
+        );
+        assert_ne!(
+            key("coll", 1, json!({ "term": "foo" })),
+            key("other", 1, json!({ "term": "foo" }))
+        );
+
+        // An expiration changes the result as a write
+        let params: SearchParams = json!({ "term": "foo" }).try_into().unwrap();
+        let expired = SearchCacheKey::try_new(
+            CollectionId("coll".to_string()),
+            Offset(1),
+            Some(1_000),
+            &params,
+        )
+        .unwrap();
+        assert_ne!(key("coll", 1, json!({ "term": "foo" })), expired);
+    }
+
+    #[test]
+    fn test_search_cache_invalidate() {
+        let cache = SearchCache::new(SearchCacheConfig { capacity: 2 });
+
+        let a = key("a", 1, json!({ "term": "foo" }));
+        let b = key("b", 1, json!({ "term": "foo" }));
+        cache.insert(a.clone(), result(1));
+        cache.insert(b.clone(), result(2));
+
+        assert_eq!(cache.get(&a), Some(result(1)));
+        assert_eq!(cache.get(&b), Some(result(2)));
+
+        cache.invalidate(&CollectionId("a".to_string()));
+        assert_eq!(cache.get(&a), None);
+        assert_eq!(cache.get(&b), Some(result(2)));
+
+        // Eviction keeps the bound
+        let c = key("c", 1, json!({ "term": "foo" }));
+        let d = key("d", 1, json!({ "term": "foo" }));
+        cache.insert(c.clone(), result(3));
+        cache.insert(d.clone(), result(4));
+        assert_eq!(cache.get(&b), None);
+        assert_eq!(cache.get(&c), Some(result(3)));
+        assert_eq!(cache.get(&d), Some(result(4)));
+    }
+}
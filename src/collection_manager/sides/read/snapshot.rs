@@ -0,0 +1,354 @@
+//! The snapshots of the committed data of the read side, to bootstrap new read replicas.
+//! A snapshot is `SNAPSHOT_MAGIC` and its offset, followed by every file of the data dir as
+//! `[path length: u32][path][size: u64][content][crc32 of the content: u32]`.
+//! An empty path ends the snapshot, followed by the number of files as `u64`.
+//! The numbers are little endian, the paths are relative to the data dir and use `/`.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::collection_manager::dto::ApiKey;
+
+use super::Offset;
+
+const SNAPSHOT_MAGIC: &[u8; 8] = b"ORAMASN1";
+const MAX_PATH_SIZE: usize = 4_096;
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SnapshotConfig {
+    /// Required to export a snapshot: the export is disabled if not set
+    #[serde(default)]
+    pub api_key: Option<ApiKey>,
+    /// Imported on startup if the data dir is empty.
+    /// The operations after the snapshot are received from the side channel.
+    #[serde(default)]
+    pub import_from: Option<PathBuf>,
+}
+
+/// Copies every file of `data_dir` in `staging_dir`, which has to be empty.
+/// The copy is written as snapshot while `data_dir` changes again.
+pub fn stage_snapshot(data_dir: &Path, staging_dir: &Path) -> Result<()> {
+    let mut paths = vec![];
+    list_files(data_dir, Path::new(""), &mut paths)?;
+
+    std::fs::create_dir_all(staging_dir)
+        .with_context(|| format!("Cannot create {:?}", staging_dir))?;
+    for path in &paths {
+        let target = staging_dir.join(path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Cannot create {:?}", parent))?;
+        }
+        // A copy and not a hard link: a commit may overwrite a file in place
+        std::fs::copy(data_dir.join(path), &target)
+            .with_context(|| format!("Cannot copy {:?} for the snapshot", path))?;
+    }
+
+    Ok(())
+}
+
+/// Writes every file in `data_dir`. The files must not change in the meantime.
+pub fn write_snapshot<W: Write>(data_dir: &Path, offset: Offset, writer: W) -> Result<()> {
+    let mut paths = vec![];
+    list_files(data_dir, Path::new(""), &mut paths)?;
+    // The same data gives the same snapshot
+    paths.sort();
+
+    let mut writer = BufWriter::new(writer);
+    writer.write_all(SNAPSHOT_MAGIC)?;
+    writer.write_all(&offset.0.to_le_bytes())?;
+
+    let mut buf = vec![0; COPY_BUFFER_SIZE];
+    for path in &paths {
+        let name = path
+            .components()
+            .map(|component| component.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()
+            .with_context(|| format!("The path {:?} is not UTF-8", path))?
+            .join("/");
+
+        let mut file = File::open(data_dir.join(path))
+            .with_context(|| format!("Cannot open {:?} for the snapshot", path))?;
+        let size = file.metadata()?.len();
+
+        writer.write_all(&(name.len() as u32).to_le_bytes())?;
+        writer.write_all(name.as_bytes())?;
+        writer.write_all(&size.to_le_bytes())?;
+
+        let mut hasher = crc32fast::Hasher::new();
+        let mut remaining = size;
+        while remaining > 0 {
+            let len = remaining.min(buf.len() as u64) as usize;
+            file.read_exact(&mut buf[..len])
+                .with_context(|| format!("Cannot read {:?} for the snapshot", path))?;
+            hasher.update(&buf[..len]);
+            writer.write_all(&buf[..len])?;
+            remaining -= len as u64;
+        }
+        writer.write_all(&hasher.finalize().to_le_bytes())?;
+    }
+
+    writer.write_all(&0_u32.to_le_bytes())?;
+    writer.write_all(&(paths.len() as u64).to_le_bytes())?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Extracts the snapshot in `data_dir`, which has to be empty.
+/// The files are moved in `data_dir` only once the whole snapshot is verified.
+/// Returns the offset of the snapshot.
+pub fn import_snapshot<R: Read>(reader: R, data_dir: &Path) -> Result<Offset> {
+    if !is_empty_dir(data_dir)? {
+        bail!("The data dir {:?} is not empty", data_dir);
+    }
+
+    let tmp_dir = data_dir.with_extension("snapshot-import");
+    if tmp_dir.exists() {
+        // Left by an interrupted import
+        std::fs::remove_dir_all(&tmp_dir)
+            .with_context(|| format!("Cannot remove {:?}", tmp_dir))?;
+    }
+    std::fs::create_dir_all(&tmp_dir).with_context(|| format!("Cannot create {:?}", tmp_dir))?;
+
+    let offset = match extract(reader, &tmp_dir) {
+        Ok(offset) => offset,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&tmp_dir);
+            return Err(e);
+        }
+    };
+
+    if data_dir.exists() {
+        std::fs::remove_dir(data_dir).with_context(|| format!("Cannot remove {:?}", data_dir))?;
+    }
+    std::fs::rename(&tmp_dir, data_dir)
+        .with_context(|| format!("Cannot move the snapshot in {:?}", data_dir))?;
+
+    Ok(offset)
+}
+
+pub fn is_empty_dir(dir: &Path) -> Result<bool> {
+    match std::fs::read_dir(dir) {
+        Ok(mut entries) => Ok(entries.next().is_none()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(e).with_context(|| format!("Cannot read {:?}", dir)),
+    }
+}
+
+fn extract<R: Read>(reader: R, dir: &Path) -> Result<Offset> {
+    let mut reader = BufReader::new(reader);
+
+    let mut magic = [0; SNAPSHOT_MAGIC.len()];
+    reader
+        .read_exact(&mut magic)
+        .context("Cannot read the snapshot header")?;
+    if &magic != SNAPSHOT_MAGIC {
+        bail!("Not a snapshot");
+    }
+    let offset = Offset(read_u64(&mut reader)?);
+
+    let mut buf = vec![0; COPY_BUFFER_SIZE];
+    let mut file_count = 0_u64;
+    loop {
+        let path_len = read_u32(&mut reader).context("Truncated snapshot")? as usize;
+        if path_len == 0 {
+            break;
+        }
+        if path_len > MAX_PATH_SIZE {
+            bail!("Invalid path length {} in the snapshot", path_len);
+        }
+        let mut name = vec![0; path_len];
+        reader.read_exact(&mut name).context("Truncated snapshot")?;
+        let name = String::from_utf8(name).context("Invalid path in the snapshot")?;
+        let path = relative_path(&name)?;
+
+        let target = dir.join(&path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Cannot create {:?}", parent))?;
+        }
+        let mut file = BufWriter::new(
+            File::create(&target).with_context(|| format!("Cannot create {:?}", target))?,
+        );
+
+        let size = read_u64(&mut reader).context("Truncated snapshot")?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut remaining = size;
+        while remaining > 0 {
+            let len = remaining.min(buf.len() as u64) as usize;
+            reader
+                .read_exact(&mut buf[..len])
+                .context("Truncated snapshot")?;
+            hasher.update(&buf[..len]);
+            file.write_all(&buf[..len])?;
+            remaining -= len as u64;
+        }
+        let crc = read_u32(&mut reader).context("Truncated snapshot")?;
+        if crc != hasher.finalize() {
+            bail!("Corrupted snapshot: wrong checksum of {:?}", name);
+        }
+        file.into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()
+            .with_context(|| format!("Cannot sync {:?}", target))?;
+
+        file_count += 1;
+    }
+
+    let expected_count = read_u64(&mut reader).context("Truncated snapshot")?;
+    if expected_count != file_count {
+        bail!(
+            "Corrupted snapshot: {} files instead of {}",
+            file_count,
+            expected_count
+        );
+    }
+
+    Ok(offset)
+}
+
+/// Only the plain relative paths: a snapshot cannot write outside of the data dir
+fn relative_path(name: &str) -> Result<PathBuf> {
+    let path: PathBuf = name.split('/').collect();
+    let is_relative = name.split('/').all(|part| !part.is_empty())
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if !is_relative {
+        bail!("Invalid path {:?} in the snapshot", name);
+    }
+    Ok(path)
+}
+
+fn list_files(dir: &Path, relative: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir.join(relative))
+        .with_context(|| format!("Cannot read {:?}", dir.join(relative)))?;
+    for entry in entries {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            list_files(dir, &path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::generate_new_path;
+
+    use super::*;
+
+    fn write_files(dir: &Path) {
+        std::fs::create_dir_all(dir.join("collections/books/fields")).unwrap();
+        std::fs::write(dir.join("info.json"), br#"{"1":{}}"#).unwrap();
+        std::fs::write(dir.join("collections/books/info-offset-3.info"), b"{}").unwrap();
+        std::fs::write(
+            dir.join("collections/books/fields/data.bin"),
+            vec![7; COPY_BUFFER_SIZE * 2 + 5],
+        )
+        .unwrap();
+        std::fs::write(dir.join("collections/books/fields/empty.bin"), b"").unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let dir = generate_new_path();
+        write_files(&dir);
+
+        let mut snapshot = vec![];
+        write_snapshot(&dir, Offset(42), &mut snapshot).unwrap();
+
+        let target = generate_new_path().join("reader");
+        let offset = import_snapshot(snapshot.as_slice(), &target).unwrap();
+        assert_eq!(offset, Offset(42));
+
+        let mut expected = vec![];
+        list_files(&dir, Path::new(""), &mut expected).unwrap();
+        let mut imported = vec![];
+        list_files(&target, Path::new(""), &mut imported).unwrap();
+        expected.sort();
+        imported.sort();
+        assert_eq!(expected, imported);
+        for path in &expected {
+            assert_eq!(
+                std::fs::read(dir.join(path)).unwrap(),
+                std::fs::read(target.join(path)).unwrap()
+            );
+        }
+
+        // A replica with its own data is not overwritten
+        let output = import_snapshot(snapshot.as_slice(), &target);
+        assert!(output.is_err());
+    }
+
+    #[test]
+    fn test_stage_snapshot() {
+        let dir = generate_new_path();
+        write_files(&dir);
+
+        let mut expected = vec![];
+        write_snapshot(&dir, Offset(42), &mut expected).unwrap();
+
+        let staging_dir = generate_new_path();
+        stage_snapshot(&dir, &staging_dir).unwrap();
+        // A commit after the copy doesn't change the snapshot
+        std::fs::write(dir.join("info.json"), br#"{"2":{}}"#).unwrap();
+
+        let mut snapshot = vec![];
+        write_snapshot(&staging_dir, Offset(42), &mut snapshot).unwrap();
+        assert_eq!(snapshot, expected);
+    }
+
+    #[test]
+    fn test_snapshot_corruption() {
+        let dir = generate_new_path();
+        write_files(&dir);
+
+        let mut snapshot = vec![];
+        write_snapshot(&dir, Offset(42), &mut snapshot).unwrap();
+
+        let mut corrupted = snapshot.clone();
+        let middle = corrupted.len() / 2;
+        corrupted[middle] ^= 0xFF;
+        let target = generate_new_path();
+        let output = import_snapshot(corrupted.as_slice(), &target);
+        assert!(output.is_err());
+        // Nothing is left in the data dir
+        assert!(is_empty_dir(&target).unwrap());
+
+        let truncated = &snapshot[..snapshot.len() - 3];
+        let output = import_snapshot(truncated, &target);
+        assert!(output.is_err());
+        assert!(is_empty_dir(&target).unwrap());
+    }
+
+    #[test]
+    fn test_snapshot_relative_path() {
+        assert!(relative_path("collections/books/info.json").is_ok());
+        assert!(relative_path("../info.json").is_err());
+        assert!(relative_path("/etc/passwd").is_err());
+    }
+}
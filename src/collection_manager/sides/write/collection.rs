@@ -1,31 +1,44 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::PathBuf,
+    str::FromStr,
     sync::{
         atomic::{AtomicU16, AtomicU64},
         Arc,
     },
+    time::Duration,
 };
 
 use anyhow::{anyhow, bail, Context, Ok, Result};
+use chrono::{DateTime, Utc};
 use doc_id_storage::DocIdStorage;
 use redact::Secret;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
 use tokio::sync::RwLock;
 use tracing::{debug, info, instrument, trace, warn};
 
 use crate::{
     collection_manager::{
-        dto::{ApiKey, CollectionDTO, FieldId},
-        sides::hooks::{HookName, HooksRuntime},
+        dto::{ApiKey, CollectionDTO, FieldId, Filter, Number},
+        sides::{
+            document_storage::DocumentStorage,
+            expirations::Expirations,
+            hooks::{HookName, HooksRuntime},
+        },
     },
     file_utils::BufferedFile,
     metrics::{CommitLabels, COMMIT_METRIC},
-    nlp::{locales::Locale, NLPService, TextParser},
-    types::{CollectionId, ComplexType, Document, DocumentId, ScalarType, ValueType},
+    nlp::{chunker::Chunker, locales::Locale, NLPService, TextParser},
+    types::{
+        CollectionId, ComplexType, Document, DocumentId, FlattenDocument, ScalarType, ValueType,
+    },
 };
 
-use crate::collection_manager::dto::{LanguageDTO, TypedField};
+use crate::collection_manager::dto::{
+    LanguageDTO, SchemaField, SchemaFieldType, TypedField, UnknownFieldsPolicy,
+};
 
 use super::{
     embedding::EmbeddingCalculationRequest, CollectionField, CollectionWriteOperation,
@@ -33,13 +46,49 @@ use super::{
 };
 
 mod doc_id_storage;
+mod schema;
+
+pub use schema::{validate_schema, SchemaError};
 
 pub const DEFAULT_EMBEDDING_FIELD_NAME: &str = "___orama_auto_embedding";
 
+/// Number of stored documents read at once while deleting by filter
+const DELETE_BY_FILTER_BATCH_SIZE: usize = 1_000;
+
+/// The document property with the expiration of the document:
+/// a RFC 3339 datetime or a timestamp in milliseconds.
+/// It overrides the collection TTL.
+pub const EXPIRES_AT_PROPERTY: &str = "expiresAt";
+
+/// The write is rejected because the document is changed in the meantime.
+/// See `DocumentId::version`: the version of a missing document is `0`.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Document \"{id}\" has version {current}, expected {expected}")]
+pub struct VersionConflict {
+    pub id: String,
+    pub expected: u64,
+    pub current: u64,
+}
+
+/// The delete by filter reads the stored documents, not the indexes of the read side:
+/// only the filters on the number, datetime and boolean fields are supported.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Cannot delete by a {filter} filter on field \"{field}\": only the number, datetime and boolean fields can be filtered")]
+pub struct UnsupportedFilterError {
+    pub field: String,
+    pub filter: &'static str,
+}
+
 pub struct CollectionWriter {
     id: CollectionId,
     description: Option<String>,
     default_language: LanguageDTO,
+    field_languages: HashMap<String, LanguageDTO>,
+    language_property: Option<String>,
+    infix_fields: HashSet<String>,
+    schema: HashMap<String, SchemaField>,
+    unknown_fields: UnknownFieldsPolicy,
+    ttl: Option<Duration>,
     fields: RwLock<HashMap<FieldId, (String, ValueType, CollectionField)>>,
     write_api_key: ApiKey,
     collection_document_count: AtomicU64,
@@ -50,6 +99,12 @@ pub struct CollectionWriter {
     embedding_sender: tokio::sync::mpsc::Sender<EmbeddingCalculationRequest>,
 
     doc_id_storage: RwLock<DocIdStorage>,
+    /// The expiration, in milliseconds, by document id.
+    /// Taken after `doc_id_storage`
+    expirations: RwLock<Expirations<String>>,
+
+    nlp_service: Arc<NLPService>,
+    chunker: Arc<Chunker>,
 }
 
 impl CollectionWriter {
@@ -59,21 +114,62 @@ impl CollectionWriter {
         write_api_key: ApiKey,
         default_language: LanguageDTO,
         embedding_sender: tokio::sync::mpsc::Sender<EmbeddingCalculationRequest>,
+        nlp_service: Arc<NLPService>,
+        chunker: Arc<Chunker>,
     ) -> Self {
         Self {
             id: id.clone(),
             description,
             write_api_key,
             default_language,
+            field_languages: Default::default(),
+            language_property: None,
+            infix_fields: Default::default(),
+            schema: Default::default(),
+            unknown_fields: Default::default(),
+            ttl: None,
             collection_document_count: Default::default(),
             fields: Default::default(),
             field_id_by_name: Default::default(),
             field_id_generator: Default::default(),
             embedding_sender,
             doc_id_storage: Default::default(),
+            expirations: Default::default(),
+            nlp_service,
+            chunker,
         }
     }
 
+    pub fn with_language_overrides(
+        mut self,
+        field_languages: HashMap<String, LanguageDTO>,
+        language_property: Option<String>,
+    ) -> Self {
+        self.field_languages = field_languages;
+        self.language_property = language_property;
+        self
+    }
+
+    pub fn with_infix_fields(mut self, infix_fields: HashSet<String>) -> Self {
+        self.infix_fields = infix_fields;
+        self
+    }
+
+    pub fn with_schema(
+        mut self,
+        schema: HashMap<String, SchemaField>,
+        unknown_fields: UnknownFieldsPolicy,
+    ) -> Self {
+        self.schema = schema;
+        self.unknown_fields = unknown_fields;
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
     pub fn check_write_api_key(&self, api_key: ApiKey) -> Result<()> {
         if self.write_api_key == api_key {
             Ok(())
@@ -99,6 +195,25 @@ impl CollectionWriter {
         }
     }
 
+    pub async fn document_ids(&self) -> Vec<DocumentId> {
+        let doc_id_storage = self.doc_id_storage.read().await;
+        doc_id_storage.iter().map(|(_, doc_id)| *doc_id).collect()
+    }
+
+    /// The ids of the documents, as given by the user
+    pub async fn ids(&self) -> Vec<String> {
+        let doc_id_storage = self.doc_id_storage.read().await;
+        doc_id_storage.iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    /// The current `DocumentId` of every id, or `None` if the document doesn't exist
+    pub async fn get_document_ids(&self, ids: &[String]) -> Vec<Option<DocumentId>> {
+        let doc_id_storage = self.doc_id_storage.read().await;
+        ids.iter()
+            .map(|id| doc_id_storage.get_document_id(id))
+            .collect()
+    }
+
     pub async fn set_embedding_hook(&self, hook_name: HookName) -> Result<()> {
         let field_id_by_name = self.field_id_by_name.read().await;
         let field_id = field_id_by_name
@@ -117,38 +232,169 @@ impl CollectionWriter {
         Ok(())
     }
 
+    /// Fails with `VersionConflict` if the current version of the document is not `if_version`
+    pub async fn check_version(&self, doc_id_str: &str, if_version: u64) -> Result<()> {
+        let doc_id_storage = self.doc_id_storage.read().await;
+        check_version(&doc_id_storage, doc_id_str, Some(if_version))
+    }
+
+    /// Checks the document against the collection schema, before it is enqueued
+    pub fn validate_document(&self, doc: &Document) -> Result<()> {
+        schema::validate_document(&self.schema, self.unknown_fields, &doc.into_flatten())?;
+        Ok(())
+    }
+
+    /// Inserts the document.
+    /// If a document with the same id already exists, it is replaced.
+    /// If `if_version` is set, the document is inserted only if its current version is that.
+    #[allow(clippy::too_many_arguments)]
     pub async fn process_new_document(
         &self,
         doc_id: DocumentId,
         doc: Document,
+        if_version: Option<u64>,
+        document_storage: &DocumentStorage,
         sender: OperationSender,
         hooks_runtime: Arc<HooksRuntime>,
     ) -> Result<()> {
-        self.collection_document_count
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        // Those `?` is never triggered, but it's here to make the compiler happy
+        // TODO: do this better
+        let doc_id_str = doc
+            .inner
+            .get("id")
+            .context("Document does not have an id")?
+            .as_str()
+            .context("Document id is not a string")?;
+
+        // The lock is kept until the end: the replacement of a document is atomic
+        let mut doc_id_storage = self.doc_id_storage.write().await;
+        check_version(&doc_id_storage, doc_id_str, if_version)?;
+        let previous = match doc_id_storage.get_document_id(doc_id_str) {
+            Some(previous_doc_id) => {
+                let previous_doc = get_stored_document(document_storage, previous_doc_id)
+                    .await
+                    .context("Cannot get the previous document")?;
+                Some((previous_doc_id, previous_doc))
+            }
+            None => None,
+        };
+
+        self.index_document(
+            &mut doc_id_storage,
+            doc_id,
+            doc,
+            previous,
+            document_storage,
+            sender,
+            hooks_runtime,
+        )
+        .await
+    }
+
+    /// Merges `patch` into the stored document with id `doc_id_str`,
+    /// and indexes the result as `doc_id`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn patch_document(
+        &self,
+        doc_id: DocumentId,
+        doc_id_str: String,
+        patch: Map<String, Value>,
+        if_version: Option<u64>,
+        document_storage: &DocumentStorage,
+        sender: OperationSender,
+        hooks_runtime: Arc<HooksRuntime>,
+    ) -> Result<()> {
+        if patch
+            .get("id")
+            .is_some_and(|id| id.as_str() != Some(doc_id_str.as_str()))
+        {
+            bail!("The document id cannot be changed");
+        }
+
+        let mut doc_id_storage = self.doc_id_storage.write().await;
+        check_version(&doc_id_storage, &doc_id_str, if_version)?;
+        let previous_doc_id = doc_id_storage
+            .get_document_id(&doc_id_str)
+            .with_context(|| format!("Document \"{}\" not found", doc_id_str))?;
+        let previous_doc = get_stored_document(document_storage, previous_doc_id)
+            .await
+            .context("Cannot get the document to patch")?
+            .with_context(|| format!("Document \"{}\" is not stored", doc_id_str))?;
+
+        let mut doc = previous_doc.clone();
+        doc.merge_patch(patch);
+
+        self.index_document(
+            &mut doc_id_storage,
+            doc_id,
+            doc,
+            Some((previous_doc_id, Some(previous_doc))),
+            document_storage,
+            sender,
+            hooks_runtime,
+        )
+        .await
+    }
+
+    /// `previous` is the document replaced by `doc`, if any.
+    /// The embeddings are calculated again only if their input is changed.
+    #[allow(clippy::too_many_arguments)]
+    async fn index_document(
+        &self,
+        doc_id_storage: &mut DocIdStorage,
+        doc_id: DocumentId,
+        doc: Document,
+        previous: Option<(DocumentId, Option<Document>)>,
+        document_storage: &DocumentStorage,
+        sender: OperationSender,
+        hooks_runtime: Arc<HooksRuntime>,
+    ) -> Result<()> {
+        let flatten = doc.into_flatten();
+        schema::validate_document(&self.schema, self.unknown_fields, &flatten)?;
+        let expires_at = self.get_expiration(&doc)?;
+
+        if previous.is_none() {
+            self.collection_document_count
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+
+        let raw_doc = doc.into_raw()?;
+        let doc_id_str = raw_doc.id.clone().context("Document id is not a string")?;
 
         sender
             .send(WriteOperation::Collection(
                 self.id.clone(),
                 CollectionWriteOperation::InsertDocument {
                     doc_id,
-                    doc: doc.into_raw()?,
+                    doc: raw_doc.clone(),
                 },
             ))
             .await
             .map_err(|e| anyhow!("Error sending document to index writer: {:?}", e))?;
 
-        // Those `?` is never triggered, but it's here to make the compiler happy
-        // TODO: do this better
-        let doc_id_str = doc
-            .inner
-            .get("id")
-            .context("Document does not have an id")?
-            .as_str()
-            .context("Document id is not a string")?;
-        let mut doc_id_storage = self.doc_id_storage.write().await;
-        doc_id_storage.insert_document_id(doc_id_str.to_string(), doc_id);
-        drop(doc_id_storage);
+        let mut expirations = self.expirations.write().await;
+        match expires_at {
+            Some(expires_at) => {
+                sender
+                    .send(WriteOperation::Collection(
+                        self.id.clone(),
+                        CollectionWriteOperation::SetExpiration { doc_id, expires_at },
+                    ))
+                    .await
+                    .context("Cannot send the document expiration")?;
+                expirations.insert(doc_id_str.clone(), expires_at);
+            }
+            None => {
+                expirations.remove(&doc_id_str);
+            }
+        }
+        drop(expirations);
+
+        doc_id_storage.insert_document_id(doc_id_str, doc_id);
+        document_storage
+            .add_document(doc_id, raw_doc)
+            .await
+            .context("Cannot store document")?;
 
         let fields_to_index = self
             .get_fields_to_index(doc.clone(), sender.clone(), hooks_runtime)
@@ -156,7 +402,15 @@ impl CollectionWriter {
             .context("Cannot get fields to index")?;
         trace!("Fields to index: {:?}", fields_to_index);
 
-        let flatten = doc.clone().into_flatten();
+        let document_text_parser = self.get_document_text_parser(&doc);
+
+        let previous_flatten = previous
+            .as_ref()
+            .and_then(|(previous_doc_id, previous_doc)| {
+                previous_doc
+                    .as_ref()
+                    .map(|previous_doc| (*previous_doc_id, previous_doc.into_flatten()))
+            });
 
         let r = self.fields.read().await;
         for field_id in fields_to_index {
@@ -168,21 +422,127 @@ impl CollectionWriter {
                 Some(v) => v,
             };
 
+            // The other fields are cheap to index, so they are indexed again
+            // under the new document id
+            if let (
+                CollectionField::Embedding(embedding_field),
+                Some((previous_doc_id, previous_flatten)),
+            ) = (field, &previous_flatten)
+            {
+                // If the embedding of the previous document is still in the queue,
+                // the read side has nothing to copy yet: it is calculated again
+                if !embedding_field.is_pending(*previous_doc_id)
+                    && !embedding_field
+                        .is_input_changed(previous_flatten, &flatten)
+                        .await
+                {
+                    trace!(
+                        ?doc_id,
+                        ?previous_doc_id,
+                        "Embedding input unchanged: copy it"
+                    );
+                    sender
+                        .send(WriteOperation::Collection(
+                            self.id.clone(),
+                            CollectionWriteOperation::CopyEmbedding {
+                                field_id,
+                                from: *previous_doc_id,
+                                to: doc_id,
+                            },
+                        ))
+                        .await
+                        .context("Cannot send copy embedding")?;
+                    continue;
+                }
+            }
+
+            // The field language override wins over the document language
+            let has_language = self.field_languages.contains_key(field_name)
+                || self
+                    .schema
+                    .get(field_name)
+                    .is_some_and(|field| field.language.is_some());
+            let text_parser = if has_language {
+                None
+            } else {
+                document_text_parser.clone()
+            };
+
             field
-                .get_write_operations(doc_id, &flatten, sender.clone())
+                .get_write_operations(doc_id, &flatten, text_parser, sender.clone())
                 .await
                 .with_context(|| format!("Cannot index field {}", field_name))?;
         }
+        drop(r);
+
+        // The previous document is removed only after the new one is indexed:
+        // the read side copies the embeddings from it
+        if let Some((previous_doc_id, _)) = previous {
+            info!(coll_id= ?self.id, ?previous_doc_id, ?doc_id, "Replacing document");
+            sender
+                .send(WriteOperation::Collection(
+                    self.id.clone(),
+                    CollectionWriteOperation::DeleteDocuments {
+                        doc_ids: vec![previous_doc_id],
+                    },
+                ))
+                .await
+                .context("Cannot send delete of the previous document")?;
+            document_storage
+                .delete_document(&previous_doc_id)
+                .await
+                .context("Cannot delete the previous document")?;
+        }
 
         trace!("Document field indexed");
 
         Ok(())
     }
 
-    fn value_to_typed_field(&self, value_type: ValueType) -> Option<TypedField> {
+    /// Returns the expiration in milliseconds: the document one, or the collection TTL
+    fn get_expiration(&self, doc: &Document) -> Result<Option<i64>> {
+        match doc.inner.get(EXPIRES_AT_PROPERTY) {
+            None | Some(Value::Null) => {}
+            Some(Value::String(expires_at)) => {
+                let expires_at = DateTime::parse_from_rfc3339(expires_at).with_context(|| {
+                    format!(
+                        "\"{}\" is not a RFC 3339 datetime: \"{}\"",
+                        EXPIRES_AT_PROPERTY, expires_at
+                    )
+                })?;
+                return Ok(Some(expires_at.timestamp_millis()));
+            }
+            Some(Value::Number(expires_at)) => {
+                let expires_at = expires_at.as_i64().with_context(|| {
+                    format!(
+                        "\"{}\" is not a timestamp in milliseconds: {}",
+                        EXPIRES_AT_PROPERTY, expires_at
+                    )
+                })?;
+                return Ok(Some(expires_at));
+            }
+            Some(_) => bail!(
+                "\"{}\" must be a RFC 3339 datetime or a timestamp in milliseconds",
+                EXPIRES_AT_PROPERTY
+            ),
+        }
+
+        let Some(ttl) = self.ttl else {
+            return Ok(None);
+        };
+        let ttl = chrono::Duration::from_std(ttl).context("The collection TTL is too long")?;
+        Ok(Some((Utc::now() + ttl).timestamp_millis()))
+    }
+
+    fn value_to_typed_field(&self, field_name: &str, value_type: ValueType) -> Option<TypedField> {
         match value_type {
             ValueType::Scalar(ScalarType::String) => {
-                Some(TypedField::Text(self.default_language.into()))
+                let language = self
+                    .field_languages
+                    .get(field_name)
+                    .copied()
+                    .unwrap_or(self.default_language);
+                Some(TypedField::Text(language.into()))
             }
             ValueType::Scalar(ScalarType::Number) => Some(TypedField::Number),
             ValueType::Scalar(ScalarType::Boolean) => Some(TypedField::Bool),
@@ -190,11 +550,54 @@ impl CollectionWriter {
         }
     }
 
+    /// Returns `None` for the fields not indexed.
+    /// The embedding fields are registered on the collection creation.
+    fn schema_to_typed_field(&self, field_name: &str, field: &SchemaField) -> Option<TypedField> {
+        if !field.indexed {
+            return None;
+        }
+
+        match field.field_type {
+            SchemaFieldType::Text => {
+                let language = field
+                    .language
+                    .or_else(|| self.field_languages.get(field_name).copied())
+                    .unwrap_or(self.default_language);
+                Some(TypedField::Text(language.into()))
+            }
+            SchemaFieldType::Keyword => Some(TypedField::Keyword),
+            SchemaFieldType::Number => Some(TypedField::Number),
+            SchemaFieldType::Bool => Some(TypedField::Bool),
+            SchemaFieldType::Datetime => Some(TypedField::Datetime),
+            SchemaFieldType::Embedding => None,
+        }
+    }
+
     fn get_text_parser(&self, locale: Locale) -> Arc<TextParser> {
-        // TextParser is expensive to create, so we cache it
-        // TODO: add a cache
-        let parser = TextParser::from_locale(locale);
-        Arc::new(parser)
+        // TextParser is expensive to create: `NLPService` caches it
+        self.nlp_service.get(locale)
+    }
+
+    /// Returns the text parser for the language set in the document, if any
+    fn get_document_text_parser(&self, doc: &Document) -> Option<Arc<TextParser>> {
+        let language_property = self.language_property.as_ref()?;
+        let language = doc.inner.get(language_property)?;
+
+        let language = match language
+            .as_str()
+            .and_then(|language| LanguageDTO::from_str(language).ok())
+        {
+            Some(language) => language,
+            None => {
+                warn!(
+                    ?language,
+                    "Invalid document language. Fallback to the field language"
+                );
+                return None;
+            }
+        };
+
+        Some(self.get_text_parser(language.into()))
     }
 
     pub async fn register_fields(
@@ -248,8 +651,41 @@ impl CollectionWriter {
         sender: OperationSender,
         hooks_runtime: Arc<HooksRuntime>,
     ) -> Result<()> {
+        self.add_field(
+            field_id,
+            field_name.clone(),
+            &typed_field,
+            embedding_sender,
+            hooks_runtime,
+        )
+        .await;
+
+        sender
+            .send(WriteOperation::Collection(
+                self.id.clone(),
+                CollectionWriteOperation::CreateField {
+                    field_id,
+                    field_name,
+                    field: typed_field,
+                },
+            ))
+            .await
+            .context("Cannot sent creation field")?;
+        info!("Field created");
+
+        Ok(())
+    }
+
+    async fn add_field(
+        &self,
+        field_id: FieldId,
+        field_name: String,
+        typed_field: &TypedField,
+        embedding_sender: tokio::sync::mpsc::Sender<EmbeddingCalculationRequest>,
+        hooks_runtime: Arc<HooksRuntime>,
+    ) {
         let mut w = self.fields.write().await;
-        match &typed_field {
+        match typed_field {
             TypedField::Embedding(embedding_field) => {
                 w.insert(
                     field_id,
@@ -261,6 +697,7 @@ impl CollectionWriter {
                             embedding_field.document_fields.clone(),
                             embedding_sender,
                             hooks_runtime,
+                            self.chunker.clone(),
                             self.id.clone(),
                             field_id,
                         ),
@@ -276,6 +713,7 @@ impl CollectionWriter {
                         ValueType::Scalar(ScalarType::String),
                         CollectionField::new_string(
                             parser,
+                            self.infix_fields.contains(&field_name),
                             self.id.clone(),
                             field_id,
                             field_name.clone(),
@@ -283,6 +721,16 @@ impl CollectionWriter {
                     ),
                 );
             }
+            TypedField::Keyword => {
+                w.insert(
+                    field_id,
+                    (
+                        field_name.clone(),
+                        ValueType::Scalar(ScalarType::String),
+                        CollectionField::new_keyword(self.id.clone(), field_id, field_name.clone()),
+                    ),
+                );
+            }
             TypedField::Number => {
                 w.insert(
                     field_id,
@@ -293,6 +741,20 @@ impl CollectionWriter {
                     ),
                 );
             }
+            TypedField::Datetime => {
+                w.insert(
+                    field_id,
+                    (
+                        field_name.clone(),
+                        ValueType::Scalar(ScalarType::Number),
+                        CollectionField::new_datetime(
+                            self.id.clone(),
+                            field_id,
+                            field_name.clone(),
+                        ),
+                    ),
+                );
+            }
             TypedField::Bool => {
                 w.insert(
                     field_id,
@@ -304,22 +766,6 @@ impl CollectionWriter {
                 );
             }
         }
-        drop(w);
-
-        sender
-            .send(WriteOperation::Collection(
-                self.id.clone(),
-                CollectionWriteOperation::CreateField {
-                    field_id,
-                    field_name,
-                    field: typed_field,
-                },
-            ))
-            .await
-            .context("Cannot sent creation field")?;
-        info!("Field created");
-
-        Ok(())
     }
 
     #[instrument(skip(self, doc, sender, hooks_runtime))]
@@ -337,6 +783,14 @@ impl CollectionWriter {
         if let Some(field_id) = field_id_by_name.get(DEFAULT_EMBEDDING_FIELD_NAME) {
             field_ids.push(*field_id);
         }
+        for (field_name, field) in &self.schema {
+            if field.field_type != SchemaFieldType::Embedding {
+                continue;
+            }
+            if let Some(field_id) = field_id_by_name.get(field_name) {
+                field_ids.push(*field_id);
+            }
+        }
         drop(field_id_by_name);
 
         for (field_name, value_type) in schema {
@@ -349,6 +803,20 @@ impl CollectionWriter {
                 continue;
             }
 
+            let typed_field = match self.schema.get(&field_name) {
+                Some(field) => match self.schema_to_typed_field(&field_name, field) {
+                    Some(typed_field) => Some(typed_field),
+                    None => continue,
+                },
+                None => match self.unknown_fields {
+                    UnknownFieldsPolicy::Index => {
+                        self.value_to_typed_field(&field_name, value_type.clone())
+                    }
+                    // The documents with unknown fields are rejected before
+                    UnknownFieldsPolicy::Ignore | UnknownFieldsPolicy::Reject => continue,
+                },
+            };
+
             let field_id = self
                 .field_id_generator
                 .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
@@ -360,7 +828,7 @@ impl CollectionWriter {
             field_ids.push(field_id);
 
             // @todo: add support to other types
-            if let Some(typed_field) = self.value_to_typed_field(value_type.clone()) {
+            if let Some(typed_field) = typed_field {
                 self.create_field(
                     field_id,
                     field_name,
@@ -379,18 +847,230 @@ impl CollectionWriter {
         Ok(field_ids)
     }
 
+    /// If `if_version` is set, nothing is deleted unless every document has that version
     pub async fn delete_documents(
         &self,
         doc_ids: Vec<String>,
+        if_version: Option<u64>,
+        document_storage: &DocumentStorage,
         sender: OperationSender,
-    ) -> Result<()> {
-        let doc_ids = self
+    ) -> Result<usize> {
+        let mut doc_id_storage = self.doc_id_storage.write().await;
+        for doc_id in &doc_ids {
+            check_version(&doc_id_storage, doc_id, if_version)?;
+        }
+        self.delete_document_ids(&mut doc_id_storage, doc_ids, document_storage, sender)
+            .await
+    }
+
+    /// Deletes the documents matching all the filters.
+    /// The stored documents are scanned: the write side doesn't keep the indexes.
+    /// So every filter is checked upfront, and it fails with `UnsupportedFilterError`
+    /// on a filter the scan cannot apply, instead of matching nothing.
+    pub async fn delete_documents_by_filter(
+        &self,
+        where_filter: HashMap<String, Filter>,
+        document_storage: &DocumentStorage,
+        sender: OperationSender,
+    ) -> Result<usize> {
+        if where_filter.is_empty() {
+            bail!("The filter cannot be empty");
+        }
+        self.check_filter(&where_filter).await?;
+
+        // The scan doesn't block the inserts: the lock is taken only to delete
+        let ids: Vec<(String, DocumentId)> = self
             .doc_id_storage
-            .write()
+            .read()
+            .await
+            .iter()
+            .map(|(id, doc_id)| (id.clone(), *doc_id))
+            .collect();
+
+        let mut candidates = vec![];
+        let mut not_stored = vec![];
+        for chunk in ids.chunks(DELETE_BY_FILTER_BATCH_SIZE) {
+            let (matching, missing) = self
+                .matching_documents(chunk, &where_filter, document_storage)
+                .await?;
+            candidates.extend(matching);
+            not_stored.extend(missing);
+        }
+
+        let mut doc_id_storage = self.doc_id_storage.write().await;
+
+        // The documents replaced during the scan are checked again
+        let mut doc_ids_to_delete = vec![];
+        let mut replaced = vec![];
+        for (id, doc_id) in candidates {
+            match doc_id_storage.get_document_id(&id) {
+                Some(current) if current == doc_id => doc_ids_to_delete.push(id),
+                Some(current) => replaced.push((id, current)),
+                None => {}
+            }
+        }
+        for (id, doc_id) in not_stored {
+            match doc_id_storage.get_document_id(&id) {
+                Some(current) if current == doc_id => return Err(not_stored_error(&id)),
+                Some(current) => replaced.push((id, current)),
+                None => {}
+            }
+        }
+
+        let (matching, missing) = self
+            .matching_documents(&replaced, &where_filter, document_storage)
+            .await?;
+        if let Some((id, _)) = missing.first() {
+            return Err(not_stored_error(id));
+        }
+        doc_ids_to_delete.extend(matching.into_iter().map(|(id, _)| id));
+
+        self.delete_document_ids(
+            &mut doc_id_storage,
+            doc_ids_to_delete,
+            document_storage,
+            sender,
+        )
+        .await
+    }
+
+    /// Splits `ids` in the documents whose stored copy matches all the filters
+    /// and the documents without a stored copy
+    async fn matching_documents(
+        &self,
+        ids: &[(String, DocumentId)],
+        where_filter: &HashMap<String, Filter>,
+        document_storage: &DocumentStorage,
+    ) -> Result<(Vec<(String, DocumentId)>, Vec<(String, DocumentId)>)> {
+        let docs = document_storage
+            .get_documents_by_ids(ids.iter().map(|(_, doc_id)| *doc_id).collect())
+            .await
+            .context("Cannot read the stored documents")?;
+
+        let mut matching = vec![];
+        let mut missing = vec![];
+        for ((id, doc_id), doc) in ids.iter().zip(docs) {
+            let Some(doc) = doc else {
+                missing.push((id.clone(), *doc_id));
+                continue;
+            };
+            let doc: Document =
+                serde_json::from_str(doc.inner.get()).context("Cannot parse stored document")?;
+            if self.matches_filter(&doc.into_flatten(), where_filter) {
+                matching.push((id.clone(), *doc_id));
+            }
+        }
+
+        Ok((matching, missing))
+    }
+
+    async fn check_filter(&self, where_filter: &HashMap<String, Filter>) -> Result<()> {
+        let field_id_by_name = self.field_id_by_name.read().await;
+        let fields = self.fields.read().await;
+        for (field_name, filter) in where_filter {
+            let value_type = field_id_by_name
+                .get(field_name)
+                .and_then(|field_id| fields.get(field_id))
+                .map(|(_, value_type, _)| value_type)
+                .with_context(|| format!("Unknown field name {}", field_name))?;
+
+            let supported = matches!(
+                (filter, value_type),
+                (Filter::Number(_), ValueType::Scalar(ScalarType::Number))
+                    | (Filter::Bool(_), ValueType::Scalar(ScalarType::Boolean))
+            );
+            if !supported {
+                let filter = match filter {
+                    Filter::Number(_) => "number",
+                    Filter::Bool(_) => "boolean",
+                };
+                return Err(UnsupportedFilterError {
+                    field: field_name.clone(),
+                    filter,
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn matches_filter(
+        &self,
+        doc: &FlattenDocument,
+        where_filter: &HashMap<String, Filter>,
+    ) -> bool {
+        where_filter.iter().all(|(field_name, filter)| {
+            let Some(value) = doc.get(field_name) else {
+                return false;
+            };
+
+            match filter {
+                Filter::Bool(filter_bool) => value.as_bool() == Some(*filter_bool),
+                Filter::Number(filter_number) => {
+                    let is_datetime = self
+                        .schema
+                        .get(field_name)
+                        .is_some_and(|field| field.field_type == SchemaFieldType::Datetime);
+                    let number = if is_datetime {
+                        value
+                            .as_str()
+                            .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+                            .map(|value| Number::from(value.timestamp_millis()))
+                    } else {
+                        Number::try_from(value).ok()
+                    };
+                    number.is_some_and(|number| filter_number.matches(&number))
+                }
+            }
+        })
+    }
+
+    /// Deletes the documents expired before now
+    pub async fn delete_expired_documents(
+        &self,
+        document_storage: &DocumentStorage,
+        sender: OperationSender,
+    ) -> Result<usize> {
+        let mut doc_id_storage = self.doc_id_storage.write().await;
+
+        let now = Utc::now().timestamp_millis();
+        let expired: Vec<String> = self
+            .expirations
+            .read()
             .await
-            .remove_document_id(doc_ids);
+            .expired(now)
+            .cloned()
+            .collect();
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        info!(coll_id= ?self.id, count = expired.len(), "Deleting expired documents");
+        self.delete_document_ids(&mut doc_id_storage, expired, document_storage, sender)
+            .await
+    }
+
+    async fn delete_document_ids(
+        &self,
+        doc_id_storage: &mut DocIdStorage,
+        doc_ids: Vec<String>,
+        document_storage: &DocumentStorage,
+        sender: OperationSender,
+    ) -> Result<usize> {
+        let mut expirations = self.expirations.write().await;
+        for doc_id in &doc_ids {
+            expirations.remove(doc_id);
+        }
+        drop(expirations);
+
+        let doc_ids = doc_id_storage.remove_document_id(doc_ids);
         info!(coll_id= ?self.id, ?doc_ids, "Deleting documents");
 
+        for doc_id in &doc_ids {
+            document_storage.delete_document(doc_id).await?;
+        }
+
         let doc_ids_len = doc_ids.len();
 
         sender
@@ -403,6 +1083,98 @@ impl CollectionWriter {
         self.collection_document_count
             .fetch_sub(doc_ids_len as u64, std::sync::atomic::Ordering::Relaxed);
 
+        Ok(doc_ids_len)
+    }
+
+    /// Applies an operation of the side channel sent after the last commit.
+    /// The commit is not atomic with the sends, so the operation may be applied already.
+    pub async fn replay(
+        &self,
+        op: CollectionWriteOperation,
+        document_storage: &DocumentStorage,
+        hooks_runtime: Arc<HooksRuntime>,
+    ) -> Result<()> {
+        match op {
+            CollectionWriteOperation::InsertDocument { doc_id, doc } => {
+                let doc_id_str = doc.id.clone().context("Document id is not a string")?;
+
+                let mut doc_id_storage = self.doc_id_storage.write().await;
+                // The replaced document is deleted by the next operations
+                if doc_id_storage.get_document_id(&doc_id_str).is_none() {
+                    self.collection_document_count
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                }
+                // The expiration, if any, is set by the next operations
+                self.expirations.write().await.remove(&doc_id_str);
+                doc_id_storage.insert_document_id(doc_id_str, doc_id);
+                document_storage
+                    .add_document(doc_id, doc)
+                    .await
+                    .context("Cannot store document")?;
+            }
+            CollectionWriteOperation::DeleteDocuments { doc_ids } => {
+                let docs = document_storage
+                    .get_documents_by_ids(doc_ids.clone())
+                    .await
+                    .context("Cannot get the deleted documents")?;
+
+                let mut doc_id_storage = self.doc_id_storage.write().await;
+                let mut expirations = self.expirations.write().await;
+                for (doc_id, doc) in doc_ids.into_iter().zip(docs) {
+                    // A replaced document has its id pointing to the new version
+                    if let Some(doc_id_str) = doc.and_then(|doc| doc.id) {
+                        if doc_id_storage.get_document_id(&doc_id_str) == Some(doc_id) {
+                            doc_id_storage.remove_document_id(vec![doc_id_str.clone()]);
+                            expirations.remove(&doc_id_str);
+                            self.collection_document_count
+                                .fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+                        }
+                    }
+                    document_storage.delete_document(&doc_id).await?;
+                }
+            }
+            CollectionWriteOperation::SetExpiration { doc_id, expires_at } => {
+                let doc = document_storage
+                    .get_documents_by_ids(vec![doc_id])
+                    .await
+                    .context("Cannot get the expiring document")?
+                    .pop()
+                    .flatten();
+                if let Some(doc_id_str) = doc.and_then(|doc| doc.id) {
+                    self.expirations
+                        .write()
+                        .await
+                        .insert(doc_id_str, expires_at);
+                }
+            }
+            CollectionWriteOperation::CreateField {
+                field_id,
+                field_name,
+                field,
+            } => {
+                let mut field_id_by_name = self.field_id_by_name.write().await;
+                if field_id_by_name.contains_key(&field_name) {
+                    return Ok(());
+                }
+                field_id_by_name.insert(field_name.clone(), field_id);
+                drop(field_id_by_name);
+
+                self.field_id_generator
+                    .fetch_max(field_id.0 + 1, std::sync::atomic::Ordering::Relaxed);
+                self.add_field(
+                    field_id,
+                    field_name,
+                    &field,
+                    self.embedding_sender.clone(),
+                    hooks_runtime,
+                )
+                .await;
+            }
+            // The indexes are on the read side only
+            CollectionWriteOperation::Index(..)
+            | CollectionWriteOperation::CopyEmbedding { .. } => {}
+        }
+
         Ok(())
     }
 
@@ -440,6 +1212,27 @@ impl CollectionWriter {
             description: self.description.clone(),
             write_api_key: self.write_api_key.0.expose_secret().clone(),
             default_language: self.default_language,
+            field_languages: self
+                .field_languages
+                .iter()
+                .map(|(k, v)| (k.clone(), *v))
+                .collect(),
+            language_property: self.language_property.clone(),
+            infix_fields: self.infix_fields.iter().cloned().collect(),
+            schema: self
+                .schema
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            unknown_fields: self.unknown_fields,
+            ttl: self.ttl,
+            expirations: self
+                .expirations
+                .read()
+                .await
+                .iter()
+                .map(|(k, v)| (k.clone(), v))
+                .collect(),
             fields,
             document_count: self
                 .collection_document_count
@@ -461,12 +1254,7 @@ impl CollectionWriter {
         Ok(())
     }
 
-    pub async fn load(
-        &mut self,
-        path: PathBuf,
-        hooks_runtime: Arc<HooksRuntime>,
-        nlp_service: Arc<NLPService>,
-    ) -> Result<()> {
+    pub async fn load(&mut self, path: PathBuf, hooks_runtime: Arc<HooksRuntime>) -> Result<()> {
         let dump: CollectionDump = BufferedFile::open(path.join("info.json"))
             .context("Cannot open info.json file")?
             .read_json_data()
@@ -478,6 +1266,13 @@ impl CollectionWriter {
         self.description = dump.description;
         self.write_api_key = ApiKey(Secret::new(dump.write_api_key));
         self.default_language = dump.default_language;
+        self.field_languages = dump.field_languages.into_iter().collect();
+        self.language_property = dump.language_property;
+        self.infix_fields = dump.infix_fields.into_iter().collect();
+        self.schema = dump.schema.into_iter().collect();
+        self.unknown_fields = dump.unknown_fields;
+        self.ttl = dump.ttl;
+        self.expirations = RwLock::new(dump.expirations.into_iter().collect());
         self.field_id_by_name = RwLock::new(dump.field_id_by_name.into_iter().collect());
         self.doc_id_storage = RwLock::new(DocIdStorage::load(dump.doc_id_storage_path)?);
 
@@ -498,16 +1293,25 @@ impl CollectionWriter {
                 SerializedFieldIndexer::String(locale) => (
                     ValueType::Scalar(ScalarType::String),
                     CollectionField::new_string(
-                        nlp_service.get(locale),
+                        self.nlp_service.get(locale),
+                        self.infix_fields.contains(&field_name),
                         self.id.clone(),
                         field_id,
                         field_name.clone(),
                     ),
                 ),
+                SerializedFieldIndexer::Keyword => (
+                    ValueType::Scalar(ScalarType::String),
+                    CollectionField::new_keyword(self.id.clone(), field_id, field_name.clone()),
+                ),
                 SerializedFieldIndexer::Number => (
                     ValueType::Scalar(ScalarType::Number),
                     CollectionField::new_number(self.id.clone(), field_id, field_name.clone()),
                 ),
+                SerializedFieldIndexer::Datetime => (
+                    ValueType::Scalar(ScalarType::Number),
+                    CollectionField::new_datetime(self.id.clone(), field_id, field_name.clone()),
+                ),
                 SerializedFieldIndexer::Bool => (
                     ValueType::Scalar(ScalarType::Boolean),
                     CollectionField::new_bool(self.id.clone(), field_id, field_name.clone()),
@@ -519,6 +1323,7 @@ impl CollectionWriter {
                         fields,
                         self.embedding_sender.clone(),
                         hooks_runtime.clone(),
+                        self.chunker.clone(),
                         self.id.clone(),
                         field_id,
                     ),
@@ -548,9 +1353,74 @@ struct CollectionDumpV1 {
     description: Option<String>,
     write_api_key: String,
     default_language: LanguageDTO,
+    #[serde(default)]
+    field_languages: Vec<(String, LanguageDTO)>,
+    #[serde(default)]
+    language_property: Option<String>,
+    #[serde(default)]
+    infix_fields: Vec<String>,
+    #[serde(default)]
+    schema: Vec<(String, SchemaField)>,
+    #[serde(default)]
+    unknown_fields: UnknownFieldsPolicy,
+    #[serde(default)]
+    ttl: Option<Duration>,
+    /// The expiration, in milliseconds, by document id
+    #[serde(default)]
+    expirations: Vec<(String, i64)>,
     fields: Vec<(String, SerializedFieldIndexer)>,
     document_count: u64,
     field_id_generator: u16,
     field_id_by_name: Vec<(String, FieldId)>,
     doc_id_storage_path: PathBuf,
 }
+
+/// Returns `None` if the document was inserted before the write side stored the documents
+async fn get_stored_document(
+    document_storage: &DocumentStorage,
+    doc_id: DocumentId,
+) -> Result<Option<Document>> {
+    let raw = document_storage
+        .get_documents_by_ids(vec![doc_id])
+        .await?
+        .pop()
+        .flatten();
+
+    raw.map(|raw| serde_json::from_str(raw.inner.get()).context("Cannot parse stored document"))
+        .transpose()
+}
+
+/// Fails with `VersionConflict` if `if_version` is set and the document has another version
+fn check_version(
+    doc_id_storage: &DocIdStorage,
+    doc_id_str: &str,
+    if_version: Option<u64>,
+) -> Result<()> {
+    let Some(expected) = if_version else {
+        return Ok(());
+    };
+
+    let current = doc_id_storage
+        .get_document_id(doc_id_str)
+        .map(|doc_id| doc_id.version())
+        .unwrap_or(0);
+    if current != expected {
+        return Err(VersionConflict {
+            id: doc_id_str.to_string(),
+            expected,
+            current,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Deleting only the documents that can be read would be a partial delete:
+/// the documents inserted before the write side stored them cannot be filtered
+fn not_stored_error(id: &str) -> anyhow::Error {
+    anyhow!(
+        "Document {:?} has no stored copy and cannot be filtered: delete it by id or insert it again",
+        id
+    )
+}
@@ -15,6 +15,14 @@ pub struct DocIdStorage {
 }
 
 impl DocIdStorage {
+    pub fn get_document_id(&self, doc_id: &str) -> Option<DocumentId> {
+        self.document_id.get(doc_id).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &DocumentId)> {
+        self.document_id.iter()
+    }
+
     pub fn remove_document_id(&mut self, doc_id: Vec<String>) -> Vec<DocumentId> {
         doc_id
             .into_iter()
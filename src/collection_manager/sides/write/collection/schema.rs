@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use chrono::DateTime;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{
+    collection_manager::dto::{SchemaField, SchemaFieldType, UnknownFieldsPolicy},
+    types::FlattenDocument,
+};
+
+use super::EXPIRES_AT_PROPERTY;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SchemaError {
+    #[error("Invalid schema for field \"{field}\": {reason}")]
+    InvalidSchema { field: String, reason: &'static str },
+    #[error("Field \"{field}\" is not declared in the collection schema")]
+    UnknownField { field: String },
+    #[error("Field \"{field}\" expects a {expected} value, found {found}")]
+    TypeMismatch {
+        field: String,
+        expected: SchemaFieldType,
+        found: &'static str,
+    },
+    #[error("Field \"{field}\" is not a RFC 3339 datetime: \"{value}\"")]
+    InvalidDatetime { field: String, value: String },
+    #[error("Field \"{field}\" is calculated from other fields: it cannot be set")]
+    CalculatedField { field: String },
+}
+
+/// Checks the schema given on the collection creation
+pub fn validate_schema(schema: &HashMap<String, SchemaField>) -> Result<(), SchemaError> {
+    for (field, schema_field) in schema {
+        let invalid = |reason| {
+            Err(SchemaError::InvalidSchema {
+                field: field.clone(),
+                reason,
+            })
+        };
+
+        if field == "id" {
+            return invalid("the document id is always a string");
+        }
+        if schema_field.language.is_some() && schema_field.field_type != SchemaFieldType::Text {
+            return invalid("only the text fields have a language");
+        }
+        match schema_field.field_type {
+            SchemaFieldType::Embedding => {
+                if schema_field.document_fields.is_empty() {
+                    return invalid("an embedding field needs the document fields to embed");
+                }
+                if !schema_field.indexed {
+                    return invalid("an embedding field is always indexed");
+                }
+            }
+            _ => {
+                if schema_field.model.is_some() || !schema_field.document_fields.is_empty() {
+                    return invalid("only the embedding fields have a model and document fields");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the document values against the collection schema
+pub fn validate_document(
+    schema: &HashMap<String, SchemaField>,
+    unknown_fields: UnknownFieldsPolicy,
+    doc: &FlattenDocument,
+) -> Result<(), SchemaError> {
+    for (field, value) in doc.iter() {
+        if field == "id" || value.is_null() {
+            continue;
+        }
+
+        let schema_field = match schema.get(field) {
+            Some(schema_field) => schema_field,
+            // A reserved property: it is checked when the expiration is read
+            None if field == EXPIRES_AT_PROPERTY => continue,
+            None if unknown_fields == UnknownFieldsPolicy::Reject => {
+                return Err(SchemaError::UnknownField {
+                    field: field.clone(),
+                })
+            }
+            None => continue,
+        };
+
+        let type_mismatch = || SchemaError::TypeMismatch {
+            field: field.clone(),
+            expected: schema_field.field_type,
+            found: value_kind(value),
+        };
+        match schema_field.field_type {
+            SchemaFieldType::Text if !value.is_string() => return Err(type_mismatch()),
+            SchemaFieldType::Keyword if !is_string_or_strings(value) => return Err(type_mismatch()),
+            SchemaFieldType::Number if !value.is_number() => return Err(type_mismatch()),
+            SchemaFieldType::Bool if !value.is_boolean() => return Err(type_mismatch()),
+            SchemaFieldType::Datetime => {
+                let value = value.as_str().ok_or_else(type_mismatch)?;
+                if DateTime::parse_from_rfc3339(value).is_err() {
+                    return Err(SchemaError::InvalidDatetime {
+                        field: field.clone(),
+                        value: value.to_string(),
+                    });
+                }
+            }
+            SchemaFieldType::Embedding => {
+                return Err(SchemaError::CalculatedField {
+                    field: field.clone(),
+                })
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// The keyword fields accept a string or an array of strings
+fn is_string_or_strings(value: &Value) -> bool {
+    match value {
+        Value::String(_) => true,
+        Value::Array(values) => values.iter().all(Value::is_string),
+        _ => false,
+    }
+}
+
+fn value_kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::types::Document;
+
+    use super::*;
+
+    fn schema() -> HashMap<String, SchemaField> {
+        serde_json::from_value(json!({
+            "price": { "type": "number" },
+            "sku": { "type": "keyword" },
+            "published_at": { "type": "datetime" },
+            "author.name": { "type": "text", "language": "Italian" },
+            "notes": { "type": "text", "indexed": false },
+        }))
+        .unwrap()
+    }
+
+    fn flatten(doc: Value) -> FlattenDocument {
+        Document::try_from(doc).unwrap().into_flatten()
+    }
+
+    #[test]
+    fn test_validate_schema() {
+        assert_eq!(validate_schema(&schema()), Ok(()));
+
+        let schema: HashMap<String, SchemaField> = serde_json::from_value(json!({
+            "price": { "type": "number", "language": "Italian" },
+        }))
+        .unwrap();
+        assert!(matches!(
+            validate_schema(&schema),
+            Err(SchemaError::InvalidSchema { .. })
+        ));
+
+        let schema: HashMap<String, SchemaField> = serde_json::from_value(json!({
+            "vector": { "type": "embedding" },
+        }))
+        .unwrap();
+        assert!(matches!(
+            validate_schema(&schema),
+            Err(SchemaError::InvalidSchema { .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_document() {
+        let schema = schema();
+
+        let doc = flatten(json!({
+            "id": "1",
+            "price": 10,
+            "sku": "XJ-45201-B",
+            "published_at": "2024-10-18T10:00:00Z",
+            "author": { "name": "Dante" },
+            "notes": null,
+            "expiresAt": "2024-11-18T10:00:00Z",
+            "unknown": true,
+        }));
+        assert_eq!(
+            validate_document(&schema, UnknownFieldsPolicy::Index, &doc),
+            Ok(())
+        );
+        assert_eq!(
+            validate_document(&schema, UnknownFieldsPolicy::Reject, &doc),
+            Err(SchemaError::UnknownField {
+                field: "unknown".to_string()
+            })
+        );
+
+        let doc = flatten(json!({ "id": "1", "price": "10" }));
+        assert_eq!(
+            validate_document(&schema, UnknownFieldsPolicy::Index, &doc),
+            Err(SchemaError::TypeMismatch {
+                field: "price".to_string(),
+                expected: SchemaFieldType::Number,
+                found: "string",
+            })
+        );
+
+        let doc = flatten(json!({ "id": "1", "sku": ["XJ-45201", "XJ-45201-B"] }));
+        assert_eq!(
+            validate_document(&schema, UnknownFieldsPolicy::Index, &doc),
+            Ok(())
+        );
+
+        let doc = flatten(json!({ "id": "1", "sku": ["XJ-45201", 45201] }));
+        assert_eq!(
+            validate_document(&schema, UnknownFieldsPolicy::Index, &doc),
+            Err(SchemaError::TypeMismatch {
+                field: "sku".to_string(),
+                expected: SchemaFieldType::Keyword,
+                found: "array",
+            })
+        );
+
+        // Only the keyword fields accept an array
+        let doc = flatten(json!({ "id": "1", "notes": ["discontinued", "sold out"] }));
+        assert_eq!(
+            validate_document(&schema, UnknownFieldsPolicy::Index, &doc),
+            Err(SchemaError::TypeMismatch {
+                field: "notes".to_string(),
+                expected: SchemaFieldType::Text,
+                found: "array",
+            })
+        );
+
+        let doc = flatten(json!({ "id": "1", "published_at": "yesterday" }));
+        assert_eq!(
+            validate_document(&schema, UnknownFieldsPolicy::Index, &doc),
+            Err(SchemaError::InvalidDatetime {
+                field: "published_at".to_string(),
+                value: "yesterday".to_string(),
+            })
+        );
+    }
+}
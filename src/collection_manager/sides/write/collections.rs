@@ -7,35 +7,63 @@ use redact::Secret;
 use tokio::sync::{RwLock, RwLockReadGuard};
 use tracing::{info, instrument};
 
+use crate::collection_manager::sides::document_storage::DocumentStorage;
 use crate::collection_manager::sides::hooks::HooksRuntime;
-use crate::collection_manager::sides::write::collection::DEFAULT_EMBEDDING_FIELD_NAME;
-use crate::nlp::NLPService;
+use crate::collection_manager::sides::write::collection::{
+    validate_schema, DEFAULT_EMBEDDING_FIELD_NAME,
+};
+use crate::nlp::{chunker::Chunker, NLPService};
 use crate::{
-    collection_manager::dto::CollectionDTO, file_utils::list_directory_in_path, types::CollectionId,
+    collection_manager::dto::CollectionDTO,
+    file_utils::{list_directory_in_path, BufferedFile},
+    types::CollectionId,
 };
 
 use crate::collection_manager::dto::{
-    ApiKey, CreateCollection, DocumentFields, EmbeddingTypedField, LanguageDTO, TypedField,
+    ApiKey, CreateCollection, DocumentFields, EmbeddingTypedField, LanguageDTO, SchemaFieldType,
+    TypedField,
 };
 
 use super::{collection::CollectionWriter, embedding::EmbeddingCalculationRequest, WriteOperation};
-use super::{CollectionsWriterConfig, OperationSender};
+use super::{CollectionsWriterConfig, OperationSender, INTERNAL_DIR};
+
+const ALIASES_FILE_NAME: &str = "aliases.json";
+
+/// What `set_alias` expects about the current alias
+#[derive(Debug, Clone, Copy)]
+pub enum AliasUpdate {
+    /// The alias must not exist
+    Create,
+    /// The alias must exist
+    Swap,
+    CreateOrSwap,
+}
 
 pub struct CollectionsWriter {
     collections: RwLock<HashMap<CollectionId, CollectionWriter>>,
+    /// Alias -> collection id.
+    /// To avoid deadlocks, this lock is always taken before `collections`.
+    aliases: RwLock<HashMap<CollectionId, CollectionId>>,
     config: CollectionsWriterConfig,
     embedding_sender: tokio::sync::mpsc::Sender<EmbeddingCalculationRequest>,
+    nlp_service: Arc<NLPService>,
+    chunker: Arc<Chunker>,
 }
 
 impl CollectionsWriter {
     pub fn new(
         config: CollectionsWriterConfig,
         embedding_sender: tokio::sync::mpsc::Sender<EmbeddingCalculationRequest>,
+        nlp_service: Arc<NLPService>,
+        chunker: Arc<Chunker>,
     ) -> CollectionsWriter {
         CollectionsWriter {
             collections: Default::default(),
+            aliases: Default::default(),
             config,
             embedding_sender,
+            nlp_service,
+            chunker,
         }
     }
 
@@ -46,10 +74,16 @@ impl CollectionsWriter {
     where
         's: 'coll,
     {
+        let id = self.resolve_alias(id).await;
         let r = self.collections.read().await;
         CollectionWriteLock::try_new(r, id)
     }
 
+    async fn resolve_alias(&self, id: CollectionId) -> CollectionId {
+        let aliases = self.aliases.read().await;
+        aliases.get(&id).cloned().unwrap_or(id)
+    }
+
     pub async fn create_collection(
         &self,
         collection_option: CreateCollection,
@@ -60,6 +94,12 @@ impl CollectionsWriter {
             id,
             description,
             language,
+            field_languages,
+            language_property,
+            infix_fields,
+            schema,
+            unknown_fields,
+            ttl,
             embeddings,
             write_api_key,
             read_api_key,
@@ -67,15 +107,25 @@ impl CollectionsWriter {
 
         info!("Creating collection {:?}", id);
 
+        if id.0 == INTERNAL_DIR {
+            return Err(anyhow!(format!("\"{}\" is a reserved collection id", id.0)));
+        }
+        validate_schema(&schema)?;
+
         let collection = CollectionWriter::new(
             id.clone(),
             description,
             write_api_key,
             language.unwrap_or(LanguageDTO::English),
             self.embedding_sender.clone(),
-        );
-
-        let typed_fields = if !cfg!(feature = "no_auto_embedding_field_on_creation") {
+            self.nlp_service.clone(),
+            self.chunker.clone(),
+        )
+        .with_language_overrides(field_languages, language_property)
+        .with_infix_fields(infix_fields)
+        .with_ttl(ttl);
+
+        let mut typed_fields = if !cfg!(feature = "no_auto_embedding_field_on_creation") {
             let model = embeddings
                 .as_ref()
                 .and_then(|embeddings| embeddings.model.as_ref())
@@ -93,7 +143,26 @@ impl CollectionsWriter {
         } else {
             HashMap::new()
         };
+        for (field_name, field) in &schema {
+            if field.field_type != SchemaFieldType::Embedding {
+                continue;
+            }
+            let model = field
+                .model
+                .as_ref()
+                .unwrap_or(&self.config.default_embedding_model);
+            let typed_field = TypedField::Embedding(EmbeddingTypedField {
+                model: model.0,
+                document_fields: DocumentFields::Properties(field.document_fields.clone()),
+            });
+            typed_fields.insert(field_name.clone(), typed_field);
+        }
+        let collection = collection.with_schema(schema, unknown_fields);
 
+        let aliases = self.aliases.read().await;
+        if aliases.contains_key(&id) {
+            return Err(anyhow!(format!("\"{}\" is already an alias", id.0)));
+        }
         let mut collections = self.collections.write().await;
         if collections.contains_key(&id) {
             // This error should be typed.
@@ -116,10 +185,136 @@ impl CollectionsWriter {
 
         collections.insert(id, collection);
         drop(collections);
+        drop(aliases);
+
+        Ok(())
+    }
+
+    /// Removes the collection with its documents, also from disk.
+    /// A collection pointed by an alias cannot be deleted.
+    pub async fn delete_collection(
+        &self,
+        id: CollectionId,
+        document_storage: &DocumentStorage,
+        sender: OperationSender,
+    ) -> Result<()> {
+        let aliases = self.aliases.read().await;
+        if let Some((alias, _)) = aliases.iter().find(|(_, target)| *target == &id) {
+            return Err(anyhow!(format!(
+                "Collection \"{}\" is pointed by the alias \"{}\": swap or delete it before",
+                id.0, alias.0
+            )));
+        }
+
+        let mut collections = self.collections.write().await;
+        let collection = collections
+            .remove(&id)
+            .with_context(|| format!("Collection \"{}\" not found", id.0))?;
+
+        info!("Deleting collection {:?}", id);
+
+        sender
+            .send(WriteOperation::DeleteCollection(id.clone()))
+            .await
+            .context("Cannot send delete collection")?;
+
+        for doc_id in collection.document_ids().await {
+            document_storage
+                .delete_document(&doc_id)
+                .await
+                .context("Cannot delete document")?;
+        }
+
+        let collection_dir = self.config.data_dir.join(&id.0);
+        match tokio::fs::remove_dir_all(&collection_dir).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| {
+                    format!("Cannot remove collection directory {:?}", collection_dir)
+                });
+            }
+            _ => {}
+        }
+
+        drop(collections);
+        drop(aliases);
 
         Ok(())
     }
 
+    /// Creates the alias or points it to another collection, as `update` requires.
+    /// The check and the change happen under the same lock.
+    /// The read side switches in a single operation: no request sees a missing collection.
+    /// Returns the collection pointed before.
+    pub async fn set_alias(
+        &self,
+        alias: CollectionId,
+        collection_id: CollectionId,
+        update: AliasUpdate,
+        sender: OperationSender,
+    ) -> Result<Option<CollectionId>> {
+        let mut aliases = self.aliases.write().await;
+        let collections = self.collections.read().await;
+
+        if collections.contains_key(&alias) {
+            return Err(anyhow!(format!(
+                "\"{}\" is a collection: it cannot be an alias",
+                alias.0
+            )));
+        }
+        if !collections.contains_key(&collection_id) {
+            return Err(anyhow!(format!(
+                "Collection \"{}\" not found",
+                collection_id.0
+            )));
+        }
+        match (aliases.get(&alias), update) {
+            (None, AliasUpdate::Swap) => {
+                return Err(anyhow!(format!("Alias \"{}\" not found", alias.0)))
+            }
+            (Some(_), AliasUpdate::Create) => {
+                return Err(anyhow!(format!("Alias \"{}\" already exists", alias.0)))
+            }
+            _ => {}
+        }
+
+        info!(?alias, ?collection_id, "Setting alias");
+
+        sender
+            .send(WriteOperation::SetAlias {
+                alias: alias.clone(),
+                collection_id: collection_id.clone(),
+            })
+            .await
+            .context("Cannot send set alias")?;
+        let previous = aliases.insert(alias, collection_id);
+
+        drop(collections);
+        drop(aliases);
+
+        Ok(previous)
+    }
+
+    pub async fn delete_alias(&self, alias: CollectionId, sender: OperationSender) -> Result<()> {
+        let mut aliases = self.aliases.write().await;
+        if !aliases.contains_key(&alias) {
+            return Err(anyhow!(format!("Alias \"{}\" not found", alias.0)));
+        }
+
+        sender
+            .send(WriteOperation::DeleteAlias {
+                alias: alias.clone(),
+            })
+            .await
+            .context("Cannot send delete alias")?;
+        aliases.remove(&alias);
+
+        Ok(())
+    }
+
+    pub async fn list_aliases(&self) -> HashMap<CollectionId, CollectionId> {
+        self.aliases.read().await.clone()
+    }
+
     pub async fn list(&self) -> Vec<CollectionDTO> {
         let collections = self.collections.read().await;
 
@@ -133,10 +328,17 @@ impl CollectionsWriter {
     pub async fn commit(&self) -> Result<()> {
         let data_dir = &self.config.data_dir;
 
+        let aliases: Vec<_> = self.aliases.read().await.clone().into_iter().collect();
+
         let collections = self.collections.read().await;
 
         std::fs::create_dir_all(data_dir).context("Cannot create data directory")?;
 
+        BufferedFile::create_or_overwrite(data_dir.join(ALIASES_FILE_NAME))
+            .context("Cannot create aliases file")?
+            .write_json_data(&aliases)
+            .context("Cannot write aliases file")?;
+
         for (collection_id, collection) in collections.iter() {
             let collection_dir = data_dir.join(collection_id.0.clone());
             collection.commit(collection_dir).await?;
@@ -149,18 +351,68 @@ impl CollectionsWriter {
         Ok(())
     }
 
-    #[instrument(skip(self))]
-    pub async fn load(
-        &mut self,
+    /// Deletes the expired documents of every collection
+    pub async fn delete_expired_documents(
+        &self,
+        document_storage: &DocumentStorage,
+        sender: OperationSender,
+    ) -> Result<usize> {
+        let collections = self.collections.read().await;
+
+        let mut deleted = 0;
+        for (collection_id, collection) in collections.iter() {
+            deleted += collection
+                .delete_expired_documents(document_storage, sender.clone())
+                .await
+                .with_context(|| {
+                    format!("Cannot delete the expired documents of {:?}", collection_id)
+                })?;
+        }
+
+        Ok(deleted)
+    }
+
+    /// Applies an operation of the side channel sent after the last commit.
+    /// The collections and the aliases are committed right away,
+    /// so only the operations of the documents and of the fields are applied.
+    pub async fn replay(
+        &self,
+        op: WriteOperation,
+        document_storage: &DocumentStorage,
         hooks_runtime: Arc<HooksRuntime>,
-        nlp_service: Arc<NLPService>,
     ) -> Result<()> {
+        let WriteOperation::Collection(collection_id, op) = op else {
+            return Ok(());
+        };
+
+        let collections = self.collections.read().await;
+        // The collection is deleted after the operation
+        let Some(collection) = collections.get(&collection_id) else {
+            return Ok(());
+        };
+        collection
+            .replay(op, document_storage, hooks_runtime)
+            .await
+            .with_context(|| format!("Cannot replay an operation of {:?}", collection_id))
+    }
+
+    #[instrument(skip(self))]
+    pub async fn load(&mut self, hooks_runtime: Arc<HooksRuntime>) -> Result<()> {
         // `&mut self` isn't needed here
         // but we need to ensure that the method is not called concurrently
         let data_dir = &self.config.data_dir;
 
         info!("Loading collections from disk from {:?}", data_dir);
 
+        let aliases_path = data_dir.join(ALIASES_FILE_NAME);
+        if aliases_path.exists() {
+            let aliases: Vec<(CollectionId, CollectionId)> = BufferedFile::open(aliases_path)
+                .context("Cannot open aliases file")?
+                .read_json_data()
+                .context("Cannot read aliases file")?;
+            *self.aliases.write().await = aliases.into_iter().collect();
+        }
+
         let collection_dirs =
             list_directory_in_path(data_dir).context("Cannot read collection list from disk")?;
 
@@ -180,6 +432,10 @@ impl CollectionsWriter {
                 .file_name()
                 .expect("File name is always given at this point");
             let file_name: String = file_name.to_string_lossy().into();
+            // The write side internals, not a collection
+            if file_name == INTERNAL_DIR {
+                continue;
+            }
 
             let collection_id = CollectionId(file_name);
 
@@ -190,9 +446,11 @@ impl CollectionsWriter {
                 ApiKey(Secret::new("".to_string())),
                 LanguageDTO::English,
                 self.embedding_sender.clone(),
+                self.nlp_service.clone(),
+                self.chunker.clone(),
             );
             collection
-                .load(collection_dir, hooks_runtime.clone(), nlp_service.clone())
+                .load(collection_dir, hooks_runtime.clone())
                 .await?;
 
             self.collections
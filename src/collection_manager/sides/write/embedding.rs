@@ -1,8 +1,8 @@
 use std::{collections::HashMap, sync::Arc};
 
-use anyhow::{Context, Result};
-use tokio::sync::mpsc::Receiver;
-use tracing::{debug, info, warn};
+use dashmap::DashSet;
+use tokio::sync::{mpsc::Receiver, oneshot};
+use tracing::{debug, error, info, warn};
 
 use crate::{
     ai::{AIService, OramaModel},
@@ -19,19 +19,30 @@ use crate::{
 use super::{OperationSender, WriteOperation};
 
 pub struct EmbeddingCalculationRequestInput {
-    pub text: String,
+    /// The chunks of the input text: every chunk has its own embedding
+    pub chunks: Vec<String>,
     pub coll_id: CollectionId,
     pub doc_id: DocumentId,
     pub field_id: FieldId,
     pub op_sender: OperationSender,
+    /// The documents of the field with an embedding in the queue.
+    /// `doc_id` is removed once its embedding is sent.
+    pub pending: Arc<DashSet<DocumentId>>,
 }
 
-pub struct EmbeddingCalculationRequest {
-    pub model: OramaModel,
-    pub input: EmbeddingCalculationRequestInput,
+pub enum EmbeddingCalculationRequest {
+    Calculate {
+        model: OramaModel,
+        input: EmbeddingCalculationRequestInput,
+    },
+    /// Answered when the requests sent before are processed:
+    /// their operations have an offset already.
+    Flush(oneshot::Sender<()>),
 }
 
-async fn process<I>(ai_service: Arc<AIService>, cache: I) -> Result<()>
+/// A failed embedding is logged: its document stays pending,
+/// so the embedding is calculated again when the document is written again
+async fn process<I>(ai_service: Arc<AIService>, cache: I)
 where
     I: Iterator<Item = (OramaModel, Vec<EmbeddingCalculationRequestInput>)>,
 {
@@ -45,44 +56,54 @@ where
             model: model_name.to_string(),
         });
 
-        let text_inputs: Vec<&String> = inputs.iter().map(|input| &input.text).collect();
-        let output = ai_service
-            .embed_passage(model, text_inputs)
-            .await
-            .context("Failed to embed text")?;
+        let text_inputs: Vec<&String> = inputs.iter().flat_map(|input| &input.chunks).collect();
+        let output = match ai_service.embed_passage(model, text_inputs).await {
+            Ok(output) => output,
+            Err(e) => {
+                error!(model_name = ?model_name, "Failed to embed text: {:?}", e);
+                continue;
+            }
+        };
 
         info!("Embedding done");
 
         drop(metric);
 
-        for (input, output) in inputs.into_iter().zip(output.into_iter()) {
+        // The embeddings are in the same order of the chunks
+        let mut output = output.into_iter();
+        for input in inputs {
             let EmbeddingCalculationRequestInput {
+                chunks,
                 doc_id,
                 coll_id,
                 field_id,
                 op_sender,
-                ..
+                pending,
             } = input;
+            let vectors: Vec<_> = output.by_ref().take(chunks.len()).collect();
 
-            op_sender
+            let output = op_sender
                 .send(WriteOperation::Collection(
                     coll_id,
                     CollectionWriteOperation::Index(
                         doc_id,
                         field_id,
-                        DocumentFieldIndexOperation::IndexEmbedding { value: output },
+                        DocumentFieldIndexOperation::IndexEmbedding { vectors, chunks },
                     ),
                 ))
-                .await
-                .unwrap();
+                .await;
+            match output {
+                Ok(()) => {
+                    pending.remove(&doc_id);
+                }
+                Err(e) => error!(?doc_id, "Cannot send the embedding: {:?}", e),
+            }
         }
 
         info!("Embedding sent to the read side");
     }
 
     debug!("Embedding batch processed");
-
-    Ok(())
 }
 
 pub fn start_calculate_embedding_loop(
@@ -107,17 +128,25 @@ pub fn start_calculate_embedding_loop(
                 break;
             }
 
-            EMBEDDING_REQUEST_GAUDGE
-                .create(Empty {})
-                .decrement_by(item_count as u32);
+            let mut flushes = vec![];
             for item in buffer.drain(..) {
-                let EmbeddingCalculationRequest { model, input } = item;
-
-                let inputs = cache.entry(model).or_default();
-                inputs.push(input);
+                match item {
+                    EmbeddingCalculationRequest::Calculate { model, input } => {
+                        EMBEDDING_REQUEST_GAUDGE.create(Empty {}).decrement_by_one();
+
+                        let inputs = cache.entry(model).or_default();
+                        inputs.push(input);
+                    }
+                    EmbeddingCalculationRequest::Flush(done) => flushes.push(done),
+                }
             }
 
-            process(ai_service.clone(), cache.drain()).await.unwrap();
+            process(ai_service.clone(), cache.drain()).await;
+
+            for done in flushes {
+                // The caller may not wait anymore
+                let _ = done.send(());
+            }
         }
 
         warn!("Stop embedding calculation loop");
@@ -6,8 +6,11 @@ use std::{
 
 use anyhow::Result;
 use axum_openapi3::utoipa::{openapi::schema::AnyOfBuilder, PartialSchema, ToSchema};
-use dashmap::DashMap;
+use chrono::DateTime;
+use dashmap::{DashMap, DashSet};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::trace;
 
 use crate::{
     ai::OramaModel,
@@ -19,22 +22,24 @@ use crate::{
         Empty, StringCalculationLabels, EMBEDDING_REQUEST_GAUDGE, PENDING_EMBEDDING_REQUEST_GAUDGE,
         STRING_CALCULATION_METRIC,
     },
-    nlp::{locales::Locale, TextParser},
+    nlp::{chunker::Chunker, locales::Locale, TextParser},
     types::{CollectionId, DocumentId, FlattenDocument, ValueType},
 };
 
 use super::{
     embedding::{EmbeddingCalculationRequest, EmbeddingCalculationRequestInput},
-    CollectionWriteOperation, DocumentFieldIndexOperation, OperationSender, Term, TermStringField,
-    WriteOperation,
+    keyword_term, CollectionWriteOperation, DocumentFieldIndexOperation, OperationSender, Term,
+    TermStringField, WriteOperation, INFIX_MIN_LENGTH, INFIX_TERM_PREFIX,
 };
 
 pub type FieldsToIndex = DashMap<String, (ValueType, CollectionField)>;
 
 pub enum CollectionField {
     Number(NumberField),
+    Datetime(DatetimeField),
     Bool(BoolField),
     String(StringField),
+    Keyword(KeywordField),
     Embedding(EmbeddingField),
 }
 impl CollectionField {
@@ -42,18 +47,32 @@ impl CollectionField {
         CollectionField::Number(NumberField::new(collection_id, field_id, field_name))
     }
 
+    pub fn new_datetime(
+        collection_id: CollectionId,
+        field_id: FieldId,
+        field_name: String,
+    ) -> Self {
+        CollectionField::Datetime(DatetimeField::new(collection_id, field_id, field_name))
+    }
+
+    pub fn new_keyword(collection_id: CollectionId, field_id: FieldId, field_name: String) -> Self {
+        CollectionField::Keyword(KeywordField::new(collection_id, field_id, field_name))
+    }
+
     pub fn new_bool(collection_id: CollectionId, field_id: FieldId, field_name: String) -> Self {
         CollectionField::Bool(BoolField::new(collection_id, field_id, field_name))
     }
 
     pub fn new_string(
         parser: Arc<TextParser>,
+        infix: bool,
         collection_id: CollectionId,
         field_id: FieldId,
         field_name: String,
     ) -> Self {
         CollectionField::String(StringField::new(
             parser,
+            infix,
             collection_id,
             field_id,
             field_name,
@@ -65,6 +84,7 @@ impl CollectionField {
         document_fields: DocumentFields,
         embedding_sender: tokio::sync::mpsc::Sender<EmbeddingCalculationRequest>,
         hooks_runtime: Arc<HooksRuntime>,
+        chunker: Arc<Chunker>,
         collection_id: CollectionId,
         field_id: FieldId,
     ) -> Self {
@@ -73,6 +93,7 @@ impl CollectionField {
             document_fields,
             embedding_sender,
             hooks_runtime,
+            chunker,
             collection_id,
             field_id,
         ))
@@ -84,16 +105,23 @@ impl CollectionField {
         }
     }
 
+    /// `text_parser` overrides the parser of the string fields
     pub async fn get_write_operations(
         &self,
         doc_id: DocumentId,
         doc: &FlattenDocument,
+        text_parser: Option<Arc<TextParser>>,
         sender: OperationSender,
     ) -> Result<()> {
         match self {
             CollectionField::Number(f) => f.get_write_operations(doc_id, doc, sender).await,
+            CollectionField::Datetime(f) => f.get_write_operations(doc_id, doc, sender).await,
             CollectionField::Bool(f) => f.get_write_operations(doc_id, doc, sender).await,
-            CollectionField::String(f) => f.get_write_operations(doc_id, doc, sender).await,
+            CollectionField::String(f) => {
+                f.get_write_operations(doc_id, doc, text_parser, sender)
+                    .await
+            }
+            CollectionField::Keyword(f) => f.get_write_operations(doc_id, doc, sender).await,
             CollectionField::Embedding(f) => f.get_write_operations(doc_id, doc, sender).await,
         }
     }
@@ -101,8 +129,10 @@ impl CollectionField {
     pub fn serialized(&self) -> SerializedFieldIndexer {
         match self {
             CollectionField::Number(f) => f.serialized(),
+            CollectionField::Datetime(f) => f.serialized(),
             CollectionField::Bool(f) => f.serialized(),
             CollectionField::String(f) => f.serialized(),
+            CollectionField::Keyword(f) => f.serialized(),
             CollectionField::Embedding(f) => f.serialized(),
         }
     }
@@ -150,8 +180,10 @@ impl ToSchema for OramaModelSerializable {}
 #[derive(Debug, Serialize, Deserialize)]
 pub enum SerializedFieldIndexer {
     Number,
+    Datetime,
     Bool,
     String(Locale),
+    Keyword,
     Embedding(OramaModelSerializable, DocumentFields),
 }
 
@@ -207,6 +239,58 @@ impl NumberField {
     }
 }
 
+#[derive(Debug)]
+pub struct DatetimeField {
+    collection_id: CollectionId,
+    field_id: FieldId,
+    field_name: String,
+}
+
+impl DatetimeField {
+    pub fn new(collection_id: CollectionId, field_id: FieldId, field_name: String) -> Self {
+        Self {
+            collection_id,
+            field_id,
+            field_name,
+        }
+    }
+
+    async fn get_write_operations(
+        &self,
+        doc_id: DocumentId,
+        doc: &FlattenDocument,
+        sender: OperationSender,
+    ) -> Result<()> {
+        // The value is already validated against the collection schema
+        let value = doc
+            .get(&self.field_name)
+            .and_then(|v| v.as_str())
+            .and_then(|v| DateTime::parse_from_rfc3339(v).ok());
+
+        let value = match value {
+            None => return Ok(()),
+            Some(value) => Number::from(value.timestamp_millis()),
+        };
+
+        let op = WriteOperation::Collection(
+            self.collection_id.clone(),
+            CollectionWriteOperation::Index(
+                doc_id,
+                self.field_id,
+                DocumentFieldIndexOperation::IndexNumber { value },
+            ),
+        );
+
+        sender.send(op).await?;
+
+        Ok(())
+    }
+
+    fn serialized(&self) -> SerializedFieldIndexer {
+        SerializedFieldIndexer::Datetime
+    }
+}
+
 #[derive(Debug)]
 pub struct BoolField {
     collection_id: CollectionId,
@@ -268,16 +352,20 @@ pub struct StringField {
     field_id: FieldId,
     field_name: String,
     parser: Arc<TextParser>,
+    /// Indexes also the substrings of the tokens
+    infix: bool,
 }
 impl StringField {
     pub fn new(
         parser: Arc<TextParser>,
+        infix: bool,
         collection_id: CollectionId,
         field_id: FieldId,
         field_name: String,
     ) -> Self {
         Self {
             parser,
+            infix,
             collection_id,
             field_id,
             field_name,
@@ -288,6 +376,7 @@ impl StringField {
         &self,
         doc_id: DocumentId,
         doc: &FlattenDocument,
+        text_parser: Option<Arc<TextParser>>,
         sender: OperationSender,
     ) -> Result<()> {
         let metric = STRING_CALCULATION_METRIC.create(StringCalculationLabels {
@@ -301,7 +390,10 @@ impl StringField {
             None => return Ok(()),
             Some(value) => match value.as_str() {
                 None => return Ok(()),
-                Some(value) => self.parser.tokenize_and_stem(value),
+                Some(value) => text_parser
+                    .as_ref()
+                    .unwrap_or(&self.parser)
+                    .tokenize_and_stem(value),
             },
         };
 
@@ -320,6 +412,16 @@ impl StringField {
             // `original` & `stemmeds` appears in the `terms` hashmap with the "same value"
             // ie: the position of the origin and stemmed term are the same.
 
+            if self.infix {
+                for infix in infix_terms(&original) {
+                    terms
+                        .entry(Term(infix))
+                        .or_insert_with(|| TermStringField { positions: vec![] })
+                        .positions
+                        .push(position);
+                }
+            }
+
             let original = Term(original);
             match terms.entry(original) {
                 Entry::Occupied(mut entry) => {
@@ -376,6 +478,105 @@ impl StringField {
     }
 }
 
+#[derive(Debug)]
+pub struct KeywordField {
+    collection_id: CollectionId,
+    field_id: FieldId,
+    field_name: String,
+}
+
+impl KeywordField {
+    pub fn new(collection_id: CollectionId, field_id: FieldId, field_name: String) -> Self {
+        Self {
+            collection_id,
+            field_id,
+            field_name,
+        }
+    }
+
+    async fn get_write_operations(
+        &self,
+        doc_id: DocumentId,
+        doc: &FlattenDocument,
+        sender: OperationSender,
+    ) -> Result<()> {
+        let values = match doc.get(&self.field_name) {
+            None => return Ok(()),
+            Some(value) => string_values(value),
+        };
+
+        // Every value of an array is a keyword on its own
+        let mut terms: HashMap<Term, TermStringField> = Default::default();
+        for (position, value) in values.into_iter().enumerate() {
+            let value = keyword_term(value);
+            if value.is_empty() {
+                continue;
+            }
+            terms
+                .entry(Term(value))
+                .or_insert_with(|| TermStringField { positions: vec![] })
+                .positions
+                .push(position);
+        }
+        if terms.is_empty() {
+            return Ok(());
+        }
+        let field_length = terms.len().min(u16::MAX as usize - 1) as u16;
+
+        let op = WriteOperation::Collection(
+            self.collection_id.clone(),
+            CollectionWriteOperation::Index(
+                doc_id,
+                self.field_id,
+                DocumentFieldIndexOperation::IndexString {
+                    field_length,
+                    terms,
+                },
+            ),
+        );
+
+        sender.send(op).await?;
+
+        Ok(())
+    }
+
+    fn serialized(&self) -> SerializedFieldIndexer {
+        SerializedFieldIndexer::Keyword
+    }
+}
+
+/// The strings of a keyword value: a string or an array of strings.
+/// The other values are not indexed
+fn string_values(value: &Value) -> Vec<&str> {
+    match value {
+        Value::String(value) => vec![value.as_str()],
+        Value::Array(values) => values.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    }
+}
+
+/// Tokens longer than this are not indexed for the infix search:
+/// the number of substrings grows with the square of the length
+const INFIX_MAX_TOKEN_LENGTH: usize = 64;
+
+/// Returns the proper suffixes of the token, marked with `INFIX_TERM_PREFIX`.
+/// A prefix search on them finds the token by any of its substrings.
+fn infix_terms(token: &str) -> Vec<String> {
+    let length = token.chars().count();
+    if length > INFIX_MAX_TOKEN_LENGTH {
+        return vec![];
+    }
+
+    token
+        .char_indices()
+        .enumerate()
+        // The whole token is already indexed
+        .skip(1)
+        .take_while(|(index, _)| length - index >= INFIX_MIN_LENGTH)
+        .map(|(_, (start, _))| format!("{}{}", INFIX_TERM_PREFIX, &token[start..]))
+        .collect()
+}
+
 #[derive(Debug)]
 pub struct EmbeddingField {
     collection_id: CollectionId,
@@ -385,6 +586,9 @@ pub struct EmbeddingField {
     document_fields: DocumentFields,
     embedding_sender: tokio::sync::mpsc::Sender<EmbeddingCalculationRequest>,
     hooks_runtime: Arc<HooksRuntime>,
+    chunker: Arc<Chunker>,
+    /// The documents whose embedding is not sent to the read side yet
+    pending: Arc<DashSet<DocumentId>>,
 }
 
 impl EmbeddingField {
@@ -393,6 +597,7 @@ impl EmbeddingField {
         document_fields: DocumentFields,
         embedding_sender: tokio::sync::mpsc::Sender<EmbeddingCalculationRequest>,
         hooks_runtime: Arc<HooksRuntime>,
+        chunker: Arc<Chunker>,
         collection_id: CollectionId,
         field_id: FieldId,
     ) -> Self {
@@ -401,19 +606,32 @@ impl EmbeddingField {
             document_fields,
             embedding_sender,
             hooks_runtime,
+            chunker,
             collection_id,
             field_id,
+            pending: Default::default(),
         }
     }
 }
 
 impl EmbeddingField {
-    async fn get_write_operations(
+    /// Returns `true` if the embedding of `doc_id` is still in the queue:
+    /// the read side has nothing to copy from it yet.
+    pub fn is_pending(&self, doc_id: DocumentId) -> bool {
+        self.pending.contains(&doc_id)
+    }
+
+    /// Returns `true` if the embedding calculated for `new` would differ from the one of `previous`
+    pub async fn is_input_changed(
         &self,
-        doc_id: DocumentId,
-        doc: &FlattenDocument,
-        sender: OperationSender,
-    ) -> Result<()> {
+        previous: &FlattenDocument,
+        new: &FlattenDocument,
+    ) -> bool {
+        self.get_input(previous).await != self.get_input(new).await
+    }
+
+    /// Returns the text to embed, if any
+    async fn get_input(&self, doc: &FlattenDocument) -> Option<String> {
         let input: String = match &self.document_fields {
             DocumentFields::Properties(v) => v
                 .iter()
@@ -430,7 +648,7 @@ impl EmbeddingField {
 
                 let input: SelectEmbeddingPropertiesReturnType = match hook_exec_result {
                     Some(Ok(input)) => input,
-                    _ => return Ok(()),
+                    _ => return None,
                 };
 
                 match input {
@@ -455,27 +673,50 @@ impl EmbeddingField {
             }
         };
 
-        // The input could be:
-        // - empty: we should skip this (???)
-        // - "normal": it is ok
-        // - "too long": we should chunk it in a smart way
-        // TODO: implement that logic
+        Some(input)
+    }
+
+    async fn get_write_operations(
+        &self,
+        doc_id: DocumentId,
+        doc: &FlattenDocument,
+        sender: OperationSender,
+    ) -> Result<()> {
+        let input = match self.get_input(doc).await {
+            Some(input) => input,
+            None => return Ok(()),
+        };
+
+        // The model truncates the long inputs, so we split them in chunks
+        // and calculate an embedding for each one.
+        let chunks = self.chunker.chunk_text(&input);
+        if chunks.is_empty() {
+            trace!(?doc_id, "Empty input: skip the embedding calculation");
+            return Ok(());
+        }
 
         PENDING_EMBEDDING_REQUEST_GAUDGE
             .create(Empty {})
             .increment_by_one();
-        self.embedding_sender
-            .send(EmbeddingCalculationRequest {
+        self.pending.insert(doc_id);
+        let output = self
+            .embedding_sender
+            .send(EmbeddingCalculationRequest::Calculate {
                 model: self.model,
                 input: EmbeddingCalculationRequestInput {
-                    text: input,
+                    chunks,
                     coll_id: self.collection_id.clone(),
                     doc_id,
                     field_id: self.field_id,
                     op_sender: sender,
+                    pending: self.pending.clone(),
                 },
             })
-            .await?;
+            .await;
+        if output.is_err() {
+            self.pending.remove(&doc_id);
+        }
+        output?;
         PENDING_EMBEDDING_REQUEST_GAUDGE
             .create(Empty {})
             .decrement_by_one();
@@ -498,3 +739,21 @@ enum SelectEmbeddingPropertiesReturnType {
     Properties(Vec<String>),
     Text(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infix_terms() {
+        let terms = infix_terms("xj-45201");
+        let expected: Vec<String> = ["j-45201", "-45201", "45201", "5201", "201", "01"]
+            .iter()
+            .map(|suffix| format!("{}{}", INFIX_TERM_PREFIX, suffix))
+            .collect();
+        assert_eq!(terms, expected);
+
+        assert!(infix_terms("ab").is_empty());
+        assert!(infix_terms(&"a".repeat(INFIX_MAX_TOKEN_LENGTH + 1)).is_empty());
+    }
+}
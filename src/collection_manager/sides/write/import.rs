@@ -0,0 +1,286 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+use anyhow::{Context, Result};
+use futures::{Stream, StreamExt};
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+use tracing::trace;
+
+use crate::{collection_manager::dto::ImportFormat, types::Document};
+
+// Both channels are bounded: if the documents are not consumed,
+// the parser stops and the body is not read anymore.
+const CHUNK_CHANNEL_CAPACITY: usize = 16;
+const LINE_CHANNEL_CAPACITY: usize = 256;
+
+/// A document read from the body
+pub struct ImportLine {
+    /// The line where the document starts, 1-based
+    pub line: u64,
+    pub document: Result<Document, String>,
+}
+
+/// Parses the documents while the body is received.
+/// The returned channel yields an `Err` if the body cannot be read anymore.
+pub fn parse_documents<S, B, E>(format: ImportFormat, body: S) -> mpsc::Receiver<Result<ImportLine>>
+where
+    S: Stream<Item = Result<B, E>> + Send + Unpin + 'static,
+    B: AsRef<[u8]> + Send,
+    E: std::fmt::Display + Send,
+{
+    let (chunk_sender, chunk_receiver) = mpsc::channel(CHUNK_CHANNEL_CAPACITY);
+    let (line_sender, line_receiver) = mpsc::channel(LINE_CHANNEL_CAPACITY);
+
+    tokio::spawn(async move {
+        let mut body = body;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk
+                .map(|chunk| chunk.as_ref().to_vec())
+                .map_err(|e| e.to_string());
+            let is_err = chunk.is_err();
+            // If the parser is stopped, there's no reason to read the body
+            if chunk_sender.send(chunk).await.is_err() || is_err {
+                break;
+            }
+        }
+    });
+
+    // Both `csv` and `BufRead` are synchronous
+    tokio::task::spawn_blocking(move || {
+        let reader = ChunkReader {
+            receiver: chunk_receiver,
+            current: vec![],
+            position: 0,
+        };
+        let output = match format {
+            ImportFormat::Ndjson => parse_ndjson(reader, &line_sender),
+            ImportFormat::Csv => parse_csv(reader, &line_sender),
+        };
+        if let Err(e) = output {
+            let _ = line_sender.blocking_send(Err(e));
+        }
+        trace!("Import body parsed");
+    });
+
+    line_receiver
+}
+
+fn parse_ndjson(reader: ChunkReader, sender: &mpsc::Sender<Result<ImportLine>>) -> Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+    let mut line = 0;
+    loop {
+        buf.clear();
+        let read = reader
+            .read_until(b'\n', &mut buf)
+            .context("Cannot read the body")?;
+        if read == 0 {
+            return Ok(());
+        }
+        line += 1;
+
+        if buf.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+
+        let document = serde_json::from_slice::<Value>(&buf)
+            .map_err(|e| e.to_string())
+            .and_then(|value| Document::try_from(value).map_err(|e| e.to_string()));
+
+        // The receiver is dropped: the import is stopped
+        if sender
+            .blocking_send(Ok(ImportLine { line, document }))
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+}
+
+fn parse_csv(reader: ChunkReader, sender: &mpsc::Sender<Result<ImportLine>>) -> Result<()> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader
+        .headers()
+        .context("Cannot read the CSV headers")?
+        .clone();
+
+    for record in reader.records() {
+        let (line, document) = match record {
+            Ok(record) => {
+                let line = record.position().map_or(0, |position| position.line());
+                let document: Map<String, Value> = headers
+                    .iter()
+                    .zip(record.iter())
+                    .filter_map(|(name, value)| Some((name.to_string(), csv_value(name, value)?)))
+                    .collect();
+                (line, Ok(document.into()))
+            }
+            Err(e) => {
+                if let csv::ErrorKind::Io(_) = e.kind() {
+                    return Err(e).context("Cannot read the body");
+                }
+                let line = e.position().map_or(0, |position| position.line());
+                (line, Err(e.to_string()))
+            }
+        };
+
+        // The receiver is dropped: the import is stopped
+        if sender
+            .blocking_send(Ok(ImportLine { line, document }))
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+/// CSV has no types: numbers and booleans are detected from the text.
+/// Empty values are skipped.
+fn csv_value(name: &str, value: &str) -> Option<Value> {
+    if value.is_empty() {
+        return None;
+    }
+    // The document id is always a string
+    if name == "id" {
+        return Some(Value::String(value.to_string()));
+    }
+
+    match value {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        // JSON numbers have no leading zeros, so codes like "00123" are kept as strings
+        _ => match serde_json::from_str::<serde_json::Number>(value) {
+            Ok(number) => Some(Value::Number(number)),
+            Err(_) => Some(Value::String(value.to_string())),
+        },
+    }
+}
+
+/// Exposes the chunks of the body as `Read`. It blocks waiting for the next chunk.
+struct ChunkReader {
+    receiver: mpsc::Receiver<Result<Vec<u8>, String>>,
+    current: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position >= self.current.len() {
+            match self.receiver.blocking_recv() {
+                None => return Ok(0),
+                Some(Ok(chunk)) => {
+                    self.current = chunk;
+                    self.position = 0;
+                }
+                Some(Err(e)) => return Err(io::Error::other(e)),
+            }
+        }
+
+        let len = buf.len().min(self.current.len() - self.position);
+        buf[..len].copy_from_slice(&self.current[self.position..self.position + len]);
+        self.position += len;
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn collect(format: ImportFormat, chunks: Vec<&'static str>) -> Vec<ImportLine> {
+        let body = futures::stream::iter(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok::<_, std::convert::Infallible>(chunk.as_bytes())),
+        );
+        let mut receiver = parse_documents(format, body);
+
+        let mut lines = vec![];
+        while let Some(line) = receiver.recv().await {
+            lines.push(line.unwrap());
+        }
+        lines
+    }
+
+    #[tokio::test]
+    async fn test_import_ndjson() {
+        let lines = collect(
+            ImportFormat::Ndjson,
+            vec![
+                "{\"id\": \"1\"}\n{\"id\"",
+                ": \"2\"}\n\nnot json\n[1]\n{\"id\": \"3\"}",
+            ],
+        )
+        .await;
+
+        let lines: Vec<_> = lines
+            .into_iter()
+            .map(|line| (line.line, line.document.map(|doc| doc.inner)))
+            .collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[0],
+            (1, Ok(json!({"id": "1"}).as_object().cloned().unwrap()))
+        );
+        assert_eq!(
+            lines[1],
+            (2, Ok(json!({"id": "2"}).as_object().cloned().unwrap()))
+        );
+        assert_eq!(lines[2].0, 4);
+        assert!(lines[2].1.is_err());
+        assert_eq!(lines[3].0, 5);
+        assert!(lines[3].1.is_err());
+        assert_eq!(
+            lines[4],
+            (6, Ok(json!({"id": "3"}).as_object().cloned().unwrap()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_csv() {
+        let lines = collect(
+            ImportFormat::Csv,
+            vec![
+                "id,title,price,available,code\n1,\"A, B\",10.5,true,00123\n",
+                "2,too,many,columns,here,!\n3,C,,false,",
+                "42\n",
+            ],
+        )
+        .await;
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].line, 2);
+        assert_eq!(
+            lines[0].document.as_ref().unwrap().inner,
+            json!({
+                "id": "1",
+                "title": "A, B",
+                "price": 10.5,
+                "available": true,
+                "code": "00123",
+            })
+            .as_object()
+            .cloned()
+            .unwrap()
+        );
+        assert_eq!(lines[1].line, 3);
+        assert!(lines[1].document.is_err());
+        assert_eq!(lines[2].line, 4);
+        assert_eq!(
+            lines[2].document.as_ref().unwrap().inner,
+            json!({
+                "id": "3",
+                "title": "C",
+                "available": false,
+                "code": 42,
+            })
+            .as_object()
+            .cloned()
+            .unwrap()
+        );
+    }
+}
@@ -2,10 +2,12 @@ mod collection;
 mod collections;
 mod embedding;
 mod fields;
+mod import;
 mod operation;
+mod tasks;
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     path::PathBuf,
     sync::{
         atomic::{AtomicU64, Ordering},
@@ -14,15 +16,28 @@ use std::{
     time::Duration,
 };
 
-use super::hooks::{HookName, HooksRuntime};
+use super::{
+    document_storage::{DocumentStorage, DocumentStorageConfig},
+    hooks::{HookName, HooksRuntime},
+};
 
 use anyhow::{bail, Context, Result};
+use collection::CollectionWriter;
+use dashmap::{mapref::entry::Entry, DashMap};
 use duration_str::deserialize_duration;
+use futures::Stream;
+use import::ImportLine;
 use serde::{Deserialize, Serialize};
-use tokio::{sync::RwLock, time::MissedTickBehavior};
+use serde_json::{Map, Value};
+use tasks::{TaskPayload, TaskQueue};
+use tokio::{
+    sync::{Mutex, RwLock},
+    time::MissedTickBehavior,
+};
 use tracing::{info, instrument, trace, warn};
 
-use collections::CollectionsWriter;
+pub use collection::{SchemaError, UnsupportedFilterError, VersionConflict};
+use collections::{AliasUpdate, CollectionsWriter};
 use embedding::{start_calculate_embedding_loop, EmbeddingCalculationRequest};
 pub use operation::*;
 
@@ -31,17 +46,48 @@ pub use fields::*;
 
 use crate::{
     ai::AIService,
-    collection_manager::dto::{ApiKey, CollectionDTO, CreateCollection, DeleteDocuments},
+    collection_manager::dto::{
+        ApiKey, CollectionDTO, CreateCollection, DeleteDocuments, Filter, ImportFormat,
+        ImportLineError, ImportState, ImportStatus, ReindexDocumentError, ReindexState,
+        ReindexStatus, Task, TaskDocumentError, TaskOffsets, TaskStatus,
+    },
     file_utils::BufferedFile,
     metrics::{
         AddedDocumentsLabels, DocumentProcessLabels, ADDED_DOCUMENTS_COUNTER,
         DOCUMENT_PROCESS_METRIC,
     },
-    nlp::NLPService,
-    types::{CollectionId, DocumentId, DocumentList},
+    nlp::{
+        chunker::{Chunker, ChunkerConfig},
+        NLPService,
+    },
+    types::{CollectionId, Document, DocumentId, DocumentList},
     SideChannelType,
 };
 
+/// The errors kept in the status of an import
+const MAX_IMPORT_ERRORS: usize = 1_000;
+/// The status of the finished imports are kept in memory to be polled
+const MAX_FINISHED_IMPORTS: usize = 100;
+
+/// The errors kept in the status of a reindex
+const MAX_REINDEX_ERRORS: usize = 1_000;
+/// The status of the finished reindexes are kept in memory to be polled
+const MAX_FINISHED_REINDEXES: usize = 100;
+/// Number of stored documents read at once while reindexing
+const REINDEX_BATCH_SIZE: usize = 100;
+
+/// The directory of the write side internals, inside the data directory.
+/// The other directories are the collections: this name cannot be a collection id.
+const INTERNAL_DIR: &str = "_internal";
+/// The directory of the document storage, inside the internal directory
+const DOCUMENT_STORAGE_DIR: &str = "docs";
+/// The directory of the write tasks, inside the internal directory
+const TASKS_DIR: &str = "tasks";
+/// The errors kept in a task
+const MAX_TASK_ERRORS: usize = 1_000;
+/// How often the expired documents are deleted
+const EXPIRED_DOCUMENTS_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct CollectionsWriterConfig {
     pub data_dir: PathBuf,
@@ -55,22 +101,32 @@ pub struct CollectionsWriterConfig {
     pub javascript_queue_limit: u32,
     #[serde(deserialize_with = "deserialize_duration")]
     pub commit_interval: Duration,
+    /// How the input of the embedding fields is split before the calculation
+    #[serde(default)]
+    pub embedding_chunking: ChunkerConfig,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct WriteSideConfig {
     pub master_api_key: ApiKey,
     pub output: SideChannelType,
+    /// Serves the file side channel to the read sides running in other processes
+    #[serde(default)]
+    pub stream_server: Option<OperationStreamServerConfig>,
     pub config: CollectionsWriterConfig,
 }
 
 pub struct WriteSide {
     sender: OperationSender,
+    /// Set on load for the file side channel: its segments are needed until committed
+    log_consumer: Option<LogConsumer>,
+    embedding_sender: tokio::sync::mpsc::Sender<EmbeddingCalculationRequest>,
     collections: CollectionsWriter,
+    /// The documents are needed to apply the partial updates
+    document_storage: DocumentStorage,
     document_count: AtomicU64,
     data_dir: PathBuf,
     hook_runtime: Arc<HooksRuntime>,
-    nlp_service: Arc<NLPService>,
 
     operation_counter: RwLock<u64>,
     insert_batch_commit_size: u64,
@@ -78,16 +134,26 @@ pub struct WriteSide {
     master_api_key: ApiKey,
 
     commit_interval: Duration,
+
+    imports: DashMap<String, ImportStatus>,
+    finished_imports: Mutex<VecDeque<String>>,
+
+    reindexes: DashMap<String, ReindexStatus>,
+    finished_reindexes: Mutex<VecDeque<String>>,
+
+    tasks: TaskQueue,
+    /// Set on shutdown: the task loop stops after the current task
+    stop_tasks: tokio::sync::watch::Sender<bool>,
 }
 
 impl WriteSide {
-    pub fn new(
+    pub fn try_new(
         sender: OperationSender,
         config: WriteSideConfig,
         ai_service: Arc<AIService>,
         hook_runtime: Arc<HooksRuntime>,
         nlp_service: Arc<NLPService>,
-    ) -> WriteSide {
+    ) -> Result<WriteSide> {
         let master_api_key = config.master_api_key;
         let collections_writer_config = config.config;
         let data_dir = collections_writer_config.data_dir.clone();
@@ -104,15 +170,33 @@ impl WriteSide {
             collections_writer_config.embedding_queue_limit,
         );
 
+        let chunker = Chunker::try_new(collections_writer_config.embedding_chunking.clone())
+            .context("Cannot create the chunker")?;
+
+        let internal_dir = data_dir.join(INTERNAL_DIR);
+        let document_storage = DocumentStorage::try_new(DocumentStorageConfig {
+            data_dir: internal_dir.join(DOCUMENT_STORAGE_DIR),
+            side: "write",
+        })
+        .context("Cannot create document storage")?;
+
         let commit_interval = collections_writer_config.commit_interval;
+        let tasks = TaskQueue::new(internal_dir.join(TASKS_DIR));
 
-        WriteSide {
+        Ok(WriteSide {
             sender,
-            collections: CollectionsWriter::new(collections_writer_config, sx),
+            log_consumer: None,
+            embedding_sender: sx.clone(),
+            collections: CollectionsWriter::new(
+                collections_writer_config,
+                sx,
+                nlp_service,
+                Arc::new(chunker),
+            ),
+            document_storage,
             document_count: AtomicU64::new(0),
             data_dir,
             hook_runtime,
-            nlp_service,
 
             operation_counter: Default::default(),
             insert_batch_commit_size,
@@ -120,41 +204,200 @@ impl WriteSide {
             master_api_key,
 
             commit_interval,
-        }
+
+            imports: Default::default(),
+            finished_imports: Default::default(),
+
+            reindexes: Default::default(),
+            finished_reindexes: Default::default(),
+
+            tasks,
+            stop_tasks: tokio::sync::watch::Sender::new(false),
+        })
     }
 
     pub async fn load(mut self) -> Result<Arc<Self>> {
-        self.collections
-            .load(self.hook_runtime.clone(), self.nlp_service.clone())
-            .await?;
+        self.document_storage
+            .load()
+            .context("Cannot load document storage")?;
+        self.collections.load(self.hook_runtime.clone()).await?;
+        self.tasks.load().await.context("Cannot load tasks")?;
 
-        let info: WriteSideInfo = match BufferedFile::open(self.data_dir.join("info.json"))
+        let info: Option<WriteSideInfo> = match BufferedFile::open(self.data_dir.join("info.json"))
             .and_then(|f| f.read_json_data())
             .context("Cannot read info file")
         {
-            Ok(info) => info,
+            Ok(info) => Some(info),
             Err(err) => {
-                warn!("Cannot read info file: {}. Skip loading", err);
-                let s = Arc::new(self);
-
-                s.clone().start_commit_loop(s.commit_interval);
-
-                return Ok(s);
+                warn!(
+                    "Cannot read info file: {}. Skip loading the committed state",
+                    err
+                );
+                None
             }
         };
-        let WriteSideInfo::V1(info) = info;
+        // The offset of the first operation not committed
+        let (document_count, offset) = match info {
+            Some(WriteSideInfo::V1(info)) => (info.document_count, info.offset),
+            None => (0, Offset(1)),
+        };
 
-        self.document_count
-            .store(info.document_count, Ordering::Relaxed);
-        self.sender.set_offset(info.offset);
+        // On a crash, the operations after the commit are in the side channel only
+        let ops = self
+            .sender
+            .tail(offset)
+            .await
+            .context("Cannot read the side channel")?;
+        let last_document_id = ops.iter().filter_map(|op| op.document_id()).max();
+        let document_count = match last_document_id {
+            Some(DocumentId(id)) => document_count.max(id + 1),
+            None => document_count,
+        };
+        info!(
+            count = ops.len(),
+            "Replaying the operations after the commit"
+        );
+        for op in ops {
+            self.collections
+                .replay(op, &self.document_storage, self.hook_runtime.clone())
+                .await?;
+        }
+        self.document_count.store(document_count, Ordering::Relaxed);
+        self.sender.set_offset(offset);
+        self.log_consumer = self.sender.consumer(Offset(offset.0 - 1));
 
         let s = Arc::new(self);
 
         s.clone().start_commit_loop(s.commit_interval);
+        s.clone().start_task_loop();
+        s.clone().start_expiration_loop();
 
         Ok(s)
     }
 
+    /// Processes the enqueued tasks, one at a time
+    fn start_task_loop(self: Arc<Self>) {
+        let mut stop = self.stop_tasks.subscribe();
+        tokio::task::spawn(async move {
+            loop {
+                // `next` marks the task as processing only when it returns
+                let next = tokio::select! {
+                    next = self.tasks.next() => next,
+                    _ = stop.wait_for(|stop| *stop) => break,
+                };
+                let task = match next {
+                    Ok(task) => task,
+                    Err(e) => {
+                        tracing::error!(?e, "Cannot get the next task");
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+                info!(task_id = task.id, kind = ?task.kind, "Processing task");
+
+                let (error, offsets) = match self.process_task(&task).await {
+                    Ok(offsets) => (None, offsets),
+                    Err(e) => (Some(format!("{:#}", e)), None),
+                };
+                match self.tasks.finish(task.id, error, offsets).await {
+                    Ok(task) => info!(task_id = task.id, status = ?task.status, "Task finished"),
+                    Err(e) => tracing::error!(?e, task_id = task.id, "Cannot finish task"),
+                }
+
+                if *stop.borrow() {
+                    break;
+                }
+            }
+            info!("Task loop stopped");
+        });
+    }
+
+    /// Returns the offsets of the operations sent while the task ran, if any.
+    /// The other writes are not stopped meanwhile: see `TaskOffsets`.
+    async fn process_task(&self, task: &Task) -> Result<Option<TaskOffsets>> {
+        let payload = self.tasks.payload(task.id).await?;
+
+        // The counter contains the offset of the next operation
+        let from = self.sender.offset().0;
+
+        match payload {
+            TaskPayload::Insert(docs) => {
+                for doc in docs {
+                    let id = doc
+                        .get("id")
+                        .and_then(|id| id.as_str())
+                        .unwrap_or_default()
+                        .to_string();
+                    let output = self.import_document(&task.collection_id, doc, None).await;
+                    self.update_task_document(task.id, id, output).await;
+                }
+            }
+            TaskPayload::ConditionalInsert { doc, if_version } => {
+                let id = doc
+                    .get("id")
+                    .and_then(|id| id.as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                let output = self
+                    .import_document(&task.collection_id, doc, Some(if_version))
+                    .await;
+                self.update_task_document(task.id, id, output).await;
+            }
+            TaskPayload::Delete(doc_ids) => {
+                self.process_delete_task(task, doc_ids, None).await?;
+            }
+            TaskPayload::ConditionalDelete { doc_id, if_version } => {
+                self.process_delete_task(task, vec![doc_id], Some(if_version))
+                    .await?;
+            }
+        }
+
+        let to = self.last_offset().await?.0;
+        Ok((to >= from).then_some(TaskOffsets { from, to }))
+    }
+
+    async fn update_task_document(&self, task_id: u64, id: String, output: Result<(), String>) {
+        self.tasks
+            .update(task_id, |task| match output {
+                Ok(()) => task.succeeded += 1,
+                Err(error) => {
+                    task.failed += 1;
+                    if task.errors.len() < MAX_TASK_ERRORS {
+                        task.errors.push(TaskDocumentError { id, error });
+                    }
+                }
+            })
+            .await;
+    }
+
+    async fn process_delete_task(
+        &self,
+        task: &Task,
+        doc_ids: Vec<String>,
+        if_version: Option<u64>,
+    ) -> Result<()> {
+        let collection = self
+            .collections
+            .get_collection(task.collection_id.clone())
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Collection not found"))?;
+        let deleted = collection
+            .delete_documents(
+                doc_ids,
+                if_version,
+                &self.document_storage,
+                self.sender.clone(),
+            )
+            .await?;
+        drop(collection);
+
+        self.tasks
+            .update(task.id, |task| task.succeeded = deleted as u64)
+            .await;
+
+        Ok(())
+    }
+
     fn start_commit_loop(self: Arc<Self>, insert_batch_commit_size: Duration) {
         tokio::task::spawn(async move {
             let mut interval = tokio::time::interval(insert_batch_commit_size);
@@ -167,6 +410,7 @@ impl WriteSide {
 
             loop {
                 interval.tick().await;
+
                 info!(
                     "{:?} time reached. Committing write side",
                     insert_batch_commit_size.clone()
@@ -178,6 +422,71 @@ impl WriteSide {
         });
     }
 
+    /// The read side hides the expired documents until they are deleted here
+    fn start_expiration_loop(self: Arc<Self>) {
+        tokio::task::spawn(async move {
+            let mut interval = tokio::time::interval(EXPIRED_DOCUMENTS_INTERVAL);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+            loop {
+                interval.tick().await;
+
+                if let Err(e) = self.delete_expired_documents().await {
+                    tracing::error!(?e, "Cannot delete expired documents");
+                }
+            }
+        });
+    }
+
+    /// Returns the offset of the last operation sent to the read side.
+    /// The embeddings requested until now are waited: their operations are included.
+    pub async fn last_offset(&self) -> Result<Offset> {
+        let (done, wait) = tokio::sync::oneshot::channel();
+        self.embedding_sender
+            .send(EmbeddingCalculationRequest::Flush(done))
+            .await
+            .context("The embedding calculation loop is stopped")?;
+        wait.await
+            .context("The embedding calculation loop is stopped")?;
+
+        // The counter contains the offset of the next operation
+        Ok(Offset(self.sender.offset().0.saturating_sub(1)))
+    }
+
+    pub async fn delete_expired_documents(&self) -> Result<()> {
+        let deleted = self
+            .collections
+            .delete_expired_documents(&self.document_storage, self.sender.clone())
+            .await?;
+        if deleted > 0 {
+            info!(deleted, "Expired documents deleted");
+        }
+
+        Ok(())
+    }
+
+    /// Stops processing the tasks after the current one, waits for the queued embeddings,
+    /// and commits. The enqueued tasks are persisted: they are processed after the restart.
+    /// Returns the offset of the last operation sent to the read side.
+    pub async fn shutdown(&self) -> Result<Offset> {
+        info!("Stopping write side");
+
+        self.stop_tasks.send_replace(true);
+        // The task loop drops its receiver once stopped
+        self.stop_tasks.closed().await;
+
+        let offset = self.last_offset().await?;
+        self.commit().await?;
+        self.sender
+            .sync()
+            .await
+            .context("Cannot sync the side channel")?;
+
+        info!(?offset, "Write side stopped");
+
+        Ok(offset)
+    }
+
     #[instrument(skip(self))]
     pub async fn commit(&self) -> Result<()> {
         info!("Committing write side");
@@ -186,6 +495,11 @@ impl WriteSide {
 
         self.collections.commit().await?;
 
+        self.document_storage
+            .commit()
+            .await
+            .context("Cannot commit document storage")?;
+
         // This load is not atomic with the commit.
         // This means, we save a document count possible higher.
         // Anyway it is not a problem, because the document count is only used for the document id generation
@@ -200,6 +514,16 @@ impl WriteSide {
             .write_json_data(&info)
             .context("Cannot write info file")?;
 
+        // The operations before `offset` are not read again on load
+        if let Some(log_consumer) = &self.log_consumer {
+            if let Err(e) = log_consumer.commit(Offset(offset.0 - 1)).await {
+                warn!(
+                    "Cannot remove the committed operation log segments: {:?}",
+                    e
+                );
+            }
+        }
+
         Ok(())
     }
 
@@ -214,7 +538,80 @@ impl WriteSide {
             .create_collection(option, self.sender.clone(), self.hook_runtime.clone())
             .await?;
 
-        Ok(())
+        // The side channel doesn't carry the collection config: it is committed right away
+        self.commit().await
+    }
+
+    pub async fn delete_collection(
+        &self,
+        master_api_key: ApiKey,
+        collection_id: CollectionId,
+    ) -> Result<()> {
+        self.check_master_api_key(master_api_key)?;
+
+        self.collections
+            .delete_collection(collection_id, &self.document_storage, self.sender.clone())
+            .await?;
+
+        self.commit().await
+    }
+
+    pub async fn create_alias(
+        &self,
+        master_api_key: ApiKey,
+        alias: CollectionId,
+        collection_id: CollectionId,
+    ) -> Result<()> {
+        self.check_master_api_key(master_api_key)?;
+
+        self.collections
+            .set_alias(
+                alias,
+                collection_id,
+                AliasUpdate::Create,
+                self.sender.clone(),
+            )
+            .await?;
+
+        self.commit().await
+    }
+
+    /// Points an existing alias to `collection_id` atomically.
+    /// Returns the collection pointed before.
+    pub async fn swap_alias(
+        &self,
+        master_api_key: ApiKey,
+        alias: CollectionId,
+        collection_id: CollectionId,
+    ) -> Result<CollectionId> {
+        self.check_master_api_key(master_api_key)?;
+
+        let previous = self
+            .collections
+            .set_alias(alias, collection_id, AliasUpdate::Swap, self.sender.clone())
+            .await?;
+        self.commit().await?;
+
+        previous.context("The alias has no previous collection")
+    }
+
+    pub async fn delete_alias(&self, master_api_key: ApiKey, alias: CollectionId) -> Result<()> {
+        self.check_master_api_key(master_api_key)?;
+
+        self.collections
+            .delete_alias(alias, self.sender.clone())
+            .await?;
+
+        self.commit().await
+    }
+
+    pub async fn list_aliases(
+        &self,
+        master_api_key: ApiKey,
+    ) -> Result<HashMap<CollectionId, CollectionId>> {
+        self.check_master_api_key(master_api_key)?;
+
+        Ok(self.collections.list_aliases().await)
     }
 
     pub async fn write(
@@ -243,52 +640,658 @@ impl WriteSide {
 
         collection.check_write_api_key(write_api_key)?;
 
-        let sender = self.sender.clone();
+        for doc in document_list {
+            self.insert_document(&collection, &collection_id, doc, None)
+                .await?;
+        }
+        drop(collection);
 
-        for mut doc in document_list {
-            info!("Insert doc");
-            let m = DOCUMENT_PROCESS_METRIC.create(DocumentProcessLabels {
-                collection: collection_id.0.clone(),
-            });
+        self.increment_operation_counter(document_count as u64)
+            .await?;
 
-            let doc_id = self.document_count.fetch_add(1, Ordering::Relaxed);
+        info!("Batch of documents inserted");
 
-            let doc_id_value = doc.get("id");
-            // Forces the id to be set, if not set
-            if doc_id_value.is_none() {
-                doc.inner.insert(
-                    "id".to_string(),
-                    serde_json::Value::String(cuid2::create_id()),
-                );
-            } else if let Some(doc_id_value) = doc_id_value {
-                if !doc_id_value.is_string() {
-                    // The search result contains the document id and it is defined as a string.
-                    // So, if the original document id is not a string, we should overwrite it with a new one
-                    // Anyway, this implies the loss of the original document id. For instance we could support number as well
-                    // TODO: think better
-                    warn!("Document id is not a string, overwriting it with new one");
-                    doc.inner.insert(
-                        "id".to_string(),
-                        serde_json::Value::String(cuid2::create_id()),
-                    );
+        Ok(())
+    }
+
+    /// Enqueues the insertion of the documents: they are processed in background.
+    /// With `if_version`, the list contains a single document, inserted only if its version is that:
+    /// a conflict is reported immediately if possible, otherwise as the error of the task.
+    pub async fn enqueue_write(
+        &self,
+        write_api_key: ApiKey,
+        collection_id: CollectionId,
+        document_list: DocumentList,
+        if_version: Option<u64>,
+    ) -> Result<Task> {
+        self.check_write_api_key(write_api_key, &collection_id)
+            .await?;
+
+        let collection = self
+            .collections
+            .get_collection(collection_id.clone())
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Collection not found"))?;
+
+        let mut docs: Vec<Document> = document_list.into_iter().collect();
+        for doc in &mut docs {
+            // The ids are assigned before the payload is persisted:
+            // a task enqueued again after a restart replaces the same documents
+            ensure_document_id(doc);
+            collection.validate_document(doc)?;
+        }
+        drop(collection);
+
+        let payload = match if_version {
+            None => TaskPayload::Insert(docs),
+            Some(if_version) => {
+                if docs.len() != 1 {
+                    bail!("if_version requires a single document");
                 }
+                let doc = docs.remove(0);
+                let doc_id = doc
+                    .get("id")
+                    .and_then(|id| id.as_str())
+                    .context("if_version requires the document id")?;
+                self.check_version(&collection_id, doc_id, if_version)
+                    .await?;
+                TaskPayload::ConditionalInsert { doc, if_version }
             }
+        };
+
+        self.tasks.enqueue(collection_id, payload).await
+    }
+
+    /// Enqueues the deletion of the documents: they are processed in background.
+    /// With `if_version`, the list contains a single document id, as in `enqueue_write`.
+    pub async fn enqueue_delete(
+        &self,
+        write_api_key: ApiKey,
+        collection_id: CollectionId,
+        mut document_ids_to_delete: DeleteDocuments,
+        if_version: Option<u64>,
+    ) -> Result<Task> {
+        self.check_write_api_key(write_api_key, &collection_id)
+            .await?;
 
-            let doc_id = DocumentId(doc_id);
-            info!(?doc_id, "Inserting document");
+        let payload = match if_version {
+            None => TaskPayload::Delete(document_ids_to_delete),
+            Some(if_version) => {
+                if document_ids_to_delete.len() != 1 {
+                    bail!("if_version requires a single document id");
+                }
+                let doc_id = document_ids_to_delete.remove(0);
+                self.check_version(&collection_id, &doc_id, if_version)
+                    .await?;
+                TaskPayload::ConditionalDelete { doc_id, if_version }
+            }
+        };
+
+        self.tasks.enqueue(collection_id, payload).await
+    }
+
+    async fn check_version(
+        &self,
+        collection_id: &CollectionId,
+        doc_id: &str,
+        if_version: u64,
+    ) -> Result<()> {
+        let collection = self
+            .collections
+            .get_collection(collection_id.clone())
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Collection not found"))?;
+
+        collection.check_version(doc_id, if_version).await
+    }
+
+    /// The master api key reads every task,
+    /// the write api key only the tasks of its collection
+    pub async fn get_task(&self, api_key: ApiKey, task_id: u64) -> Result<Option<Task>> {
+        let Some(task) = self.tasks.get(task_id).await else {
+            return Ok(None);
+        };
+        if self.check_master_api_key(api_key.clone()).is_err() {
+            self.check_write_api_key(api_key, &task.collection_id)
+                .await?;
+        }
+
+        Ok(Some(task))
+    }
+
+    /// Waits until the task is finished
+    pub async fn wait_task(&self, api_key: ApiKey, task_id: u64) -> Result<Option<Task>> {
+        if self.get_task(api_key, task_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        Ok(self.tasks.wait(task_id).await)
+    }
+
+    /// The newest tasks first.
+    /// Without the master api key, the collection is required.
+    pub async fn list_tasks(
+        &self,
+        api_key: ApiKey,
+        collection_id: Option<CollectionId>,
+        status: Option<TaskStatus>,
+    ) -> Result<Vec<Task>> {
+        if self.check_master_api_key(api_key.clone()).is_err() {
+            let collection_id = collection_id
+                .as_ref()
+                .context("The collection is required without the master api key")?;
+            self.check_write_api_key(api_key, collection_id).await?;
+        }
+
+        Ok(self.tasks.list(collection_id.as_ref(), status).await)
+    }
+
+    async fn check_write_api_key(
+        &self,
+        write_api_key: ApiKey,
+        collection_id: &CollectionId,
+    ) -> Result<()> {
+        let collection = self
+            .collections
+            .get_collection(collection_id.clone())
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Collection not found"))?;
+
+        collection.check_write_api_key(write_api_key)
+    }
+
+    /// Inserts the documents read from `body` while it is received.
+    /// The invalid documents are reported in the returned status, with their line.
+    /// The status can be polled with `get_import_status` during the import.
+    /// The import runs in its own task: it ends even if the caller stops waiting for it.
+    pub async fn import_documents<S, B, E>(
+        self: Arc<Self>,
+        write_api_key: ApiKey,
+        collection_id: CollectionId,
+        format: ImportFormat,
+        import_id: Option<String>,
+        body: S,
+    ) -> Result<ImportStatus>
+    where
+        S: Stream<Item = Result<B, E>> + Send + Unpin + 'static,
+        B: AsRef<[u8]> + Send,
+        E: std::fmt::Display + Send,
+    {
+        let collection = self
+            .collections
+            .get_collection(collection_id.clone())
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Collection not found"))?;
+        collection.check_write_api_key(write_api_key)?;
+        // The collection lock is not kept during the whole import
+        drop(collection);
+
+        let import_id = import_id.unwrap_or_else(cuid2::create_id);
+        match self.imports.entry(import_id.clone()) {
+            Entry::Occupied(_) => bail!("Import \"{}\" already exists", import_id),
+            Entry::Vacant(entry) => {
+                entry.insert(ImportStatus {
+                    id: import_id.clone(),
+                    collection_id: collection_id.clone(),
+                    state: ImportState::Running,
+                    processed: 0,
+                    inserted: 0,
+                    failed: 0,
+                    errors: vec![],
+                    error: None,
+                    offset: None,
+                });
+            }
+        };
+        info!(?import_id, ?format, "Importing documents");
+
+        let lines = import::parse_documents(format, body);
+        tokio::spawn(self.clone().run_import(collection_id, import_id, lines))
+            .await
+            .context("The import task panicked")
+    }
+
+    async fn run_import(
+        self: Arc<Self>,
+        collection_id: CollectionId,
+        import_id: String,
+        mut lines: tokio::sync::mpsc::Receiver<Result<ImportLine>>,
+    ) -> ImportStatus {
+        let mut error = None;
+        while let Some(line) = lines.recv().await {
+            let ImportLine { line, document } = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    error = Some(format!("{:#}", e));
+                    break;
+                }
+            };
+
+            let output = match document {
+                Ok(doc) => self.import_document(&collection_id, doc, None).await,
+                Err(e) => Err(e),
+            };
+
+            let mut status = self
+                .imports
+                .get_mut(&import_id)
+                .expect("The import status is removed only when the import is done");
+            status.processed += 1;
+            match output {
+                Ok(()) => status.inserted += 1,
+                Err(e) => {
+                    trace!(?line, ?e, "Cannot import document");
+                    status.failed += 1;
+                    if status.errors.len() < MAX_IMPORT_ERRORS {
+                        status.errors.push(ImportLineError { line, error: e });
+                    }
+                }
+            }
+        }
+
+        let offset = match self.last_offset().await {
+            Ok(offset) => Some(offset.0),
+            Err(e) => {
+                warn!(?e, "Cannot get the import offset");
+                None
+            }
+        };
+
+        let status = {
+            let mut status = self
+                .imports
+                .get_mut(&import_id)
+                .expect("The import status is removed only when the import is done");
+            status.state = if error.is_some() {
+                ImportState::Failed
+            } else {
+                ImportState::Completed
+            };
+            status.error = error;
+            status.offset = offset;
+            status.clone()
+        };
+        info!(
+            ?import_id,
+            inserted = status.inserted,
+            failed = status.failed,
+            "Import done"
+        );
+
+        self.remove_old_imports(import_id).await;
+
+        status
+    }
+
+    pub async fn get_import_status(
+        &self,
+        write_api_key: ApiKey,
+        collection_id: CollectionId,
+        import_id: String,
+    ) -> Result<Option<ImportStatus>> {
+        let collection = self
+            .collections
+            .get_collection(collection_id.clone())
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Collection not found"))?;
+        collection.check_write_api_key(write_api_key)?;
+
+        Ok(self
+            .imports
+            .get(&import_id)
+            .filter(|status| status.collection_id == collection_id)
+            .map(|status| status.clone()))
+    }
+
+    /// Creates the collection described by `options` and copies into it
+    /// the stored documents of `source_collection_id`, in background.
+    /// The documents are indexed again: the embeddings are calculated with the new model.
+    /// The documents written into the source collection during the copy are copied
+    /// by a last pass, before the alias is swapped.
+    pub async fn reindex(
+        self: Arc<Self>,
+        master_api_key: ApiKey,
+        source_collection_id: CollectionId,
+        options: CreateCollection,
+        alias: Option<CollectionId>,
+    ) -> Result<ReindexStatus> {
+        self.check_master_api_key(master_api_key)?;
+
+        let source = self
+            .collections
+            .get_collection(source_collection_id.clone())
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Collection not found"))?;
+        let mut ids = source.ids().await;
+        drop(source);
+        ids.sort_unstable();
+
+        let collection_id = options.id.clone();
+        self.collections
+            .create_collection(options, self.sender.clone(), self.hook_runtime.clone())
+            .await
+            .context("Cannot create the new collection")?;
+        self.commit().await?;
+
+        let reindex_id = cuid2::create_id();
+        let status = ReindexStatus {
+            id: reindex_id.clone(),
+            source_collection_id: source_collection_id.clone(),
+            collection_id: collection_id.clone(),
+            alias: alias.clone(),
+            state: ReindexState::Running,
+            total: ids.len() as u64,
+            processed: 0,
+            inserted: 0,
+            failed: 0,
+            errors: vec![],
+            error: None,
+        };
+        self.reindexes.insert(reindex_id.clone(), status.clone());
+        info!(?reindex_id, ?collection_id, "Reindexing collection");
+
+        tokio::task::spawn(async move {
+            let error = self
+                .reindex_documents(
+                    &reindex_id,
+                    &source_collection_id,
+                    &collection_id,
+                    ids,
+                    alias,
+                )
+                .await
+                .err()
+                .map(|e| format!("{:#}", e));
+
+            {
+                let mut status = self
+                    .reindexes
+                    .get_mut(&reindex_id)
+                    .expect("The reindex status is removed only when the reindex is done");
+                status.state = if error.is_some() {
+                    ReindexState::Failed
+                } else {
+                    ReindexState::Completed
+                };
+                status.error = error;
+                info!(
+                    ?reindex_id,
+                    inserted = status.inserted,
+                    failed = status.failed,
+                    "Reindex done"
+                );
+            }
+
+            self.remove_old_reindexes(reindex_id).await;
+        });
+
+        Ok(status)
+    }
+
+    /// Keeps only the last `MAX_FINISHED_REINDEXES` finished reindexes
+    async fn remove_old_reindexes(&self, reindex_id: String) {
+        let mut finished_reindexes = self.finished_reindexes.lock().await;
+        finished_reindexes.push_back(reindex_id);
+        while finished_reindexes.len() > MAX_FINISHED_REINDEXES {
+            if let Some(reindex_id) = finished_reindexes.pop_front() {
+                self.reindexes.remove(&reindex_id);
+            }
+        }
+    }
+
+    /// Nothing is swapped if a document cannot be read
+    async fn reindex_documents(
+        &self,
+        reindex_id: &str,
+        source_collection_id: &CollectionId,
+        collection_id: &CollectionId,
+        ids: Vec<String>,
+        alias: Option<CollectionId>,
+    ) -> Result<()> {
+        // The `DocumentId` copied for every id: the last pass copies only the changed documents
+        let mut copied: HashMap<String, DocumentId> = HashMap::new();
+
+        for batch in ids.chunks(REINDEX_BATCH_SIZE) {
+            self.copy_documents(
+                reindex_id,
+                source_collection_id,
+                collection_id,
+                batch,
+                &mut copied,
+            )
+            .await?;
+        }
+
+        // The documents inserted, replaced or deleted during the copy
+        let source = self
+            .collections
+            .get_collection(source_collection_id.clone())
+            .await
+            .context("The source collection is deleted")?;
+        let mut ids = source.ids().await;
+        drop(source);
+        ids.extend(copied.keys().cloned());
+        ids.sort_unstable();
+        ids.dedup();
+        for batch in ids.chunks(REINDEX_BATCH_SIZE) {
+            self.copy_documents(
+                reindex_id,
+                source_collection_id,
+                collection_id,
+                batch,
+                &mut copied,
+            )
+            .await?;
+        }
+
+        if let Some(alias) = alias {
+            self.collections
+                .set_alias(
+                    alias,
+                    collection_id.clone(),
+                    AliasUpdate::CreateOrSwap,
+                    self.sender.clone(),
+                )
+                .await
+                .context("Cannot set the alias")?;
+            self.commit().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Copies the documents of `ids` not in `copied` with their current version.
+    /// The documents in `copied` but not in the source anymore are deleted.
+    async fn copy_documents(
+        &self,
+        reindex_id: &str,
+        source_collection_id: &CollectionId,
+        collection_id: &CollectionId,
+        ids: &[String],
+        copied: &mut HashMap<String, DocumentId>,
+    ) -> Result<()> {
+        let source = self
+            .collections
+            .get_collection(source_collection_id.clone())
+            .await
+            .context("The source collection is deleted")?;
+        let doc_ids = source.get_document_ids(ids).await;
+        drop(source);
+
+        let mut to_copy = vec![];
+        let mut to_delete = vec![];
+        for (id, doc_id) in ids.iter().zip(doc_ids) {
+            match doc_id {
+                Some(doc_id) if copied.get(id) == Some(&doc_id) => {}
+                Some(doc_id) => to_copy.push((id.clone(), doc_id)),
+                None if copied.remove(id).is_some() => to_delete.push(id.clone()),
+                // The document is deleted in the meantime
+                None => self.update_reindex(reindex_id, |status| status.processed += 1),
+            }
+        }
+
+        if !to_delete.is_empty() {
+            let collection = self
+                .collections
+                .get_collection(collection_id.clone())
+                .await
+                .context("Collection not found")?;
             collection
-                .process_new_document(doc_id, doc, sender.clone(), self.hook_runtime.clone())
+                .delete_documents(to_delete, None, &self.document_storage, self.sender.clone())
                 .await
-                .context("Cannot process document")?;
-            info!("Document inserted");
+                .context("Cannot delete the documents deleted from the source collection")?;
+        }
 
-            drop(m);
+        let docs = self
+            .document_storage
+            .get_documents_by_ids(to_copy.iter().map(|(_, doc_id)| *doc_id).collect())
+            .await
+            .context("Cannot read the stored documents")?;
+
+        for ((id, doc_id), doc) in to_copy.into_iter().zip(docs) {
+            let Some(doc) = doc else {
+                let source = self
+                    .collections
+                    .get_collection(source_collection_id.clone())
+                    .await
+                    .context("The source collection is deleted")?;
+                let current = source.get_document_ids(&[id.clone()]).await;
+                drop(source);
+                if current == [Some(doc_id)] {
+                    bail!(
+                        "Document {:?} was inserted before the write side stored the documents: it cannot be reindexed",
+                        id
+                    );
+                }
+                // Replaced or deleted in the meantime: the last pass handles it
+                continue;
+            };
+
+            let doc: Document = serde_json::from_str(doc.inner.get())
+                .with_context(|| format!("Cannot parse stored document {:?}", id))?;
+            let output = self.import_document(collection_id, doc, None).await;
+            // A failed document is not retried by the last pass
+            copied.insert(id.clone(), doc_id);
+
+            self.update_reindex(reindex_id, |status| {
+                status.processed += 1;
+                match output {
+                    Ok(()) => status.inserted += 1,
+                    Err(error) => {
+                        trace!(?id, ?error, "Cannot reindex document");
+                        status.failed += 1;
+                        if status.errors.len() < MAX_REINDEX_ERRORS {
+                            status.errors.push(ReindexDocumentError { id, error });
+                        }
+                    }
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    fn update_reindex<F>(&self, reindex_id: &str, f: F)
+    where
+        F: FnOnce(&mut ReindexStatus),
+    {
+        let mut status = self
+            .reindexes
+            .get_mut(reindex_id)
+            .expect("The reindex status is removed only when the reindex is done");
+        f(&mut status);
+    }
+
+    pub async fn get_reindex_status(
+        &self,
+        master_api_key: ApiKey,
+        reindex_id: String,
+    ) -> Result<Option<ReindexStatus>> {
+        self.check_master_api_key(master_api_key)?;
 
-            info!("Doc inserted");
+        Ok(self.reindexes.get(&reindex_id).map(|status| status.clone()))
+    }
+
+    async fn import_document(
+        &self,
+        collection_id: &CollectionId,
+        doc: Document,
+        if_version: Option<u64>,
+    ) -> Result<(), String> {
+        // The collection is taken for every document, so the import doesn't block the collection creation
+        let collection = self
+            .collections
+            .get_collection(collection_id.clone())
+            .await
+            .ok_or_else(|| "Collection not found".to_string())?;
+
+        ADDED_DOCUMENTS_COUNTER
+            .create(AddedDocumentsLabels {
+                collection: collection_id.0.clone(),
+            })
+            .increment_by_one();
+
+        self.insert_document(&collection, collection_id, doc, if_version)
+            .await
+            .map_err(|e| format!("{:#}", e))?;
+        drop(collection);
+
+        self.increment_operation_counter(1)
+            .await
+            .map_err(|e| format!("{:#}", e))
+    }
+
+    /// Keeps only the last `MAX_FINISHED_IMPORTS` finished imports
+    async fn remove_old_imports(&self, import_id: String) {
+        let mut finished_imports = self.finished_imports.lock().await;
+        finished_imports.push_back(import_id);
+        while finished_imports.len() > MAX_FINISHED_IMPORTS {
+            if let Some(import_id) = finished_imports.pop_front() {
+                self.imports.remove(&import_id);
+            }
         }
+    }
+
+    async fn insert_document(
+        &self,
+        collection: &CollectionWriter,
+        collection_id: &CollectionId,
+        mut doc: Document,
+        if_version: Option<u64>,
+    ) -> Result<()> {
+        info!("Insert doc");
+        let m = DOCUMENT_PROCESS_METRIC.create(DocumentProcessLabels {
+            collection: collection_id.0.clone(),
+        });
+
+        let doc_id = self.document_count.fetch_add(1, Ordering::Relaxed);
 
+        ensure_document_id(&mut doc);
+
+        let doc_id = DocumentId(doc_id);
+        info!(?doc_id, "Inserting document");
+        collection
+            .process_new_document(
+                doc_id,
+                doc,
+                if_version,
+                &self.document_storage,
+                self.sender.clone(),
+                self.hook_runtime.clone(),
+            )
+            .await
+            .context("Cannot process document")?;
+        info!("Document inserted");
+
+        drop(m);
+
+        Ok(())
+    }
+
+    /// Commits if `insert_batch_commit_size` operations are reached
+    async fn increment_operation_counter(&self, count: u64) -> Result<()> {
         let mut lock = self.operation_counter.write().await;
-        *lock += document_count as u64;
+        *lock += count;
         let should_commit = if *lock >= self.insert_batch_commit_size {
             *lock = 0;
             true
@@ -304,8 +1307,6 @@ impl WriteSide {
             trace!(insert_batch_commit_size=?self.insert_batch_commit_size, "insert_batch_commit_size not reached, not committing");
         }
 
-        info!("Batch of documents inserted");
-
         Ok(())
     }
 
@@ -324,12 +1325,85 @@ impl WriteSide {
         collection.check_write_api_key(write_api_key)?;
 
         collection
-            .delete_documents(document_ids_to_delete, self.sender.clone())
+            .delete_documents(
+                document_ids_to_delete,
+                None,
+                &self.document_storage,
+                self.sender.clone(),
+            )
             .await?;
 
         Ok(())
     }
 
+    /// Deletes the documents matching all the filters.
+    /// Returns the number of deleted documents.
+    pub async fn delete_documents_by_filter(
+        &self,
+        write_api_key: ApiKey,
+        collection_id: CollectionId,
+        where_filter: HashMap<String, Filter>,
+    ) -> Result<usize> {
+        let collection = self
+            .collections
+            .get_collection(collection_id.clone())
+            .await
+            .context("Collection not found")?;
+
+        collection.check_write_api_key(write_api_key)?;
+
+        let deleted = collection
+            .delete_documents_by_filter(where_filter, &self.document_storage, self.sender.clone())
+            .await?;
+        info!(?collection_id, deleted, "Documents deleted by filter");
+
+        Ok(deleted)
+    }
+
+    /// Merges `patch` into the document with id `doc_id`.
+    /// Only the embeddings whose input is changed are calculated again.
+    /// With `if_version`, the document is patched only if its version is that.
+    /// Returns the new version of the document.
+    pub async fn patch_document(
+        &self,
+        write_api_key: ApiKey,
+        collection_id: CollectionId,
+        doc_id: String,
+        patch: Map<String, Value>,
+        if_version: Option<u64>,
+    ) -> Result<u64> {
+        let collection = self
+            .collections
+            .get_collection(collection_id.clone())
+            .await
+            .context("Collection not found")?;
+
+        collection.check_write_api_key(write_api_key)?;
+
+        let m = DOCUMENT_PROCESS_METRIC.create(DocumentProcessLabels {
+            collection: collection_id.0.clone(),
+        });
+
+        let new_doc_id = DocumentId(self.document_count.fetch_add(1, Ordering::Relaxed));
+        info!(?new_doc_id, "Patching document");
+        collection
+            .patch_document(
+                new_doc_id,
+                doc_id,
+                patch,
+                if_version,
+                &self.document_storage,
+                self.sender.clone(),
+                self.hook_runtime.clone(),
+            )
+            .await
+            .context("Cannot patch document")?;
+
+        drop(m);
+
+        Ok(new_doc_id.version())
+    }
+
     pub async fn insert_javascript_hook(
         &self,
         write_api_key: ApiKey,
@@ -467,3 +1541,27 @@ fn embedding_model_default() -> OramaModelSerializable {
 fn default_insert_batch_commit_size() -> u64 {
     1_000
 }
+
+/// Forces the id to be set, if not set
+fn ensure_document_id(doc: &mut Document) {
+    match doc.get("id") {
+        None => {
+            doc.inner.insert(
+                "id".to_string(),
+                serde_json::Value::String(cuid2::create_id()),
+            );
+        }
+        Some(doc_id_value) if !doc_id_value.is_string() => {
+            // The search result contains the document id and it is defined as a string.
+            // So, if the original document id is not a string, we should overwrite it with a new one
+            // Anyway, this implies the loss of the original document id. For instance we could support number as well
+            // TODO: think better
+            warn!("Document id is not a string, overwriting it with new one");
+            doc.inner.insert(
+                "id".to_string(),
+                serde_json::Value::String(cuid2::create_id()),
+            );
+        }
+        Some(_) => {}
+    }
+}
@@ -1,8 +1,12 @@
 use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{collections::HashMap, fmt::Debug};
 
+use anyhow::{anyhow, Context, Result};
+use op_log::{OpLogError, OpLogReader, OpLogWriter};
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 use crate::collection_manager::dto::{FieldId, Number};
 use crate::metrics::{Empty, OPERATION_GAUGE};
@@ -10,6 +14,18 @@ use crate::types::{CollectionId, DocumentId, RawJSONDocument};
 
 use crate::collection_manager::dto::{ApiKey, TypedField};
 
+mod encoding;
+mod op_log;
+mod stream;
+
+pub use op_log::{FileSideChannelConfig, FsyncPolicy, LogConsumer};
+pub use stream::{
+    start_operation_stream_server, OperationStreamServerConfig, RemoteSideChannelConfig,
+};
+
+/// How long to wait before reading the operation log again, after an IO error
+const READ_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Debug, Clone)]
 pub enum GenericWriteOperation {
     CreateCollection,
@@ -18,6 +34,17 @@ pub enum GenericWriteOperation {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Term(pub String);
 
+/// The infix terms are indexed with this prefix, so they never match the normal tokens
+pub const INFIX_TERM_PREFIX: char = '\u{1}';
+/// The shortest substring indexed (and searched) for the infix search
+pub const INFIX_MIN_LENGTH: usize = 2;
+
+/// The term indexed for a keyword field, and searched for a query on it.
+/// The keywords are matched as a whole, ignoring the case.
+pub fn keyword_term(value: &str) -> String {
+    value.trim().to_lowercase()
+}
+
 #[derive(Debug, Clone)]
 pub struct TermStringField {
     pub positions: Vec<usize>,
@@ -32,7 +59,9 @@ pub enum DocumentFieldIndexOperation {
         terms: InsertStringTerms,
     },
     IndexEmbedding {
-        value: Vec<f32>,
+        /// One vector per chunk
+        vectors: Vec<Vec<f32>>,
+        chunks: Vec<String>,
     },
     IndexNumber {
         value: Number,
@@ -51,12 +80,25 @@ pub enum CollectionWriteOperation {
     DeleteDocuments {
         doc_ids: Vec<DocumentId>,
     },
+    /// The document is excluded from the search results after `expires_at`, in milliseconds.
+    /// The write side deletes it later.
+    SetExpiration {
+        doc_id: DocumentId,
+        expires_at: i64,
+    },
     CreateField {
         field_id: FieldId,
         field_name: String,
         field: TypedField,
     },
     Index(DocumentId, FieldId, DocumentFieldIndexOperation),
+    /// Indexes the embeddings of `from` also for `to`.
+    /// Used when a document is replaced but the embedding input is unchanged.
+    CopyEmbedding {
+        field_id: FieldId,
+        from: DocumentId,
+        to: DocumentId,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -68,15 +110,49 @@ pub enum WriteOperation {
         // TODO: add params
     },
     Collection(CollectionId, CollectionWriteOperation),
+    /// Removes the collection with its documents
+    DeleteCollection(CollectionId),
+    /// Creates the alias, or points it to another collection
+    SetAlias {
+        alias: CollectionId,
+        collection_id: CollectionId,
+    },
+    DeleteAlias {
+        alias: CollectionId,
+    },
+}
+
+impl WriteOperation {
+    /// The highest document id the operation refers to, if any
+    pub fn document_id(&self) -> Option<DocumentId> {
+        let WriteOperation::Collection(_, op) = self else {
+            return None;
+        };
+        match op {
+            CollectionWriteOperation::InsertDocument { doc_id, .. }
+            | CollectionWriteOperation::SetExpiration { doc_id, .. }
+            | CollectionWriteOperation::Index(doc_id, _, _) => Some(*doc_id),
+            CollectionWriteOperation::CopyEmbedding { to, .. } => Some(*to),
+            CollectionWriteOperation::DeleteDocuments { doc_ids } => doc_ids.iter().max().copied(),
+            CollectionWriteOperation::CreateField { .. } => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Offset(pub u64);
 
+#[derive(Clone)]
+enum OperationSenderInner {
+    /// Locked to take the offset and to enqueue the operation
+    InMemory(Arc<tokio::sync::Mutex<tokio::sync::mpsc::Sender<(Offset, WriteOperation)>>>),
+    File(Arc<OpLogWriter>),
+}
+
 #[derive(Clone)]
 pub struct OperationSender {
     offset_counter: Arc<AtomicU64>,
-    sender: tokio::sync::mpsc::Sender<(Offset, WriteOperation)>,
+    inner: OperationSenderInner,
 }
 
 impl OperationSender {
@@ -86,33 +162,136 @@ impl OperationSender {
                 .load(std::sync::atomic::Ordering::SeqCst),
         )
     }
+    /// The counter never goes back: the file side channel already contains the operations before it
     pub fn set_offset(&self, offset: Offset) {
         self.offset_counter
-            .store(offset.0, std::sync::atomic::Ordering::SeqCst);
+            .fetch_max(offset.0, std::sync::atomic::Ordering::SeqCst);
     }
 
-    pub async fn send(
-        &self,
-        operation: WriteOperation,
-    ) -> Result<(), tokio::sync::mpsc::error::SendError<(Offset, WriteOperation)>> {
-        OPERATION_GAUGE.create(Empty {}).increment_by(1);
-        let offset = self
-            .offset_counter
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-        self.sender.send((Offset(offset), operation)).await?;
+    pub async fn send(&self, operation: WriteOperation) -> Result<()> {
+        match &self.inner {
+            OperationSenderInner::InMemory(sender) => {
+                OPERATION_GAUGE.create(Empty {}).increment_by(1);
+                // The offset is taken under the lock, so the operations are received sorted by offset
+                let sender = sender.lock().await;
+                let offset = self
+                    .offset_counter
+                    .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                sender
+                    .send((Offset(offset), operation))
+                    .await
+                    .map_err(|e| anyhow!("The read side is stopped: {:?}", e.0 .0))?;
+            }
+            OperationSenderInner::File(writer) => {
+                let writer = writer.clone();
+                let offset_counter = self.offset_counter.clone();
+                tokio::task::spawn_blocking(move || writer.append(&offset_counter, &operation))
+                    .await
+                    .context("The operation log writer panicked")??;
+            }
+        }
         Ok(())
     }
+
+    /// Syncs the file side channel to the disk, whatever its fsync policy
+    pub async fn sync(&self) -> Result<()> {
+        match &self.inner {
+            OperationSenderInner::InMemory(_) => Ok(()),
+            OperationSenderInner::File(writer) => {
+                let writer = writer.clone();
+                tokio::task::spawn_blocking(move || writer.sync())
+                    .await
+                    .context("The operation log sync panicked")?
+            }
+        }
+    }
+
+    /// Registers a consumer of the file side channel, which committed the operations until `committed`.
+    /// `None` for the in-memory side channel: it has no retention.
+    pub fn consumer(&self, committed: Offset) -> Option<LogConsumer> {
+        match &self.inner {
+            OperationSenderInner::InMemory(_) => None,
+            OperationSenderInner::File(writer) => Some(writer.register_consumer(committed)),
+        }
+    }
+
+    /// The operations from `from`.
+    /// On a crash, they are in the file side channel but not in the committed write side.
+    pub async fn tail(&self, from: Offset) -> Result<Vec<WriteOperation>> {
+        // The in-memory side channel is lost on a crash too
+        let OperationSenderInner::File(writer) = &self.inner else {
+            return Ok(vec![]);
+        };
+        let mut reader = OpLogReader::new(writer.dir().to_path_buf(), from);
+        tokio::task::spawn_blocking(move || {
+            let mut ops = vec![];
+            while let Some((_, op)) = reader.try_next()? {
+                ops.push(op);
+            }
+            Ok(ops)
+        })
+        .await
+        .context("The operation log reader panicked")?
+    }
+}
+
+enum OperationReceiverInner {
+    InMemory(tokio::sync::mpsc::Receiver<(Offset, WriteOperation)>),
+    File(OpLogReader),
+    Remote(tokio::sync::mpsc::Receiver<(Offset, WriteOperation)>),
 }
 
 pub struct OperationReceiver {
-    receiver: tokio::sync::mpsc::Receiver<(Offset, WriteOperation)>,
+    inner: OperationReceiverInner,
 }
 
 impl OperationReceiver {
-    pub async fn recv(&mut self) -> Option<(Offset, WriteOperation)> {
-        let r = self.receiver.recv().await;
-        OPERATION_GAUGE.create(Empty {}).decrement_by(1);
-        r
+    /// Tails the file side channel from the operation with offset `from`
+    pub fn file(config: &FileSideChannelConfig, from: Offset) -> Self {
+        Self {
+            inner: OperationReceiverInner::File(OpLogReader::new(config.data_dir.clone(), from)),
+        }
+    }
+
+    /// Receives the operations from the operation stream server of the write side,
+    /// from the operation with offset `from`. The `committed` offsets of the read side
+    /// are sent to the write side, for the retention of its log.
+    /// Returns the offset of the last operation in the log of the write side too.
+    pub async fn remote(
+        config: &RemoteSideChannelConfig,
+        from: Offset,
+        committed: tokio::sync::watch::Receiver<Offset>,
+    ) -> Result<(Self, Offset)> {
+        let (receiver, last_offset) = stream::connect(config.clone(), from, committed).await?;
+        Ok((
+            Self {
+                inner: OperationReceiverInner::Remote(receiver),
+            },
+            last_offset,
+        ))
+    }
+
+    /// Returns `None` once the write side is stopped.
+    /// Fails if the operation log cannot be read anymore: the read side would miss the operations.
+    pub async fn recv(&mut self) -> Result<Option<(Offset, WriteOperation)>> {
+        match &mut self.inner {
+            OperationReceiverInner::InMemory(receiver) => {
+                let r = receiver.recv().await;
+                OPERATION_GAUGE.create(Empty {}).decrement_by(1);
+                Ok(r)
+            }
+            OperationReceiverInner::File(reader) => loop {
+                match reader.next().await {
+                    Ok(op) => return Ok(Some(op)),
+                    Err(e) if e.is::<OpLogError>() => return Err(e),
+                    Err(e) => {
+                        warn!("Cannot read the operation log: {:?}. Retrying", e);
+                        tokio::time::sleep(READ_RETRY_INTERVAL).await;
+                    }
+                }
+            },
+            OperationReceiverInner::Remote(receiver) => Ok(receiver.recv().await),
+        }
     }
 }
 
@@ -126,8 +305,56 @@ pub fn channel(capacity: usize) -> (OperationSender, OperationReceiver) {
             // This is a bit of a hack, we should model this better
             // TODO: model this better
             offset_counter: Arc::new(AtomicU64::new(1)),
-            sender,
+            inner: OperationSenderInner::InMemory(Arc::new(tokio::sync::Mutex::new(sender))),
+        },
+        OperationReceiver {
+            inner: OperationReceiverInner::InMemory(receiver),
         },
-        OperationReceiver { receiver },
     )
 }
+
+/// The sender of the file side channel: the operations are appended to the log in `config.data_dir`
+pub fn file_channel(config: FileSideChannelConfig) -> Result<OperationSender> {
+    let (writer, next_offset) = OpLogWriter::open(config)?;
+
+    Ok(OperationSender {
+        offset_counter: Arc::new(AtomicU64::new(next_offset.0)),
+        inner: OperationSenderInner::File(writer),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_in_memory_channel_is_sorted_by_offset() -> Result<()> {
+        let (sender, mut receiver) = channel(10);
+
+        let sends: Vec<_> = (0..100)
+            .map(|i| {
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    sender
+                        .send(WriteOperation::DeleteCollection(CollectionId(format!(
+                            "collection-{}",
+                            i
+                        ))))
+                        .await
+                })
+            })
+            .collect();
+
+        let mut last_offset = Offset(0);
+        for _ in 0..100 {
+            let (offset, _) = receiver.recv().await?.unwrap();
+            assert!(offset > last_offset);
+            last_offset = offset;
+        }
+        for send in sends {
+            send.await??;
+        }
+
+        Ok(())
+    }
+}
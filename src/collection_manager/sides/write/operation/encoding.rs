@@ -0,0 +1,555 @@
+//! The binary encoding of the write operations, used by the file side channel and the operation stream.
+//! The encoding is stable: the tags of the variants are never changed or reused,
+//! so a new variant gets a new tag and the old logs are still readable.
+//! The numbers are little endian, the strings and the lists are prefixed by their length.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use redact::Secret;
+
+use crate::{
+    ai::OramaModel,
+    collection_manager::dto::{
+        ApiKey, DocumentFields, EmbeddingTypedField, FieldId, Number, TypedField,
+    },
+    nlp::locales::Locale,
+    types::{CollectionId, DocumentId, RawJSONDocument},
+};
+
+use super::{
+    CollectionWriteOperation, DocumentFieldIndexOperation, Offset, Term, TermStringField,
+    WriteOperation,
+};
+
+pub fn encode(offset: Offset, op: &WriteOperation, buf: &mut Vec<u8>) {
+    put_u64(buf, offset.0);
+    match op {
+        WriteOperation::CreateCollection { id, read_api_key } => {
+            put_u8(buf, 0);
+            put_str(buf, &id.0);
+            put_str(buf, read_api_key.0.expose_secret());
+        }
+        WriteOperation::Collection(id, op) => {
+            put_u8(buf, 1);
+            put_str(buf, &id.0);
+            encode_collection_operation(op, buf);
+        }
+        WriteOperation::DeleteCollection(id) => {
+            put_u8(buf, 2);
+            put_str(buf, &id.0);
+        }
+        WriteOperation::SetAlias {
+            alias,
+            collection_id,
+        } => {
+            put_u8(buf, 3);
+            put_str(buf, &alias.0);
+            put_str(buf, &collection_id.0);
+        }
+        WriteOperation::DeleteAlias { alias } => {
+            put_u8(buf, 4);
+            put_str(buf, &alias.0);
+        }
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<(Offset, WriteOperation)> {
+    let mut decoder = Decoder { bytes };
+
+    let offset = Offset(decoder.u64()?);
+    let op = match decoder.u8()? {
+        0 => WriteOperation::CreateCollection {
+            id: CollectionId(decoder.string()?),
+            read_api_key: ApiKey(Secret::new(decoder.string()?)),
+        },
+        1 => WriteOperation::Collection(
+            CollectionId(decoder.string()?),
+            decode_collection_operation(&mut decoder)?,
+        ),
+        2 => WriteOperation::DeleteCollection(CollectionId(decoder.string()?)),
+        3 => WriteOperation::SetAlias {
+            alias: CollectionId(decoder.string()?),
+            collection_id: CollectionId(decoder.string()?),
+        },
+        4 => WriteOperation::DeleteAlias {
+            alias: CollectionId(decoder.string()?),
+        },
+        tag => bail!("Unknown write operation tag {}", tag),
+    };
+
+    if !decoder.bytes.is_empty() {
+        bail!(
+            "{} unexpected bytes after the operation",
+            decoder.bytes.len()
+        );
+    }
+
+    Ok((offset, op))
+}
+
+fn encode_collection_operation(op: &CollectionWriteOperation, buf: &mut Vec<u8>) {
+    match op {
+        CollectionWriteOperation::InsertDocument { doc_id, doc } => {
+            put_u8(buf, 0);
+            put_u64(buf, doc_id.0);
+            match &doc.id {
+                Some(id) => {
+                    put_u8(buf, 1);
+                    put_str(buf, id);
+                }
+                None => put_u8(buf, 0),
+            }
+            put_str(buf, doc.inner.get());
+        }
+        CollectionWriteOperation::DeleteDocuments { doc_ids } => {
+            put_u8(buf, 1);
+            put_len(buf, doc_ids.len());
+            for doc_id in doc_ids {
+                put_u64(buf, doc_id.0);
+            }
+        }
+        CollectionWriteOperation::SetExpiration { doc_id, expires_at } => {
+            put_u8(buf, 2);
+            put_u64(buf, doc_id.0);
+            buf.extend_from_slice(&expires_at.to_le_bytes());
+        }
+        CollectionWriteOperation::CreateField {
+            field_id,
+            field_name,
+            field,
+        } => {
+            put_u8(buf, 3);
+            put_u16(buf, field_id.0);
+            put_str(buf, field_name);
+            encode_typed_field(field, buf);
+        }
+        CollectionWriteOperation::Index(doc_id, field_id, op) => {
+            put_u8(buf, 4);
+            put_u64(buf, doc_id.0);
+            put_u16(buf, field_id.0);
+            encode_index_operation(op, buf);
+        }
+        CollectionWriteOperation::CopyEmbedding { field_id, from, to } => {
+            put_u8(buf, 5);
+            put_u16(buf, field_id.0);
+            put_u64(buf, from.0);
+            put_u64(buf, to.0);
+        }
+    }
+}
+
+fn decode_collection_operation(decoder: &mut Decoder) -> Result<CollectionWriteOperation> {
+    let op = match decoder.u8()? {
+        0 => {
+            let doc_id = DocumentId(decoder.u64()?);
+            let id = match decoder.u8()? {
+                0 => None,
+                _ => Some(decoder.string()?),
+            };
+            let inner = serde_json::value::RawValue::from_string(decoder.string()?)
+                .context("Invalid document")?;
+            CollectionWriteOperation::InsertDocument {
+                doc_id,
+                doc: RawJSONDocument { id, inner },
+            }
+        }
+        1 => {
+            let len = decoder.len()?;
+            let mut doc_ids = Vec::with_capacity(len);
+            for _ in 0..len {
+                doc_ids.push(DocumentId(decoder.u64()?));
+            }
+            CollectionWriteOperation::DeleteDocuments { doc_ids }
+        }
+        2 => CollectionWriteOperation::SetExpiration {
+            doc_id: DocumentId(decoder.u64()?),
+            expires_at: i64::from_le_bytes(decoder.array()?),
+        },
+        3 => CollectionWriteOperation::CreateField {
+            field_id: FieldId(decoder.u16()?),
+            field_name: decoder.string()?,
+            field: decode_typed_field(decoder)?,
+        },
+        4 => CollectionWriteOperation::Index(
+            DocumentId(decoder.u64()?),
+            FieldId(decoder.u16()?),
+            decode_index_operation(decoder)?,
+        ),
+        5 => CollectionWriteOperation::CopyEmbedding {
+            field_id: FieldId(decoder.u16()?),
+            from: DocumentId(decoder.u64()?),
+            to: DocumentId(decoder.u64()?),
+        },
+        tag => bail!("Unknown collection operation tag {}", tag),
+    };
+
+    Ok(op)
+}
+
+fn encode_typed_field(field: &TypedField, buf: &mut Vec<u8>) {
+    match field {
+        TypedField::Text(locale) => {
+            put_u8(buf, 0);
+            put_str(buf, locale.to_iso_code_639_2());
+        }
+        TypedField::Keyword => put_u8(buf, 1),
+        TypedField::Embedding(embedding) => {
+            put_u8(buf, 2);
+            put_str(buf, embedding.model.as_str_name());
+            // `DocumentFields` has a stable JSON representation: it's part of the API
+            let document_fields = serde_json::to_string(&embedding.document_fields)
+                .expect("DocumentFields is always serializable");
+            put_str(buf, &document_fields);
+        }
+        TypedField::Number => put_u8(buf, 3),
+        TypedField::Datetime => put_u8(buf, 4),
+        TypedField::Bool => put_u8(buf, 5),
+    }
+}
+
+fn decode_typed_field(decoder: &mut Decoder) -> Result<TypedField> {
+    let field = match decoder.u8()? {
+        0 => TypedField::Text(Locale::from_str(&decoder.string()?)?),
+        1 => TypedField::Keyword,
+        2 => {
+            let model = decoder.string()?;
+            let model = OramaModel::from_str_name(&model)
+                .with_context(|| format!("Unknown model {}", model))?;
+            let document_fields: DocumentFields =
+                serde_json::from_str(&decoder.string()?).context("Invalid document fields")?;
+            TypedField::Embedding(EmbeddingTypedField {
+                model,
+                document_fields,
+            })
+        }
+        3 => TypedField::Number,
+        4 => TypedField::Datetime,
+        5 => TypedField::Bool,
+        tag => bail!("Unknown field type tag {}", tag),
+    };
+
+    Ok(field)
+}
+
+fn encode_index_operation(op: &DocumentFieldIndexOperation, buf: &mut Vec<u8>) {
+    match op {
+        DocumentFieldIndexOperation::IndexString {
+            field_length,
+            terms,
+        } => {
+            put_u8(buf, 0);
+            put_u16(buf, *field_length);
+            put_len(buf, terms.len());
+            for (term, field) in terms {
+                put_str(buf, &term.0);
+                put_len(buf, field.positions.len());
+                for position in &field.positions {
+                    put_u64(buf, *position as u64);
+                }
+            }
+        }
+        DocumentFieldIndexOperation::IndexEmbedding { vectors, chunks } => {
+            put_u8(buf, 1);
+            put_len(buf, vectors.len());
+            for vector in vectors {
+                put_len(buf, vector.len());
+                for value in vector {
+                    buf.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            put_len(buf, chunks.len());
+            for chunk in chunks {
+                put_str(buf, chunk);
+            }
+        }
+        DocumentFieldIndexOperation::IndexNumber { value } => {
+            put_u8(buf, 2);
+            match value {
+                Number::I32(value) => {
+                    put_u8(buf, 0);
+                    buf.extend_from_slice(&value.to_le_bytes());
+                }
+                Number::F32(value) => {
+                    put_u8(buf, 1);
+                    buf.extend_from_slice(&value.to_le_bytes());
+                }
+                Number::I64(value) => {
+                    put_u8(buf, 2);
+                    buf.extend_from_slice(&value.to_le_bytes());
+                }
+                Number::F64(value) => {
+                    put_u8(buf, 3);
+                    buf.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        DocumentFieldIndexOperation::IndexBoolean { value } => {
+            put_u8(buf, 3);
+            put_u8(buf, *value as u8);
+        }
+    }
+}
+
+fn decode_index_operation(decoder: &mut Decoder) -> Result<DocumentFieldIndexOperation> {
+    let op = match decoder.u8()? {
+        0 => {
+            let field_length = decoder.u16()?;
+            let len = decoder.len()?;
+            let mut terms = HashMap::with_capacity(len);
+            for _ in 0..len {
+                let term = Term(decoder.string()?);
+                let positions_len = decoder.len()?;
+                let mut positions = Vec::with_capacity(positions_len);
+                for _ in 0..positions_len {
+                    positions.push(decoder.u64()? as usize);
+                }
+                terms.insert(term, TermStringField { positions });
+            }
+            DocumentFieldIndexOperation::IndexString {
+                field_length,
+                terms,
+            }
+        }
+        1 => {
+            let len = decoder.len()?;
+            let mut vectors = Vec::with_capacity(len);
+            for _ in 0..len {
+                let dimensions = decoder.len()?;
+                let mut vector = Vec::with_capacity(dimensions);
+                for _ in 0..dimensions {
+                    vector.push(f32::from_le_bytes(decoder.array()?));
+                }
+                vectors.push(vector);
+            }
+            let len = decoder.len()?;
+            let mut chunks = Vec::with_capacity(len);
+            for _ in 0..len {
+                chunks.push(decoder.string()?);
+            }
+            DocumentFieldIndexOperation::IndexEmbedding { vectors, chunks }
+        }
+        2 => {
+            let value = match decoder.u8()? {
+                0 => Number::I32(i32::from_le_bytes(decoder.array()?)),
+                1 => Number::F32(f32::from_le_bytes(decoder.array()?)),
+                2 => Number::I64(i64::from_le_bytes(decoder.array()?)),
+                3 => Number::F64(f64::from_le_bytes(decoder.array()?)),
+                tag => bail!("Unknown number tag {}", tag),
+            };
+            DocumentFieldIndexOperation::IndexNumber { value }
+        }
+        3 => DocumentFieldIndexOperation::IndexBoolean {
+            value: decoder.u8()? != 0,
+        },
+        tag => bail!("Unknown index operation tag {}", tag),
+    };
+
+    Ok(op)
+}
+
+fn put_u8(buf: &mut Vec<u8>, value: u8) {
+    buf.push(value);
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_len(buf: &mut Vec<u8>, len: usize) {
+    buf.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn put_str(buf: &mut Vec<u8>, value: &str) {
+    put_len(buf, value.len());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        if self.bytes.len() < len {
+            bail!("Unexpected end of the operation");
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.len()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).context("Invalid UTF-8 string")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::collection_manager::sides::hooks::HookName;
+
+    use super::*;
+
+    fn roundtrip(op: WriteOperation) -> WriteOperation {
+        let mut buf = vec![];
+        encode(Offset(42), &op, &mut buf);
+        let (offset, decoded) = decode(&buf).unwrap();
+        assert_eq!(offset, Offset(42));
+        decoded
+    }
+
+    #[test]
+    fn test_encoding_roundtrip() {
+        let op = roundtrip(WriteOperation::CreateCollection {
+            id: CollectionId("books".to_string()),
+            read_api_key: ApiKey(Secret::new("my-read-api-key".to_string())),
+        });
+        assert!(matches!(
+            op,
+            WriteOperation::CreateCollection { id, read_api_key }
+                if id.0 == "books" && read_api_key.0.expose_secret() == "my-read-api-key"
+        ));
+
+        let doc = RawJSONDocument::try_from(json!({ "id": "1", "title": "Inferno" })).unwrap();
+        let op = roundtrip(WriteOperation::Collection(
+            CollectionId("books".to_string()),
+            CollectionWriteOperation::InsertDocument {
+                doc_id: DocumentId(7),
+                doc,
+            },
+        ));
+        assert!(matches!(
+            op,
+            WriteOperation::Collection(_, CollectionWriteOperation::InsertDocument { doc_id, doc })
+                if doc_id == DocumentId(7)
+                    && doc.id.as_deref() == Some("1")
+                    && doc.inner.get() == r#"{"id":"1","title":"Inferno"}"#
+        ));
+
+        let op = roundtrip(WriteOperation::Collection(
+            CollectionId("books".to_string()),
+            CollectionWriteOperation::CreateField {
+                field_id: FieldId(3),
+                field_name: "vector".to_string(),
+                field: TypedField::Embedding(EmbeddingTypedField {
+                    model: OramaModel::BgeSmall,
+                    document_fields: DocumentFields::Hook(HookName::SelectEmbeddingsProperties),
+                }),
+            },
+        ));
+        assert!(matches!(
+            op,
+            WriteOperation::Collection(
+                _,
+                CollectionWriteOperation::CreateField {
+                    field_id: FieldId(3),
+                    field: TypedField::Embedding(EmbeddingTypedField {
+                        model: OramaModel::BgeSmall,
+                        document_fields: DocumentFields::Hook(HookName::SelectEmbeddingsProperties),
+                    }),
+                    ..
+                }
+            )
+        ));
+
+        let op = roundtrip(WriteOperation::Collection(
+            CollectionId("books".to_string()),
+            CollectionWriteOperation::Index(
+                DocumentId(7),
+                FieldId(1),
+                DocumentFieldIndexOperation::IndexString {
+                    field_length: 2,
+                    terms: HashMap::from([(
+                        Term("inferno".to_string()),
+                        TermStringField {
+                            positions: vec![0, 5],
+                        },
+                    )]),
+                },
+            ),
+        ));
+        assert!(matches!(
+            op,
+            WriteOperation::Collection(
+                _,
+                CollectionWriteOperation::Index(
+                    DocumentId(7),
+                    FieldId(1),
+                    DocumentFieldIndexOperation::IndexString { field_length: 2, terms }
+                )
+            ) if terms[&Term("inferno".to_string())].positions == vec![0, 5]
+        ));
+
+        let op = roundtrip(WriteOperation::Collection(
+            CollectionId("books".to_string()),
+            CollectionWriteOperation::Index(
+                DocumentId(7),
+                FieldId(2),
+                DocumentFieldIndexOperation::IndexNumber {
+                    value: Number::F64(1e40),
+                },
+            ),
+        ));
+        assert!(matches!(
+            op,
+            WriteOperation::Collection(
+                _,
+                CollectionWriteOperation::Index(
+                    _,
+                    _,
+                    DocumentFieldIndexOperation::IndexNumber { value: Number::F64(value) }
+                )
+            ) if value == 1e40
+        ));
+    }
+
+    #[test]
+    fn test_encoding_invalid() {
+        let mut buf = vec![];
+        encode(
+            Offset(1),
+            &WriteOperation::DeleteCollection(CollectionId("books".to_string())),
+            &mut buf,
+        );
+
+        assert!(decode(&buf[..buf.len() - 1]).is_err());
+
+        buf.push(0);
+        assert!(decode(&buf).is_err());
+
+        buf.pop();
+        buf[8] = 200;
+        assert!(decode(&buf).is_err());
+    }
+}
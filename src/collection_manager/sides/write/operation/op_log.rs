@@ -0,0 +1,871 @@
+//! The file side channel: an append-only log of the write operations, split in segments.
+//! Every segment starts with `SEGMENT_MAGIC` and is named after the offset of its first operation.
+//! Every record is `[payload length: u32][crc32 of the payload: u32][payload]`,
+//! where the payload is the offset followed by the operation, as in `encoding`.
+//! With the retention, the segments committed by every consumer of the log are removed.
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use duration_str::deserialize_duration;
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use super::{encoding, Offset, WriteOperation};
+
+const SEGMENT_MAGIC: &[u8; 8] = b"ORAMAOL1";
+const SEGMENT_EXTENSION: &str = "log";
+const RECORD_HEADER_SIZE: usize = 8;
+/// A bigger record is considered corrupted
+const MAX_RECORD_SIZE: usize = 1 << 30;
+/// How often the reader checks for new operations at the end of the log
+const TAIL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct FileSideChannelConfig {
+    pub data_dir: PathBuf,
+    /// A new segment is started when the current one reaches this size, in bytes
+    #[serde(default = "default_segment_size")]
+    pub segment_size: u64,
+    #[serde(default)]
+    pub fsync: FsyncPolicy,
+    /// Removes the segments committed by the write side and by the read sides known to the writer:
+    /// the one in the same process and the connected ones.
+    /// A read side disconnected for longer than `retention_grace_period`
+    /// has to be restored from a snapshot.
+    #[serde(default)]
+    pub retention: bool,
+    /// How long the segments not committed by a disconnected read side are kept,
+    /// so it can resume from the log once connected again
+    #[serde(
+        default = "default_retention_grace_period",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub retention_grace_period: Duration,
+}
+
+/// When the appended operations are synced to the disk.
+/// The operations are always visible to the readers as soon as they are appended.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum FsyncPolicy {
+    /// Every operation is synced before it's considered sent
+    #[serde(rename = "always")]
+    Always,
+    /// The operations are synced at most every interval:
+    /// on a crash of the machine, the last interval can be lost
+    #[serde(rename = "interval")]
+    Interval(#[serde(deserialize_with = "deserialize_duration")] Duration),
+    /// The OS decides when to sync
+    #[serde(rename = "never")]
+    Never,
+}
+
+impl Default for FsyncPolicy {
+    fn default() -> Self {
+        FsyncPolicy::Interval(Duration::from_secs(1))
+    }
+}
+
+/// The log cannot be read from the requested offset: retrying doesn't help
+#[derive(Debug, Error)]
+pub enum OpLogError {
+    #[error("The operation log is corrupted")]
+    Corrupted,
+    #[error("The operation {requested} is not in the log: the oldest one is {oldest}")]
+    Missing { requested: u64, oldest: u64 },
+}
+
+fn default_segment_size() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_retention_grace_period() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+struct Segment {
+    first_offset: u64,
+    file: BufWriter<File>,
+    size: u64,
+}
+
+struct WriterState {
+    segment: Option<Segment>,
+    /// Some operations are not synced yet
+    dirty: bool,
+    last_sync: Instant,
+    buf: Vec<u8>,
+}
+
+/// The offsets committed by the consumers of the log
+#[derive(Default)]
+struct Consumers {
+    next_id: u64,
+    committed: HashMap<u64, Offset>,
+    /// The offsets committed by the dropped consumers, with the drop time:
+    /// they are considered for the grace period
+    dropped: Vec<(Instant, Offset)>,
+}
+
+pub struct OpLogWriter {
+    config: FileSideChannelConfig,
+    state: Mutex<WriterState>,
+    consumers: Mutex<Consumers>,
+}
+
+impl OpLogWriter {
+    /// Opens the log, truncating the incomplete record left by a crash, if any.
+    /// Returns the writer and the offset of the next operation.
+    pub fn open(config: FileSideChannelConfig) -> Result<(Arc<Self>, Offset)> {
+        std::fs::create_dir_all(&config.data_dir).with_context(|| {
+            format!("Cannot create the operation log dir {:?}", config.data_dir)
+        })?;
+
+        let segments = list_segments(&config.data_dir)?;
+        let (segment, next_offset) = match segments.last() {
+            Some(&first_offset) => {
+                let (segment, last_offset) = open_last_segment(&config.data_dir, first_offset)?;
+                let next_offset = last_offset.map_or(first_offset, |offset| offset + 1);
+                (Some(segment), next_offset)
+            }
+            None => (None, 1),
+        };
+        info!(dir = ?config.data_dir, next_offset, "Operation log opened");
+
+        let writer = Arc::new(Self {
+            state: Mutex::new(WriterState {
+                segment,
+                dirty: false,
+                last_sync: Instant::now(),
+                buf: Vec::new(),
+            }),
+            consumers: Default::default(),
+            config,
+        });
+
+        if let FsyncPolicy::Interval(interval) = writer.config.fsync {
+            let weak = Arc::downgrade(&writer);
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    let Some(writer) = weak.upgrade() else {
+                        break;
+                    };
+                    let output = tokio::task::spawn_blocking(move || writer.sync())
+                        .await
+                        .context("The operation log sync panicked")
+                        .and_then(|output| output);
+                    if let Err(e) = output {
+                        warn!("Cannot sync the operation log: {:?}", e);
+                    }
+                }
+            });
+        }
+
+        Ok((writer, Offset(next_offset)))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.config.data_dir
+    }
+
+    /// Appends the operation with the next offset taken from `offset_counter`.
+    /// The offset is taken under the lock, so the log is always sorted by offset.
+    /// It blocks on the disk IO: not to be called on the async threads.
+    pub fn append(&self, offset_counter: &AtomicU64, op: &WriteOperation) -> Result<Offset> {
+        let mut state = self
+            .state
+            .lock()
+            .expect("the operation log lock is poisoned");
+        let offset = offset_counter.fetch_add(1, Ordering::SeqCst);
+
+        let mut buf = std::mem::take(&mut state.buf);
+        buf.clear();
+        buf.extend_from_slice(&[0; RECORD_HEADER_SIZE]);
+        encoding::encode(Offset(offset), op, &mut buf);
+        let payload_len = (buf.len() - RECORD_HEADER_SIZE) as u32;
+        let checksum = crc32fast::hash(&buf[RECORD_HEADER_SIZE..]);
+        buf[0..4].copy_from_slice(&payload_len.to_le_bytes());
+        buf[4..8].copy_from_slice(&checksum.to_le_bytes());
+
+        let output = self.write_record(&mut state, offset, &buf);
+        state.buf = buf;
+        output?;
+
+        Ok(Offset(offset))
+    }
+
+    fn write_record(&self, state: &mut WriterState, offset: u64, record: &[u8]) -> Result<()> {
+        let roll = match &state.segment {
+            Some(segment) => segment.size >= self.config.segment_size,
+            None => true,
+        };
+        if roll {
+            if let Some(mut segment) = state.segment.take() {
+                segment
+                    .file
+                    .flush()
+                    .context("Cannot flush the operation log")?;
+                if self.config.fsync != FsyncPolicy::Never {
+                    segment
+                        .file
+                        .get_ref()
+                        .sync_data()
+                        .context("Cannot sync the operation log")?;
+                }
+            }
+            state.segment = Some(self.create_segment(offset)?);
+        }
+
+        let segment = state.segment.as_mut().expect("the segment is just created");
+        // The readers see the record only after the flush
+        let output = segment
+            .file
+            .write_all(record)
+            .and_then(|_| segment.file.flush());
+        if let Err(e) = output {
+            // The partial record is removed, otherwise the readers would stop at it.
+            // The next operation starts a new segment.
+            if let Some(segment) = state.segment.take() {
+                let (file, _) = segment.file.into_parts();
+                if let Err(e) = file.set_len(segment.size) {
+                    warn!("Cannot truncate the operation log segment: {:?}", e);
+                }
+            }
+            return Err(e).context("Cannot append to the operation log");
+        }
+        segment.size += record.len() as u64;
+        state.dirty = true;
+
+        let sync = match self.config.fsync {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::Interval(interval) => state.last_sync.elapsed() >= interval,
+            FsyncPolicy::Never => false,
+        };
+        if sync {
+            sync_state(state)?;
+        }
+
+        Ok(())
+    }
+
+    fn create_segment(&self, first_offset: u64) -> Result<Segment> {
+        let path = segment_path(&self.config.data_dir, first_offset);
+        let mut file = File::create(&path)
+            .with_context(|| format!("Cannot create the operation log segment {:?}", path))?;
+        file.write_all(SEGMENT_MAGIC)
+            .context("Cannot write the operation log segment header")?;
+
+        if self.config.fsync != FsyncPolicy::Never {
+            // The new file is durable only when its directory is synced
+            File::open(&self.config.data_dir)
+                .and_then(|dir| dir.sync_all())
+                .context("Cannot sync the operation log dir")?;
+        }
+
+        Ok(Segment {
+            first_offset,
+            file: BufWriter::new(file),
+            size: SEGMENT_MAGIC.len() as u64,
+        })
+    }
+
+    /// Syncs the appended operations to the disk
+    pub fn sync(&self) -> Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .expect("the operation log lock is poisoned");
+        sync_state(&mut state)
+    }
+
+    /// Registers a consumer which committed the operations until `committed`
+    pub fn register_consumer(self: &Arc<Self>, committed: Offset) -> LogConsumer {
+        let mut consumers = self
+            .consumers
+            .lock()
+            .expect("the operation log consumers lock is poisoned");
+        let id = consumers.next_id;
+        consumers.next_id += 1;
+        consumers.committed.insert(id, committed);
+
+        LogConsumer {
+            writer: self.clone(),
+            id,
+        }
+    }
+
+    /// It blocks on the disk IO, if the segments are removed
+    fn set_committed(&self, id: u64, committed: Offset) -> Result<()> {
+        let oldest_committed = {
+            let mut consumers = self
+                .consumers
+                .lock()
+                .expect("the operation log consumers lock is poisoned");
+            consumers.committed.insert(id, committed);
+            let grace_period = self.config.retention_grace_period;
+            consumers
+                .dropped
+                .retain(|(dropped_at, _)| dropped_at.elapsed() < grace_period);
+            consumers
+                .committed
+                .values()
+                .chain(consumers.dropped.iter().map(|(_, offset)| offset))
+                .min()
+                .copied()
+        };
+
+        match oldest_committed {
+            Some(oldest_committed) if self.config.retention => {
+                self.remove_segments_before(Offset(oldest_committed.0 + 1))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn unregister_consumer(&self, id: u64) {
+        let mut consumers = self
+            .consumers
+            .lock()
+            .expect("the operation log consumers lock is poisoned");
+        if let Some(committed) = consumers.committed.remove(&id) {
+            consumers.dropped.push((Instant::now(), committed));
+        }
+    }
+
+    /// Removes the segments with only operations before `offset`.
+    /// The last segment is never removed: the next operations are appended to it.
+    fn remove_segments_before(&self, offset: Offset) -> Result<()> {
+        let segments = list_segments(&self.config.data_dir)?;
+        let mut removed = 0;
+        for pair in segments.windows(2) {
+            let (first_offset, next_first_offset) = (pair[0], pair[1]);
+            if next_first_offset > offset.0 {
+                break;
+            }
+            let path = segment_path(&self.config.data_dir, first_offset);
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Cannot remove the operation log segment {:?}", path))?;
+            removed += 1;
+        }
+        if removed > 0 {
+            info!(removed, ?offset, "Operation log segments removed");
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn current_segment(&self) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+        state.segment.as_ref().map(|segment| segment.first_offset)
+    }
+}
+
+/// A consumer of the log, like a read side: with the retention,
+/// a segment is removed once every consumer committed its operations.
+/// Once dropped, the consumer is considered only for the retention grace period.
+pub struct LogConsumer {
+    writer: Arc<OpLogWriter>,
+    id: u64,
+}
+
+impl LogConsumer {
+    /// The operations until `offset` are committed by the consumer
+    pub async fn commit(&self, offset: Offset) -> Result<()> {
+        let writer = self.writer.clone();
+        let id = self.id;
+        tokio::task::spawn_blocking(move || writer.set_committed(id, offset))
+            .await
+            .context("The operation log retention panicked")?
+    }
+}
+
+impl Drop for LogConsumer {
+    fn drop(&mut self) {
+        self.writer.unregister_consumer(self.id);
+    }
+}
+
+fn sync_state(state: &mut WriterState) -> Result<()> {
+    if !state.dirty {
+        return Ok(());
+    }
+    if let Some(segment) = &state.segment {
+        segment
+            .file
+            .get_ref()
+            .sync_data()
+            .context("Cannot sync the operation log")?;
+    }
+    state.dirty = false;
+    state.last_sync = Instant::now();
+
+    Ok(())
+}
+
+/// Finds the end of the valid records of the last segment,
+/// truncates what follows, and returns the segment with its last offset
+fn open_last_segment(dir: &Path, first_offset: u64) -> Result<(Segment, Option<u64>)> {
+    let path = segment_path(dir, first_offset);
+    let mut reader = SegmentReader::open(&path, first_offset)
+        .with_context(|| format!("Cannot open the operation log segment {:?}", path))?;
+
+    let mut last_offset = None;
+    loop {
+        match reader.read_record()? {
+            ReadOutcome::Record(offset, _) => last_offset = Some(offset.0),
+            ReadOutcome::Incomplete => break,
+            ReadOutcome::Invalid(e) => {
+                warn!(
+                    "Invalid record at the end of the operation log segment {:?}: {:?}. Truncating",
+                    path, e
+                );
+                break;
+            }
+        }
+    }
+    let size = reader.position;
+
+    let file = OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Cannot open the operation log segment {:?}", path))?;
+    file.set_len(size)
+        .context("Cannot truncate the operation log segment")?;
+    let mut file = BufWriter::new(file);
+    file.seek(SeekFrom::Start(size))
+        .context("Cannot seek the operation log segment")?;
+    // A crash during the creation of the segment
+    let size = if size == 0 {
+        file.write_all(SEGMENT_MAGIC)
+            .and_then(|_| file.flush())
+            .context("Cannot write the operation log segment header")?;
+        SEGMENT_MAGIC.len() as u64
+    } else {
+        size
+    };
+
+    Ok((
+        Segment {
+            first_offset,
+            file,
+            size,
+        },
+        last_offset,
+    ))
+}
+
+enum ReadOutcome {
+    Record(Offset, WriteOperation),
+    /// The writer is appending the record, or it crashed while appending it
+    Incomplete,
+    /// The record is corrupted, or the writer is appending it
+    Invalid(anyhow::Error),
+}
+
+struct SegmentReader {
+    first_offset: u64,
+    file: BufReader<File>,
+    /// The end of the last complete record
+    position: u64,
+    /// The next segment exists: the writer doesn't append to this one anymore
+    complete: bool,
+}
+
+impl SegmentReader {
+    fn open(path: &Path, first_offset: u64) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Cannot open the operation log segment {:?}", path))?;
+
+        Ok(Self {
+            first_offset,
+            file: BufReader::new(file),
+            position: 0,
+            complete: false,
+        })
+    }
+
+    /// An error is returned only if the segment cannot be read
+    fn read_record(&mut self) -> Result<ReadOutcome> {
+        let outcome = self.try_read_record()?;
+        if !matches!(outcome, ReadOutcome::Record(..)) {
+            // Goes back to the end of the last complete record, to read it again later
+            self.file
+                .seek(SeekFrom::Start(self.position))
+                .context("Cannot seek the operation log segment")?;
+        }
+        Ok(outcome)
+    }
+
+    fn try_read_record(&mut self) -> Result<ReadOutcome> {
+        // A segment without a complete header is being created
+        if self.position == 0 {
+            let mut magic = [0; SEGMENT_MAGIC.len()];
+            if read_full(&mut self.file, &mut magic)? < magic.len() {
+                return Ok(ReadOutcome::Incomplete);
+            }
+            if &magic != SEGMENT_MAGIC {
+                return Err(anyhow!("The file is not an operation log segment"))
+                    .context(OpLogError::Corrupted);
+            }
+            self.position = magic.len() as u64;
+        }
+
+        let mut header = [0; RECORD_HEADER_SIZE];
+        if read_full(&mut self.file, &mut header)? < header.len() {
+            return Ok(ReadOutcome::Incomplete);
+        }
+        let len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if len > MAX_RECORD_SIZE {
+            return Ok(ReadOutcome::Invalid(anyhow!(
+                "Invalid record length {}",
+                len
+            )));
+        }
+
+        let mut payload = vec![0; len];
+        if read_full(&mut self.file, &mut payload)? < len {
+            return Ok(ReadOutcome::Incomplete);
+        }
+        if crc32fast::hash(&payload) != checksum {
+            return Ok(ReadOutcome::Invalid(anyhow!("Invalid record checksum")));
+        }
+
+        // A valid record which cannot be decoded is written by an incompatible version
+        let (offset, op) = encoding::decode(&payload)
+            .context("Cannot decode the operation")
+            .context(OpLogError::Corrupted)?;
+        self.position += (RECORD_HEADER_SIZE + len) as u64;
+
+        Ok(ReadOutcome::Record(offset, op))
+    }
+}
+
+/// Reads the operations of the log from a given offset, waiting for the new ones
+pub struct OpLogReader {
+    dir: PathBuf,
+    next_offset: Offset,
+    segment: Option<SegmentReader>,
+}
+
+impl OpLogReader {
+    pub fn new(dir: PathBuf, from: Offset) -> Self {
+        Self {
+            dir,
+            next_offset: from,
+            segment: None,
+        }
+    }
+
+    /// An `OpLogError` is returned if the log cannot be read anymore,
+    /// any other error can be retried
+    pub async fn next(&mut self) -> Result<(Offset, WriteOperation)> {
+        loop {
+            match self.try_next() {
+                Ok(Some(record)) => return Ok(record),
+                Ok(None) => {}
+                Err(e) => {
+                    // A failed read can stop in the middle of a record:
+                    // the segment is opened again from the next operation
+                    self.segment = None;
+                    return Err(e);
+                }
+            }
+            tokio::time::sleep(TAIL_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Returns `None` if there's no new operation yet
+    pub fn try_next(&mut self) -> Result<Option<(Offset, WriteOperation)>> {
+        loop {
+            if self.segment.is_none() {
+                match open_segment(&self.dir, self.next_offset)? {
+                    Some(segment) => self.segment = Some(segment),
+                    None => return Ok(None),
+                }
+            }
+            let segment = self.segment.as_mut().expect("the segment is just opened");
+
+            match segment.read_record()? {
+                ReadOutcome::Record(offset, op) => {
+                    // The segment contains the operations before the requested one too
+                    if offset < self.next_offset {
+                        continue;
+                    }
+                    self.next_offset = Offset(offset.0 + 1);
+                    return Ok(Some((offset, op)));
+                }
+                // The end of the segment, or a record the writer is appending.
+                // Once the next segment exists, this one is read again till its end.
+                ReadOutcome::Incomplete | ReadOutcome::Invalid(_) if !segment.complete => {
+                    if next_segment(&self.dir, segment.first_offset)?.is_none() {
+                        return Ok(None);
+                    }
+                    segment.complete = true;
+                }
+                ReadOutcome::Incomplete => {
+                    let next = next_segment(&self.dir, segment.first_offset)?
+                        .expect("a complete segment has a next one");
+                    self.segment = Some(SegmentReader::open(&segment_path(&self.dir, next), next)?);
+                }
+                ReadOutcome::Invalid(e) => return Err(e).context(OpLogError::Corrupted),
+            }
+        }
+    }
+}
+
+/// The segment containing `offset`.
+/// Fails if the segments with `offset` are removed: the operations would be skipped.
+fn open_segment(dir: &Path, offset: Offset) -> Result<Option<SegmentReader>> {
+    let segments = list_segments(dir)?;
+    let Some(&oldest) = segments.first() else {
+        return Ok(None);
+    };
+    if offset.0 < oldest {
+        bail!(OpLogError::Missing {
+            requested: offset.0,
+            oldest,
+        });
+    }
+    let first_offset = segments
+        .iter()
+        .rev()
+        .find(|first_offset| **first_offset <= offset.0)
+        .expect("the oldest segment is before the offset");
+
+    Ok(Some(SegmentReader::open(
+        &segment_path(dir, *first_offset),
+        *first_offset,
+    )?))
+}
+
+fn next_segment(dir: &Path, first_offset: u64) -> Result<Option<u64>> {
+    let segments = list_segments(dir)?;
+    Ok(segments.into_iter().find(|offset| *offset > first_offset))
+}
+
+fn segment_path(dir: &Path, first_offset: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", first_offset, SEGMENT_EXTENSION))
+}
+
+/// The first offsets of the segments, sorted
+fn list_segments(dir: &Path) -> Result<Vec<u64>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Cannot read the operation log dir {:?}", dir))
+        }
+    };
+
+    let mut segments = vec![];
+    for entry in entries {
+        let path = entry.context("Cannot read the operation log dir")?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_EXTENSION) {
+            continue;
+        }
+        if let Some(first_offset) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse().ok())
+        {
+            segments.push(first_offset);
+        }
+    }
+    segments.sort_unstable();
+
+    Ok(segments)
+}
+
+/// Like `read_exact`, but returns the number of bytes read at the end of the file
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e).context("Cannot read the operation log segment"),
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_utils::generate_new_path, types::CollectionId};
+
+    use super::*;
+
+    fn config(segment_size: u64) -> FileSideChannelConfig {
+        FileSideChannelConfig {
+            data_dir: generate_new_path(),
+            segment_size,
+            fsync: FsyncPolicy::Always,
+            retention: false,
+            retention_grace_period: Duration::ZERO,
+        }
+    }
+
+    fn op(i: u64) -> WriteOperation {
+        WriteOperation::DeleteCollection(CollectionId(format!("collection-{}", i)))
+    }
+
+    fn collection_id(op: &WriteOperation) -> &str {
+        match op {
+            WriteOperation::DeleteCollection(id) => &id.0,
+            _ => panic!("Unexpected operation"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_op_log_append_and_tail() -> Result<()> {
+        let config = config(100);
+        let (writer, next_offset) = OpLogWriter::open(config.clone())?;
+        assert_eq!(next_offset, Offset(1));
+
+        let counter = AtomicU64::new(next_offset.0);
+        for i in 1..=10 {
+            assert_eq!(writer.append(&counter, &op(i))?, Offset(i));
+        }
+        // The small segments are rolled
+        assert!(list_segments(&config.data_dir)?.len() > 1);
+
+        let mut reader = OpLogReader::new(config.data_dir.clone(), Offset(4));
+        for i in 4..=10 {
+            let (offset, op) = reader.next().await?;
+            assert_eq!(offset, Offset(i));
+            assert_eq!(collection_id(&op), format!("collection-{}", i));
+        }
+        assert!(reader.try_next()?.is_none());
+
+        writer.append(&counter, &op(11))?;
+        let (offset, _) = reader.next().await?;
+        assert_eq!(offset, Offset(11));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_op_log_truncates_incomplete_record() -> Result<()> {
+        let config = config(1024 * 1024);
+        let (writer, next_offset) = OpLogWriter::open(config.clone())?;
+        let counter = AtomicU64::new(next_offset.0);
+        writer.append(&counter, &op(1))?;
+        writer.append(&counter, &op(2))?;
+        let first_segment = writer.current_segment().unwrap();
+        drop(writer);
+
+        // A crash in the middle of an append
+        let path = segment_path(&config.data_dir, first_segment);
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(&[10, 0, 0, 0, 1, 2])?;
+        drop(file);
+
+        let mut reader = OpLogReader::new(config.data_dir.clone(), Offset(1));
+        assert_eq!(reader.next().await?.0, Offset(1));
+        assert_eq!(reader.next().await?.0, Offset(2));
+        assert!(reader.try_next()?.is_none());
+
+        let (writer, next_offset) = OpLogWriter::open(config.clone())?;
+        assert_eq!(next_offset, Offset(3));
+        let counter = AtomicU64::new(next_offset.0);
+        writer.append(&counter, &op(3))?;
+
+        let (offset, op) = reader.next().await?;
+        assert_eq!(offset, Offset(3));
+        assert_eq!(collection_id(&op), "collection-3");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_op_log_detects_corruption() -> Result<()> {
+        let config = config(100);
+        let (writer, next_offset) = OpLogWriter::open(config.clone())?;
+        let counter = AtomicU64::new(next_offset.0);
+        for i in 1..=10 {
+            writer.append(&counter, &op(i))?;
+        }
+
+        // Flips a byte of the first record of the first segment
+        let segments = list_segments(&config.data_dir)?;
+        let path = segment_path(&config.data_dir, segments[0]);
+        let mut content = std::fs::read(&path)?;
+        let last = content.len() - 1;
+        content[last] ^= 0xff;
+        std::fs::write(&path, content)?;
+
+        let mut reader = OpLogReader::new(config.data_dir.clone(), Offset(1));
+        let mut output = Ok(None);
+        for _ in 0..10 {
+            output = reader.try_next();
+            if output.is_err() {
+                break;
+            }
+        }
+        // Not retried
+        assert!(output.unwrap_err().is::<OpLogError>());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_op_log_retention() -> Result<()> {
+        let config = FileSideChannelConfig {
+            retention: true,
+            retention_grace_period: Duration::from_millis(500),
+            ..config(50)
+        };
+        let (writer, next_offset) = OpLogWriter::open(config.clone())?;
+        let counter = AtomicU64::new(next_offset.0);
+        for i in 1..=10 {
+            writer.append(&counter, &op(i))?;
+        }
+        let segments = list_segments(&config.data_dir)?;
+        assert!(segments.len() > 2);
+
+        let first = writer.register_consumer(Offset(0));
+        let second = writer.register_consumer(Offset(0));
+        // Not committed by the second consumer yet
+        first.commit(Offset(10)).await?;
+        assert_eq!(list_segments(&config.data_dir)?, segments);
+
+        second.commit(Offset(segments[2] - 1)).await?;
+        assert_eq!(list_segments(&config.data_dir)?, segments[2..]);
+
+        // The operations of the removed segments cannot be read
+        let mut reader = OpLogReader::new(config.data_dir.clone(), Offset(1));
+        assert!(reader.try_next().unwrap_err().is::<OpLogError>());
+        let mut reader = OpLogReader::new(config.data_dir.clone(), Offset(segments[2]));
+        assert_eq!(reader.next().await?.0, Offset(segments[2]));
+
+        // The dropped consumer is considered for the grace period:
+        // a read side connecting again can resume from the log
+        drop(second);
+        first.commit(Offset(10)).await?;
+        assert_eq!(list_segments(&config.data_dir)?, segments[2..]);
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        first.commit(Offset(10)).await?;
+        assert_eq!(
+            list_segments(&config.data_dir)?,
+            segments[segments.len() - 1..]
+        );
+
+        Ok(())
+    }
+}
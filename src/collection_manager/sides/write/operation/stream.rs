@@ -0,0 +1,336 @@
+//! The operation stream: the write side serves its file side channel over TCP,
+//! so the read sides can run in other processes.
+//! The reader starts with `HANDSHAKE_MAGIC`, the API key and the offset to resume from.
+//! The writer answers with a status byte and, if the key is accepted,
+//! the offset of the last operation in the log.
+//! Then the writer sends the operations as frames `[payload length: u32][payload]`,
+//! where the payload is the offset followed by the operation, as in `encoding`.
+//! An empty frame is a heartbeat.
+//! Meanwhile, the reader sends the offsets it commits, as `u64`:
+//! the writer keeps the segments of its log until they are committed.
+//! The stream is not encrypted, and the API key is sent in clear:
+//! it is meant for a trusted private network only.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use duration_str::deserialize_duration;
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream,
+    },
+    sync::{mpsc, watch},
+};
+use tracing::{info, warn};
+
+use crate::collection_manager::dto::ApiKey;
+
+use super::{
+    encoding,
+    op_log::{LogConsumer, OpLogReader},
+    Offset, OperationSender, WriteOperation,
+};
+
+const HANDSHAKE_MAGIC: &[u8; 8] = b"ORAMAOS1";
+const STATUS_OK: u8 = 0;
+const STATUS_UNAUTHORIZED: u8 = 1;
+const MAX_API_KEY_SIZE: usize = 1_024;
+/// A bigger frame is considered corrupted
+const MAX_FRAME_SIZE: usize = 1 << 30;
+/// An idle writer sends a heartbeat with this interval
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// Without frames for this time, the reader considers the connection lost
+const READ_TIMEOUT: Duration = Duration::from_secs(15);
+/// The operations received and not applied yet by the read side
+const RECEIVER_CAPACITY: usize = 10_000;
+
+/// Connecting again doesn't help: the reader has the wrong key
+#[derive(Debug, Error)]
+#[error("The operation stream server refused the API key")]
+struct UnauthorizedError;
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct OperationStreamServerConfig {
+    pub host: IpAddr,
+    pub port: u16,
+    /// The key the read sides authenticate with
+    pub api_key: ApiKey,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct RemoteSideChannelConfig {
+    /// The `host:port` of the operation stream server of the write side
+    pub address: String,
+    pub api_key: ApiKey,
+    /// How long to wait before connecting again, if the server is not reachable
+    /// or once the connection is lost
+    #[serde(
+        default = "default_reconnect_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub reconnect_interval: Duration,
+}
+
+/// Binds the operation stream server and serves the operations appended to the log in `log_dir`.
+/// Returns the bound address.
+pub async fn start_operation_stream_server(
+    config: OperationStreamServerConfig,
+    log_dir: PathBuf,
+    sender: OperationSender,
+) -> Result<SocketAddr> {
+    let address = SocketAddr::new(config.host, config.port);
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Cannot bind the operation stream server on {}", address))?;
+    let address = listener
+        .local_addr()
+        .context("Cannot get the operation stream server address")?;
+    info!(?address, "Operation stream server started");
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Cannot accept an operation stream connection: {:?}", e);
+                    continue;
+                }
+            };
+
+            let api_key = config.api_key.clone();
+            let log_dir = log_dir.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve(stream, api_key, log_dir, sender).await {
+                    warn!(?peer, "Operation stream closed: {:?}", e);
+                }
+            });
+        }
+    });
+
+    Ok(address)
+}
+
+async fn serve(
+    mut stream: TcpStream,
+    api_key: ApiKey,
+    log_dir: PathBuf,
+    sender: OperationSender,
+) -> Result<()> {
+    let mut magic = [0; HANDSHAKE_MAGIC.len()];
+    stream
+        .read_exact(&mut magic)
+        .await
+        .context("Cannot read the handshake")?;
+    if &magic != HANDSHAKE_MAGIC {
+        bail!("Invalid handshake");
+    }
+    let key_len = stream.read_u32_le().await? as usize;
+    if key_len > MAX_API_KEY_SIZE {
+        bail!("The API key is too long: {} bytes", key_len);
+    }
+    let mut key = vec![0; key_len];
+    stream.read_exact(&mut key).await?;
+    let from = Offset(stream.read_u64_le().await?);
+
+    // In constant time: the time doesn't tell how much of the key is right
+    let expected = api_key.0.expose_secret().as_bytes();
+    if !bool::from(key.as_slice().ct_eq(expected)) {
+        stream.write_u8(STATUS_UNAUTHORIZED).await?;
+        bail!("Invalid operation stream API key");
+    }
+    // The counter holds the next offset
+    let last_offset = sender.offset().0 - 1;
+    stream.write_u8(STATUS_OK).await?;
+    stream.write_u64_le(last_offset).await?;
+    info!(
+        ?from,
+        last_offset, "Streaming the operations to a read side"
+    );
+
+    let (read_half, mut stream) = stream.into_split();
+    // The read side committed the operations before `from`
+    let consumer = sender.consumer(Offset(from.0.saturating_sub(1)));
+    let commits = tokio::spawn(receive_commits(read_half, consumer));
+
+    let output = send_operations(&mut stream, log_dir, from).await;
+    // The read side is considered by the retention only for the grace period
+    commits.abort();
+    output
+}
+
+async fn send_operations(
+    stream: &mut OwnedWriteHalf,
+    log_dir: PathBuf,
+    from: Offset,
+) -> Result<()> {
+    let mut reader = OpLogReader::new(log_dir, from);
+    let mut frame = vec![];
+    loop {
+        frame.clear();
+        // Reserved for the length
+        frame.extend_from_slice(&[0; 4]);
+        // Without a new operation, the empty frame is a heartbeat
+        if let Ok(record) = tokio::time::timeout(HEARTBEAT_INTERVAL, reader.next()).await {
+            let (offset, op) = record?;
+            encoding::encode(offset, &op, &mut frame);
+        }
+        let len = (frame.len() - 4) as u32;
+        frame[..4].copy_from_slice(&len.to_le_bytes());
+        stream.write_all(&frame).await?;
+    }
+}
+
+/// Receives the offsets committed by the read side, until it disconnects
+async fn receive_commits(mut stream: OwnedReadHalf, consumer: Option<LogConsumer>) -> Result<()> {
+    loop {
+        let committed = Offset(stream.read_u64_le().await?);
+        if let Some(consumer) = &consumer {
+            consumer.commit(committed).await?;
+        }
+    }
+}
+
+/// Connects to the operation stream server, and receives the operations from the offset `from`.
+/// Returns the offset of the last operation in the log of the write side too.
+/// Until the server is reachable, and once the connection is lost, it connects again,
+/// resuming after the last received operation. Fails only if the API key is refused.
+pub async fn connect(
+    config: RemoteSideChannelConfig,
+    from: Offset,
+    committed: watch::Receiver<Offset>,
+) -> Result<(mpsc::Receiver<(Offset, WriteOperation)>, Offset)> {
+    let (stream, last_offset) = loop {
+        match handshake(&config, from).await {
+            Ok(connection) => break connection,
+            Err(e) if e.is::<UnauthorizedError>() => return Err(e),
+            Err(e) => {
+                warn!("Cannot connect to the operation stream: {:?}", e);
+                tokio::time::sleep(config.reconnect_interval).await;
+            }
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel(RECEIVER_CAPACITY);
+    tokio::spawn(receive_loop(config, stream, from, sender, committed));
+
+    Ok((receiver, last_offset))
+}
+
+async fn handshake(config: &RemoteSideChannelConfig, from: Offset) -> Result<(TcpStream, Offset)> {
+    let mut stream = TcpStream::connect(&config.address).await.with_context(|| {
+        format!(
+            "Cannot connect to the operation stream server {}",
+            config.address
+        )
+    })?;
+
+    let key = config.api_key.0.expose_secret().as_bytes();
+    let mut handshake = Vec::with_capacity(HANDSHAKE_MAGIC.len() + 4 + key.len() + 8);
+    handshake.extend_from_slice(HANDSHAKE_MAGIC);
+    handshake.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    handshake.extend_from_slice(key);
+    handshake.extend_from_slice(&from.0.to_le_bytes());
+    stream.write_all(&handshake).await?;
+
+    match stream
+        .read_u8()
+        .await
+        .context("Cannot read the handshake answer")?
+    {
+        STATUS_OK => {}
+        STATUS_UNAUTHORIZED => bail!(UnauthorizedError),
+        status => bail!("Unknown handshake status {}", status),
+    }
+    let last_offset = Offset(stream.read_u64_le().await?);
+
+    Ok((stream, last_offset))
+}
+
+async fn receive_loop(
+    config: RemoteSideChannelConfig,
+    mut stream: TcpStream,
+    mut next_offset: Offset,
+    sender: mpsc::Sender<(Offset, WriteOperation)>,
+    committed: watch::Receiver<Offset>,
+) {
+    loop {
+        let (read_half, write_half) = stream.into_split();
+        let commits = tokio::spawn(send_commits(write_half, committed.clone()));
+        let output = receive(read_half, &mut next_offset, &sender).await;
+        commits.abort();
+
+        match output {
+            // The read side is stopped
+            Ok(()) => return,
+            Err(e) => warn!(?next_offset, "Operation stream lost: {:?}", e),
+        }
+
+        stream = loop {
+            tokio::time::sleep(config.reconnect_interval).await;
+            match handshake(&config, next_offset).await {
+                Ok((stream, _)) => break stream,
+                Err(e) => warn!("Cannot connect to the operation stream again: {:?}", e),
+            }
+        };
+        info!(?next_offset, "Operation stream resumed");
+    }
+}
+
+/// Sends the offsets committed by the read side, on every commit
+async fn send_commits(
+    mut stream: OwnedWriteHalf,
+    mut committed: watch::Receiver<Offset>,
+) -> Result<()> {
+    loop {
+        let offset = *committed.borrow_and_update();
+        stream.write_u64_le(offset.0).await?;
+        // Fails once the read side is stopped
+        committed.changed().await?;
+    }
+}
+
+/// Returns `Ok` once the read side is stopped
+async fn receive(
+    stream: impl AsyncRead + Unpin,
+    next_offset: &mut Offset,
+    sender: &mpsc::Sender<(Offset, WriteOperation)>,
+) -> Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut payload = vec![];
+    loop {
+        let len = tokio::time::timeout(READ_TIMEOUT, stream.read_u32_le())
+            .await
+            .context("No heartbeat from the operation stream server")?? as usize;
+        if len == 0 {
+            continue;
+        }
+        if len > MAX_FRAME_SIZE {
+            bail!("Frame too big: {} bytes", len);
+        }
+
+        payload.resize(len, 0);
+        tokio::time::timeout(READ_TIMEOUT, stream.read_exact(&mut payload))
+            .await
+            .context("Timeout reading an operation")??;
+        let (offset, op) = encoding::decode(&payload).context("Cannot decode an operation")?;
+
+        *next_offset = Offset(offset.0 + 1);
+        if sender.send((offset, op)).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+fn default_reconnect_interval() -> Duration {
+    Duration::from_secs(1)
+}
@@ -0,0 +1,325 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, Notify, RwLock};
+use tracing::info;
+
+use crate::{
+    collection_manager::dto::{Task, TaskKind, TaskOffsets, TaskStatus},
+    file_utils::{create_if_not_exists_async, create_or_overwrite, read_file, BufferedFile},
+    types::{CollectionId, Document},
+};
+
+/// The finished tasks kept: the oldest ones are removed
+const MAX_FINISHED_TASKS: usize = 1_000;
+const TASKS_FILE_NAME: &str = "tasks.json";
+/// Written and renamed to `TASKS_FILE_NAME`: a crash never leaves a partial file
+const TASKS_TMP_FILE_NAME: &str = "tasks.json.tmp";
+
+/// The input of a task, stored until the task is finished
+#[derive(Debug, Serialize, Deserialize)]
+pub enum TaskPayload {
+    Insert(Vec<Document>),
+    Delete(Vec<String>),
+    /// Applied only if the version of the document is `if_version`
+    ConditionalInsert {
+        doc: Document,
+        if_version: u64,
+    },
+    ConditionalDelete {
+        doc_id: String,
+        if_version: u64,
+    },
+}
+
+impl TaskPayload {
+    fn kind(&self) -> TaskKind {
+        match self {
+            TaskPayload::Insert(_) | TaskPayload::ConditionalInsert { .. } => {
+                TaskKind::DocumentInsertion
+            }
+            TaskPayload::Delete(_) | TaskPayload::ConditionalDelete { .. } => {
+                TaskKind::DocumentDeletion
+            }
+        }
+    }
+
+    fn document_count(&self) -> usize {
+        match self {
+            TaskPayload::Insert(docs) => docs.len(),
+            TaskPayload::Delete(ids) => ids.len(),
+            TaskPayload::ConditionalInsert { .. } | TaskPayload::ConditionalDelete { .. } => 1,
+        }
+    }
+}
+
+/// The write tasks, processed one at a time in the enqueue order.
+/// The tasks, and the payloads of the unfinished ones, are persisted in `dir`.
+pub struct TaskQueue {
+    dir: PathBuf,
+    tasks: RwLock<BTreeMap<u64, Task>>,
+    next_id: AtomicU64,
+    /// Incremented on every change of `tasks`, under its lock
+    generation: AtomicU64,
+    /// The generation of the tasks file. Also serializes the writes
+    persisted: Mutex<u64>,
+    enqueued: Notify,
+    finished: Notify,
+}
+
+impl TaskQueue {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            tasks: Default::default(),
+            next_id: AtomicU64::new(1),
+            generation: AtomicU64::new(0),
+            persisted: Mutex::new(0),
+            enqueued: Notify::new(),
+            finished: Notify::new(),
+        }
+    }
+
+    /// The unfinished tasks are enqueued again:
+    /// both the insertion and the deletion can be applied twice,
+    /// because the document ids are in the payload.
+    /// A conditional task already applied fails with a version conflict.
+    pub async fn load(&mut self) -> Result<()> {
+        let path = self.dir.join(TASKS_FILE_NAME);
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let tasks: Vec<Task> = BufferedFile::open(path)
+            .context("Cannot open tasks file")?
+            .read_json_data()
+            .context("Cannot read tasks file")?;
+
+        let mut tasks: BTreeMap<u64, Task> = tasks.into_iter().map(|t| (t.id, t)).collect();
+        for task in tasks.values_mut() {
+            if task.status == TaskStatus::Processing {
+                info!(task_id = task.id, "Task interrupted: enqueue it again");
+                task.status = TaskStatus::Enqueued;
+                task.started_at = None;
+                task.succeeded = 0;
+                task.failed = 0;
+                task.errors.clear();
+            }
+        }
+
+        let next_id = tasks.keys().last().map(|id| id + 1).unwrap_or(1);
+        self.next_id.store(next_id, Ordering::SeqCst);
+        *self.tasks.get_mut() = tasks;
+        self.enqueued.notify_one();
+
+        Ok(())
+    }
+
+    pub async fn enqueue(&self, collection_id: CollectionId, payload: TaskPayload) -> Result<Task> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+
+        // The payload is stored before the task is visible
+        create_if_not_exists_async(&self.dir)
+            .await
+            .context("Cannot create tasks directory")?;
+        create_or_overwrite(self.payload_path(id), &payload)
+            .await
+            .context("Cannot write task payload")?;
+
+        let task = Task {
+            id,
+            collection_id,
+            kind: payload.kind(),
+            status: TaskStatus::Enqueued,
+            document_count: payload.document_count() as u64,
+            succeeded: 0,
+            failed: 0,
+            errors: vec![],
+            error: None,
+            enqueued_at: now(),
+            started_at: None,
+            finished_at: None,
+            offsets: None,
+        };
+
+        let mut tasks = self.tasks.write().await;
+        tasks.insert(id, task.clone());
+        let snapshot = self.snapshot(&tasks);
+        drop(tasks);
+        self.persist(snapshot).await?;
+
+        self.enqueued.notify_one();
+
+        Ok(task)
+    }
+
+    /// Waits for the oldest enqueued task, and marks it as processing
+    pub async fn next(&self) -> Result<Task> {
+        loop {
+            let mut tasks = self.tasks.write().await;
+            let next = tasks
+                .values_mut()
+                .find(|task| task.status == TaskStatus::Enqueued);
+            if let Some(task) = next {
+                task.status = TaskStatus::Processing;
+                task.started_at = Some(now());
+                let task = task.clone();
+                let snapshot = self.snapshot(&tasks);
+                drop(tasks);
+                self.persist(snapshot).await?;
+                return Ok(task);
+            }
+            drop(tasks);
+
+            // `notify_one` stores a permit: an enqueue in the meantime is not lost
+            self.enqueued.notified().await;
+        }
+    }
+
+    pub async fn payload(&self, task_id: u64) -> Result<TaskPayload> {
+        read_file(self.payload_path(task_id))
+            .await
+            .context("Cannot read task payload")
+    }
+
+    /// The counters are persisted when the task is finished
+    pub async fn update<F>(&self, task_id: u64, f: F)
+    where
+        F: FnOnce(&mut Task),
+    {
+        if let Some(task) = self.tasks.write().await.get_mut(&task_id) {
+            f(task);
+        }
+    }
+
+    pub async fn finish(
+        &self,
+        task_id: u64,
+        error: Option<String>,
+        offsets: Option<TaskOffsets>,
+    ) -> Result<Task> {
+        let mut tasks = self.tasks.write().await;
+        let task = tasks.get_mut(&task_id).context("Task not found")?;
+        task.status = if error.is_some() || task.failed > 0 {
+            TaskStatus::Failed
+        } else {
+            TaskStatus::Succeeded
+        };
+        task.error = error;
+        task.offsets = offsets;
+        task.finished_at = Some(now());
+        let task = task.clone();
+        self.finished.notify_waiters();
+
+        let finished: Vec<u64> = tasks
+            .values()
+            .filter(|task| is_finished(task.status))
+            .map(|task| task.id)
+            .collect();
+        if finished.len() > MAX_FINISHED_TASKS {
+            for task_id in &finished[..finished.len() - MAX_FINISHED_TASKS] {
+                tasks.remove(task_id);
+            }
+        }
+
+        let snapshot = self.snapshot(&tasks);
+        drop(tasks);
+        self.persist(snapshot).await?;
+
+        match tokio::fs::remove_file(self.payload_path(task_id)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).context("Cannot remove task payload");
+            }
+            _ => {}
+        }
+
+        Ok(task)
+    }
+
+    /// Waits until the task is finished. Returns `None` if the task doesn't exist.
+    pub async fn wait(&self, task_id: u64) -> Option<Task> {
+        loop {
+            // Registered before the check: a finish in the meantime is not lost
+            let finished = self.finished.notified();
+            tokio::pin!(finished);
+            finished.as_mut().enable();
+
+            let task = self.get(task_id).await?;
+            if is_finished(task.status) {
+                return Some(task);
+            }
+
+            finished.await;
+        }
+    }
+
+    pub async fn get(&self, task_id: u64) -> Option<Task> {
+        self.tasks.read().await.get(&task_id).cloned()
+    }
+
+    /// The newest tasks first
+    pub async fn list(
+        &self,
+        collection_id: Option<&CollectionId>,
+        status: Option<TaskStatus>,
+    ) -> Vec<Task> {
+        self.tasks
+            .read()
+            .await
+            .values()
+            .rev()
+            .filter(|task| collection_id.is_none() || collection_id == Some(&task.collection_id))
+            .filter(|task| status.is_none() || status == Some(task.status))
+            .cloned()
+            .collect()
+    }
+
+    /// Called with the write lock of `tasks`: the generations follow the changes
+    fn snapshot(&self, tasks: &BTreeMap<u64, Task>) -> (u64, Vec<Task>) {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        (generation, tasks.values().cloned().collect())
+    }
+
+    /// Writes the tasks without the lock of `tasks`.
+    /// A snapshot older than the persisted one is skipped.
+    async fn persist(&self, (generation, tasks): (u64, Vec<Task>)) -> Result<()> {
+        let mut persisted = self.persisted.lock().await;
+        if *persisted >= generation {
+            return Ok(());
+        }
+
+        create_if_not_exists_async(&self.dir)
+            .await
+            .context("Cannot create tasks directory")?;
+        let tmp_path = self.dir.join(TASKS_TMP_FILE_NAME);
+        // `create_or_overwrite` syncs the file before the rename
+        create_or_overwrite(tmp_path.clone(), &tasks)
+            .await
+            .context("Cannot write tasks file")?;
+        tokio::fs::rename(&tmp_path, self.dir.join(TASKS_FILE_NAME))
+            .await
+            .context("Cannot replace tasks file")?;
+
+        *persisted = generation;
+
+        Ok(())
+    }
+
+    fn payload_path(&self, task_id: u64) -> PathBuf {
+        self.dir.join(format!("task-{}.json", task_id))
+    }
+}
+
+fn is_finished(status: TaskStatus) -> bool {
+    matches!(status, TaskStatus::Succeeded | TaskStatus::Failed)
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339()
+}
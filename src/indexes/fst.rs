@@ -60,6 +60,10 @@ impl FSTIndex {
         self.file_path.clone()
     }
 
+    pub fn get(&self, token: &str) -> Option<u64> {
+        self.inner.get(token)
+    }
+
     pub fn search<'s, 'input>(&'s self, token: &'input str) -> FTSIter<'s, 'input>
     where
         'input: 's,
@@ -17,6 +17,10 @@ impl<Value: Clone> RadixIndex<Value> {
         Self { inner: Trie::new() }
     }
 
+    pub fn get<I: Iterator<Item = u8>>(&self, key: I) -> Option<&Value> {
+        self.inner.get(key)
+    }
+
     pub fn get_mut<I: Iterator<Item = u8>>(&mut self, key: I) -> Option<&mut Value> {
         self.inner.get_mut(key)
     }
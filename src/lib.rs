@@ -1,15 +1,18 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use ai::{AIService, AIServiceConfig};
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use collection_manager::sides::{
-    channel, hooks::HooksRuntime, OperationReceiver, ReadSide, ReadSideConfig, WriteSide,
-    WriteSideConfig,
+    channel, file_channel, hooks::HooksRuntime, start_operation_stream_server,
+    FileSideChannelConfig, LogConsumer, Offset, OperationReceiver, ReadSide, ReadSideConfig,
+    RemoteSideChannelConfig, WriteSide, WriteSideConfig,
 };
+use duration_str::deserialize_duration;
 use metrics_exporter_prometheus::PrometheusBuilder;
 use nlp::NLPService;
 use serde::Deserialize;
-use tracing::info;
+use tokio::sync::watch;
+use tracing::{info, warn};
 use web_server::{HttpConfig, WebServer};
 
 pub mod indexes;
@@ -26,6 +29,7 @@ pub mod web_server;
 
 mod capped_heap;
 pub mod js;
+mod lru_cache;
 
 mod metrics;
 
@@ -44,8 +48,32 @@ pub mod test_utils;
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub enum SideChannelType {
+    /// The operations not committed by the read side are lost on a crash
     #[serde(rename = "in-memory")]
     InMemory,
+    /// A durable log of the operations: on startup the read side replays it
+    /// from its committed offsets, and reports "catching up" until the end of the log
+    #[serde(rename = "file")]
+    File(FileSideChannelConfig),
+    /// The operation stream served by a write side in another process. Only for the read side.
+    #[serde(rename = "remote")]
+    Remote(RemoteSideChannelConfig),
+}
+
+/// The sides run by the node.
+/// The read replicas are scaled independently: a writer node serves its operations
+/// with the `stream_server`, the reader nodes receive them with the `remote` side channel.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NodeMode {
+    #[default]
+    #[serde(rename = "all")]
+    All,
+    /// The `reader_side` config is not needed, and ignored if set
+    #[serde(rename = "writer")]
+    Writer,
+    /// The `writer_side` config is not needed, and ignored if set
+    #[serde(rename = "reader")]
+    Reader,
 }
 
 #[derive(Debug, Deserialize, Clone, Default)]
@@ -53,13 +81,41 @@ pub struct LogConfig {
     pub file_path: Option<PathBuf>,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShutdownConfig {
+    /// On SIGTERM, the node exits with an error if the sides are not committed within this time
+    #[serde(
+        default = "default_shutdown_timeout",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub timeout: Duration,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            timeout: default_shutdown_timeout(),
+        }
+    }
+}
+
+fn default_shutdown_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct OramacoreConfig {
+    #[serde(default)]
+    pub mode: NodeMode,
     pub log: LogConfig,
     pub http: HttpConfig,
     pub ai_server: AIServiceConfig,
-    pub writer_side: WriteSideConfig,
-    pub reader_side: ReadSideConfig,
+    /// Required unless the node is a reader
+    pub writer_side: Option<WriteSideConfig>,
+    /// Required unless the node is a writer
+    pub reader_side: Option<ReadSideConfig>,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
 }
 
 pub async fn start(config: OramacoreConfig) -> Result<()> {
@@ -77,84 +133,328 @@ pub async fn start(config: OramacoreConfig) -> Result<()> {
 
     let (write_side, read_side, receiver) = build_orama(config.clone()).await?;
 
-    connect_write_and_read_side(receiver, read_side.clone().unwrap());
+    let side_channel = match (receiver, read_side.clone()) {
+        (Some(receiver), Some(read_side)) => Some(connect_write_and_read_side(receiver, read_side)),
+        _ => None,
+    };
+    // The node stops if the read side cannot receive the operations anymore
+    let side_channel = async move {
+        match side_channel {
+            Some(side_channel) => match side_channel.await {
+                Ok(Ok(())) => Err(anyhow!("The side channel is closed")),
+                Ok(Err(e)) => Err(e),
+                Err(e) => Err(e).context("The side channel receiver panicked"),
+            },
+            None => std::future::pending().await,
+        }
+    };
 
     info!(
         "Starting web server on {}:{}",
         config.http.host, config.http.port
     );
 
-    let web_server = WebServer::new(write_side, read_side, prometheus_hadler);
-    web_server.start(config.http).await?;
+    let (stop_sender, stop_receiver) = tokio::sync::oneshot::channel::<()>();
+    let web_server = WebServer::new(write_side.clone(), read_side.clone(), prometheus_hadler);
+    let mut web_server = tokio::spawn(web_server.start_with_graceful_shutdown(
+        config.http,
+        async move {
+            let _ = stop_receiver.await;
+        },
+    ));
+
+    tokio::select! {
+        output = &mut web_server => return output.context("The web server panicked")?,
+        output = side_channel => return output.context("Cannot receive the operations"),
+        _ = shutdown_signal() => {}
+    }
+
+    let timeout = config.shutdown.timeout;
+    info!(?timeout, "Shutting down");
+    let _ = stop_sender.send(());
+    tokio::time::timeout(
+        timeout,
+        shutdown(web_server, write_side, read_side, timeout),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("The shutdown is not completed in {:?}", timeout))??;
+    info!("Shutdown completed");
+
+    Ok(())
+}
+
+/// Completes on SIGTERM or Ctrl+C
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Cannot install the Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Cannot install the SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// The requests in progress are answered, the queues of the write side are drained,
+/// then the write side is committed, and the read side after it
+async fn shutdown(
+    web_server: tokio::task::JoinHandle<Result<()>>,
+    write_side: Option<Arc<WriteSide>>,
+    read_side: Option<Arc<ReadSide>>,
+    timeout: Duration,
+) -> Result<()> {
+    if let Err(e) = web_server.await.context("The web server panicked")? {
+        warn!("The web server stopped with an error: {:?}", e);
+    }
+
+    let last_offset = match &write_side {
+        Some(write_side) => Some(
+            write_side
+                .shutdown()
+                .await
+                .context("Cannot stop the write side")?,
+        ),
+        None => None,
+    };
+
+    if let Some(read_side) = &read_side {
+        // A reader node commits what it received: the operation stream resumes from there
+        if let Some(last_offset) = last_offset {
+            read_side
+                .wait_for_offset(last_offset, timeout)
+                .await
+                .context("Cannot drain the side channel")?;
+        }
+        read_side
+            .commit()
+            .await
+            .context("Cannot commit the read side")?;
+        info!("Read side committed");
+    }
 
     Ok(())
 }
 
-pub fn connect_write_and_read_side(mut receiver: OperationReceiver, read_side: Arc<ReadSide>) {
+/// The segments of the file side channel are kept until committed by the read side
+fn report_committed_offsets(consumer: LogConsumer, mut committed: watch::Receiver<Offset>) {
     tokio::spawn(async move {
-        while let Some(op) = receiver.recv().await {
-            read_side.update(op).await.expect("OUCH!");
+        // The read side is stopped once the sender is dropped
+        while committed.changed().await.is_ok() {
+            let offset = *committed.borrow_and_update();
+            if let Err(e) = consumer.commit(offset).await {
+                warn!(
+                    "Cannot remove the committed operation log segments: {:?}",
+                    e
+                );
+            }
         }
     });
 }
 
+/// Applies the received operations to the read side.
+/// The task completes once the write side is stopped, or fails if an operation cannot be applied.
+pub fn connect_write_and_read_side(
+    mut receiver: OperationReceiver,
+    read_side: Arc<ReadSide>,
+) -> tokio::task::JoinHandle<Result<()>> {
+    tokio::spawn(async move {
+        while let Some(op) = receiver.recv().await? {
+            read_side
+                .update(op)
+                .await
+                .context("Cannot apply the operation")?;
+        }
+        Ok(())
+    })
+}
+
+/// Builds the sides run by the node.
+/// The receiver of the operations is returned if the node runs the read side.
 pub async fn build_orama(
     config: OramacoreConfig,
 ) -> Result<(
     Option<Arc<WriteSide>>,
     Option<Arc<ReadSide>>,
-    OperationReceiver,
+    Option<OperationReceiver>,
 )> {
     let OramacoreConfig {
+        mode,
         ai_server,
         writer_side,
         reader_side,
         ..
     } = config;
 
+    let (writer_side, reader_side) = side_configs(mode, writer_side, reader_side)?;
+    check_side_channels(mode, writer_side.as_ref(), reader_side.as_ref())?;
+
     info!("Building ai_service");
     let ai_service = AIService::new(ai_server);
     let ai_service = Arc::new(ai_service);
 
-    info!("Building hooks_runtime");
-    let hooks_runtime = HooksRuntime::new(50).await;
-    let hooks_runtime = Arc::new(hooks_runtime);
-
-    let (sender, receiver) = channel(10_000);
-
-    assert_eq!(
-        writer_side.output,
-        SideChannelType::InMemory,
-        "Only in-memory is supported"
-    );
-    assert_eq!(
-        reader_side.input,
-        SideChannelType::InMemory,
-        "Only in-memory is supported"
-    );
-
     info!("Building nlp_service");
     let nlp_service = Arc::new(NLPService::new());
 
-    info!("Building write_side");
-    let write_side = WriteSide::new(
-        sender.clone(),
-        writer_side,
-        ai_service.clone(),
-        hooks_runtime,
-        nlp_service.clone(),
-    );
-    let write_side = write_side.load().await.context("Cannot load write side")?;
+    let mut in_memory_receiver = None;
+    let mut file_sender = None;
+    // The last operation in the log: the write side doesn't send anything before it is loaded
+    let mut log_end = Offset(0);
+    let write_side = if let Some(writer_side) = writer_side {
+        let sender = match &writer_side.output {
+            SideChannelType::InMemory => {
+                let (sender, receiver) = channel(10_000);
+                in_memory_receiver = Some(receiver);
+                sender
+            }
+            SideChannelType::File(config) => {
+                let sender =
+                    file_channel(config.clone()).context("Cannot open the file side channel")?;
+                log_end = Offset(sender.offset().0 - 1);
+                file_sender = Some(sender.clone());
+                if let Some(stream_server) = &writer_side.stream_server {
+                    start_operation_stream_server(
+                        stream_server.clone(),
+                        config.data_dir.clone(),
+                        sender.clone(),
+                    )
+                    .await
+                    .context("Cannot start the operation stream server")?;
+                }
+                sender
+            }
+            SideChannelType::Remote(_) => unreachable!("checked above"),
+        };
+
+        info!("Building hooks_runtime");
+        let hooks_runtime = HooksRuntime::new(50).await;
+        let hooks_runtime = Arc::new(hooks_runtime);
+
+        info!("Building write_side");
+        let write_side = WriteSide::try_new(
+            sender,
+            writer_side,
+            ai_service.clone(),
+            hooks_runtime,
+            nlp_service.clone(),
+        )
+        .context("Cannot create write side")?;
+        Some(write_side.load().await.context("Cannot load write side")?)
+    } else {
+        None
+    };
+
+    let Some(reader_side) = reader_side else {
+        return Ok((write_side, None, None));
+    };
 
     info!("Building read_side");
+    let reader_input = reader_side.input.clone();
     let read_side = ReadSide::try_new(ai_service, nlp_service, reader_side)
+        .await
         .context("Cannot create read side")?;
     let read_side = read_side
         .load()
         .await
         .context("Cannot load collection reader")?;
 
-    let write_side = Some(write_side);
+    // The operations after the oldest committed offset are replayed:
+    // the already applied ones are skipped by the read side
+    let from = Offset(read_side.recovery_offset().await.0 + 1);
+    let receiver = match (in_memory_receiver, reader_input) {
+        (Some(receiver), _) => receiver,
+        (None, SideChannelType::File(config)) => {
+            read_side.catch_up(log_end);
+            let committed = read_side.committed_offset();
+            if let Some(consumer) =
+                file_sender.and_then(|sender| sender.consumer(*committed.borrow()))
+            {
+                report_committed_offsets(consumer, committed);
+            }
+            info!(?from, ?log_end, "Tailing the file side channel");
+            OperationReceiver::file(&config, from)
+        }
+        (None, SideChannelType::Remote(config)) => {
+            let (receiver, log_end) =
+                OperationReceiver::remote(&config, from, read_side.committed_offset())
+                    .await
+                    .context("Cannot connect to the write side")?;
+            read_side.catch_up(log_end);
+            info!(?from, ?log_end, address = ?config.address, "Receiving the operation stream");
+            receiver
+        }
+        (None, SideChannelType::InMemory) => unreachable!("checked above"),
+    };
+
+    Ok((write_side, Some(read_side), Some(receiver)))
+}
 
-    Ok((write_side, Some(read_side), receiver))
+/// The configs of the sides run by the node: the other ones are dropped
+fn side_configs(
+    mode: NodeMode,
+    writer_side: Option<WriteSideConfig>,
+    reader_side: Option<ReadSideConfig>,
+) -> Result<(Option<WriteSideConfig>, Option<ReadSideConfig>)> {
+    let writer_side = match mode {
+        NodeMode::Reader => None,
+        NodeMode::All | NodeMode::Writer => Some(
+            writer_side
+                .context("The writer_side config is required unless the node is a reader")?,
+        ),
+    };
+    let reader_side = match mode {
+        NodeMode::Writer => None,
+        NodeMode::All | NodeMode::Reader => Some(
+            reader_side
+                .context("The reader_side config is required unless the node is a writer")?,
+        ),
+    };
+    Ok((writer_side, reader_side))
+}
+
+fn check_side_channels(
+    mode: NodeMode,
+    writer_side: Option<&WriteSideConfig>,
+    reader_side: Option<&ReadSideConfig>,
+) -> Result<()> {
+    let output = writer_side.map(|writer_side| &writer_side.output);
+    let input = reader_side.map(|reader_side| &reader_side.input);
+    match mode {
+        NodeMode::All => match (output, input) {
+            (Some(SideChannelType::InMemory), Some(SideChannelType::InMemory))
+            | (Some(SideChannelType::File(_)), Some(SideChannelType::File(_))) => {}
+            _ => bail!("The write side and the read side must use the same side channel type"),
+        },
+        NodeMode::Writer => {
+            if !matches!(output, Some(SideChannelType::File(_))) {
+                bail!("A writer node needs the file side channel, to serve it to the read sides");
+            }
+        }
+        NodeMode::Reader => {
+            if !matches!(input, Some(SideChannelType::Remote(_))) {
+                bail!("A reader node needs the remote side channel");
+            }
+        }
+    }
+
+    if let Some(writer_side) = writer_side {
+        if writer_side.stream_server.is_some()
+            && !matches!(writer_side.output, SideChannelType::File(_))
+        {
+            bail!("The operation stream server needs the file side channel");
+        }
+    }
+
+    Ok(())
 }
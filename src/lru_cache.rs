@@ -0,0 +1,127 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+/// A bounded map which evicts the least recently used entry when full
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    entries: HashMap<K, (V, u64)>,
+    // Last access tick -> key. The first entry is the least recently used
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::with_capacity(capacity),
+            order: BTreeMap::new(),
+            tick: 0,
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let tick = self.next_tick();
+        let (value, last_access) = self.entries.get_mut(key)?;
+        self.order.remove(last_access);
+        self.order.insert(tick, key.clone());
+        *last_access = tick;
+        Some(value)
+    }
+
+    /// Inserts the entry, returning the evicted ones
+    pub fn insert(&mut self, key: K, value: V) -> Vec<(K, V)> {
+        if self.capacity == 0 {
+            return vec![(key, value)];
+        }
+
+        let tick = self.next_tick();
+        if let Some((_, last_access)) = self.entries.insert(key.clone(), (value, tick)) {
+            self.order.remove(&last_access);
+        }
+        self.order.insert(tick, key);
+
+        let mut evicted = vec![];
+        while self.entries.len() > self.capacity {
+            let (_, key) = match self.order.pop_first() {
+                Some(first) => first,
+                None => break,
+            };
+            if let Some((value, _)) = self.entries.remove(&key) {
+                evicted.push((key, value));
+            }
+        }
+        evicted
+    }
+
+    /// Removes the entries for which `f` returns `false`
+    pub fn retain(&mut self, mut f: impl FnMut(&K) -> bool) {
+        let order = &mut self.order;
+        self.entries.retain(|key, (_, last_access)| {
+            let keep = f(key);
+            if !keep {
+                order.remove(last_access);
+            }
+            keep
+        });
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_cache_evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+
+        assert!(cache.insert("a", 1).is_empty());
+        assert!(cache.insert("b", 2).is_empty());
+
+        // "a" becomes the most recently used
+        assert_eq!(cache.get(&"a"), Some(&1));
+
+        let evicted = cache.insert("c", 3);
+        assert_eq!(evicted, vec![("b", 2)]);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        assert_eq!(cache.get(&"c"), Some(&3));
+
+        // Overwriting doesn't evict
+        assert!(cache.insert("c", 4).is_empty());
+        assert_eq!(cache.get(&"c"), Some(&4));
+
+        cache.retain(|k| *k != "a");
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&"a"), None);
+
+        assert!(cache.insert("d", 5).is_empty());
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_lru_cache_zero_capacity() {
+        let mut cache = LruCache::new(0);
+
+        assert_eq!(cache.insert("a", 1), vec![("a", 1)]);
+        assert_eq!(cache.len(), 0);
+        assert_eq!(cache.get(&"a"), None);
+    }
+}
@@ -162,6 +162,29 @@ pub static COLLECTION_OPERATION_COUNTER: Counter<CollectionOperationLabels> = Co
     phantom: std::marker::PhantomData,
 };
 
+create_label_struct!(SearchCacheLabels, {
+    collection: String,
+});
+pub static SEARCH_CACHE_HIT_COUNTER: Counter<SearchCacheLabels> = Counter {
+    key: "reader_search_cache_hit_counter",
+    phantom: std::marker::PhantomData,
+};
+pub static SEARCH_CACHE_MISS_COUNTER: Counter<SearchCacheLabels> = Counter {
+    key: "reader_search_cache_miss_counter",
+    phantom: std::marker::PhantomData,
+};
+create_label_struct!(EmbeddingCacheLabels, {
+    model: String,
+});
+pub static EMBEDDING_CACHE_HIT_COUNTER: Counter<EmbeddingCacheLabels> = Counter {
+    key: "embedding_query_cache_hit_counter",
+    phantom: std::marker::PhantomData,
+};
+pub static EMBEDDING_CACHE_MISS_COUNTER: Counter<EmbeddingCacheLabels> = Counter {
+    key: "embedding_query_cache_miss_counter",
+    phantom: std::marker::PhantomData,
+};
+
 pub struct Histogram<Labels> {
     key: &'static str,
     phantom: std::marker::PhantomData<Labels>,
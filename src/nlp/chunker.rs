@@ -1,5 +1,8 @@
+use std::fmt::{Debug, Formatter};
+
 use anyhow::Result;
 use dashmap::DashMap;
+use serde::Deserialize;
 use text_splitter::{Characters, ChunkConfig, CodeSplitter, MarkdownSplitter, TextSplitter};
 use tiktoken_rs::*;
 
@@ -7,26 +10,54 @@ use crate::code_parser::CodeLanguage;
 
 pub struct Chunker {
     max_tokens: usize,
+    overlap: usize,
     text_splitter: TextSplitter<CoreBPE>,
     code_splitters: DashMap<CodeLanguage, CodeSplitter<Characters>>,
     markdown_splitter: MarkdownSplitter<Characters>,
 }
 
+impl Debug for Chunker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Chunker")
+            .field("max_tokens", &self.max_tokens)
+            .field("overlap", &self.overlap)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct ChunkerConfig {
+    /// The maximum number of tokens of a chunk
     pub max_tokens: usize,
+    /// The number of tokens shared by two consecutive chunks
     pub overlap: Option<usize>,
 }
 
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        Self {
+            max_tokens: 512,
+            overlap: None,
+        }
+    }
+}
+
 impl Chunker {
     pub fn try_new(config: ChunkerConfig) -> Result<Self> {
+        let overlap = config.overlap.unwrap_or(0);
+
         let tokenizer = cl100k_base()?;
-        let text_tokenizer_config = ChunkConfig::new(config.max_tokens).with_sizer(tokenizer);
+        let text_tokenizer_config = ChunkConfig::new(config.max_tokens)
+            .with_sizer(tokenizer)
+            .with_overlap(overlap)?;
+        let markdown_config = ChunkConfig::new(config.max_tokens).with_overlap(overlap)?;
 
         Ok(Chunker {
             max_tokens: config.max_tokens,
+            overlap,
             code_splitters: DashMap::new(),
             text_splitter: TextSplitter::new(text_tokenizer_config),
-            markdown_splitter: MarkdownSplitter::new(config.max_tokens),
+            markdown_splitter: MarkdownSplitter::new(markdown_config),
         })
     }
 
@@ -55,8 +86,11 @@ impl Chunker {
                 CodeLanguage::HTML => tree_sitter_html::LANGUAGE,
             };
 
-            CodeSplitter::new(lang, self.max_tokens)
-                .expect("Unable to create CodeSplitter instance")
+            // The overlap is already validated in `try_new`
+            let config = ChunkConfig::new(self.max_tokens)
+                .with_overlap(self.overlap)
+                .expect("Invalid overlap");
+            CodeSplitter::new(lang, config).expect("Unable to create CodeSplitter instance")
         });
 
         code_splitter
@@ -65,3 +99,33 @@ impl Chunker {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_text() {
+        let chunker = Chunker::try_new(ChunkerConfig {
+            max_tokens: 10,
+            overlap: Some(2),
+        })
+        .unwrap();
+
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(10);
+        let chunks = chunker.chunk_text(&text);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| !chunk.is_empty()));
+
+        assert!(chunker.chunk_text("").is_empty());
+    }
+
+    #[test]
+    fn test_chunker_invalid_overlap() {
+        let chunker = Chunker::try_new(ChunkerConfig {
+            max_tokens: 10,
+            overlap: Some(10),
+        });
+        assert!(chunker.is_err());
+    }
+}
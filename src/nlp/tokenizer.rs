@@ -3,17 +3,30 @@ use std::collections::HashSet;
 use crate::nlp::locales::Locale;
 use regex::Regex;
 
+#[derive(Debug, Clone)]
+enum Splitter {
+    Regex(Regex),
+    /// Chinese, Japanese and Korean don't separate the words with spaces:
+    /// the CJK runs are split into overlapping bigrams
+    Cjk,
+}
+
 #[derive(Debug, Clone)]
 pub struct Tokenizer {
-    split_regex: Regex,
+    splitter: Splitter,
     stop_words: HashSet<&'static str>,
 }
 
 impl Tokenizer {
     fn new(locale: Locale) -> Self {
         let stop_words: HashSet<&'static str> = locale.stop_words().unwrap();
+        // Only the CJK locales have no split regex
+        let splitter = match locale.split_regex() {
+            Some(split_regex) => Splitter::Regex(split_regex),
+            None => Splitter::Cjk,
+        };
         Tokenizer {
-            split_regex: locale.split_regex().unwrap(),
+            splitter,
             stop_words,
         }
     }
@@ -154,11 +167,17 @@ impl Tokenizer {
     where
         'a: 'b,
     {
-        let b = self
-            .split_regex
-            .split(input)
-            .filter(|token| !token.is_empty())
-            .filter_map(|token| self.normalize_token(token.to_lowercase()))
+        let tokens: Box<dyn Iterator<Item = String> + 'b> = match &self.splitter {
+            Splitter::Regex(split_regex) => Box::new(
+                split_regex
+                    .split(input)
+                    .filter(|token| !token.is_empty())
+                    .map(|token| token.to_lowercase()),
+            ),
+            Splitter::Cjk => Box::new(segment_cjk(input).into_iter()),
+        };
+        let b = tokens
+            .filter_map(|token| self.normalize_token(token))
             .filter(|token| !token.is_empty() && !self.stop_words.contains(token.as_str()));
         b
     }
@@ -175,6 +194,72 @@ impl Tokenizer {
     }
 }
 
+/// Splits the text in words, emitting the overlapping bigrams of every CJK run.
+/// A single CJK character is emitted as is.
+/// Because the same segmentation is applied at index and at query time,
+/// a query matches when its bigrams are contained in the document.
+fn segment_cjk(input: &str) -> Vec<String> {
+    fn flush_run(run: &mut Vec<char>, tokens: &mut Vec<String>) {
+        match run.len() {
+            0 => {}
+            1 => tokens.push(run[0].to_string()),
+            _ => tokens.extend(run.windows(2).map(|bigram| bigram.iter().collect())),
+        }
+        run.clear();
+    }
+    fn flush_word(word: &mut String, tokens: &mut Vec<String>) {
+        if !word.is_empty() {
+            tokens.push(std::mem::take(word));
+        }
+    }
+
+    let mut tokens = vec![];
+    let mut run: Vec<char> = vec![];
+    let mut word = String::new();
+    for c in input.chars() {
+        if is_cjk(c) {
+            flush_word(&mut word, &mut tokens);
+            run.push(c);
+        } else if c.is_alphanumeric() {
+            flush_run(&mut run, &mut tokens);
+            word.extend(c.to_lowercase());
+        } else {
+            flush_run(&mut run, &mut tokens);
+            flush_word(&mut word, &mut tokens);
+        }
+    }
+    flush_run(&mut run, &mut tokens);
+    flush_word(&mut word, &mut tokens);
+
+    tokens
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(
+        u32::from(c),
+        // Hangul Jamo
+        0x1100..=0x11FF
+        // Hiragana, Katakana
+        | 0x3040..=0x30FF
+        // Hangul Compatibility Jamo
+        | 0x3130..=0x318F
+        // Katakana Phonetic Extensions
+        | 0x31F0..=0x31FF
+        // CJK Unified Ideographs Extension A
+        | 0x3400..=0x4DBF
+        // CJK Unified Ideographs
+        | 0x4E00..=0x9FFF
+        // Hangul Syllables
+        | 0xAC00..=0xD7AF
+        // CJK Compatibility Ideographs
+        | 0xF900..=0xFAFF
+        // Halfwidth Katakana
+        | 0xFF66..=0xFF9F
+        // CJK Unified Ideographs Extension B
+        | 0x20000..=0x2A6DF
+    )
+}
+
 fn replace_char(c: char) -> char {
     let code = u32::from(c);
     if !(DIACRITICS_CHARCODE_START..=DIACRITICS_CHARCODE_END).contains(&code) {
@@ -218,4 +303,19 @@ mod tests {
         let tokens: Vec<String> = tokenizer.tokenize("Hello, - world!").collect();
         assert_eq!(tokens, vec!["hello", "-", "world"]);
     }
+
+    #[test]
+    fn test_tokenizer_cjk() {
+        let tokenizer = super::Tokenizer::chinese();
+        let tokens: Vec<String> = tokenizer.tokenize("北京大学 Rust语言").collect();
+        assert_eq!(tokens, vec!["北京", "京大", "大学", "rust", "语言"]);
+
+        let tokenizer = super::Tokenizer::japanese();
+        let tokens: Vec<String> = tokenizer.tokenize("東京タワー").collect();
+        assert_eq!(tokens, vec!["東京", "京タ", "タワ", "ワー"]);
+
+        let tokenizer = super::Tokenizer::korean();
+        let tokens: Vec<String> = tokenizer.tokenize("한국어 검색").collect();
+        assert_eq!(tokens, vec!["한국", "국어", "검색"]);
+    }
 }
@@ -5,6 +5,7 @@ use std::{
 };
 
 use anyhow::Result;
+use futures::{StreamExt, TryStreamExt};
 use http::uri::Scheme;
 use redact::Secret;
 use serde_json::json;
@@ -14,20 +15,28 @@ use crate::{
     ai::AIServiceConfig,
     build_orama,
     collection_manager::{
-        dto::ApiKey,
+        dto::{
+            ApiKey, ImportFormat, ImportState, ReindexState, SchemaFieldType, SearchResult,
+            TaskStatus,
+        },
         sides::{
-            CollectionsWriterConfig, IndexesConfig, OramaModelSerializable, ReadSide, WriteSide,
+            CatchingUpError, CollectionsWriterConfig, FederatedSearchError, FileSideChannelConfig,
+            FsyncPolicy, IndexesConfig, Offset, OperationStreamServerConfig,
+            OramaModelSerializable, ReadSide, RemoteSideChannelConfig, SchemaError,
+            SearchCacheConfig, UnsupportedFilterError, VersionConflict, WriteSide,
         },
     },
     connect_write_and_read_side,
+    nlp::chunker::ChunkerConfig,
     test_utils::{create_grpc_server, generate_new_path},
     types::{CollectionId, DocumentList},
     web_server::HttpConfig,
-    OramacoreConfig, ReadSideConfig, SideChannelType, WriteSideConfig,
+    NodeMode, OramacoreConfig, ReadSideConfig, SideChannelType, WriteSideConfig,
 };
 
 fn create_oramacore_config() -> OramacoreConfig {
     OramacoreConfig {
+        mode: NodeMode::All,
         log: Default::default(),
         http: HttpConfig {
             host: "127.0.0.1".parse().unwrap(),
@@ -40,11 +49,13 @@ fn create_oramacore_config() -> OramacoreConfig {
             port: 0,
             api_key: None,
             max_connections: 1,
+            embedding_cache_size: 0,
             scheme: Scheme::HTTP,
         },
-        writer_side: WriteSideConfig {
+        writer_side: Some(WriteSideConfig {
             master_api_key: ApiKey(Secret::new("my-master-api-key".to_string())),
             output: SideChannelType::InMemory,
+            stream_server: None,
             config: CollectionsWriterConfig {
                 data_dir: generate_new_path(),
                 embedding_queue_limit: 50,
@@ -54,9 +65,10 @@ fn create_oramacore_config() -> OramacoreConfig {
                 insert_batch_commit_size: 10_000,
                 javascript_queue_limit: 10_000,
                 commit_interval: Duration::from_secs(3_000),
+                embedding_chunking: Default::default(),
             },
-        },
-        reader_side: ReadSideConfig {
+        }),
+        reader_side: Some(ReadSideConfig {
             input: SideChannelType::InMemory,
             config: IndexesConfig {
                 data_dir: generate_new_path(),
@@ -65,7 +77,10 @@ fn create_oramacore_config() -> OramacoreConfig {
                 insert_batch_commit_size: 10_000,
                 commit_interval: Duration::from_secs(3_000),
             },
-        },
+            search_cache: None,
+            snapshot: Default::default(),
+        }),
+        shutdown: Default::default(),
     }
 }
 
@@ -79,7 +94,7 @@ async fn create(mut config: OramacoreConfig) -> Result<(Arc<WriteSide>, Arc<Read
 
     let (write_side, read_side, rec) = build_orama(config).await?;
 
-    connect_write_and_read_side(rec, read_side.clone().unwrap());
+    connect_write_and_read_side(rec.unwrap(), read_side.clone().unwrap());
 
     let write_side = write_side.unwrap();
     let read_side = read_side.unwrap();
@@ -616,6 +631,87 @@ async fn test_filter_number() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_filter_number_64_bit() -> Result<()> {
+    let (write_side, read_side) = create(create_oramacore_config()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        (0..10).map(|i| {
+            json!({
+                "id": i.to_string(),
+                "text": "text",
+                // Milliseconds timestamps don't fit in 32 bits
+                "timestamp": 1_700_000_000_000_i64 + i,
+                "price": 19.99 + i as f64,
+            })
+        }),
+    )
+    .await?;
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "term": "text",
+                "where": {
+                    "timestamp": {
+                        "eq": 1_700_000_000_005_i64,
+                    },
+                }
+            })
+            .try_into()?,
+        )
+        .await?;
+
+    assert_eq!(output.count, 1);
+    assert_eq!(output.hits[0].id, "5");
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "term": "text",
+                "where": {
+                    "timestamp": {
+                        "between": [1_700_000_000_002_i64, 1_700_000_000_004_i64],
+                    },
+                }
+            })
+            .try_into()?,
+        )
+        .await?;
+
+    assert_eq!(output.count, 3);
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "term": "text",
+                "where": {
+                    "price": {
+                        "eq": 19.99 + 2.0,
+                    },
+                }
+            })
+            .try_into()?,
+        )
+        .await?;
+
+    assert_eq!(output.count, 1);
+    assert_eq!(output.hits[0].id, "2");
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_facets_number() -> Result<()> {
     let (write_side, read_side) = create(create_oramacore_config()).await?;
@@ -1059,6 +1155,97 @@ async fn test_vector_search_grpc() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_vector_search_chunks() -> Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let mut config = create_oramacore_config();
+    config
+        .writer_side
+        .as_mut()
+        .unwrap()
+        .config
+        .embedding_chunking = ChunkerConfig {
+        max_tokens: 10,
+        overlap: None,
+    };
+    let (write_side, read_side) = create(config).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    write_side
+        .create_collection(
+            ApiKey(Secret::new("my-master-api-key".to_string())),
+            json!({
+                "id": collection_id.0.clone(),
+                "embeddings": {
+                    "model": "BGESmall",
+                    "document_fields": ["text"],
+                },
+                "read_api_key": "my-read-api-key",
+                "write_api_key": "my-write-api-key",
+            })
+            .try_into()?,
+        )
+        .await?;
+
+    sleep(Duration::from_millis(100)).await;
+
+    write_side
+        .write(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            vec![
+                json!({
+                    "id": "1",
+                    "text": "The stock market closed higher today. The cat is sleeping on the table. The new train line opens next year.",
+                }),
+                json!({
+                    "id": "2",
+                    "text": "The dog is barking loudly in the yard.",
+                }),
+            ]
+            .try_into()?,
+        )
+        .await?;
+
+    sleep(Duration::from_millis(500)).await;
+
+    let read_side = &read_side;
+    let collection_id = &collection_id;
+    let search = || async move {
+        read_side
+            .search(
+                ApiKey(Secret::new("my-read-api-key".to_string())),
+                collection_id.clone(),
+                json!({
+                    "mode": "vector",
+                    "term": "The feline is napping comfortably indoors.",
+                    "include_chunk": true,
+                })
+                .try_into()
+                .unwrap(),
+            )
+            .await
+    };
+
+    // Every chunk has its own embedding: the document scores as its best chunk
+    let output = search().await?;
+    assert_eq!(output.hits[0].id, "1");
+    let chunk = output.hits[0].chunk.as_deref().unwrap();
+    assert!(chunk.contains("cat"));
+    assert!(!chunk.contains("stock"));
+
+    read_side.commit().await?;
+
+    let output = search().await?;
+    assert_eq!(output.hits[0].id, "1");
+    let chunk = output.hits[0].chunk.as_deref().unwrap();
+    assert!(chunk.contains("cat"));
+    assert!(!chunk.contains("stock"));
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_handle_bool() -> Result<()> {
     let _ = tracing_subscriber::fmt::try_init();
@@ -1372,8 +1559,18 @@ async fn test_commit_and_load2() -> Result<()> {
 async fn test_read_commit_should_not_block_search() -> Result<()> {
     let _ = tracing_subscriber::fmt::try_init();
     let mut config = create_oramacore_config();
-    config.reader_side.config.insert_batch_commit_size = 1_000_000;
-    config.writer_side.config.insert_batch_commit_size = 1_000_000;
+    config
+        .reader_side
+        .as_mut()
+        .unwrap()
+        .config
+        .insert_batch_commit_size = 1_000_000;
+    config
+        .writer_side
+        .as_mut()
+        .unwrap()
+        .config
+        .insert_batch_commit_size = 1_000_000;
 
     let (write_side, read_side) = create(config.clone()).await?;
 
@@ -1452,8 +1649,18 @@ async fn test_read_commit_should_not_block_search() -> Result<()> {
 async fn test_delete_documents() -> Result<()> {
     let _ = tracing_subscriber::fmt::try_init();
     let mut config = create_oramacore_config();
-    config.reader_side.config.insert_batch_commit_size = 1_000_000;
-    config.writer_side.config.insert_batch_commit_size = 1_000_000;
+    config
+        .reader_side
+        .as_mut()
+        .unwrap()
+        .config
+        .insert_batch_commit_size = 1_000_000;
+    config
+        .writer_side
+        .as_mut()
+        .unwrap()
+        .config
+        .insert_batch_commit_size = 1_000_000;
 
     let (write_side, read_side) = create(config.clone()).await?;
 
@@ -1608,6 +1815,2505 @@ async fn test_delete_documents() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_upsert_and_patch_document() -> Result<()> {
+    let (write_side, read_side) = create(create_oramacore_config()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        vec![
+            json!({
+                "id": "1",
+                "title": "old title",
+                "category": "books",
+            }),
+            json!({
+                "id": "2",
+                "title": "other title",
+            }),
+        ],
+    )
+    .await?;
+
+    // Same id: the document is replaced
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        vec![json!({
+            "id": "1",
+            "title": "new title",
+            "category": "books",
+        })],
+    )
+    .await?;
+
+    let read_side = &read_side;
+    let collection_id = &collection_id;
+    let count = |term: &'static str| async move {
+        read_side
+            .search(
+                ApiKey(Secret::new("my-read-api-key".to_string())),
+                collection_id.clone(),
+                json!({
+                    "term": term,
+                })
+                .try_into()
+                .unwrap(),
+            )
+            .await
+            .unwrap()
+            .count
+    };
+
+    assert_eq!(count("title").await, 2);
+    assert_eq!(count("old").await, 0);
+    assert_eq!(count("new").await, 1);
+
+    write_side
+        .patch_document(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            "1".to_string(),
+            json!({
+                "category": "music",
+            })
+            .as_object()
+            .cloned()
+            .unwrap(),
+            None,
+        )
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(count("title").await, 2);
+    assert_eq!(count("books").await, 0);
+    assert_eq!(count("music").await, 1);
+    assert_eq!(count("new").await, 1);
+
+    let docs = read_side
+        .get_documents(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            vec!["1".to_string()],
+        )
+        .await?;
+    let doc: serde_json::Value = serde_json::from_str(docs[0].as_ref().unwrap().inner.get())?;
+    assert_eq!(
+        doc,
+        json!({
+            "id": "1",
+            "title": "new title",
+            "category": "music",
+        })
+    );
+
+    // Unknown document
+    let output = write_side
+        .patch_document(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            "unknown".to_string(),
+            json!({ "category": "music" }).as_object().cloned().unwrap(),
+            None,
+        )
+        .await;
+    assert!(output.is_err());
+
+    // The id cannot be changed
+    let output = write_side
+        .patch_document(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            "1".to_string(),
+            json!({ "id": "3" }).as_object().cloned().unwrap(),
+            None,
+        )
+        .await;
+    assert!(output.is_err());
+
+    write_side.commit().await?;
+    read_side.commit().await?;
+
+    assert_eq!(count("title").await, 2);
+    assert_eq!(count("music").await, 1);
+
+    // The patch works also on the committed documents
+    write_side
+        .patch_document(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            "2".to_string(),
+            json!({ "title": null, "category": "music" })
+                .as_object()
+                .cloned()
+                .unwrap(),
+            None,
+        )
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(count("title").await, 1);
+    assert_eq!(count("music").await, 2);
+
+    // Patched while the embedding of the previous version can still be in the queue:
+    // the patched document has an embedding anyway
+    let docs: Vec<serde_json::Value> = vec![json!({
+        "id": "4",
+        "title": "The cat is sleeping on the table.",
+    })];
+    write_side
+        .write(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            docs.try_into()?,
+        )
+        .await?;
+    write_side
+        .patch_document(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            "4".to_string(),
+            json!({ "year": 2000 }).as_object().cloned().unwrap(),
+            None,
+        )
+        .await?;
+    sleep(Duration::from_millis(1_000)).await;
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "mode": "vector",
+                "term": "The cat is sleeping on the table.",
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert!(output.hits.iter().any(|hit| hit.id == "4"));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_collection_ids_of_the_write_side_internals() -> Result<()> {
+    let config = create_oramacore_config();
+    let (write_side, _) = create(config.clone()).await?;
+
+    // The document storage and the tasks are not in the data dir of the collections
+    for id in ["docs", "tasks"] {
+        let collection_id = CollectionId(id.to_string());
+        create_collection(write_side.clone(), collection_id.clone()).await?;
+        insert_docs(
+            write_side.clone(),
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id,
+            vec![json!({
+                "id": "1",
+                "name": "John Doe",
+            })],
+        )
+        .await?;
+    }
+    write_side.commit().await?;
+
+    let (write_side, _) = create(config).await?;
+    let collection_ids: HashSet<_> = write_side
+        .list_collections(ApiKey(Secret::new("my-master-api-key".to_string())))
+        .await?
+        .into_iter()
+        .map(|collection| collection.id.0)
+        .collect();
+    assert_eq!(
+        collection_ids,
+        HashSet::from(["docs".to_string(), "tasks".to_string()])
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_import_documents() -> Result<()> {
+    let (write_side, read_side) = create(create_oramacore_config()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+
+    let body = futures::stream::iter(
+        vec![
+            "{\"id\": \"1\", \"title\": \"first\"}\n{\"id\": \"2\", ",
+            "\"title\": \"second\"}\n",
+            "invalid\n{\"id\": \"3\", \"title\": \"third\"}\n",
+        ]
+        .into_iter()
+        .map(Ok::<_, std::convert::Infallible>),
+    );
+    let status = write_side
+        .clone()
+        .import_documents(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            ImportFormat::Ndjson,
+            Some("my-import".to_string()),
+            body,
+        )
+        .await?;
+    assert_eq!(status.id, "my-import");
+    assert_eq!(status.state, ImportState::Completed);
+    assert_eq!(status.processed, 4);
+    assert_eq!(status.inserted, 3);
+    assert_eq!(status.failed, 1);
+    assert_eq!(status.errors.len(), 1);
+    assert_eq!(status.errors[0].line, 3);
+
+    let status = write_side
+        .get_import_status(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            "my-import".to_string(),
+        )
+        .await?
+        .unwrap();
+    assert_eq!(status.inserted, 3);
+
+    // The import id is unique
+    let output = write_side
+        .clone()
+        .import_documents(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            ImportFormat::Ndjson,
+            Some("my-import".to_string()),
+            futures::stream::empty::<Result<&'static str, std::convert::Infallible>>(),
+        )
+        .await;
+    assert!(output.is_err());
+
+    let status = write_side
+        .clone()
+        .import_documents(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            ImportFormat::Csv,
+            None,
+            futures::stream::iter(vec![Ok::<_, std::convert::Infallible>(
+                "id,title,year\n4,fourth,2021\n5,fifth,2023\n",
+            )]),
+        )
+        .await?;
+    assert_eq!(status.state, ImportState::Completed);
+    assert_eq!(status.inserted, 2);
+    assert_eq!(status.failed, 0);
+    sleep(Duration::from_millis(100)).await;
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "term": "",
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 5);
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "term": "",
+                "where": {
+                    "year": {
+                        "gt": 2022,
+                    },
+                },
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 1);
+    assert_eq!(output.hits[0].id, "5");
+
+    // The caller stops waiting, then the body is interrupted: the import fails anyway
+    let body = futures::stream::iter(vec![Ok("{\"id\": \"6\"}\n".to_string())]).chain(
+        futures::stream::once(async {
+            sleep(Duration::from_millis(200)).await;
+            Err("connection closed".to_string())
+        }),
+    );
+    let output = tokio::time::timeout(
+        Duration::from_millis(50),
+        write_side.clone().import_documents(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            ImportFormat::Ndjson,
+            Some("interrupted-import".to_string()),
+            Box::pin(body),
+        ),
+    )
+    .await;
+    assert!(output.is_err());
+    sleep(Duration::from_millis(500)).await;
+
+    let status = write_side
+        .get_import_status(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            "interrupted-import".to_string(),
+        )
+        .await?
+        .unwrap();
+    assert_eq!(status.state, ImportState::Failed);
+    assert_eq!(status.inserted, 1);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_federated_search() -> Result<()> {
+    let (write_side, read_side) = create(create_oramacore_config()).await?;
+
+    let collection_id_1 = CollectionId("test-collection-1".to_string());
+    let collection_id_2 = CollectionId("test-collection-2".to_string());
+    create_collection(write_side.clone(), collection_id_1.clone()).await?;
+    create_collection(write_side.clone(), collection_id_2.clone()).await?;
+
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id_1.clone(),
+        vec![
+            json!({
+                "id": "1",
+                "name": "John Doe",
+            }),
+            json!({
+                "id": "2",
+                "name": "Jane Doe",
+            }),
+        ],
+    )
+    .await?;
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id_2.clone(),
+        vec![json!({
+            "id": "3",
+            "name": "Doe",
+            "age": 42,
+        })],
+    )
+    .await?;
+
+    let result = read_side
+        .federated_search(
+            json!({
+                "queries": [
+                    {
+                        "collection": collection_id_1.0.clone(),
+                        "api_key": "my-read-api-key",
+                        "params": { "term": "Doe" },
+                    },
+                    {
+                        "collection": collection_id_2.0.clone(),
+                        "api_key": "my-read-api-key",
+                        "params": {
+                            "term": "Doe",
+                            "facets": {
+                                "age": { "ranges": [{ "from": 0, "to": 100 }] },
+                            },
+                        },
+                    },
+                ],
+                "merge": "rrf",
+            })
+            .try_into()?,
+        )
+        .await?;
+
+    assert_eq!(result.count, 3);
+    assert_eq!(result.hits.len(), 3);
+    assert_eq!(result.hits[2].collection_id, collection_id_1);
+    assert_eq!(result.hits[2].id, "2".to_string());
+    let collection_ids: HashSet<_> = result
+        .hits
+        .iter()
+        .map(|hit| hit.collection_id.clone())
+        .collect();
+    assert_eq!(
+        collection_ids,
+        HashSet::from([collection_id_1.clone(), collection_id_2.clone()])
+    );
+    // Only the second query asks for facets
+    assert_eq!(result.facets.len(), 1);
+    assert_eq!(result.facets[&1]["age"].values["0-100"], 1);
+
+    let output = read_side
+        .federated_search(
+            json!({
+                "queries": [
+                    {
+                        "collection": collection_id_1.0.clone(),
+                        "api_key": "wrong-api-key",
+                        "params": { "term": "Doe" },
+                    },
+                ],
+            })
+            .try_into()?,
+        )
+        .await;
+    let err = output.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<FederatedSearchError>(),
+        Some(&FederatedSearchError::InvalidApiKey { index: 0 })
+    );
+
+    // The same collection twice: every query has its own facets
+    let result = read_side
+        .federated_search(
+            json!({
+                "queries": [
+                    {
+                        "collection": collection_id_2.0.clone(),
+                        "api_key": "my-read-api-key",
+                        "params": {
+                            "term": "Doe",
+                            "facets": {
+                                "age": { "ranges": [{ "from": 0, "to": 10 }] },
+                            },
+                        },
+                    },
+                    {
+                        "collection": collection_id_2.0.clone(),
+                        "api_key": "my-read-api-key",
+                        "params": {
+                            "term": "Doe",
+                            "facets": {
+                                "age": { "ranges": [{ "from": 40, "to": 50 }] },
+                            },
+                        },
+                    },
+                ],
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(result.facets.len(), 2);
+    assert_eq!(result.facets[&0]["age"].values["0-10"], 0);
+    assert_eq!(result.facets[&1]["age"].values["40-50"], 1);
+
+    let output = read_side
+        .federated_search(
+            json!({
+                "queries": [
+                    {
+                        "collection": "unknown-collection",
+                        "api_key": "my-read-api-key",
+                        "params": { "term": "Doe" },
+                    },
+                ],
+            })
+            .try_into()?,
+        )
+        .await;
+    let err = output.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<FederatedSearchError>(),
+        Some(FederatedSearchError::CollectionNotFound { index: 0, .. })
+    ));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_field_and_document_languages() -> Result<()> {
+    let (write_side, read_side) = create(create_oramacore_config()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    write_side
+        .create_collection(
+            ApiKey(Secret::new("my-master-api-key".to_string())),
+            json!({
+                "id": collection_id.0.clone(),
+                "read_api_key": "my-read-api-key",
+                "write_api_key": "my-write-api-key",
+                "language": "English",
+                "field_languages": {
+                    "title_it": "Italian",
+                },
+                "language_property": "lang",
+            })
+            .try_into()?,
+        )
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+
+    // The english stemmer transforms "happy" into "happi", the italian one doesn't
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        vec![
+            json!({
+                "id": "1",
+                "lang": "it",
+                "title": "happy",
+            }),
+            json!({
+                "id": "2",
+                "title": "happy",
+            }),
+            json!({
+                "id": "3",
+                "title_it": "happy",
+            }),
+        ],
+    )
+    .await?;
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "term": "happi",
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 1);
+    assert_eq!(output.hits[0].id, "2");
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "term": "happy",
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 3);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_search_chinese() -> Result<()> {
+    let (write_side, read_side) = create(create_oramacore_config()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    write_side
+        .create_collection(
+            ApiKey(Secret::new("my-master-api-key".to_string())),
+            json!({
+                "id": collection_id.0.clone(),
+                "read_api_key": "my-read-api-key",
+                "write_api_key": "my-write-api-key",
+                "language": "Chinese",
+            })
+            .try_into()?,
+        )
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        vec![
+            json!({
+                "id": "1",
+                "title": "我在北京大学读书",
+            }),
+            json!({
+                "id": "2",
+                "title": "上海是一个大城市",
+            }),
+        ],
+    )
+    .await?;
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "term": "北京",
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 1);
+    assert_eq!(output.hits[0].id, "1");
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "term": "城市",
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 1);
+    assert_eq!(output.hits[0].id, "2");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_infix_search() -> Result<()> {
+    let (write_side, read_side) = create(create_oramacore_config()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    write_side
+        .create_collection(
+            ApiKey(Secret::new("my-master-api-key".to_string())),
+            json!({
+                "id": collection_id.0.clone(),
+                "read_api_key": "my-read-api-key",
+                "write_api_key": "my-write-api-key",
+                "infix_fields": ["sku"],
+            })
+            .try_into()?,
+        )
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        vec![
+            json!({
+                "id": "1",
+                "sku": "XJ-45201-B",
+            }),
+            json!({
+                "id": "2",
+                "sku": "4520-AA",
+            }),
+            json!({
+                "id": "3",
+                "sku": "ZZ-999",
+            }),
+            // The infix search is not enabled on this field
+            json!({
+                "id": "4",
+                "code": "XJ-45201-B",
+            }),
+        ],
+    )
+    .await?;
+
+    let search = || async {
+        read_side
+            .search(
+                ApiKey(Secret::new("my-read-api-key".to_string())),
+                collection_id.clone(),
+                json!({
+                    "term": "4520",
+                })
+                .try_into()
+                .unwrap(),
+            )
+            .await
+    };
+
+    // The prefix match ranks above the infix one
+    let output = search().await?;
+    assert_eq!(output.count, 2);
+    assert_eq!(output.hits[0].id, "2");
+    assert_eq!(output.hits[1].id, "1");
+    assert!(output.hits[0].score > output.hits[1].score);
+
+    write_side.commit().await?;
+    read_side.commit().await?;
+
+    let output = search().await?;
+    assert_eq!(output.count, 2);
+    assert_eq!(output.hits[0].id, "2");
+    assert_eq!(output.hits[1].id, "1");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_infix_search_score() -> Result<()> {
+    let (write_side, read_side) = create(create_oramacore_config()).await?;
+
+    let docs = vec![
+        json!({ "id": "1", "title": "The green boots" }),
+        json!({ "id": "2", "title": "A green jacket for the long winter nights" }),
+        json!({ "id": "3", "title": "Red shoes" }),
+    ];
+
+    // The same documents, with the infix search enabled on the field or not
+    let with_infix = CollectionId("with-infix".to_string());
+    let without_infix = CollectionId("without-infix".to_string());
+    for (collection_id, infix_fields) in
+        [(&with_infix, json!(["title"])), (&without_infix, json!([]))]
+    {
+        write_side
+            .create_collection(
+                ApiKey(Secret::new("my-master-api-key".to_string())),
+                json!({
+                    "id": collection_id.0.clone(),
+                    "read_api_key": "my-read-api-key",
+                    "write_api_key": "my-write-api-key",
+                    "infix_fields": infix_fields,
+                })
+                .try_into()?,
+            )
+            .await?;
+        insert_docs(
+            write_side.clone(),
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            docs.clone(),
+        )
+        .await?;
+    }
+
+    let search = |collection_id: CollectionId| {
+        let read_side = read_side.clone();
+        async move {
+            read_side
+                .search(
+                    ApiKey(Secret::new("my-read-api-key".to_string())),
+                    collection_id,
+                    json!({
+                        "term": "green",
+                    })
+                    .try_into()
+                    .unwrap(),
+                )
+                .await
+        }
+    };
+    let assert_same_scores = |a: SearchResult, b: SearchResult| {
+        assert_eq!(a.count, 2);
+        assert_eq!(a.count, b.count);
+        for (a, b) in a.hits.iter().zip(&b.hits) {
+            assert_eq!(a.id, b.id);
+            assert!((a.score - b.score).abs() < f32::EPSILON);
+        }
+    };
+
+    // The infix terms don't change the length of the field
+    assert_same_scores(
+        search(with_infix.clone()).await?,
+        search(without_infix.clone()).await?,
+    );
+
+    write_side.commit().await?;
+    read_side.commit().await?;
+
+    assert_same_scores(
+        search(with_infix.clone()).await?,
+        search(without_infix.clone()).await?,
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_search_cache() -> Result<()> {
+    let mut config = create_oramacore_config();
+    config.reader_side.as_mut().unwrap().search_cache = Some(SearchCacheConfig { capacity: 10 });
+    let (write_side, read_side) = create(config).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        (0..5).map(|i| {
+            json!({
+                "id": i.to_string(),
+                "text": "text",
+            })
+        }),
+    )
+    .await?;
+
+    let read_side = &read_side;
+    let collection_id = &collection_id;
+    let search = || async move {
+        read_side
+            .search(
+                ApiKey(Secret::new("my-read-api-key".to_string())),
+                collection_id.clone(),
+                json!({
+                    "term": "text",
+                })
+                .try_into()
+                .unwrap(),
+            )
+            .await
+    };
+
+    let first = search().await?;
+    let second = search().await?;
+    assert_eq!(first.count, 5);
+    assert_eq!(first, second);
+
+    // A write operation invalidates the cached result
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        vec![json!({
+            "id": "5",
+            "text": "text",
+        })],
+    )
+    .await?;
+
+    let output = search().await?;
+    assert_eq!(output.count, 6);
+
+    // The API key is checked also for cached results
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("wrong-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "term": "text",
+            })
+            .try_into()?,
+        )
+        .await;
+    assert!(output.is_err());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_similar_search() -> Result<()> {
+    let (write_side, read_side) = create(create_oramacore_config()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        vec![
+            json!({
+                "id": "1",
+                "title": "The quick brown fox jumps over the lazy dog",
+                "year": 2020,
+            }),
+            json!({
+                "id": "2",
+                "title": "A quick brown fox",
+                "year": 2021,
+            }),
+            json!({
+                "id": "3",
+                "title": "The lazy dog sleeps",
+                "year": 2022,
+            }),
+            json!({
+                "id": "4",
+                "title": "Rust is a programming language",
+                "year": 2022,
+            }),
+        ],
+    )
+    .await?;
+
+    let result = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "mode": "similar",
+                "id": "1",
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert!(result.count > 0);
+    assert!(result.hits.iter().all(|hit| hit.id != "1"));
+
+    let result = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "mode": "similar",
+                "id": "1",
+                "where": {
+                    "year": {
+                        "gte": 2022,
+                    },
+                },
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert!(result.count > 0);
+    assert!(result.hits.iter().all(|hit| hit.id == "3" || hit.id == "4"));
+
+    // After the commit, the embeddings are read from the committed index
+    write_side.commit().await?;
+    read_side.commit().await?;
+
+    let result = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "mode": "similar",
+                "id": "1",
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert!(result.count > 0);
+    assert!(result.hits.iter().all(|hit| hit.id != "1"));
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "mode": "similar",
+                "id": "unknown",
+            })
+            .try_into()?,
+        )
+        .await;
+    assert!(output.is_err());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_and_export_documents() -> Result<()> {
+    let (write_side, read_side) = create(create_oramacore_config()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+
+    let document_count = 25;
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        (0..document_count).map(|i| {
+            json!({
+                "id": i.to_string(),
+                "text": format!("text {}", i),
+            })
+        }),
+    )
+    .await?;
+
+    let docs = read_side
+        .get_documents(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            vec!["3".to_string(), "unknown".to_string(), "1".to_string()],
+        )
+        .await?;
+    assert_eq!(docs.len(), 3);
+    assert_eq!(
+        docs[0].as_ref().and_then(|d| d.id.clone()),
+        Some("3".to_string())
+    );
+    assert!(docs[1].is_none());
+    assert_eq!(
+        docs[2].as_ref().and_then(|d| d.id.clone()),
+        Some("1".to_string())
+    );
+
+    write_side
+        .delete_documents(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            vec!["3".to_string()],
+        )
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+
+    let docs = read_side
+        .get_documents(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            vec!["3".to_string()],
+        )
+        .await?;
+    assert!(docs[0].is_none());
+
+    // Commit in the middle to read both from committed and uncommitted storage
+    write_side.commit().await?;
+    read_side.commit().await?;
+
+    let mut exported_ids = HashSet::new();
+    let mut cursor = None;
+    loop {
+        let (documents, next_cursor) = read_side
+            .clone()
+            .export_documents(
+                ApiKey(Secret::new("my-read-api-key".to_string())),
+                collection_id.clone(),
+                cursor,
+                10,
+            )
+            .await?;
+        let documents: Vec<_> = documents.try_collect().await?;
+        assert!(documents.len() <= 10);
+        for doc in documents {
+            assert!(exported_ids.insert(doc.id.expect("Document should have an id")));
+        }
+
+        cursor = next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(exported_ids.len(), document_count - 1);
+    assert!(!exported_ids.contains("3"));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_collection_schema() -> Result<()> {
+    let (write_side, read_side) = create(create_oramacore_config()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    write_side
+        .create_collection(
+            ApiKey(Secret::new("my-master-api-key".to_string())),
+            json!({
+                "id": collection_id.0.clone(),
+                "read_api_key": "my-read-api-key",
+                "write_api_key": "my-write-api-key",
+                "schema": {
+                    "title": { "type": "text" },
+                    "price": { "type": "number" },
+                    "sku": { "type": "keyword" },
+                    "published_at": { "type": "datetime" },
+                    "notes": { "type": "text", "indexed": false },
+                },
+                "unknown_fields": "reject",
+            })
+            .try_into()?,
+        )
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        vec![
+            json!({
+                "id": "1",
+                "title": "The red shoes",
+                "price": 10,
+                "sku": "XJ-45201-B",
+                "published_at": "2024-10-18T10:00:00Z",
+                "notes": "discontinued",
+            }),
+            json!({
+                "id": "2",
+                "title": "The blue shoes",
+                "price": 20,
+                "sku": "XJ-45202-B",
+                "published_at": "2023-10-18T10:00:00Z",
+            }),
+            json!({
+                "id": "3",
+                "title": "The green boots",
+                "sku": ["XJ-45201", "SKU-10"],
+            }),
+        ],
+    )
+    .await?;
+
+    let insert = |doc: serde_json::Value| {
+        let write_side = write_side.clone();
+        let collection_id = collection_id.clone();
+        async move {
+            let document_list: DocumentList = vec![doc].try_into().unwrap();
+            write_side
+                .write(
+                    ApiKey(Secret::new("my-write-api-key".to_string())),
+                    collection_id,
+                    document_list,
+                )
+                .await
+        }
+    };
+
+    // The first document doesn't decide the type anymore
+    let err = insert(json!({ "id": "3", "price": "10" }))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<SchemaError>(),
+        Some(&SchemaError::TypeMismatch {
+            field: "price".to_string(),
+            expected: SchemaFieldType::Number,
+            found: "string",
+        })
+    );
+    let err = insert(json!({ "id": "3", "color": "red" }))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<SchemaError>(),
+        Some(&SchemaError::UnknownField {
+            field: "color".to_string(),
+        })
+    );
+    let err = insert(json!({ "id": "3", "published_at": "yesterday" }))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SchemaError>(),
+        Some(SchemaError::InvalidDatetime { .. })
+    ));
+
+    let read_side = &read_side;
+    let collection_id = &collection_id;
+    let search = |params: serde_json::Value| async move {
+        read_side
+            .search(
+                ApiKey(Secret::new("my-read-api-key".to_string())),
+                collection_id.clone(),
+                params.try_into().unwrap(),
+            )
+            .await
+            .unwrap()
+    };
+
+    let check = || async {
+        // The keyword is matched as a whole, ignoring the case
+        let output = search(json!({
+            "term": "xj-45201-b",
+            "properties": ["sku"],
+        }))
+        .await;
+        assert_eq!(output.count, 1);
+        assert_eq!(output.hits[0].id, "1");
+
+        // A keyword doesn't match the keywords it is a prefix of
+        let output = search(json!({
+            "term": "XJ-45201",
+            "properties": ["sku"],
+        }))
+        .await;
+        assert_eq!(output.count, 1);
+        assert_eq!(output.hits[0].id, "3");
+        let output = search(json!({
+            "term": "sku-1",
+            "properties": ["sku"],
+        }))
+        .await;
+        assert_eq!(output.count, 0);
+
+        // Every value of an array is indexed
+        let output = search(json!({
+            "term": "sku-10",
+            "properties": ["sku"],
+        }))
+        .await;
+        assert_eq!(output.count, 1);
+        assert_eq!(output.hits[0].id, "3");
+
+        // The not indexed fields are only stored
+        let output = search(json!({ "term": "discontinued" })).await;
+        assert_eq!(output.count, 0);
+
+        let output = search(json!({
+            "term": "shoes",
+            "where": {
+                "published_at": {
+                    // 2024-01-01T00:00:00Z
+                    "gte": 1_704_067_200_000_i64,
+                },
+            },
+        }))
+        .await;
+        assert_eq!(output.count, 1);
+        assert_eq!(output.hits[0].id, "1");
+    };
+
+    check().await;
+
+    write_side.commit().await?;
+    read_side.commit().await?;
+
+    check().await;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_delete_documents_by_filter() -> Result<()> {
+    let (write_side, read_side) = create(create_oramacore_config()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        (0..10).map(|i| {
+            json!({
+                "id": i.to_string(),
+                "text": "text",
+                "price": i,
+                "expired": i % 2 == 0,
+            })
+        }),
+    )
+    .await?;
+
+    let delete = |where_filter: serde_json::Value| {
+        let write_side = write_side.clone();
+        let collection_id = collection_id.clone();
+        async move {
+            write_side
+                .delete_documents_by_filter(
+                    ApiKey(Secret::new("my-write-api-key".to_string())),
+                    collection_id,
+                    serde_json::from_value(where_filter).unwrap(),
+                )
+                .await
+        }
+    };
+
+    let read_side = &read_side;
+    let collection_id = &collection_id;
+    let search = || async move {
+        read_side
+            .search(
+                ApiKey(Secret::new("my-read-api-key".to_string())),
+                collection_id.clone(),
+                json!({
+                    "term": "text",
+                    "limit": 20,
+                })
+                .try_into()
+                .unwrap(),
+            )
+            .await
+            .unwrap()
+    };
+
+    assert!(delete(json!({})).await.is_err());
+    assert!(delete(json!({ "unknown": true })).await.is_err());
+    // Only number and boolean fields can be filtered
+    let error = delete(json!({ "text": true })).await.unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<UnsupportedFilterError>(),
+        Some(&UnsupportedFilterError {
+            field: "text".to_string(),
+            filter: "boolean",
+        })
+    );
+
+    let deleted = delete(json!({ "expired": true })).await?;
+    assert_eq!(deleted, 5);
+    sleep(Duration::from_millis(100)).await;
+
+    let output = search().await;
+    assert_eq!(output.count, 5);
+
+    write_side.commit().await?;
+    read_side.commit().await?;
+
+    let deleted = delete(json!({
+        "price": { "between": [3, 6] },
+        "expired": false,
+    }))
+    .await?;
+    assert_eq!(deleted, 2);
+    sleep(Duration::from_millis(100)).await;
+
+    let output = search().await;
+    let mut ids: Vec<_> = output.hits.into_iter().map(|hit| hit.id).collect();
+    ids.sort();
+    assert_eq!(ids, vec!["1", "7", "9"]);
+
+    // Nothing left to delete
+    let deleted = delete(json!({ "expired": true })).await?;
+    assert_eq!(deleted, 0);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_delete_collection_and_aliases() -> Result<()> {
+    let config = create_oramacore_config();
+    let (write_side, read_side) = create(config.clone()).await?;
+
+    let master_api_key = ApiKey(Secret::new("my-master-api-key".to_string()));
+    let v1 = CollectionId("products-v1".to_string());
+    let v2 = CollectionId("products-v2".to_string());
+    let alias = CollectionId("products".to_string());
+
+    for (collection_id, text) in [(&v1, "first"), (&v2, "second")] {
+        create_collection(write_side.clone(), collection_id.clone()).await?;
+        insert_docs(
+            write_side.clone(),
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            vec![json!({
+                "id": "1",
+                "text": text,
+            })],
+        )
+        .await?;
+    }
+
+    write_side
+        .create_alias(master_api_key.clone(), alias.clone(), v1.clone())
+        .await?;
+    // The alias name is taken
+    assert!(write_side
+        .create_alias(master_api_key.clone(), alias.clone(), v2.clone())
+        .await
+        .is_err());
+    // A collection pointed by an alias cannot be deleted
+    assert!(write_side
+        .delete_collection(master_api_key.clone(), v1.clone())
+        .await
+        .is_err());
+    sleep(Duration::from_millis(100)).await;
+
+    let search = |read_side: Arc<ReadSide>, collection_id: CollectionId, term: &'static str| async move {
+        read_side
+            .search(
+                ApiKey(Secret::new("my-read-api-key".to_string())),
+                collection_id,
+                json!({
+                    "term": term,
+                })
+                .try_into()
+                .unwrap(),
+            )
+            .await
+    };
+
+    assert_eq!(
+        search(read_side.clone(), alias.clone(), "first")
+            .await?
+            .count,
+        1
+    );
+    assert_eq!(
+        search(read_side.clone(), alias.clone(), "second")
+            .await?
+            .count,
+        0
+    );
+
+    let previous = write_side
+        .swap_alias(master_api_key.clone(), alias.clone(), v2.clone())
+        .await?;
+    assert_eq!(previous, v1);
+    sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(
+        search(read_side.clone(), alias.clone(), "first")
+            .await?
+            .count,
+        0
+    );
+    assert_eq!(
+        search(read_side.clone(), alias.clone(), "second")
+            .await?
+            .count,
+        1
+    );
+
+    // The writes through the alias go to the pointed collection
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        alias.clone(),
+        vec![json!({
+            "id": "2",
+            "text": "second",
+        })],
+    )
+    .await?;
+    assert_eq!(
+        search(read_side.clone(), v2.clone(), "second").await?.count,
+        2
+    );
+
+    write_side.commit().await?;
+    read_side.commit().await?;
+
+    write_side
+        .delete_collection(master_api_key.clone(), v1.clone())
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+
+    assert!(search(read_side.clone(), v1.clone(), "first")
+        .await
+        .is_err());
+    assert!(!config
+        .writer_side
+        .as_ref()
+        .unwrap()
+        .config
+        .data_dir
+        .join(&v1.0)
+        .exists());
+    assert!(!config
+        .reader_side
+        .as_ref()
+        .unwrap()
+        .config
+        .data_dir
+        .join("collections")
+        .join(&v1.0)
+        .exists());
+
+    write_side.commit().await?;
+    read_side.commit().await?;
+
+    // The aliases are kept after a restart
+    let (write_side, read_side) = create(config.clone()).await?;
+    assert_eq!(
+        search(read_side.clone(), alias.clone(), "second")
+            .await?
+            .count,
+        2
+    );
+    assert!(search(read_side.clone(), v1.clone(), "first")
+        .await
+        .is_err());
+
+    write_side
+        .delete_alias(master_api_key.clone(), alias.clone())
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+    assert!(search(read_side.clone(), alias.clone(), "second")
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_reindex_collection() -> Result<()> {
+    let config = create_oramacore_config();
+    let (write_side, read_side) = create(config.clone()).await?;
+
+    let master_api_key = ApiKey(Secret::new("my-master-api-key".to_string()));
+    let v1 = CollectionId("products-v1".to_string());
+    let v2 = CollectionId("products-v2".to_string());
+    let alias = CollectionId("products".to_string());
+
+    create_collection(write_side.clone(), v1.clone()).await?;
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        v1.clone(),
+        (0..10).map(|i| {
+            json!({
+                "id": i.to_string(),
+                "text": "a good product",
+                "sku": format!("SKU-{}", i),
+            })
+        }),
+    )
+    .await?;
+
+    let status = write_side
+        .clone()
+        .reindex(
+            master_api_key.clone(),
+            v1.clone(),
+            json!({
+                "id": v2.0.clone(),
+                "read_api_key": "my-read-api-key",
+                "write_api_key": "my-write-api-key",
+                "schema": {
+                    "sku": { "type": "keyword" },
+                },
+            })
+            .try_into()?,
+            Some(alias.clone()),
+        )
+        .await?;
+    assert_eq!(status.total, 10);
+
+    let status = loop {
+        let status = write_side
+            .get_reindex_status(master_api_key.clone(), status.id.clone())
+            .await?
+            .expect("The reindex status should be kept");
+        if status.state != ReindexState::Running {
+            break status;
+        }
+        sleep(Duration::from_millis(100)).await;
+    };
+    assert_eq!(status.state, ReindexState::Completed);
+    assert_eq!(status.processed, 10);
+    assert_eq!(status.inserted, 10);
+    assert_eq!(status.failed, 0);
+    sleep(Duration::from_millis(500)).await;
+
+    let aliases = write_side.list_aliases(master_api_key.clone()).await?;
+    assert_eq!(aliases.get(&alias), Some(&v2));
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            alias.clone(),
+            json!({
+                "term": "good",
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 10);
+
+    // The keyword field matches only the whole value
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            v2.clone(),
+            json!({
+                "term": "sku-3",
+                "properties": ["sku"],
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 1);
+
+    // The source collection is untouched
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            v1.clone(),
+            json!({
+                "term": "good",
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 10);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_document_expiration() -> Result<()> {
+    let mut config = create_oramacore_config();
+    // A cached result doesn't show an expired document
+    config.reader_side.as_mut().unwrap().search_cache = Some(SearchCacheConfig { capacity: 10 });
+    let (write_side, read_side) = create(config.clone()).await?;
+
+    let master_api_key = ApiKey(Secret::new("my-master-api-key".to_string()));
+    let collection_id = CollectionId("promotions".to_string());
+    write_side
+        .create_collection(
+            master_api_key.clone(),
+            json!({
+                "id": collection_id.0.clone(),
+                "read_api_key": "my-read-api-key",
+                "write_api_key": "my-write-api-key",
+                "ttl": "1h",
+            })
+            .try_into()?,
+        )
+        .await?;
+    sleep(Duration::from_millis(100)).await;
+
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(2);
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        vec![
+            json!({
+                "id": "1",
+                "text": "flash sale",
+                "expiresAt": expires_at.to_rfc3339(),
+            }),
+            // Expires after the collection TTL
+            json!({
+                "id": "2",
+                "text": "weekly sale",
+            }),
+            json!({
+                "id": "3",
+                "text": "yearly sale",
+                "expiresAt": expires_at.timestamp_millis() + 1_000_000,
+            }),
+        ],
+    )
+    .await?;
+
+    let search = || async {
+        read_side
+            .search(
+                ApiKey(Secret::new("my-read-api-key".to_string())),
+                collection_id.clone(),
+                json!({
+                    "term": "sale",
+                })
+                .try_into()
+                .unwrap(),
+            )
+            .await
+    };
+
+    assert_eq!(search().await?.count, 3);
+
+    sleep(Duration::from_secs(2)).await;
+
+    // The expired document is hidden, and deleted by the write side in background
+    let output = search().await?;
+    assert_eq!(output.count, 2);
+    assert!(output.hits.iter().all(|hit| hit.id != "1"));
+
+    write_side.delete_expired_documents().await?;
+    sleep(Duration::from_millis(100)).await;
+
+    let collection = write_side
+        .get_collection_dto(master_api_key, collection_id.clone())
+        .await?
+        .unwrap();
+    assert_eq!(collection.document_count, 2);
+    assert_eq!(search().await?.count, 2);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_read_your_writes() -> Result<()> {
+    let config = create_oramacore_config();
+    let (write_side, read_side) = create(config.clone()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+
+    let documents: Vec<_> = (0..100)
+        .map(|i| {
+            json!({
+                "id": i.to_string(),
+                "text": format!("text {}", i),
+            })
+        })
+        .collect();
+    let documents: DocumentList = documents.try_into()?;
+    write_side
+        .write(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            documents,
+        )
+        .await?;
+    let offset = write_side.last_offset().await?;
+
+    // No sleep: the search waits for the write
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "term": "text",
+                "min_offset": offset.0,
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 100);
+
+    // The embeddings are included in the offset
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "mode": "vector",
+                "term": "text 42",
+                "min_offset": offset.0,
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_ne!(output.count, 0);
+
+    // An offset never reached
+    let result = read_side
+        .wait_for_offset(Offset(offset.0 + 1_000), Duration::from_millis(100))
+        .await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_write_tasks() -> Result<()> {
+    let config = create_oramacore_config();
+    let (write_side, read_side) = create(config.clone()).await?;
+
+    let master_api_key = ApiKey(Secret::new("my-master-api-key".to_string()));
+    let write_api_key = ApiKey(Secret::new("my-write-api-key".to_string()));
+    let collection_id = CollectionId("products".to_string());
+    write_side
+        .create_collection(
+            master_api_key.clone(),
+            json!({
+                "id": collection_id.0.clone(),
+                "read_api_key": "my-read-api-key",
+                "write_api_key": "my-write-api-key",
+                "schema": {
+                    "price": { "type": "number" },
+                },
+            })
+            .try_into()?,
+        )
+        .await?;
+
+    // The documents are checked against the schema before they are enqueued
+    let documents: DocumentList = json!([
+        { "id": "1", "text": "a shirt", "price": 10 },
+        { "id": "2", "text": "a shirt", "price": "ten" },
+    ])
+    .try_into()?;
+    let err = write_side
+        .enqueue_write(
+            write_api_key.clone(),
+            collection_id.clone(),
+            documents,
+            None,
+        )
+        .await
+        .unwrap_err();
+    assert!(err.downcast_ref::<SchemaError>().is_some());
+
+    let documents: DocumentList = json!([
+        { "id": "1", "text": "a shirt", "price": 10 },
+        { "id": "2", "text": "a shirt", "price": 20 },
+    ])
+    .try_into()?;
+    let task = write_side
+        .enqueue_write(
+            write_api_key.clone(),
+            collection_id.clone(),
+            documents,
+            None,
+        )
+        .await?;
+    assert_eq!(task.document_count, 2);
+
+    let task = write_side
+        .wait_task(write_api_key.clone(), task.id)
+        .await?
+        .unwrap();
+    assert_eq!(task.status, TaskStatus::Succeeded);
+    assert_eq!(task.succeeded, 2);
+    assert_eq!(task.failed, 0);
+    assert!(task.errors.is_empty());
+    assert!(task.started_at.is_some());
+    assert!(task.finished_at.is_some());
+    let offsets = task.offsets.expect("The task sent operations");
+
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({
+                "term": "shirt",
+                "min_offset": offsets.to,
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 2);
+
+    let task = write_side
+        .enqueue_delete(
+            write_api_key.clone(),
+            collection_id.clone(),
+            vec!["1".to_string()],
+            None,
+        )
+        .await?;
+    let task = write_side
+        .wait_task(write_api_key.clone(), task.id)
+        .await?
+        .unwrap();
+    assert_eq!(task.status, TaskStatus::Succeeded);
+    assert_eq!(task.succeeded, 1);
+
+    // A wrong api key cannot enqueue
+    assert!(write_side
+        .enqueue_delete(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            vec!["1".to_string()],
+            None,
+        )
+        .await
+        .is_err());
+
+    let tasks = write_side
+        .list_tasks(master_api_key.clone(), None, None)
+        .await?;
+    assert_eq!(tasks.len(), 2);
+    assert!(tasks[0].id > tasks[1].id);
+    // The write api key lists only the tasks of its collection
+    assert!(write_side
+        .list_tasks(write_api_key.clone(), None, None)
+        .await
+        .is_err());
+    let tasks = write_side
+        .list_tasks(
+            write_api_key.clone(),
+            Some(collection_id.clone()),
+            Some(TaskStatus::Succeeded),
+        )
+        .await?;
+    assert_eq!(tasks.len(), 2);
+
+    write_side.commit().await?;
+    read_side.commit().await?;
+
+    // The tasks are kept after a restart
+    let (write_side, _) = create(config.clone()).await?;
+    let tasks = write_side
+        .list_tasks(master_api_key.clone(), None, None)
+        .await?;
+    assert_eq!(tasks.len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_document_versions() -> Result<()> {
+    let config = create_oramacore_config();
+    let (write_side, read_side) = create(config.clone()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+
+    let write_api_key = ApiKey(Secret::new("my-write-api-key".to_string()));
+    let read_api_key = ApiKey(Secret::new("my-read-api-key".to_string()));
+    let document =
+        || -> Result<DocumentList> { Ok(json!([{ "id": "1", "text": "a shirt" }]).try_into()?) };
+    fn conflict<T>(output: Result<T>) -> Option<VersionConflict> {
+        output.err()?.downcast::<VersionConflict>().ok()
+    }
+
+    // `0` is the version of a missing document
+    let task = write_side
+        .enqueue_write(
+            write_api_key.clone(),
+            collection_id.clone(),
+            document()?,
+            Some(0),
+        )
+        .await?;
+    let task = write_side
+        .wait_task(write_api_key.clone(), task.id)
+        .await?
+        .unwrap();
+    assert_eq!(task.status, TaskStatus::Succeeded);
+
+    let output = read_side
+        .search(
+            read_api_key.clone(),
+            collection_id.clone(),
+            json!({
+                "term": "shirt",
+                "min_offset": task.offsets.unwrap().to,
+            })
+            .try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 1);
+    let version = output.hits[0].version;
+    assert_ne!(version, 0);
+
+    let docs = read_side
+        .get_versioned_documents(
+            read_api_key.clone(),
+            collection_id.clone(),
+            vec!["1".to_string()],
+        )
+        .await?;
+    assert_eq!(docs[0].as_ref().unwrap().0, version);
+
+    // The document exists now
+    let output = write_side
+        .enqueue_write(
+            write_api_key.clone(),
+            collection_id.clone(),
+            document()?,
+            Some(0),
+        )
+        .await;
+    assert_eq!(
+        conflict(output),
+        Some(VersionConflict {
+            id: "1".to_string(),
+            expected: 0,
+            current: version,
+        })
+    );
+
+    let new_version = write_side
+        .patch_document(
+            write_api_key.clone(),
+            collection_id.clone(),
+            "1".to_string(),
+            json!({ "text": "a red shirt" })
+                .as_object()
+                .cloned()
+                .unwrap(),
+            Some(version),
+        )
+        .await?;
+    assert_ne!(new_version, version);
+
+    // The patch above changed the version
+    let output = write_side
+        .patch_document(
+            write_api_key.clone(),
+            collection_id.clone(),
+            "1".to_string(),
+            json!({ "text": "a blue shirt" })
+                .as_object()
+                .cloned()
+                .unwrap(),
+            Some(version),
+        )
+        .await;
+    assert!(conflict(output).is_some());
+
+    // `if_version` applies to a single document
+    let output = write_side
+        .enqueue_write(
+            write_api_key.clone(),
+            collection_id.clone(),
+            json!([{ "id": "1" }, { "id": "2" }]).try_into()?,
+            Some(new_version),
+        )
+        .await;
+    assert!(output.is_err());
+    assert!(conflict(output).is_none());
+
+    let output = write_side
+        .enqueue_delete(
+            write_api_key.clone(),
+            collection_id.clone(),
+            vec!["1".to_string()],
+            Some(version),
+        )
+        .await;
+    assert!(conflict(output).is_some());
+
+    let task = write_side
+        .enqueue_delete(
+            write_api_key.clone(),
+            collection_id.clone(),
+            vec!["1".to_string()],
+            Some(new_version),
+        )
+        .await?;
+    let task = write_side
+        .wait_task(write_api_key.clone(), task.id)
+        .await?
+        .unwrap();
+    assert_eq!(task.status, TaskStatus::Succeeded);
+    assert_eq!(task.succeeded, 1);
+
+    read_side
+        .wait_for_offset(Offset(task.offsets.unwrap().to), Duration::from_secs(5))
+        .await?;
+    let docs = read_side
+        .get_versioned_documents(
+            read_api_key.clone(),
+            collection_id.clone(),
+            vec!["1".to_string()],
+        )
+        .await?;
+    assert!(docs[0].is_none());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_file_side_channel() -> Result<()> {
+    let mut config = create_oramacore_config();
+    let side_channel = SideChannelType::File(FileSideChannelConfig {
+        data_dir: generate_new_path(),
+        // Many small segments
+        segment_size: 1_024,
+        fsync: FsyncPolicy::Always,
+        retention: false,
+        retention_grace_period: Duration::ZERO,
+    });
+    config.writer_side.as_mut().unwrap().output = side_channel.clone();
+    config.reader_side.as_mut().unwrap().input = side_channel;
+
+    let (write_side, read_side) = create(config.clone()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        (0..20).map(|i| json!({ "id": i.to_string(), "text": format!("text {}", i) })),
+    )
+    .await?;
+
+    let last_offset = write_side.last_offset().await?;
+    read_side
+        .wait_for_offset(last_offset, Duration::from_secs(5))
+        .await?;
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({ "term": "text" }).try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 20);
+
+    // The read side is not committed: the operations are read again from the log
+    let (_, read_side) = create(config.clone()).await?;
+    read_side
+        .wait_for_offset(last_offset, Duration::from_secs(5))
+        .await?;
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({ "term": "text" }).try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 20);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_read_side_recovery() -> Result<()> {
+    let mut config = create_oramacore_config();
+    let side_channel = SideChannelType::File(FileSideChannelConfig {
+        data_dir: generate_new_path(),
+        segment_size: 1_024,
+        fsync: FsyncPolicy::Always,
+        retention: false,
+        retention_grace_period: Duration::ZERO,
+    });
+    config.writer_side.as_mut().unwrap().output = side_channel.clone();
+    config.reader_side.as_mut().unwrap().input = side_channel;
+
+    let (write_side, read_side) = create(config.clone()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        (0..10).map(|i| json!({ "id": i.to_string(), "text": format!("text {}", i) })),
+    )
+    .await?;
+    read_side
+        .wait_for_offset(write_side.last_offset().await?, Duration::from_secs(5))
+        .await?;
+    read_side.commit().await?;
+
+    // Not committed by the read side
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        (10..20).map(|i| json!({ "id": i.to_string(), "text": format!("text {}", i) })),
+    )
+    .await?;
+    let last_offset = write_side.last_offset().await?;
+    read_side
+        .wait_for_offset(last_offset, Duration::from_secs(5))
+        .await?;
+    assert!(!read_side.is_catching_up());
+
+    // The committed operations are skipped: the documents are not counted twice
+    let (_, read_side) = create(config.clone()).await?;
+    read_side
+        .wait_for_offset(last_offset, Duration::from_secs(5))
+        .await?;
+    assert!(!read_side.is_catching_up());
+    assert_eq!(
+        read_side
+            .count_document_in_collection(collection_id.clone())
+            .await,
+        Some(20)
+    );
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({ "term": "text" }).try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 20);
+
+    // The search doesn't wait for the catch up
+    read_side.catch_up(Offset(last_offset.0 + 1));
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({ "term": "text" }).try_into()?,
+        )
+        .await;
+    assert!(output.unwrap_err().is::<CatchingUpError>());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_write_side_recovery() -> Result<()> {
+    let mut config = create_oramacore_config();
+    let side_channel = SideChannelType::File(FileSideChannelConfig {
+        data_dir: generate_new_path(),
+        segment_size: 1_024,
+        fsync: FsyncPolicy::Always,
+        retention: false,
+        retention_grace_period: Duration::ZERO,
+    });
+    config.writer_side.as_mut().unwrap().output = side_channel.clone();
+    config.reader_side.as_mut().unwrap().input = side_channel;
+
+    let (write_side, _) = create(config.clone()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        (0..10).map(|i| json!({ "id": i.to_string(), "text": format!("text {}", i) })),
+    )
+    .await?;
+    write_side.commit().await?;
+
+    // Not committed by the write side
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        (10..20).map(|i| json!({ "id": i.to_string(), "text": format!("text {}", i) })),
+    )
+    .await?;
+
+    // A crash: the operations after the commit are replayed into the write side,
+    // so the new document doesn't reuse an id and the replaced one isn't duplicated
+    let (write_side, read_side) = create(config.clone()).await?;
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        vec![
+            json!({ "id": "new", "text": "new document" }),
+            json!({ "id": "15", "text": "replaced document" }),
+        ],
+    )
+    .await?;
+    read_side
+        .wait_for_offset(write_side.last_offset().await?, Duration::from_secs(5))
+        .await?;
+
+    let collection = write_side
+        .get_collection_dto(
+            ApiKey(Secret::new("my-master-api-key".to_string())),
+            collection_id.clone(),
+        )
+        .await?
+        .unwrap();
+    assert_eq!(collection.document_count, 21);
+    assert_eq!(
+        read_side
+            .count_document_in_collection(collection_id.clone())
+            .await,
+        Some(21)
+    );
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({ "term": "text", "limit": 30 }).try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 19);
+    assert!(output
+        .hits
+        .iter()
+        .all(|hit| hit.id != "new" && hit.id != "15"));
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({ "term": "replaced" }).try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 1);
+    assert_eq!(output.hits[0].id, "15");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_writer_and_reader_nodes() -> Result<()> {
+    let mut config = create_oramacore_config();
+    let address = create_grpc_server().await?;
+    config.ai_server.host = address.ip();
+    config.ai_server.port = address.port();
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")?
+        .local_addr()?
+        .port();
+
+    let mut writer_config = config.clone();
+    writer_config.mode = NodeMode::Writer;
+    writer_config.reader_side = None;
+    writer_config.writer_side.as_mut().unwrap().output =
+        SideChannelType::File(FileSideChannelConfig {
+            data_dir: generate_new_path(),
+            segment_size: 1_024,
+            fsync: FsyncPolicy::Always,
+            retention: false,
+            retention_grace_period: Duration::ZERO,
+        });
+    writer_config.writer_side.as_mut().unwrap().stream_server = Some(OperationStreamServerConfig {
+        host: "127.0.0.1".parse().unwrap(),
+        port,
+        api_key: ApiKey(Secret::new("my-stream-api-key".to_string())),
+    });
+    let (write_side, read_side, receiver) = build_orama(writer_config).await?;
+    assert!(read_side.is_none());
+    assert!(receiver.is_none());
+    let write_side = write_side.unwrap();
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        (0..10).map(|i| json!({ "id": i.to_string(), "text": format!("text {}", i) })),
+    )
+    .await?;
+
+    let remote = |port: u16, api_key: &str| {
+        let mut reader_config = config.clone();
+        reader_config.mode = NodeMode::Reader;
+        reader_config.writer_side = None;
+        reader_config.reader_side.as_mut().unwrap().config.data_dir = generate_new_path();
+        reader_config.reader_side.as_mut().unwrap().input =
+            SideChannelType::Remote(RemoteSideChannelConfig {
+                address: format!("127.0.0.1:{}", port),
+                api_key: ApiKey(Secret::new(api_key.to_string())),
+                reconnect_interval: Duration::from_millis(100),
+            });
+        reader_config
+    };
+
+    let output = build_orama(remote(port, "wrong-key")).await;
+    assert!(output.is_err());
+
+    // Without a server, the reader waits for it instead of failing
+    let unused_port = std::net::TcpListener::bind("127.0.0.1:0")?
+        .local_addr()?
+        .port();
+    let output = tokio::time::timeout(
+        Duration::from_millis(500),
+        build_orama(remote(unused_port, "my-stream-api-key")),
+    )
+    .await;
+    assert!(output.is_err());
+
+    // Two replicas, started after the writes
+    for i in 0..2 {
+        let (write_side_of_reader, read_side, receiver) =
+            build_orama(remote(port, "my-stream-api-key")).await?;
+        assert!(write_side_of_reader.is_none());
+        let read_side = read_side.unwrap();
+        connect_write_and_read_side(receiver.unwrap(), read_side.clone());
+
+        insert_docs(
+            write_side.clone(),
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            [json!({ "id": format!("new-{}", i), "text": "text new" })],
+        )
+        .await?;
+
+        read_side
+            .wait_for_offset(write_side.last_offset().await?, Duration::from_secs(5))
+            .await?;
+        assert!(!read_side.is_catching_up());
+        let output = read_side
+            .search(
+                ApiKey(Secret::new("my-read-api-key".to_string())),
+                collection_id.clone(),
+                json!({ "term": "text" }).try_into()?,
+            )
+            .await?;
+        assert_eq!(output.count, 11 + i);
+    }
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_read_side_snapshot() -> Result<()> {
+    let mut config = create_oramacore_config();
+    let side_channel = SideChannelType::File(FileSideChannelConfig {
+        data_dir: generate_new_path(),
+        segment_size: 1_024,
+        fsync: FsyncPolicy::Always,
+        retention: false,
+        retention_grace_period: Duration::ZERO,
+    });
+    config.writer_side.as_mut().unwrap().output = side_channel.clone();
+    config.reader_side.as_mut().unwrap().input = side_channel;
+    config.reader_side.as_mut().unwrap().snapshot.api_key =
+        Some(ApiKey(Secret::new("my-snapshot-api-key".to_string())));
+
+    let (write_side, read_side) = create(config.clone()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        (0..10).map(|i| json!({ "id": i.to_string(), "text": format!("text {}", i) })),
+    )
+    .await?;
+    read_side
+        .wait_for_offset(write_side.last_offset().await?, Duration::from_secs(5))
+        .await?;
+
+    assert!(read_side
+        .check_snapshot_api_key(ApiKey(Secret::new("wrong-key".to_string())))
+        .is_err());
+    read_side.check_snapshot_api_key(ApiKey(Secret::new("my-snapshot-api-key".to_string())))?;
+
+    let snapshot_path = generate_new_path().join("snapshot.bin");
+    let snapshot_offset = read_side
+        .export_snapshot(std::fs::File::create(&snapshot_path)?)
+        .await?;
+    assert!(snapshot_offset.0 > 0);
+
+    // After the snapshot
+    insert_docs(
+        write_side.clone(),
+        ApiKey(Secret::new("my-write-api-key".to_string())),
+        collection_id.clone(),
+        (10..15).map(|i| json!({ "id": i.to_string(), "text": format!("text {}", i) })),
+    )
+    .await?;
+    let last_offset = write_side.last_offset().await?;
+
+    // A new replica starts from the snapshot, then receives the next operations
+    let mut replica_config = config.clone();
+    replica_config.reader_side.as_mut().unwrap().config.data_dir = generate_new_path();
+    replica_config
+        .reader_side
+        .as_mut()
+        .unwrap()
+        .snapshot
+        .import_from = Some(snapshot_path);
+    let (_, replica) = create(replica_config.clone()).await?;
+    replica
+        .wait_for_offset(last_offset, Duration::from_secs(5))
+        .await?;
+    assert_eq!(
+        replica
+            .count_document_in_collection(collection_id.clone())
+            .await,
+        Some(15)
+    );
+    let output = replica
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({ "term": "text" }).try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 15);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_write_side_shutdown() -> Result<()> {
+    let config = create_oramacore_config();
+    let (write_side, read_side) = create(config.clone()).await?;
+
+    let collection_id = CollectionId("test-collection".to_string());
+    create_collection(write_side.clone(), collection_id.clone()).await?;
+    write_side
+        .write(
+            ApiKey(Secret::new("my-write-api-key".to_string())),
+            collection_id.clone(),
+            json!((0..10)
+                .map(|i| json!({ "id": i.to_string(), "text": format!("text {}", i) }))
+                .collect::<Vec<_>>())
+            .try_into()?,
+        )
+        .await?;
+
+    // The queued embeddings are calculated before the commit
+    let last_offset = write_side.shutdown().await?;
+    read_side
+        .wait_for_offset(last_offset, Duration::from_secs(5))
+        .await?;
+    read_side.commit().await?;
+
+    // Nothing is replayed with the in-memory side channel: everything is committed
+    let (_, read_side) = create(config.clone()).await?;
+    let output = read_side
+        .search(
+            ApiKey(Secret::new("my-read-api-key".to_string())),
+            collection_id.clone(),
+            json!({ "term": "text" }).try_into()?,
+        )
+        .await?;
+    assert_eq!(output.count, 10);
+
+    Ok(())
+}
+
 async fn create_collection(write_side: Arc<WriteSide>, collection_id: CollectionId) -> Result<()> {
     write_side
         .create_collection(
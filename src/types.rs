@@ -47,6 +47,14 @@ pub struct CollectionId(pub String);
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize, PartialOrd, Ord)]
 pub struct DocumentId(pub u64);
 
+impl DocumentId {
+    /// Every write of a document uses a new id, so the id identifies the version of the document.
+    /// The version `0` is kept for the missing documents.
+    pub fn version(&self) -> u64 {
+        self.0 + 1
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Document {
     #[serde(flatten)]
@@ -87,6 +95,34 @@ impl Document {
     pub fn get(&self, key: &str) -> Option<&Value> {
         self.inner.get(key)
     }
+
+    /// Applies a JSON merge patch (RFC 7396):
+    /// `null` removes the property and the objects are merged recursively
+    pub fn merge_patch(&mut self, patch: Map<String, Value>) {
+        merge_patch(&mut self.inner, patch);
+    }
+}
+
+fn merge_patch(target: &mut Map<String, Value>, patch: Map<String, Value>) {
+    for (key, value) in patch {
+        match value {
+            Value::Null => {
+                target.remove(&key);
+            }
+            Value::Object(patch) => {
+                if let Some(Value::Object(target)) = target.get_mut(&key) {
+                    merge_patch(target, patch);
+                    continue;
+                }
+                let mut object = Map::new();
+                merge_patch(&mut object, patch);
+                target.insert(key, Value::Object(object));
+            }
+            value => {
+                target.insert(key, value);
+            }
+        }
+    }
 }
 
 impl From<Map<String, Value>> for Document {
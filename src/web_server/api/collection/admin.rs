@@ -1,21 +1,29 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
     response::IntoResponse,
+    routing::{get, patch, post},
     Json, Router,
 };
 use axum_extra::{headers, TypedHeader};
 use axum_openapi3::*;
 use redact::Secret;
-use serde_json::json;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
 use tracing::{error, info};
+use utoipa::IntoParams;
 
 use crate::{
     collection_manager::{
-        dto::{ApiKey, CollectionDTO, CreateCollection, DeleteDocuments},
-        sides::WriteSide,
+        dto::{
+            ApiKey, CollectionAlias, CollectionDTO, CreateCollection, DeleteAlias,
+            DeleteCollection, DeleteDocuments, DeleteDocumentsByQuery, ImportFormat,
+            ReindexCollection, Task, TaskStatus,
+        },
+        sides::{SchemaError, VersionConflict, WriteSide},
     },
     types::{CollectionId, DocumentList},
 };
@@ -25,14 +33,42 @@ pub fn apis(write_side: Arc<WriteSide>) -> Router {
         .add(get_collections())
         .add(get_collection_by_id())
         .add(create_collection())
+        .add(delete_collection())
+        .add(list_aliases())
+        .add(create_alias())
+        .add(swap_alias())
+        .add(delete_alias())
         .add(add_documents())
         .add(delete_documents())
+        .add(delete_documents_by_query())
+        .add(reindex_collection())
+        .add(get_reindex_status())
+        .add(get_task())
+        .route("/v1/tasks", get(list_tasks))
+        .route(
+            "/v1/collections/{id}/documents/{doc_id}",
+            patch(patch_document),
+        )
+        .route("/v1/collections/{id}/import", post(import_documents))
+        .route(
+            "/v1/collections/{id}/imports/{import_id}",
+            get(get_import_status),
+        )
         .with_state(write_side)
 }
 
 type AuthorizationBearerHeader =
     TypedHeader<headers::Authorization<headers::authorization::Bearer>>;
 
+/// The write is applied, but its offset is unknown
+fn offset_error(e: anyhow::Error) -> (StatusCode, Json<Value>) {
+    error!("Error getting the write offset: {:?}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": format!("{:#}", e) })),
+    )
+}
+
 #[endpoint(
     method = "GET",
     path = "/v1/collections",
@@ -127,15 +163,163 @@ async fn create_collection(
     Ok((StatusCode::CREATED, Json(json!({ "collection_id": () }))))
 }
 
+#[endpoint(
+    method = "POST",
+    path = "/v1/collections/delete",
+    description = "Delete a collection with its documents"
+)]
+async fn delete_collection(
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+    Json(json): Json<DeleteCollection>,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let master_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    match write_side.delete_collection(master_api_key, json.id).await {
+        Ok(()) => Ok((
+            StatusCode::OK,
+            Json(json!({ "message": "collection deleted" })),
+        )),
+        Err(e) => {
+            error!("Error deleting collection: {:?}", e);
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
+
+#[endpoint(method = "GET", path = "/v1/aliases", description = "List the aliases")]
+async fn list_aliases(
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let master_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    match write_side.list_aliases(master_api_key).await {
+        Ok(aliases) => {
+            let aliases: Vec<_> = aliases
+                .into_iter()
+                .map(|(alias, collection_id)| {
+                    json!({ "alias": alias.0, "collection_id": collection_id.0 })
+                })
+                .collect();
+            Ok((StatusCode::OK, Json(json!(aliases))))
+        }
+        Err(e) => {
+            error!("Error listing aliases: {:?}", e);
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
+
+#[endpoint(
+    method = "POST",
+    path = "/v1/aliases/create",
+    description = "Create an alias of a collection"
+)]
+async fn create_alias(
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+    Json(json): Json<CollectionAlias>,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let master_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    match write_side
+        .create_alias(master_api_key, json.alias, json.collection_id)
+        .await
+    {
+        Ok(()) => Ok((
+            StatusCode::CREATED,
+            Json(json!({ "message": "alias created" })),
+        )),
+        Err(e) => {
+            error!("Error creating alias: {:?}", e);
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
+
+#[endpoint(
+    method = "POST",
+    path = "/v1/aliases/swap",
+    description = "Point an alias to another collection atomically"
+)]
+async fn swap_alias(
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+    Json(json): Json<CollectionAlias>,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let master_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    match write_side
+        .swap_alias(master_api_key, json.alias, json.collection_id)
+        .await
+    {
+        Ok(previous) => Ok((
+            StatusCode::OK,
+            Json(json!({ "previous_collection_id": previous.0 })),
+        )),
+        Err(e) => {
+            error!("Error swapping alias: {:?}", e);
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
+
+#[endpoint(
+    method = "POST",
+    path = "/v1/aliases/delete",
+    description = "Delete an alias"
+)]
+async fn delete_alias(
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+    Json(json): Json<DeleteAlias>,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let master_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    match write_side.delete_alias(master_api_key, json.alias).await {
+        Ok(()) => Ok((StatusCode::OK, Json(json!({ "message": "alias deleted" })))),
+        Err(e) => {
+            error!("Error deleting alias: {:?}", e);
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+struct WriteQueryParams {
+    /// Waits until the task is finished
+    #[serde(default)]
+    wait: bool,
+    /// Writes a single document only if its version is this. `0` means the document doesn't exist
+    if_version: Option<u64>,
+}
+
 #[endpoint(
     method = "POST",
     path = "/v1/collections/{id}/insert",
-    description = "Add documents to a collection"
+    description = "Enqueue the insertion of documents into a collection"
 )]
 async fn add_documents(
     Path(id): Path<String>,
     write_side: State<Arc<WriteSide>>,
     TypedHeader(auth): AuthorizationBearerHeader,
+    Query(query): Query<WriteQueryParams>,
     Json(json): Json<DocumentList>,
 ) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
     let collection_id = CollectionId(id);
@@ -143,37 +327,46 @@ async fn add_documents(
     let write_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
 
     info!("Adding documents to collection {:?}", collection_id);
-    match write_side.write(write_api_key, collection_id, json).await {
-        Ok(_) => {
-            info!("Documents added to collection");
-        }
+    let task = match write_side
+        .enqueue_write(write_api_key.clone(), collection_id, json, query.if_version)
+        .await
+    {
+        Ok(task) => task,
         Err(e) => {
             error!("Error adding documents to collection: {}", e);
             e.chain()
                 .skip(1)
                 .for_each(|cause| error!("because: {}", cause));
+            if let Some(conflict) = e.downcast_ref::<VersionConflict>() {
+                return Err(conflict_error(conflict));
+            }
+            if let Some(schema_error) = e.downcast_ref::<SchemaError>() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": schema_error.to_string() })),
+                ));
+            }
             return Err((
                 StatusCode::NOT_FOUND,
                 Json(json!({ "error": format!("collection not found {}", e) })),
             ));
         }
     };
+    info!(task_id = task.id, "Documents insertion enqueued");
 
-    Ok((
-        StatusCode::OK,
-        Json(json!({ "message": "documents added" })),
-    ))
+    task_response(&write_side, write_api_key, task, query.wait).await
 }
 
 #[endpoint(
     method = "POST",
     path = "/v1/collections/{id}/delete",
-    description = "Delete documents from a collection"
+    description = "Enqueue the deletion of documents from a collection"
 )]
 async fn delete_documents(
     Path(id): Path<String>,
     write_side: State<Arc<WriteSide>>,
     TypedHeader(auth): AuthorizationBearerHeader,
+    Query(query): Query<WriteQueryParams>,
     Json(json): Json<DeleteDocuments>,
 ) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
     let collection_id = CollectionId(id);
@@ -181,27 +374,377 @@ async fn delete_documents(
     let write_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
 
     info!("Delete documents to collection {:?}", collection_id);
-    match write_side
-        .delete_documents(write_api_key, collection_id, json)
+    let task = match write_side
+        .enqueue_delete(write_api_key.clone(), collection_id, json, query.if_version)
         .await
     {
-        Ok(_) => {
-            info!("Documents deleted to collection");
-        }
+        Ok(task) => task,
         Err(e) => {
             error!("Error deleting documents to collection: {}", e);
             e.chain()
                 .skip(1)
                 .for_each(|cause| error!("because: {}", cause));
+            if let Some(conflict) = e.downcast_ref::<VersionConflict>() {
+                return Err(conflict_error(conflict));
+            }
             return Err((
                 StatusCode::NOT_FOUND,
                 Json(json!({ "error": format!("collection not found {}", e) })),
             ));
         }
     };
+    info!(task_id = task.id, "Documents deletion enqueued");
+
+    task_response(&write_side, write_api_key, task, query.wait).await
+}
+
+fn conflict_error(conflict: &VersionConflict) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::CONFLICT,
+        Json(json!({
+            "error": conflict.to_string(),
+            "current_version": conflict.current,
+        })),
+    )
+}
+
+/// The enqueued task, or the finished one if `wait` is set
+async fn task_response(
+    write_side: &WriteSide,
+    api_key: ApiKey,
+    task: Task,
+    wait: bool,
+) -> Result<(StatusCode, Json<Task>), (StatusCode, Json<Value>)> {
+    if !wait {
+        return Ok((StatusCode::ACCEPTED, Json(task)));
+    }
+
+    match write_side.wait_task(api_key, task.id).await {
+        Ok(Some(task)) => Ok((StatusCode::OK, Json(task))),
+        // The task is removed only when many newer tasks are finished
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "task not found" })),
+        )),
+        Err(e) => {
+            error!("Error waiting task: {:?}", e);
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ListTasksQueryParams {
+    collection_id: Option<String>,
+    status: Option<TaskStatus>,
+}
+
+async fn list_tasks(
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+    Query(query): Query<ListTasksQueryParams>,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    match write_side
+        .list_tasks(api_key, query.collection_id.map(CollectionId), query.status)
+        .await
+    {
+        Ok(tasks) => Ok((StatusCode::OK, Json(tasks))),
+        Err(e) => {
+            error!("Error listing tasks: {:?}", e);
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
+
+#[endpoint(
+    method = "GET",
+    path = "/v1/tasks/{id}",
+    description = "Get the status of a write task"
+)]
+async fn get_task(
+    Path(id): Path<String>,
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    let Ok(task_id) = id.parse::<u64>() else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "invalid task id" })),
+        ));
+    };
+
+    match write_side.get_task(api_key, task_id).await {
+        Ok(Some(task)) => Ok((StatusCode::OK, Json(task))),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "task not found" })),
+        )),
+        Err(e) => {
+            error!("Error getting task: {:?}", e);
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
+
+#[endpoint(
+    method = "POST",
+    path = "/v1/collections/{id}/delete-by-query",
+    description = "Delete the documents matching a filter from a collection. Only the number, datetime and boolean fields can be filtered"
+)]
+async fn delete_documents_by_query(
+    Path(id): Path<String>,
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+    Json(json): Json<DeleteDocumentsByQuery>,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let collection_id = CollectionId(id);
+
+    let write_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    info!(
+        "Delete documents by query to collection {:?}",
+        collection_id
+    );
+    match write_side
+        .delete_documents_by_filter(write_api_key, collection_id, json.where_filter)
+        .await
+    {
+        Ok(deleted) => {
+            let offset = write_side.last_offset().await.map_err(offset_error)?;
+            Ok((
+                StatusCode::OK,
+                Json(json!({ "deleted": deleted, "offset": offset.0 })),
+            ))
+        }
+        Err(e) => {
+            error!("Error deleting documents by query: {:?}", e);
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
+
+#[endpoint(
+    method = "POST",
+    path = "/v1/collections/{id}/reindex",
+    description = "Copy the documents of a collection into a new collection with different options"
+)]
+async fn reindex_collection(
+    Path(id): Path<String>,
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+    Json(json): Json<ReindexCollection>,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let collection_id = CollectionId(id);
+
+    let master_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    info!("Reindexing collection {:?}", collection_id);
+    match write_side
+        .0
+        .clone()
+        .reindex(master_api_key, collection_id, json.collection, json.alias)
+        .await
+    {
+        Ok(status) => Ok((StatusCode::ACCEPTED, Json(status))),
+        Err(e) => {
+            error!("Error reindexing collection: {:?}", e);
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
+
+#[endpoint(
+    method = "GET",
+    path = "/v1/reindexes/{id}",
+    description = "Get the progress of a reindex"
+)]
+async fn get_reindex_status(
+    Path(id): Path<String>,
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let master_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    match write_side.get_reindex_status(master_api_key, id).await {
+        Ok(Some(status)) => Ok((StatusCode::OK, Json(status))),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "reindex not found" })),
+        )),
+        Err(e) => {
+            error!("Error getting reindex status: {:?}", e);
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PatchQueryParams {
+    /// Patches the document only if its version is this
+    if_version: Option<u64>,
+}
+
+async fn patch_document(
+    Path((id, doc_id)): Path<(String, String)>,
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+    Query(query): Query<PatchQueryParams>,
+    Json(json): Json<Map<String, Value>>,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let collection_id = CollectionId(id);
+
+    let write_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    info!(
+        "Patch document {:?} of collection {:?}",
+        doc_id, collection_id
+    );
+    let version = match write_side
+        .patch_document(write_api_key, collection_id, doc_id, json, query.if_version)
+        .await
+    {
+        Ok(version) => {
+            info!("Document patched");
+            version
+        }
+        Err(e) => {
+            error!("Error patching document: {}", e);
+            e.chain()
+                .skip(1)
+                .for_each(|cause| error!("because: {}", cause));
+            if let Some(conflict) = e.downcast_ref::<VersionConflict>() {
+                return Err(conflict_error(conflict));
+            }
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ));
+        }
+    };
+
+    let offset = write_side.last_offset().await.map_err(offset_error)?;
 
     Ok((
         StatusCode::OK,
-        Json(json!({ "message": "documents deleted" })),
+        Json(json!({
+            "message": "document patched",
+            "offset": offset.0,
+            "version": version,
+        })),
     ))
 }
+
+#[derive(Deserialize)]
+struct ImportQueryParams {
+    /// If not set, it is detected from the content type
+    format: Option<ImportFormat>,
+    /// Used to poll the status of the import. If not set, it is generated
+    import_id: Option<String>,
+}
+
+async fn import_documents(
+    Path(id): Path<String>,
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+    Query(query): Query<ImportQueryParams>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let collection_id = CollectionId(id);
+
+    let write_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    let format = query.format.unwrap_or_else(|| {
+        let is_csv = headers
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("text/csv"));
+        if is_csv {
+            ImportFormat::Csv
+        } else {
+            ImportFormat::Ndjson
+        }
+    });
+
+    info!(
+        ?format,
+        "Importing documents to collection {:?}", collection_id
+    );
+    match write_side
+        .0
+        .clone()
+        .import_documents(
+            write_api_key,
+            collection_id,
+            format,
+            query.import_id,
+            body.into_data_stream(),
+        )
+        .await
+    {
+        Ok(status) => Ok((StatusCode::OK, Json(status))),
+        Err(e) => {
+            error!("Error importing documents: {}", e);
+            e.chain()
+                .skip(1)
+                .for_each(|cause| error!("because: {}", cause));
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
+
+async fn get_import_status(
+    Path((id, import_id)): Path<(String, String)>,
+    write_side: State<Arc<WriteSide>>,
+    TypedHeader(auth): AuthorizationBearerHeader,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let collection_id = CollectionId(id);
+
+    let write_api_key = ApiKey(Secret::new(auth.0.token().to_string()));
+
+    match write_side
+        .get_import_status(write_api_key, collection_id, import_id)
+        .await
+    {
+        Ok(Some(status)) => Ok((StatusCode::OK, Json(status))),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "import not found" })),
+        )),
+        Err(e) => {
+            error!("Error getting import status: {}", e);
+            e.chain()
+                .skip(1)
+                .for_each(|cause| error!("because: {}", cause));
+            Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("{:#}", e) })),
+            ))
+        }
+    }
+}
@@ -200,6 +200,10 @@ async fn answer_v0(
                     boost: HashMap::new(),
                     facets: HashMap::new(),
                     properties: crate::collection_manager::dto::Properties::Star,
+                    select: vec![],
+                    exclude: vec![],
+                    include_chunk: false,
+                    min_offset: None,
                 },
             )
             .await
@@ -9,6 +9,7 @@ mod admin;
 mod answer;
 mod hooks;
 mod search;
+mod snapshot;
 
 pub fn apis(write_side: Option<Arc<WriteSide>>, read_side: Option<Arc<ReadSide>>) -> Router {
     let collection_router = Router::new();
@@ -25,7 +26,8 @@ pub fn apis(write_side: Option<Arc<WriteSide>>, read_side: Option<Arc<ReadSide>>
         collection_router
             .merge(search::apis(read_side.clone()))
             .merge(actions::apis(read_side.clone()))
-            .merge(answer::apis(read_side))
+            .merge(answer::apis(read_side.clone()))
+            .merge(snapshot::apis(read_side))
     } else {
         collection_router
     }
@@ -1,12 +1,15 @@
 use std::sync::Arc;
 
 use axum::{
+    body::Body,
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     response::IntoResponse,
+    routing::get,
     Json, Router,
 };
 use axum_openapi3::*;
+use futures::StreamExt;
 use serde::Deserialize;
 use serde_json::json;
 use tracing::error;
@@ -14,16 +17,22 @@ use utoipa::IntoParams;
 
 use crate::{
     collection_manager::{
-        dto::{ApiKey, SearchParams},
-        sides::ReadSide,
+        dto::{ApiKey, FederatedSearchParams, GetDocumentsParams, SearchParams},
+        sides::{CatchingUpError, FederatedSearchError, ReadSide},
     },
-    types::CollectionId,
+    types::{CollectionId, DocumentId},
 };
 
 pub fn apis(read_side: Arc<ReadSide>) -> Router {
     Router::new()
         .add(search())
-        // .route("/:collection_id/documents/:document_id", get(get_doc_by_id))
+        .add(federated_search())
+        .add(get_docs_by_ids())
+        .route(
+            "/v1/collections/{id}/documents/{doc_id}",
+            get(get_doc_by_id),
+        )
+        .route("/v1/collections/{id}/export", get(export_documents))
         .with_state(read_side)
 }
 
@@ -53,6 +62,74 @@ async fn search(
         Ok(data) => Ok((StatusCode::OK, Json(data))),
         Err(e) => {
             error!("Error deleting documents to collection: {}", e);
+            e.chain()
+                .skip(1)
+                .for_each(|cause| error!("because: {}", cause));
+            let status = if e.downcast_ref::<CatchingUpError>().is_some() {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+            Err((status, Json(json!({ "error": e.to_string() }))))
+        }
+    }
+}
+
+#[endpoint(
+    method = "POST",
+    path = "/v1/search",
+    description = "Search across multiple collections at once"
+)]
+async fn federated_search(
+    read_side: State<Arc<ReadSide>>,
+    Json(json): Json<FederatedSearchParams>,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let output = read_side.federated_search(json).await;
+
+    match output {
+        Ok(data) => Ok((StatusCode::OK, Json(data))),
+        Err(e) => {
+            error!("Error in federated search: {}", e);
+            e.chain()
+                .skip(1)
+                .for_each(|cause| error!("because: {}", cause));
+            let status = if e.downcast_ref::<FederatedSearchError>().is_some() {
+                StatusCode::BAD_REQUEST
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+            Err((status, Json(json!({ "error": e.to_string() }))))
+        }
+    }
+}
+
+async fn get_doc_by_id(
+    Path((id, doc_id)): Path<(String, String)>,
+    read_side: State<Arc<ReadSide>>,
+    Query(query): Query<SearchQueryParams>,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let collection_id = CollectionId(id);
+    let read_api_key = query.api_key;
+
+    let output = read_side
+        .get_versioned_documents(read_api_key, collection_id, vec![doc_id])
+        .await;
+
+    match output {
+        // The version is given as `if_version` to the write side
+        Ok(mut docs) => match docs.pop().flatten() {
+            Some((version, doc)) => Ok((
+                StatusCode::OK,
+                [(header::ETAG, format!("\"{}\"", version))],
+                Json(doc),
+            )),
+            None => Err((
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "document not found" })),
+            )),
+        },
+        Err(e) => {
+            error!("Error getting document: {}", e);
             e.chain()
                 .skip(1)
                 .for_each(|cause| error!("because: {}", cause));
@@ -64,39 +141,101 @@ async fn search(
     }
 }
 
-/*
-async fn get_doc_by_id(
-    Path((collection_id, document_id)): Path<(String, String)>,
-    readers: State<Arc<CollectionsReader>>,
+#[endpoint(
+    method = "POST",
+    path = "/v1/collections/{id}/get-documents",
+    description = "Get multiple documents by id. Unknown ids are returned as null"
+)]
+async fn get_docs_by_ids(
+    Path(id): Path<String>,
+    read_side: State<Arc<ReadSide>>,
+    Query(query): Query<SearchQueryParams>,
+    Json(json): Json<GetDocumentsParams>,
 ) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
-    let collection_id = CollectionId(collection_id);
+    let collection_id = CollectionId(id);
+    let read_api_key = query.api_key;
+
+    let output = read_side
+        .get_documents(read_api_key, collection_id, json.ids)
+        .await;
+
+    match output {
+        Ok(data) => Ok((StatusCode::OK, Json(data))),
+        Err(e) => {
+            error!("Error getting documents: {}", e);
+            e.chain()
+                .skip(1)
+                .for_each(|cause| error!("because: {}", cause));
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": e.to_string() })),
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ExportQueryParams {
+    #[serde(rename = "api-key")]
+    api_key: ApiKey,
+    cursor: Option<u64>,
+    #[serde(default = "default_export_limit")]
+    limit: usize,
+}
 
-    let collection = readers.get_collection(collection_id).await;
+fn default_export_limit() -> usize {
+    1_000
+}
 
-    let collection = match collection {
-        Some(collection) => collection,
-        None => {
+/// Streams the documents as NDJSON.
+/// The cursor of the next page is returned in the `x-next-cursor` header.
+async fn export_documents(
+    Path(id): Path<String>,
+    read_side: State<Arc<ReadSide>>,
+    Query(query): Query<ExportQueryParams>,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    let collection_id = CollectionId(id);
+
+    let output = read_side
+        .0
+        .clone()
+        .export_documents(
+            query.api_key,
+            collection_id,
+            query.cursor.map(DocumentId),
+            query.limit,
+        )
+        .await;
+
+    let (documents, next_cursor) = match output {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Error exporting documents: {}", e);
+            e.chain()
+                .skip(1)
+                .for_each(|cause| error!("because: {}", cause));
             return Err((
-                StatusCode::NOT_FOUND,
-                Json(json!({ "error": "collection not found" })),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": e.to_string() })),
             ));
         }
     };
 
-    let output = collection
-        .get_doc_by_unique_field("id".to_string(), document_id)
-        .await;
-
-    match output {
-        Ok(Some(data)) => Ok((StatusCode::OK, Json(data))),
-        Ok(None) => Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({ "error": "document not found" })),
-        )),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({ "error": e.to_string() })),
-        )),
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    if let Some(next_cursor) = next_cursor {
+        headers.insert("x-next-cursor", HeaderValue::from(next_cursor.0));
     }
+
+    let lines = documents.map(|doc| -> Result<Vec<u8>, std::io::Error> {
+        let doc = doc.map_err(|e| std::io::Error::other(e.to_string()))?;
+        let mut line = serde_json::to_vec(&doc)?;
+        line.push(b'\n');
+        Ok(line)
+    });
+
+    Ok((StatusCode::OK, headers, Body::from_stream(lines)))
 }
-*/
@@ -0,0 +1,92 @@
+use std::{io::Write, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::error;
+
+use crate::collection_manager::{dto::ApiKey, sides::ReadSide};
+
+pub fn apis(read_side: Arc<ReadSide>) -> Router {
+    Router::new()
+        .route("/v1/snapshot", get(export_snapshot))
+        .with_state(read_side)
+}
+
+#[derive(Deserialize)]
+struct SnapshotQueryParams {
+    #[serde(rename = "api-key")]
+    api_key: ApiKey,
+}
+
+/// Streams a snapshot of the committed data, to bootstrap a new read replica.
+/// The commits of the read side wait only until the committed files are copied.
+async fn export_snapshot(
+    read_side: State<Arc<ReadSide>>,
+    Query(query): Query<SnapshotQueryParams>,
+) -> Result<impl IntoResponse, (StatusCode, impl IntoResponse)> {
+    if let Err(e) = read_side.check_snapshot_api_key(query.api_key) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": format!("{:#}", e) })),
+        ));
+    }
+
+    let (sender, receiver) = mpsc::channel(16);
+    let writer = ChannelWriter {
+        sender: sender.clone(),
+    };
+    let read_side = read_side.0.clone();
+    tokio::spawn(async move {
+        if let Err(e) = read_side.export_snapshot(writer).await {
+            error!("Error exporting the snapshot: {:?}", e);
+            // The client receives a truncated snapshot, refused by the import
+            let _ = sender
+                .send(Err(std::io::Error::other(format!("{:#}", e))))
+                .await;
+        }
+    });
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment; filename=\"snapshot.bin\""),
+    );
+
+    Ok((
+        StatusCode::OK,
+        headers,
+        Body::from_stream(ReceiverStream::new(receiver)),
+    ))
+}
+
+/// The snapshot is written by a blocking task: the chunks are sent to the response body
+struct ChannelWriter {
+    sender: mpsc::Sender<Result<Vec<u8>, std::io::Error>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.sender.blocking_send(Ok(buf.to_vec())).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, "The client disconnected")
+        })?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
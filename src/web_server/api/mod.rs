@@ -1,12 +1,16 @@
 use std::sync::{atomic::AtomicUsize, Arc};
 
-use axum::{extract::State, response::IntoResponse, Json, Router};
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Json, Router,
+};
 use axum_openapi3::{
     build_openapi, endpoint, reset_openapi,
     utoipa::openapi::{InfoBuilder, OpenApiBuilder},
     AddRoute,
 };
-use http::Request;
+use http::{Request, StatusCode};
 use metrics_exporter_prometheus::PrometheusHandle;
 use tower_http::trace::TraceLayer;
 use tracing::{info, info_span};
@@ -22,7 +26,10 @@ pub fn api_config(
     reset_openapi();
 
     // build our application with a route
-    let router = Router::new().add(index()).add(health()).add(openapi());
+    let router = Router::new()
+        .add(index())
+        .add(openapi())
+        .merge(Router::new().add(health()).with_state(read_side.clone()));
 
     let router = if let Some(prometheus_handle) = prometheus_handle {
         let metric = Router::new()
@@ -64,9 +71,19 @@ async fn index() -> Json<&'static str> {
 }
 
 static HEALTH_MESSAGE: &str = "up";
-#[endpoint(method = "GET", path = "/health", description = "Health check")]
-async fn health() -> Json<&'static str> {
-    Json(HEALTH_MESSAGE)
+static CATCHING_UP_MESSAGE: &str = "catching up";
+#[endpoint(
+    method = "GET",
+    path = "/health",
+    description = "Health check. Unavailable while the read side replays the operations on startup"
+)]
+async fn health(read_side: State<Option<Arc<ReadSide>>>) -> Response {
+    match &*read_side {
+        Some(read_side) if read_side.is_catching_up() => {
+            (StatusCode::SERVICE_UNAVAILABLE, Json(CATCHING_UP_MESSAGE)).into_response()
+        }
+        _ => Json(HEALTH_MESSAGE).into_response(),
+    }
 }
 
 #[endpoint(method = "GET", path = "/openapi.json", description = "OpenAPI spec")]
@@ -1,4 +1,5 @@
 use std::{
+    future::Future,
     net::{IpAddr, SocketAddr},
     sync::Arc,
 };
@@ -42,6 +43,16 @@ impl WebServer {
     }
 
     pub async fn start(self, config: HttpConfig) -> Result<()> {
+        self.start_with_graceful_shutdown(config, std::future::pending())
+            .await
+    }
+
+    /// Once `signal` completes, no new connection is accepted:
+    /// the server stops when the requests in progress are answered
+    pub async fn start_with_graceful_shutdown<F>(self, config: HttpConfig, signal: F) -> Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
         let addr = SocketAddr::new(config.host, config.port);
 
         let router = api_config(self.write_side, self.read_side, self.prometheus_handler);
@@ -61,7 +72,9 @@ impl WebServer {
         let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
 
         info!("Address binded. Starting web server on http://{}", addr);
-        let output = axum::serve(listener, router).await;
+        let output = axum::serve(listener, router)
+            .with_graceful_shutdown(signal)
+            .await;
 
         match output {
             Ok(_) => Ok(()),
@@ -58,7 +58,8 @@ async fn wait_for_server() {
 async fn start_server() {
     let address = create_grpc_server().await.unwrap();
 
-    let (collections_writer, collections_reader, mut receiver) = build_orama(OramacoreConfig {
+    let (collections_writer, collections_reader, receiver) = build_orama(OramacoreConfig {
+        mode: Default::default(),
         log: Default::default(),
         http: HttpConfig {
             host: "127.0.0.1".parse().unwrap(),
@@ -72,10 +73,12 @@ async fn start_server() {
             port: address.port(),
             api_key: None,
             max_connections: 1,
+            embedding_cache_size: 0,
         },
-        writer_side: WriteSideConfig {
+        writer_side: Some(WriteSideConfig {
             master_api_key: ApiKey(Secret::new("my-master-api-key".to_string())),
             output: oramacore::SideChannelType::InMemory,
+            stream_server: None,
             config: CollectionsWriterConfig {
                 data_dir: generate_new_path(),
                 embedding_queue_limit: 50,
@@ -83,16 +86,20 @@ async fn start_server() {
                 insert_batch_commit_size: 10,
                 javascript_queue_limit: 10_000,
                 commit_interval: Duration::from_secs(3_000),
+                embedding_chunking: Default::default(),
             },
-        },
-        reader_side: ReadSideConfig {
+        }),
+        reader_side: Some(ReadSideConfig {
             input: oramacore::SideChannelType::InMemory,
             config: IndexesConfig {
                 data_dir: generate_new_path(),
                 insert_batch_commit_size: 10,
                 commit_interval: Duration::from_secs(3_000),
             },
-        },
+            search_cache: None,
+            snapshot: Default::default(),
+        }),
+        shutdown: Default::default(),
     })
     .await
     .unwrap();
@@ -100,8 +107,9 @@ async fn start_server() {
     let web_server = WebServer::new(collections_writer, collections_reader.clone(), None);
 
     let collections_reader = collections_reader.unwrap();
+    let mut receiver = receiver.unwrap();
     tokio::spawn(async move {
-        while let Some(op) = receiver.recv().await {
+        while let Some(op) = receiver.recv().await.unwrap() {
             let r = collections_reader.update(op).await;
             if let Err(e) = r {
                 println!("--------");
@@ -26,7 +26,8 @@ pub fn generate_new_path() -> PathBuf {
 async fn start_server() -> Result<(Arc<WriteSide>, Arc<ReadSide>)> {
     let address = create_grpc_server().await.unwrap();
 
-    let (collections_writer, collections_reader, mut receiver) = build_orama(OramacoreConfig {
+    let (collections_writer, collections_reader, receiver) = build_orama(OramacoreConfig {
+        mode: Default::default(),
         log: Default::default(),
         http: HttpConfig {
             host: "127.0.0.1".parse().unwrap(),
@@ -40,10 +41,12 @@ async fn start_server() -> Result<(Arc<WriteSide>, Arc<ReadSide>)> {
             port: address.port(),
             api_key: None,
             max_connections: 1,
+            embedding_cache_size: 0,
         },
-        writer_side: WriteSideConfig {
+        writer_side: Some(WriteSideConfig {
             master_api_key: ApiKey(Secret::new("my-master-api-key".to_string())),
             output: oramacore::SideChannelType::InMemory,
+            stream_server: None,
             config: CollectionsWriterConfig {
                 data_dir: generate_new_path(),
                 embedding_queue_limit: 50,
@@ -51,24 +54,29 @@ async fn start_server() -> Result<(Arc<WriteSide>, Arc<ReadSide>)> {
                 insert_batch_commit_size: 10_000,
                 javascript_queue_limit: 10_000,
                 commit_interval: Duration::from_secs(3_000),
+                embedding_chunking: Default::default(),
             },
-        },
-        reader_side: ReadSideConfig {
+        }),
+        reader_side: Some(ReadSideConfig {
             input: oramacore::SideChannelType::InMemory,
             config: IndexesConfig {
                 data_dir: generate_new_path(),
                 insert_batch_commit_size: 10_000,
                 commit_interval: Duration::from_secs(3_000),
             },
-        },
+            search_cache: None,
+            snapshot: Default::default(),
+        }),
+        shutdown: Default::default(),
     })
     .await
     .unwrap();
 
     let collections_reader = collections_reader.unwrap();
     let collections_reader2 = collections_reader.clone();
+    let mut receiver = receiver.unwrap();
     tokio::spawn(async move {
-        while let Some(op) = receiver.recv().await {
+        while let Some(op) = receiver.recv().await.unwrap() {
             let r = collections_reader2.update(op).await;
             if let Err(e) = r {
                 eprintln!("Error: {:?}", e);
@@ -109,6 +117,12 @@ async fn run_tests() {
                 id: collection_id.clone(),
                 description: None,
                 language: None,
+                field_languages: Default::default(),
+                language_property: None,
+                infix_fields: Default::default(),
+                schema: Default::default(),
+                unknown_fields: Default::default(),
+                ttl: None,
                 embeddings: None,
                 read_api_key: ApiKey(Secret::new("my-read-api-key".to_string())),
                 write_api_key: ApiKey(Secret::new("my-write-api-key".to_string())),
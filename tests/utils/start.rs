@@ -1,16 +1,22 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use anyhow::Result;
+use http::uri::Scheme;
 use oramacore::{
+    ai::{AIServiceConfig, OramaModel},
     build_orama,
-    collection_manager::sides::{
-        ReadSide, IndexesConfig,
-        CollectionsWriterConfig, WriteSide,
+    collection_manager::{
+        dto::ApiKey,
+        sides::{
+            CollectionsWriterConfig, IndexesConfig, OramaModelSerializable, ReadSide,
+            ReadSideConfig, WriteSide, WriteSideConfig,
+        },
     },
-    embeddings::EmbeddingConfig,
+    test_utils::create_grpc_server,
     web_server::HttpConfig,
-    OramacoreConfig, ReadSideConfig, WriteSideConfig,
+    OramacoreConfig,
 };
+use redact::Secret;
 use tempdir::TempDir;
 
 fn generate_new_path() -> PathBuf {
@@ -23,38 +29,57 @@ pub async fn start_all() -> Result<(
     Arc<ReadSide>,
     tokio::task::JoinHandle<()>,
 )> {
-    let (collections_writer, collections_reader, mut receiver) = build_orama(OramacoreConfig {
+    let address = create_grpc_server().await?;
+
+    let (collections_writer, collections_reader, receiver) = build_orama(OramacoreConfig {
+        mode: Default::default(),
+        log: Default::default(),
         http: HttpConfig {
             host: "127.0.0.1".parse().unwrap(),
             port: 2222,
             allow_cors: false,
             with_prometheus: false,
         },
-        embeddings: EmbeddingConfig {
-            preload: vec![],
-            hugging_face: None,
-            fastembed: None,
-            models: HashMap::new(),
+        ai_server: AIServiceConfig {
+            scheme: Scheme::HTTP,
+            host: address.ip(),
+            port: address.port(),
+            api_key: None,
+            max_connections: 1,
+            embedding_cache_size: 0,
         },
-        writer_side: WriteSideConfig {
+        writer_side: Some(WriteSideConfig {
+            master_api_key: ApiKey(Secret::new("my-master-api-key".to_string())),
             output: oramacore::SideChannelType::InMemory,
+            stream_server: None,
             config: CollectionsWriterConfig {
                 data_dir: generate_new_path(),
                 embedding_queue_limit: 50,
+                default_embedding_model: OramaModelSerializable(OramaModel::BgeSmall),
+                insert_batch_commit_size: 10_000,
+                javascript_queue_limit: 10_000,
+                commit_interval: Duration::from_secs(3_000),
+                embedding_chunking: Default::default(),
             },
-        },
-        reader_side: ReadSideConfig {
+        }),
+        reader_side: Some(ReadSideConfig {
             input: oramacore::SideChannelType::InMemory,
             config: IndexesConfig {
                 data_dir: generate_new_path(),
+                insert_batch_commit_size: 10_000,
+                commit_interval: Duration::from_secs(3_000),
             },
-        },
+            search_cache: None,
+            snapshot: Default::default(),
+        }),
+        shutdown: Default::default(),
     })
     .await?;
 
     let collections_reader_inner = collections_reader.clone().unwrap();
+    let mut receiver = receiver.unwrap();
     let handler = tokio::spawn(async move {
-        while let Ok(op) = receiver.recv().await {
+        while let Some(op) = receiver.recv().await.unwrap() {
             collections_reader_inner.update(op).await.expect("OUCH!");
         }
     });